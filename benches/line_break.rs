@@ -0,0 +1,62 @@
+//! Benchmarks for the Knuth-Plass line breaker
+//!
+//! Run with: cargo bench --bench line_break
+
+#![feature(test)]
+
+extern crate test;
+
+use mdreport::layout::{
+    Item,
+    LineBreakParams,
+    find_line_breaks,
+};
+use test::Bencher;
+
+/// A paragraph of words with varying widths separated by justified glue
+fn paragraph(words: usize) -> Vec<Item> {
+    let mut items = Vec::with_capacity(words * 2 + 3);
+    for idx in 0..words {
+        if idx > 0 {
+            items.push(Item::Glue {
+                width: 1.0,
+                stretch: 0.5,
+                shrink: 0.3,
+            });
+        }
+        items.push(Item::Box {
+            width: 2.0 + (idx * 7 % 11) as f32 * 0.5,
+        });
+    }
+    Item::finish_paragraph(&mut items);
+    items
+}
+
+#[bench]
+fn bench_short_paragraph(b: &mut Bencher) {
+    let items = paragraph(100);
+    let params = LineBreakParams::new(60.0);
+    b.iter(|| find_line_breaks(test::black_box(&items), &params));
+}
+
+#[bench]
+fn bench_long_paragraph(b: &mut Bencher) {
+    let items = paragraph(5_000);
+    let params = LineBreakParams::new(60.0);
+    b.iter(|| find_line_breaks(test::black_box(&items), &params));
+}
+
+#[bench]
+fn bench_huge_paragraph(b: &mut Bencher) {
+    let items = paragraph(100_000);
+    let params = LineBreakParams::new(60.0);
+    b.iter(|| find_line_breaks(test::black_box(&items), &params));
+}
+
+#[bench]
+fn bench_overfull_paragraph(b: &mut Bencher) {
+    // Too narrow for any word, so every line goes through the emergency pass
+    let items = paragraph(5_000);
+    let params = LineBreakParams::new(1.0);
+    b.iter(|| find_line_breaks(test::black_box(&items), &params));
+}
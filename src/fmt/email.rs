@@ -10,9 +10,20 @@ use pulldown_cmark::{
 };
 
 use super::{
+    AlertStyle,
+    CODE_MUTED,
+    CodeKind,
+    CodeLine,
+    DIFF_ADDED,
+    DIFF_REMOVED,
+    Footnotes,
+    add_heading_ids,
+    add_html_footnotes,
     build_github_url,
+    css_color,
     html_escape,
     resolve_repo,
+    warn_code_block_attributes,
 };
 use crate::parse::{
     CodeBlockInfo,
@@ -24,10 +35,6 @@ pub fn to_plain_text(markdown_content: &str) -> String {
     let front_matter = parser.front_matter();
 
     let mut output = String::new();
-    let mut in_code_block = false;
-    let mut in_heading = false;
-    let mut heading_text = String::new();
-    let mut list_depth: usize = 0;
 
     // Add front matter at the top if present
     if let Some(fm) = front_matter {
@@ -52,7 +59,33 @@ pub fn to_plain_text(markdown_content: &str) -> String {
         }
     }
 
-    for event in parser.into_inner() {
+    let mut events: Vec<Event> = parser.into_inner().collect();
+    let footnotes = Footnotes::extract(&mut events);
+    write_plain_text(&events, &footnotes, &mut output);
+
+    // Numbered notes at the end, as the references in the text show them
+    if !footnotes.is_empty() {
+        output.push_str("Notes\n-----\n\n");
+        for (number, body) in footnotes.iter() {
+            let mut note = String::new();
+            write_plain_text(body, &footnotes, &mut note);
+            output.push_str(&format!("[{}] {}\n\n", number, note.trim()));
+        }
+    }
+
+    output
+}
+
+/// Write the plain text of a run of events
+fn write_plain_text(events: &[Event], footnotes: &Footnotes, output: &mut String) {
+    let mut in_code_block = false;
+    let mut in_heading = false;
+    let mut heading_text = String::new();
+    let mut list_depth: usize = 0;
+    // Superscript or subscript text being read, written out at its end
+    let mut script: Option<String> = None;
+
+    for event in events {
         match event {
             Event::Start(Tag::Heading { level: _, .. }) => {
                 in_heading = true;
@@ -81,9 +114,9 @@ pub fn to_plain_text(markdown_content: &str) -> String {
             }
             Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
                 in_code_block = true;
-                let code_info = CodeBlockInfo::from_str(&info).unwrap();
-                if let Some(filename) = code_info.filename {
-                    output.push_str(&filename);
+                let code_info = CodeBlockInfo::from_str(info).unwrap();
+                if let Some(heading) = code_info.heading() {
+                    output.push_str(heading);
                     output.push_str(":\n");
                 }
             }
@@ -107,30 +140,58 @@ pub fn to_plain_text(markdown_content: &str) -> String {
             Event::End(TagEnd::Item) => {
                 output.push('\n');
             }
-            Event::Start(Tag::BlockQuote(_)) => {
+            Event::Start(Tag::BlockQuote(kind)) => {
                 output.push_str("> ");
+                if let Some(kind) = kind {
+                    output.push_str(AlertStyle::of(*kind).title);
+                    output.push_str(": ");
+                }
             }
             Event::End(TagEnd::BlockQuote(_)) => {
                 output.push_str("\n\n");
             }
+            Event::Start(Tag::Strikethrough) | Event::End(TagEnd::Strikethrough) => {
+                let target = if in_heading {
+                    &mut heading_text
+                } else {
+                    &mut *output
+                };
+                target.push_str("~~");
+            }
+            Event::Start(Tag::Superscript) | Event::Start(Tag::Subscript) => {
+                script = Some(String::new());
+            }
+            Event::End(end @ (TagEnd::Superscript | TagEnd::Subscript)) => {
+                let text = script.take().unwrap_or_default();
+                let target = if in_heading {
+                    &mut heading_text
+                } else {
+                    &mut *output
+                };
+                target.push_str(&script_text(&text, *end == TagEnd::Superscript));
+            }
             Event::Code(code) => {
-                if in_heading {
-                    heading_text.push_str(&code);
+                if let Some(script) = &mut script {
+                    script.push_str(code);
+                } else if in_heading {
+                    heading_text.push_str(code);
                 } else {
                     output.push('`');
-                    output.push_str(&code);
+                    output.push_str(code);
                     output.push('`');
                 }
             }
             Event::Text(text) => {
-                if in_heading {
-                    heading_text.push_str(&text);
+                if let Some(script) = &mut script {
+                    script.push_str(text);
+                } else if in_heading {
+                    heading_text.push_str(text);
                 } else {
-                    output.push_str(&text);
+                    output.push_str(text);
                 }
             }
             Event::SoftBreak => {
-                if in_code_block {
+                if in_code_block || output.ends_with("$$") {
                     output.push('\n');
                 } else {
                     output.push(' ');
@@ -143,11 +204,67 @@ pub fn to_plain_text(markdown_content: &str) -> String {
                 output.push_str(&"-".repeat(70));
                 output.push_str("\n\n");
             }
+            Event::InlineMath(tex) => {
+                let target = if in_heading {
+                    &mut heading_text
+                } else {
+                    &mut *output
+                };
+                target.push_str(&format!("${}$", tex));
+            }
+            // Display math goes on a line of its own
+            Event::DisplayMath(tex) => {
+                if !output.is_empty() && !output.ends_with('\n') {
+                    output.push('\n');
+                }
+                output.push_str(&format!("$${}$$", tex));
+            }
+            Event::FootnoteReference(label) => {
+                let reference = match footnotes.number(label) {
+                    Some(number) => format!("[{}]", number),
+                    None => format!("[^{}]", label),
+                };
+                let target = if in_heading {
+                    &mut heading_text
+                } else {
+                    &mut *output
+                };
+                target.push_str(&reference);
+            }
             _ => {}
         }
     }
+}
 
-    output
+/// Plain text form of superscript or subscript text: the Unicode raised or
+/// lowered characters when there is one for every character, otherwise
+/// `^x`, `_x` or `^(...)`, `_(...)`
+fn script_text(text: &str, superscript: bool) -> String {
+    const SUPERSCRIPTS: &str = "⁰¹²³⁴⁵⁶⁷⁸⁹⁺⁻⁼⁽⁾ⁿⁱ";
+    const SUBSCRIPTS: &str = "₀₁₂₃₄₅₆₇₈₉₊₋₌₍₎";
+    const PLAIN: &str = "0123456789+-=()ni";
+
+    let forms = if superscript {
+        SUPERSCRIPTS
+    } else {
+        SUBSCRIPTS
+    };
+    let converted: Option<String> = text
+        .chars()
+        .map(|c| PLAIN.chars().position(|plain| plain == c))
+        .map(|idx| forms.chars().nth(idx?))
+        .collect();
+    match converted {
+        Some(converted) if !converted.is_empty() => converted,
+        _ => {
+            let mark = if superscript { '^' } else { '_' };
+            if text.chars().count() == 1 {
+                format!("{}{}", mark, text)
+            } else {
+                format!("{}({})", mark, text)
+            }
+        }
+    }
 }
 
 pub fn to_html(markdown_content: &str) -> String {
@@ -165,6 +282,9 @@ pub fn to_html(markdown_content: &str) -> String {
             Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => {
                 in_code_block = true;
                 code_block_info = CodeBlockInfo::from_str(info).ok();
+                if let Some(info) = &code_block_info {
+                    warn_code_block_attributes(info);
+                }
                 code_content.clear();
             }
             Event::End(TagEnd::CodeBlock) if in_code_block => {
@@ -175,10 +295,10 @@ pub fn to_html(markdown_content: &str) -> String {
 
                     custom_html.push_str("<div style=\"margin: 16px 0;\">");
 
-                    if let Some(filename) = info.filename.as_ref() {
+                    if let Some(heading) = info.heading() {
                         let repo_to_use = resolve_repo(info.repo.as_ref(), front_matter.as_ref());
 
-                        if let Some(repo) = repo_to_use {
+                        if let (Some(filename), Some(repo)) = (&info.filename, repo_to_use) {
                             let github_url = build_github_url(
                                 filename,
                                 info.start_line,
@@ -188,12 +308,12 @@ pub fn to_html(markdown_content: &str) -> String {
                             custom_html.push_str(&format!(
                                 "<div style=\"background-color: #e1e4e8; color: #24292e; padding: 8px 16px; font-family: 'Courier New', Courier, monospace; font-size: 14px; font-weight: 600; border-bottom: 1px solid #d0d7de;\"><a href=\"{}\" style=\"color: #24292e; text-decoration: none;\">{}</a></div>",
                                 html_escape(&github_url),
-                                html_escape(filename)
+                                html_escape(heading)
                             ));
                         } else {
                             custom_html.push_str(&format!(
                                 "<div style=\"background-color: #e1e4e8; color: #24292e; padding: 8px 16px; font-family: 'Courier New', Courier, monospace; font-size: 14px; font-weight: 600; border-bottom: 1px solid #d0d7de;\">{}</div>",
-                                html_escape(filename)
+                                html_escape(heading)
                             ));
                         }
                     }
@@ -204,14 +324,25 @@ pub fn to_html(markdown_content: &str) -> String {
                     }
                     custom_html.push('>');
 
-                    if let Some(start_line) = info.start_line {
-                        let lines: Vec<&str> = code_content.lines().collect();
-                        for (idx, line) in lines.iter().enumerate() {
-                            let line_num = start_line + idx;
-                            custom_html.push_str(&format!(
-                                "<span style=\"color: #8b949e; margin-right: 16px; user-select: none; display: inline-block; text-align: right; min-width: 48px;\">{:>4}</span>{}\n",
-                                line_num,
-                                html_escape(line)
+                    let first_number = info.first_line_number();
+                    let kind = CodeKind::of(&info.language);
+                    if first_number.is_some()
+                        || !info.highlight_lines.is_empty()
+                        || kind != CodeKind::Plain
+                    {
+                        for (idx, line) in code_content.lines().enumerate() {
+                            let number = first_number
+                                .map(|first| {
+                                    format!(
+                                        "<span style=\"color: #8b949e; margin-right: 16px; user-select: none; display: inline-block; text-align: right; min-width: 48px;\">{:>4}</span>",
+                                        first + idx
+                                    )
+                                })
+                                .unwrap_or_default();
+                            custom_html.push_str(&code_line_html(
+                                kind.line(line),
+                                &number,
+                                info.is_highlighted(idx),
                             ));
                         }
                     } else {
@@ -236,6 +367,37 @@ pub fn to_html(markdown_content: &str) -> String {
             Event::Text(ref text) if in_code_block => {
                 code_content.push_str(text);
             }
+            Event::Start(Tag::BlockQuote(None)) => {
+                events.push(Event::Html(CowStr::Borrowed(
+                    "<blockquote style=\"border-left: 4px solid #dfe2e5; padding-left: 16px; margin: 16px 0; color: #6a737d;\">\n",
+                )));
+            }
+            Event::Start(Tag::BlockQuote(Some(kind))) => {
+                let style = AlertStyle::of(kind);
+                let color = css_color(style.color);
+                events.push(Event::Html(CowStr::from(format!(
+                    "<div style=\"border-left: 4px solid {color}; background-color: {}; padding: 8px 16px; margin: 16px 0;\">\n<p style=\"margin: 0 0 8px; font-weight: 600; color: {color};\"><span style=\"display: inline-block; width: 20px; height: 20px; line-height: 20px; border-radius: 10px; margin-right: 8px; background-color: {color}; color: #fff; font-size: 13px; text-align: center;\">{}</span>{}</p>\n",
+                    css_color(style.background()),
+                    html_escape(&style.icon.to_string()),
+                    style.title,
+                ))));
+            }
+            Event::End(TagEnd::BlockQuote(Some(_))) => {
+                events.push(Event::Html(CowStr::Borrowed("</div>\n")));
+            }
+            // Mail clients seldom render MathML, so math keeps its TeX source
+            Event::InlineMath(tex) => {
+                events.push(Event::InlineHtml(CowStr::from(format!(
+                    "<code style=\"background-color: #f6f8fa; padding: 2px 4px; border-radius: 3px;\">{}</code>",
+                    html_escape(&tex)
+                ))));
+            }
+            Event::DisplayMath(tex) => {
+                events.push(Event::InlineHtml(CowStr::from(format!(
+                    "<span style=\"display: block; margin: 12px 0; text-align: center; font-family: monospace;\">{}</span>",
+                    html_escape(&tex)
+                ))));
+            }
             _ if !in_code_block => {
                 events.push(event);
             }
@@ -243,6 +405,12 @@ pub fn to_html(markdown_content: &str) -> String {
         }
     }
 
+    add_html_footnotes(
+        &mut events,
+        "<section style=\"margin-top: 32px; padding-top: 8px; border-top: 1px solid #eaecef; font-size: 14px; color: #555;\">",
+    );
+    add_heading_ids(&mut events);
+
     let mut html_output = String::new();
     html::push_html(&mut html_output, events.into_iter());
 
@@ -291,3 +459,69 @@ pub fn to_html(markdown_content: &str) -> String {
         metadata_html, html_output
     )
 }
+
+/// One line of a code block with inline styles. Highlighted lines and the
+/// added, removed and header lines of a diff are blocks of their own, so no
+/// newline follows them.
+fn code_line_html(line: CodeLine, number: &str, highlighted: bool) -> String {
+    let band = |background: (u8, u8, u8)| {
+        format!(
+            "display: block; background-color: {}; margin: 0 -16px; padding: 0 16px;",
+            css_color(background)
+        )
+    };
+    let marker = |marker: char, color: Option<(u8, u8, u8)>| {
+        let color = color.map_or(String::new(), |color| {
+            format!(" color: {};", css_color(color))
+        });
+        format!(
+            "<span style=\"display: inline-block; width: 2ch;{}\">{}</span>",
+            color, marker
+        )
+    };
+    let (style, text) = match line {
+        CodeLine::Plain(text) => (None, html_escape(text)),
+        CodeLine::Added(text) => (
+            Some(band(DIFF_ADDED.0)),
+            marker('+', Some(DIFF_ADDED.1)) + &html_escape(text),
+        ),
+        CodeLine::Removed(text) => (
+            Some(band(DIFF_REMOVED.0)),
+            marker('-', Some(DIFF_REMOVED.1)) + &html_escape(text),
+        ),
+        CodeLine::Unchanged(text) => (None, marker(' ', None) + &html_escape(text)),
+        CodeLine::DiffHeader(text) => (
+            Some(format!(
+                "{} color: {};",
+                band((0xea, 0xee, 0xf2)),
+                css_color(CODE_MUTED)
+            )),
+            html_escape(text),
+        ),
+        CodeLine::Command { prompt, command } => (
+            None,
+            format!(
+                "<span style=\"color: {}; user-select: none;\">{}</span><span style=\"font-weight: 600;\">{}</span>",
+                css_color(CODE_MUTED),
+                html_escape(prompt),
+                html_escape(command)
+            ),
+        ),
+        CodeLine::Output(text) => (
+            None,
+            format!(
+                "<span style=\"color: #57606a;\">{}</span>",
+                html_escape(text)
+            ),
+        ),
+    };
+    let style = if highlighted {
+        Some(band((0xff, 0xf8, 0xc5)))
+    } else {
+        style
+    };
+    match style {
+        Some(style) => format!("<span style=\"{}\">{}{}</span>", style, number, text),
+        None => format!("{}{}\n", number, text),
+    }
+}
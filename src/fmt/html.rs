@@ -1,6 +1,7 @@
 use std::str::FromStr;
 
 use pulldown_cmark::{
+    BlockQuoteKind,
     CodeBlockKind,
     CowStr,
     Event,
@@ -10,9 +11,22 @@ use pulldown_cmark::{
 };
 
 use super::{
+    ALERT_KINDS,
+    AlertStyle,
+    CODE_MUTED,
+    CodeKind,
+    CodeLine,
+    DIFF_ADDED,
+    DIFF_REMOVED,
+    add_heading_ids,
+    add_html_footnotes,
     build_github_url,
+    css_color,
+    find_toc_marker,
     html_escape,
+    math::to_mathml,
     resolve_repo,
+    warn_code_block_attributes,
 };
 use crate::parse::{
     CodeBlockInfo,
@@ -33,7 +47,9 @@ pub fn to_html(markdown_content: &str) -> String {
         match event {
             Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
                 in_code_block = true;
-                code_block_info = Some(CodeBlockInfo::from_str(&info).unwrap());
+                let info = CodeBlockInfo::from_str(&info).unwrap();
+                warn_code_block_attributes(&info);
+                code_block_info = Some(info);
                 code_content.clear();
             }
             Event::End(TagEnd::CodeBlock) if in_code_block => {
@@ -45,14 +61,14 @@ pub fn to_html(markdown_content: &str) -> String {
 
                     custom_html.push_str("<div class=\"code-block-container\">");
 
-                    if let Some(filename) = info.filename {
+                    if let Some(heading) = info.heading() {
                         // Determine which repo to use: code block repo or frontmatter default
                         let repo_to_use = resolve_repo(info.repo.as_ref(), front_matter.as_ref());
 
-                        if let Some(repo) = repo_to_use {
+                        if let (Some(filename), Some(repo)) = (&info.filename, repo_to_use) {
                             // Build GitHub URL and make filename clickable
                             let github_url = build_github_url(
-                                &filename,
+                                filename,
                                 info.start_line,
                                 repo,
                                 info.refspec.as_deref(),
@@ -60,13 +76,13 @@ pub fn to_html(markdown_content: &str) -> String {
                             custom_html.push_str(&format!(
                                 "<div class=\"code-filename\"><a href=\"{}\" target=\"_blank\">{}</a></div>",
                                 html_escape(&github_url),
-                                html_escape(&filename)
+                                html_escape(heading)
                             ));
                         } else {
                             // No repo info, just display filename as text
                             custom_html.push_str(&format!(
                                 "<div class=\"code-filename\">{}</div>",
-                                html_escape(&filename)
+                                html_escape(heading)
                             ));
                         }
                     }
@@ -77,15 +93,27 @@ pub fn to_html(markdown_content: &str) -> String {
                     }
                     custom_html.push('>');
 
-                    // Add line numbers if start_line is specified
-                    if let Some(start_line) = info.start_line {
-                        let lines: Vec<&str> = code_content.lines().collect();
-                        for (idx, line) in lines.iter().enumerate() {
-                            let line_num = start_line + idx;
-                            custom_html.push_str(&format!(
-                                "<span class=\"line-number\">{:>4}</span> {}\n",
-                                line_num,
-                                html_escape(line)
+                    // Add line numbers, highlighted lines and diff or console
+                    // styling when asked for
+                    let first_number = info.first_line_number();
+                    let kind = CodeKind::of(&info.language);
+                    if first_number.is_some()
+                        || !info.highlight_lines.is_empty()
+                        || kind != CodeKind::Plain
+                    {
+                        for (idx, line) in code_content.lines().enumerate() {
+                            let number = first_number
+                                .map(|first| {
+                                    format!(
+                                        "<span class=\"line-number\">{:>4}</span> ",
+                                        first + idx
+                                    )
+                                })
+                                .unwrap_or_default();
+                            custom_html.push_str(&code_line_html(
+                                kind.line(line),
+                                &number,
+                                info.is_highlighted(idx),
                             ));
                         }
                     } else {
@@ -111,6 +139,18 @@ pub fn to_html(markdown_content: &str) -> String {
             Event::Text(ref text) if in_code_block => {
                 code_content.push_str(text);
             }
+            Event::Start(Tag::BlockQuote(Some(kind))) => {
+                events.push(Event::Html(CowStr::from(alert_start(kind))));
+            }
+            Event::End(TagEnd::BlockQuote(Some(_))) => {
+                events.push(Event::Html(CowStr::Borrowed("</div>\n")));
+            }
+            Event::InlineMath(tex) => {
+                events.push(Event::InlineHtml(CowStr::from(to_mathml(&tex, false))));
+            }
+            Event::DisplayMath(tex) => {
+                events.push(Event::InlineHtml(CowStr::from(to_mathml(&tex, true))));
+            }
             _ => {
                 if !in_code_block {
                     events.push(event);
@@ -119,8 +159,16 @@ pub fn to_html(markdown_content: &str) -> String {
         }
     }
 
+    add_html_footnotes(&mut events, "<section class=\"footnotes\">");
+    add_heading_ids(&mut events);
+    let toc = front_matter.as_ref().and_then(|fm| fm.toc).unwrap_or(false);
+    let toc_depth = front_matter.as_ref().and_then(|fm| fm.toc_depth);
+    insert_table_of_contents(&mut events, toc, toc_depth);
+
     let mut html_output = String::new();
     html::push_html(&mut html_output, events.into_iter());
+    let alert_css = alert_css();
+    let code_line_css = code_line_css();
 
     format!(
         r#"<!DOCTYPE html>
@@ -212,6 +260,12 @@ pub fn to_html(markdown_content: &str) -> String {
             margin-top: 0;
             border-radius: 0 0 5px 5px;
         }}
+        .highlighted-line {{
+            display: block;
+            background-color: #fff8c5;
+            margin: 0 -1em;
+            padding: 0 1em;
+        }}
         .line-number {{
             color: #8b949e;
             margin-right: 1em;
@@ -226,7 +280,31 @@ pub fn to_html(markdown_content: &str) -> String {
             margin-left: 0;
             color: #6a737d;
         }}
-        table {{
+        .markdown-alert {{
+            border-left: 4px solid;
+            border-radius: 0 5px 5px 0;
+            padding: 0.5em 1em;
+            margin: 1em 0;
+        }}
+        .markdown-alert > :last-child {{
+            margin-bottom: 0;
+        }}
+        .markdown-alert-title {{
+            font-weight: 600;
+            margin: 0 0 0.5em;
+        }}
+        .markdown-alert-icon {{
+            display: inline-block;
+            width: 1.25em;
+            height: 1.25em;
+            line-height: 1.25em;
+            border-radius: 50%;
+            margin-right: 0.5em;
+            color: #fff;
+            font-size: 0.85em;
+            text-align: center;
+        }}
+{alert_css}{code_line_css}        table {{
             border-collapse: collapse;
             width: 100%;
             margin: 1em 0;
@@ -265,6 +343,29 @@ pub fn to_html(markdown_content: &str) -> String {
             border-top: 2px solid #eaecef;
             margin: 2em 0;
         }}
+        .toc {{
+            margin: 1em 0 2em;
+        }}
+        .footnotes {{
+            margin-top: 3em;
+            padding-top: 0.5em;
+            border-top: 1px solid #eaecef;
+            font-size: 0.9em;
+            color: #555;
+        }}
+        .footnote-ref a {{
+            padding: 0 0.1em;
+        }}
+        .footnote-backref {{
+            font-family: sans-serif;
+        }}
+        .toc ul {{
+            list-style: none;
+            padding-left: 1.5em;
+        }}
+        .toc > ul {{
+            padding-left: 0;
+        }}
     </style>
 </head>
 <body>
@@ -273,3 +374,185 @@ pub fn to_html(markdown_content: &str) -> String {
 </html>"#,
     )
 }
+
+/// Opening of a GitHub alert callout, with its icon and title line
+fn alert_start(kind: BlockQuoteKind) -> String {
+    let style = AlertStyle::of(kind);
+    format!(
+        "<div class=\"markdown-alert markdown-alert-{}\">\n<p class=\"markdown-alert-title\"><span class=\"markdown-alert-icon\">{}</span>{}</p>\n",
+        style.class(),
+        html_escape(&style.icon.to_string()),
+        style.title
+    )
+}
+
+/// Colours of each alert kind
+fn alert_css() -> String {
+    let mut css = String::new();
+    for kind in ALERT_KINDS {
+        let style = AlertStyle::of(kind);
+        let color = css_color(style.color);
+        css.push_str(&format!(
+            "        .markdown-alert-{class} {{ border-color: {color}; background-color: {background}; }}\n        .markdown-alert-{class} .markdown-alert-title {{ color: {color}; }}\n        .markdown-alert-{class} .markdown-alert-icon {{ background-color: {color}; }}\n",
+            class = style.class(),
+            color = color,
+            background = css_color(style.background()),
+        ));
+    }
+    css
+}
+
+/// One line of a code block. Highlighted lines and the added, removed and
+/// header lines of a diff are blocks of their own, so no newline follows
+/// them. Shell prompts cannot be selected, so copied commands leave them out.
+fn code_line_html(line: CodeLine, number: &str, highlighted: bool) -> String {
+    let (class, text) = match line {
+        CodeLine::Plain(text) => (None, html_escape(text)),
+        CodeLine::Added(text) | CodeLine::Removed(text) | CodeLine::Unchanged(text) => {
+            let class = match line {
+                CodeLine::Added(_) => Some("diff-added"),
+                CodeLine::Removed(_) => Some("diff-removed"),
+                _ => None,
+            };
+            let marker = line.diff_marker().unwrap_or(' ');
+            (
+                class,
+                format!(
+                    "<span class=\"diff-marker\">{}</span>{}",
+                    marker,
+                    html_escape(text)
+                ),
+            )
+        }
+        CodeLine::DiffHeader(text) => (Some("diff-header"), html_escape(text)),
+        CodeLine::Command { prompt, command } => (
+            None,
+            format!(
+                "<span class=\"console-prompt\">{}</span><span class=\"console-command\">{}</span>",
+                html_escape(prompt),
+                html_escape(command)
+            ),
+        ),
+        CodeLine::Output(text) => (
+            None,
+            format!(
+                "<span class=\"console-output\">{}</span>",
+                html_escape(text)
+            ),
+        ),
+    };
+    match (highlighted, class) {
+        (true, _) => format!("<span class=\"highlighted-line\">{}{}</span>", number, text),
+        (false, Some(class)) => format!("<span class=\"{}\">{}{}</span>", class, number, text),
+        (false, None) => format!("{}{}\n", number, text),
+    }
+}
+
+/// Colours of diff and console lines in code blocks
+fn code_line_css() -> String {
+    let muted = css_color(CODE_MUTED);
+    let mut css = String::new();
+    for (class, (background, marker)) in
+        [("diff-added", DIFF_ADDED), ("diff-removed", DIFF_REMOVED)]
+    {
+        css.push_str(&format!(
+            "        .{class} {{ background-color: {background}; }}\n        .{class} .diff-marker {{ color: {marker}; }}\n",
+            class = class,
+            background = css_color(background),
+            marker = css_color(marker),
+        ));
+    }
+    css.push_str(&format!(
+        "        .diff-header {{ background-color: #eaeef2; color: {muted}; }}\n        .diff-added, .diff-removed, .diff-header {{ display: block; margin: 0 -1em; padding: 0 1em; }}\n        .diff-marker {{ display: inline-block; width: 2ch; }}\n        .console-prompt {{ color: {muted}; user-select: none; }}\n        .console-command {{ font-weight: 600; }}\n        .console-output {{ color: #57606a; }}\n",
+        muted = muted,
+    ));
+    css
+}
+
+/// Replace the `[[_TOC_]]` paragraph with a table of contents, or put one at
+/// the top when `toc` is set and there is no marker
+fn insert_table_of_contents(events: &mut Vec<Event<'_>>, toc: bool, depth: Option<u8>) {
+    let marker = find_toc_marker(events);
+    if marker.is_none() && !toc {
+        return;
+    }
+
+    let nav = Event::Html(CowStr::from(table_of_contents(events, depth.unwrap_or(3))));
+    match marker {
+        Some(range) => {
+            events.splice(range, [nav]);
+        }
+        None => events.insert(0, nav),
+    }
+}
+
+/// Nested list of links to the headings, which must already have ids. Each
+/// heading goes under the last heading of a lower level before it, so
+/// skipped levels (an H3 directly after an H1) still nest, as in the PDF
+/// outline.
+fn table_of_contents(events: &[Event<'_>], depth: u8) -> String {
+    let mut html = String::from("<nav class=\"toc\">\n");
+    // Level of each open item, and whether it has opened a list of its own
+    let mut open: Vec<(u8, bool)> = Vec::new();
+    let mut started = false;
+    let mut heading: Option<(u8, String, String)> = None;
+    let close = |html: &mut String, (_, has_list): (u8, bool)| {
+        if has_list {
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</li>\n");
+    };
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) if (*level as u8) <= depth => {
+                let id = id.as_deref().unwrap_or_default().to_string();
+                heading = Some((*level as u8, id, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, title)) = &mut heading {
+                    title.push_str(text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let Some((level, id, title)) = heading.take() else {
+                    continue;
+                };
+
+                while let Some(&item) = open.last()
+                    && item.0 >= level
+                {
+                    open.pop();
+                    close(&mut html, item);
+                }
+                match open.last_mut() {
+                    Some((_, has_list)) if !*has_list => {
+                        html.push_str("<ul>\n");
+                        *has_list = true;
+                    }
+                    None if !started => {
+                        html.push_str("<ul>\n");
+                        started = true;
+                    }
+                    _ => {}
+                }
+                html.push_str(&format!(
+                    "<li><a href=\"#{}\">{}</a>",
+                    html_escape(&id),
+                    html_escape(&title)
+                ));
+                open.push((level, false));
+            }
+            _ => {}
+        }
+    }
+
+    while let Some(item) = open.pop() {
+        close(&mut html, item);
+    }
+    if started {
+        html.push_str("</ul>\n");
+    }
+    html.push_str("</nav>\n");
+    html
+}
@@ -0,0 +1,865 @@
+//! TeX math, parsed once and written out by each format
+//!
+//! A practical subset of TeX math mode is understood: letters, numbers and
+//! operators, Greek letters and common symbols, fractions, roots, sub- and
+//! superscripts, sums and integrals with limits, delimiters sized with
+//! `\left` and `\right`, accents, text, and the matrix environments. HTML
+//! output writes the tree as MathML; PDF output lays it out itself.
+
+use super::html_escape;
+
+/// How an atom is spaced from its neighbours, as in TeX
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Class {
+    Ord,
+    /// Large operator or function name
+    Op,
+    /// Binary operator such as `+`
+    Bin,
+    /// Relation such as `=`
+    Rel,
+    Open,
+    Close,
+    Punct,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Accent {
+    Bar,
+    Hat,
+    Tilde,
+    Vec,
+    Dot,
+}
+
+impl Accent {
+    fn as_char(self) -> char {
+        match self {
+            Accent::Bar => '¯',
+            Accent::Hat => '^',
+            Accent::Tilde => '~',
+            Accent::Vec => '→',
+            Accent::Dot => '˙',
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// Variable: a Latin letter, set in italics, or a Greek letter
+    Ident(char),
+    Number(String),
+    /// Upright text from `\text`, `\mathrm` or `\mathbf`
+    Text {
+        text: String,
+        bold: bool,
+    },
+    /// Operator, relation, delimiter or other symbol
+    Symbol {
+        symbol: char,
+        class: Class,
+    },
+    /// Sum, product or integral, drawn larger in display math. With
+    /// `limits`, display math puts its scripts above and below it.
+    LargeOp {
+        symbol: char,
+        limits: bool,
+    },
+    /// Function name such as `sin` or `lim`
+    Function {
+        name: String,
+        limits: bool,
+    },
+    Group(Vec<Node>),
+    Frac {
+        numerator: Vec<Node>,
+        denominator: Vec<Node>,
+    },
+    Sqrt {
+        index: Option<Vec<Node>>,
+        body: Vec<Node>,
+    },
+    Scripts {
+        base: Box<Node>,
+        sub: Option<Vec<Node>>,
+        sup: Option<Vec<Node>>,
+    },
+    /// Content between delimiters that grow with it; `None` is the blank
+    /// delimiter `.`
+    Fenced {
+        open: Option<char>,
+        close: Option<char>,
+        body: Vec<Node>,
+    },
+    /// Rows of cells, with columns centred or, for `cases`, aligned left
+    Matrix {
+        rows: Vec<Vec<Vec<Node>>>,
+        align_left: bool,
+    },
+    Accent {
+        accent: Accent,
+        body: Vec<Node>,
+    },
+    /// Horizontal space in ems
+    Space(f32),
+}
+
+/// Parse TeX math into a list of nodes
+pub fn parse(tex: &str) -> Result<Vec<Node>, String> {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+    };
+    let (nodes, end) = parser.list()?;
+    match end {
+        End::Eof => Ok(nodes),
+        End::Brace => Err("unmatched }".to_string()),
+        End::Right(_) => Err("\\right without \\left".to_string()),
+        End::Cell | End::Row => Err("& or \\\\ outside a matrix".to_string()),
+        End::Env(name) => Err(format!("\\end{{{}}} without \\begin", name)),
+    }
+}
+
+/// Write TeX math as a MathML `<math>` element, keeping the source as an
+/// annotation. Math that cannot be parsed is shown as code.
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let Ok(nodes) = parse(tex) else {
+        return format!("<code class=\"math\">{}</code>", html_escape(tex));
+    };
+    let mut row = String::new();
+    write_row(&nodes, &mut row, true);
+    format!(
+        "<math{}><semantics>{}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { " display=\"block\"" } else { "" },
+        row,
+        html_escape(tex)
+    )
+}
+
+/// What ended a list of nodes
+enum End {
+    Eof,
+    Brace,
+    Right(Option<char>),
+    Cell,
+    Row,
+    Env(String),
+}
+
+enum Atom {
+    Node(Node),
+    End(End),
+    /// `\limits` or `\nolimits` after an operator
+    Limits(bool),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Nodes up to the end of the input, a closing brace, `&`, `\\`,
+    /// `\right` or `\end`
+    fn list(&mut self) -> Result<(Vec<Node>, End), String> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_space();
+            let Some(c) = self.peek() else {
+                return Ok((nodes, End::Eof));
+            };
+            match c {
+                '}' => {
+                    self.pos += 1;
+                    return Ok((nodes, End::Brace));
+                }
+                '&' => {
+                    self.pos += 1;
+                    return Ok((nodes, End::Cell));
+                }
+                '^' | '_' | '\'' => self.scripts(&mut nodes)?,
+                _ => match self.atom(false)? {
+                    Atom::Node(node) => nodes.push(node),
+                    Atom::End(end) => return Ok((nodes, end)),
+                    Atom::Limits(limits) => match nodes.last_mut() {
+                        Some(
+                            Node::LargeOp { limits: op, .. } | Node::Function { limits: op, .. },
+                        ) => *op = limits,
+                        _ => return Err("\\limits without an operator".to_string()),
+                    },
+                },
+            }
+        }
+    }
+
+    /// Attach a subscript, superscript or primes to the last node
+    fn scripts(&mut self, nodes: &mut Vec<Node>) -> Result<(), String> {
+        let (base, mut sub, mut sup) = match nodes.pop() {
+            Some(Node::Scripts { base, sub, sup }) => (base, sub, sup),
+            Some(node) => (Box::new(node), None, None),
+            None => (Box::new(Node::Group(Vec::new())), None, None),
+        };
+        match self.next() {
+            Some('_') if sub.is_some() => return Err("double subscript".to_string()),
+            Some('_') => sub = Some(self.argument()?),
+            Some(_) if sup.is_some() => return Err("double superscript".to_string()),
+            Some('^') => sup = Some(self.argument()?),
+            _ => {
+                let mut primes = vec![Node::Symbol {
+                    symbol: '′',
+                    class: Class::Ord,
+                }];
+                while self.peek() == Some('\'') {
+                    self.pos += 1;
+                    primes.push(primes[0].clone());
+                }
+                self.skip_space();
+                if self.peek() == Some('^') {
+                    self.pos += 1;
+                    primes.extend(self.argument()?);
+                }
+                sup = Some(primes);
+            }
+        }
+        nodes.push(Node::Scripts { base, sub, sup });
+        Ok(())
+    }
+
+    /// A braced group or a single atom, as taken by `^` and `\frac`
+    fn argument(&mut self) -> Result<Vec<Node>, String> {
+        self.skip_space();
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.group()
+            }
+            Some('}' | '&' | '^' | '_') | None => Err("missing argument".to_string()),
+            Some(_) => match self.atom(true)? {
+                Atom::Node(node) => Ok(vec![node]),
+                _ => Err("missing argument".to_string()),
+            },
+        }
+    }
+
+    /// The rest of a group whose opening brace has been read
+    fn group(&mut self) -> Result<Vec<Node>, String> {
+        match self.list()? {
+            (nodes, End::Brace) => Ok(nodes),
+            _ => Err("unmatched {".to_string()),
+        }
+    }
+
+    /// The text of a braced argument, unparsed
+    fn raw_argument(&mut self) -> Result<String, String> {
+        self.skip_space();
+        if self.next() != Some('{') {
+            return Err("expected {".to_string());
+        }
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                None => return Err("unmatched {".to_string()),
+                Some('\\') => {
+                    if let Some(c) = self.next() {
+                        // `\{`, `\%` and the like stand for the character
+                        if c.is_alphabetic() {
+                            text.push('\\');
+                        }
+                        text.push(c);
+                    }
+                }
+                Some('{') => {
+                    depth += 1;
+                    text.push('{');
+                }
+                Some('}') if depth == 0 => return Ok(text),
+                Some('}') => {
+                    depth -= 1;
+                    text.push('}');
+                }
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// One node; a number is a single digit when `single`, as in `x^23`
+    fn atom(&mut self, single: bool) -> Result<Atom, String> {
+        let Some(c) = self.next() else {
+            return Err("missing argument".to_string());
+        };
+        let node = match c {
+            '{' => Node::Group(self.group()?),
+            '\\' => return self.command(),
+            '0'..='9' => {
+                let mut number = c.to_string();
+                if !single {
+                    loop {
+                        match (self.peek(), self.chars.get(self.pos + 1)) {
+                            (Some(digit @ '0'..='9'), _) => number.push(digit),
+                            (Some('.'), Some('0'..='9')) => number.push('.'),
+                            _ => break,
+                        }
+                        self.pos += 1;
+                    }
+                }
+                Node::Number(number)
+            }
+            c if c.is_alphabetic() => Node::Ident(c),
+            '-' => Node::Symbol {
+                symbol: '−',
+                class: Class::Bin,
+            },
+            '*' => Node::Symbol {
+                symbol: '∗',
+                class: Class::Bin,
+            },
+            c => Node::Symbol {
+                symbol: c,
+                class: char_class(c),
+            },
+        };
+        Ok(Atom::Node(node))
+    }
+
+    fn command(&mut self) -> Result<Atom, String> {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            name.push(c);
+            self.pos += 1;
+        }
+        if name.is_empty() {
+            match self.next() {
+                Some(c) => name.push(c),
+                None => return Err("\\ at the end".to_string()),
+            }
+        }
+
+        let node = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => Node::Frac {
+                numerator: self.argument()?,
+                denominator: self.argument()?,
+            },
+            "sqrt" => {
+                self.skip_space();
+                let index = if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c != ']') {
+                        self.pos += 1;
+                    }
+                    if self.next().is_none() {
+                        return Err("unmatched [".to_string());
+                    }
+                    let index: String = self.chars[start..self.pos - 1].iter().collect();
+                    Some(parse(&index)?)
+                } else {
+                    None
+                };
+                Node::Sqrt {
+                    index,
+                    body: self.argument()?,
+                }
+            }
+            "left" => {
+                let open = self.delimiter()?;
+                match self.list()? {
+                    (body, End::Right(close)) => Node::Fenced { open, close, body },
+                    _ => return Err("\\left without \\right".to_string()),
+                }
+            }
+            "right" => return Ok(Atom::End(End::Right(self.delimiter()?))),
+            "begin" => {
+                let name = self.raw_argument()?;
+                self.environment(&name)?
+            }
+            "end" => return Ok(Atom::End(End::Env(self.raw_argument()?))),
+            "\\" | "cr" => return Ok(Atom::End(End::Row)),
+            "limits" => return Ok(Atom::Limits(true)),
+            "nolimits" => return Ok(Atom::Limits(false)),
+            "text" | "textrm" | "mathrm" | "textup" => Node::Text {
+                text: self.raw_argument()?,
+                bold: false,
+            },
+            "mathbf" | "textbf" | "boldsymbol" => Node::Text {
+                text: self.raw_argument()?,
+                bold: true,
+            },
+            "operatorname" => Node::Function {
+                name: self.raw_argument()?,
+                limits: false,
+            },
+            "bar" | "overline" => self.accent(Accent::Bar)?,
+            "hat" | "widehat" => self.accent(Accent::Hat)?,
+            "tilde" | "widetilde" => self.accent(Accent::Tilde)?,
+            "vec" | "overrightarrow" => self.accent(Accent::Vec)?,
+            "dot" => self.accent(Accent::Dot)?,
+            "," | "thinspace" => Node::Space(3.0 / 18.0),
+            ":" | ">" | "medspace" => Node::Space(4.0 / 18.0),
+            ";" | "thickspace" => Node::Space(5.0 / 18.0),
+            "!" | "negthinspace" => Node::Space(-3.0 / 18.0),
+            " " => Node::Space(1.0 / 3.0),
+            "quad" => Node::Space(1.0),
+            "qquad" => Node::Space(2.0),
+            "sum" => Node::LargeOp {
+                symbol: '∑',
+                limits: true,
+            },
+            "prod" => Node::LargeOp {
+                symbol: '∏',
+                limits: true,
+            },
+            "int" => Node::LargeOp {
+                symbol: '∫',
+                limits: false,
+            },
+            "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr"
+            | "argmax" | "argmin" => Node::Function {
+                name: match name.as_str() {
+                    "liminf" => "lim inf".to_string(),
+                    "limsup" => "lim sup".to_string(),
+                    "argmax" => "arg max".to_string(),
+                    "argmin" => "arg min".to_string(),
+                    _ => name,
+                },
+                limits: true,
+            },
+            "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan"
+            | "sinh" | "cosh" | "tanh" | "log" | "ln" | "lg" | "exp" | "deg" | "dim" | "ker"
+            | "arg" | "hom" => Node::Function {
+                name,
+                limits: false,
+            },
+            _ => match symbol(&name) {
+                Some((symbol, Class::Ord)) if symbol.is_alphabetic() => Node::Ident(symbol),
+                Some((symbol, class)) => Node::Symbol { symbol, class },
+                None => return Err(format!("unsupported command \\{}", name)),
+            },
+        };
+        Ok(Atom::Node(node))
+    }
+
+    fn accent(&mut self, accent: Accent) -> Result<Node, String> {
+        Ok(Node::Accent {
+            accent,
+            body: self.argument()?,
+        })
+    }
+
+    /// The delimiter after `\left` or `\right`
+    fn delimiter(&mut self) -> Result<Option<char>, String> {
+        self.skip_space();
+        let delimiter = match self.next() {
+            Some('.') => return Ok(None),
+            Some(c @ ('(' | ')' | '[' | ']' | '|' | '/')) => c,
+            Some('<') => '⟨',
+            Some('>') => '⟩',
+            Some('\\') => match self.command()? {
+                Atom::Node(Node::Symbol {
+                    symbol,
+                    class: Class::Open | Class::Close,
+                }) => symbol,
+                Atom::Node(Node::Symbol { symbol: '|', .. }) => '|',
+                Atom::Node(Node::Symbol { symbol: '‖', .. }) => '‖',
+                _ => return Err("unsupported delimiter".to_string()),
+            },
+            _ => return Err("missing delimiter".to_string()),
+        };
+        Ok(Some(delimiter))
+    }
+
+    /// The body of `\begin{name}`, up to its `\end`
+    fn environment(&mut self, name: &str) -> Result<Node, String> {
+        let (open, close, align_left) = match name {
+            "matrix" | "smallmatrix" => (None, None, false),
+            "pmatrix" => (Some('('), Some(')'), false),
+            "bmatrix" => (Some('['), Some(']'), false),
+            "Bmatrix" => (Some('{'), Some('}'), false),
+            "vmatrix" => (Some('|'), Some('|'), false),
+            "Vmatrix" => (Some('‖'), Some('‖'), false),
+            "cases" => (Some('{'), None, true),
+            _ => return Err(format!("unsupported environment {}", name)),
+        };
+
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            let (cell, end) = self.list()?;
+            row.push(cell);
+            match end {
+                End::Cell => {}
+                End::Row => rows.push(std::mem::take(&mut row)),
+                End::Env(end) if end == name => {
+                    // A final `\\` adds no empty row
+                    if row.len() > 1 || !row[0].is_empty() {
+                        rows.push(row);
+                    }
+                    break;
+                }
+                _ => return Err(format!("\\begin{{{}}} without \\end", name)),
+            }
+        }
+
+        let matrix = Node::Matrix { rows, align_left };
+        Ok(match (open, close) {
+            (None, None) => matrix,
+            (open, close) => Node::Fenced {
+                open,
+                close,
+                body: vec![matrix],
+            },
+        })
+    }
+}
+
+fn char_class(c: char) -> Class {
+    match c {
+        '+' => Class::Bin,
+        '=' | '<' | '>' | ':' => Class::Rel,
+        ',' | ';' => Class::Punct,
+        '(' | '[' => Class::Open,
+        ')' | ']' | '!' | '?' => Class::Close,
+        _ => Class::Ord,
+    }
+}
+
+/// Character and class of a symbol command such as `\alpha` or `\leq`
+fn symbol(name: &str) -> Option<(char, Class)> {
+    use Class::*;
+
+    let symbol = match name {
+        "alpha" => ('α', Ord),
+        "beta" => ('β', Ord),
+        "gamma" => ('γ', Ord),
+        "delta" => ('δ', Ord),
+        "epsilon" | "varepsilon" => ('ε', Ord),
+        "zeta" => ('ζ', Ord),
+        "eta" => ('η', Ord),
+        "theta" => ('θ', Ord),
+        "vartheta" => ('ϑ', Ord),
+        "iota" => ('ι', Ord),
+        "kappa" => ('κ', Ord),
+        "lambda" => ('λ', Ord),
+        "mu" => ('μ', Ord),
+        "nu" => ('ν', Ord),
+        "xi" => ('ξ', Ord),
+        "pi" => ('π', Ord),
+        "varpi" => ('ϖ', Ord),
+        "rho" => ('ρ', Ord),
+        "sigma" => ('σ', Ord),
+        "varsigma" => ('ς', Ord),
+        "tau" => ('τ', Ord),
+        "upsilon" => ('υ', Ord),
+        "phi" => ('ϕ', Ord),
+        "varphi" => ('φ', Ord),
+        "chi" => ('χ', Ord),
+        "psi" => ('ψ', Ord),
+        "omega" => ('ω', Ord),
+        "Gamma" => ('Γ', Ord),
+        "Delta" => ('Δ', Ord),
+        "Theta" => ('Θ', Ord),
+        "Lambda" => ('Λ', Ord),
+        "Xi" => ('Ξ', Ord),
+        "Pi" => ('Π', Ord),
+        "Sigma" => ('Σ', Ord),
+        "Upsilon" => ('ϒ', Ord),
+        "Phi" => ('Φ', Ord),
+        "Psi" => ('Ψ', Ord),
+        "Omega" => ('Ω', Ord),
+        "aleph" => ('ℵ', Ord),
+        "infty" => ('∞', Ord),
+        "partial" => ('∂', Ord),
+        "nabla" => ('∇', Ord),
+        "emptyset" | "varnothing" => ('∅', Ord),
+        "forall" => ('∀', Ord),
+        "exists" => ('∃', Ord),
+        "neg" | "lnot" => ('¬', Ord),
+        "angle" => ('∠', Ord),
+        "prime" => ('′', Ord),
+        "ldots" | "dots" | "cdots" => ('…', Ord),
+        "degree" => ('°', Ord),
+        "%" | "#" | "$" | "&" | "_" => (name.chars().next()?, Ord),
+        "|" | "Vert" => ('‖', Ord),
+        "vert" | "mid" => ('|', Ord),
+        "pm" => ('±', Bin),
+        "times" => ('×', Bin),
+        "div" => ('÷', Bin),
+        "cdot" => ('⋅', Bin),
+        "ast" => ('∗', Bin),
+        "cup" => ('∪', Bin),
+        "cap" => ('∩', Bin),
+        "wedge" | "land" => ('∧', Bin),
+        "vee" | "lor" => ('∨', Bin),
+        "oplus" => ('⊕', Bin),
+        "otimes" => ('⊗', Bin),
+        "leq" | "le" => ('≤', Rel),
+        "geq" | "ge" => ('≥', Rel),
+        "neq" | "ne" => ('≠', Rel),
+        "approx" => ('≈', Rel),
+        "equiv" => ('≡', Rel),
+        "sim" => ('∼', Rel),
+        "cong" => ('≅', Rel),
+        "propto" => ('∝', Rel),
+        "in" => ('∈', Rel),
+        "notin" => ('∉', Rel),
+        "ni" => ('∋', Rel),
+        "subset" => ('⊂', Rel),
+        "subseteq" => ('⊆', Rel),
+        "supset" => ('⊃', Rel),
+        "supseteq" => ('⊇', Rel),
+        "perp" => ('⊥', Rel),
+        "to" | "rightarrow" => ('→', Rel),
+        "leftarrow" | "gets" => ('←', Rel),
+        "leftrightarrow" => ('↔', Rel),
+        "Rightarrow" | "implies" => ('⇒', Rel),
+        "Leftarrow" => ('⇐', Rel),
+        "Leftrightarrow" | "iff" => ('⇔', Rel),
+        "uparrow" => ('↑', Rel),
+        "downarrow" => ('↓', Rel),
+        "colon" => (':', Punct),
+        "{" | "lbrace" => ('{', Open),
+        "}" | "rbrace" => ('}', Close),
+        "langle" => ('⟨', Open),
+        "rangle" => ('⟩', Close),
+        "lfloor" => ('⌊', Open),
+        "rfloor" => ('⌋', Close),
+        "lceil" => ('⌈', Open),
+        "rceil" => ('⌉', Close),
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+/// Write nodes as one MathML element, wrapping several in `<mrow>`
+fn write_row(nodes: &[Node], output: &mut String, always_wrap: bool) {
+    if let [node] = nodes
+        && !always_wrap
+    {
+        write_node(node, output);
+        return;
+    }
+    output.push_str("<mrow>");
+    for node in nodes {
+        write_node(node, output);
+    }
+    output.push_str("</mrow>");
+}
+
+fn write_node(node: &Node, output: &mut String) {
+    let mut element = |tag: &str, text: &str| {
+        output.push_str(&format!("<{}>{}</{}>", tag, html_escape(text), tag));
+    };
+    match node {
+        Node::Ident(c) => element("mi", &c.to_string()),
+        Node::Number(number) => element("mn", number),
+        Node::Text { text, bold: false } => element("mtext", text),
+        Node::Text { text, bold: true } => output.push_str(&format!(
+            "<mtext style=\"font-weight: bold\">{}</mtext>",
+            html_escape(text)
+        )),
+        Node::Symbol {
+            symbol,
+            class: Class::Ord,
+        } if !symbol.is_ascii_punctuation() && !matches!(symbol, '‖' | '…') => {
+            element("mi", &symbol.to_string())
+        }
+        Node::Symbol { symbol, .. } | Node::LargeOp { symbol, .. } => {
+            element("mo", &symbol.to_string())
+        }
+        Node::Function { name, .. } => element("mi", name),
+        Node::Group(nodes) => write_row(nodes, output, true),
+        Node::Frac {
+            numerator,
+            denominator,
+        } => {
+            output.push_str("<mfrac>");
+            write_row(numerator, output, false);
+            write_row(denominator, output, false);
+            output.push_str("</mfrac>");
+        }
+        Node::Sqrt { index: None, body } => {
+            output.push_str("<msqrt>");
+            write_row(body, output, false);
+            output.push_str("</msqrt>");
+        }
+        Node::Sqrt {
+            index: Some(index),
+            body,
+        } => {
+            output.push_str("<mroot>");
+            write_row(body, output, false);
+            write_row(index, output, false);
+            output.push_str("</mroot>");
+        }
+        Node::Scripts { base, sub, sup } => {
+            let limits = matches!(
+                **base,
+                Node::LargeOp { limits: true, .. } | Node::Function { limits: true, .. }
+            );
+            let tag = match (sub.is_some(), sup.is_some(), limits) {
+                (true, true, true) => "munderover",
+                (true, false, true) => "munder",
+                (false, _, true) => "mover",
+                (true, true, false) => "msubsup",
+                (true, false, false) => "msub",
+                (false, _, false) => "msup",
+            };
+            output.push_str(&format!("<{}>", tag));
+            write_node(base, output);
+            for script in [sub, sup].into_iter().flatten() {
+                write_row(script, output, false);
+            }
+            output.push_str(&format!("</{}>", tag));
+        }
+        Node::Fenced { open, close, body } => {
+            output.push_str("<mrow>");
+            if let Some(open) = open {
+                output.push_str(&format!("<mo>{}</mo>", html_escape(&open.to_string())));
+            }
+            for node in body {
+                write_node(node, output);
+            }
+            if let Some(close) = close {
+                output.push_str(&format!("<mo>{}</mo>", html_escape(&close.to_string())));
+            }
+            output.push_str("</mrow>");
+        }
+        Node::Matrix { rows, align_left } => {
+            output.push_str(if *align_left {
+                "<mtable columnalign=\"left\">"
+            } else {
+                "<mtable>"
+            });
+            for row in rows {
+                output.push_str("<mtr>");
+                for cell in row {
+                    output.push_str("<mtd>");
+                    write_row(cell, output, false);
+                    output.push_str("</mtd>");
+                }
+                output.push_str("</mtr>");
+            }
+            output.push_str("</mtable>");
+        }
+        Node::Accent { accent, body } => {
+            output.push_str("<mover accent=\"true\">");
+            write_row(body, output, false);
+            output.push_str(&format!("<mo>{}</mo></mover>", accent.as_char()));
+        }
+        Node::Space(em) => output.push_str(&format!("<mspace width=\"{:.3}em\"/>", em)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ident = Node::Ident;
+        assert_eq!(
+            parse("x^23").unwrap(),
+            vec![
+                Node::Scripts {
+                    base: Box::new(ident('x')),
+                    sub: None,
+                    sup: Some(vec![Node::Number("2".to_string())]),
+                },
+                Node::Number("3".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse("a_i^2 - 3.5").unwrap(),
+            vec![
+                Node::Scripts {
+                    base: Box::new(ident('a')),
+                    sub: Some(vec![ident('i')]),
+                    sup: Some(vec![Node::Number("2".to_string())]),
+                },
+                Node::Symbol {
+                    symbol: '−',
+                    class: Class::Bin,
+                },
+                Node::Number("3.5".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse("\\frac{\\alpha}2").unwrap(),
+            vec![Node::Frac {
+                numerator: vec![ident('α')],
+                denominator: vec![Node::Number("2".to_string())],
+            }]
+        );
+        assert_eq!(
+            parse("\\sum\\nolimits").unwrap(),
+            vec![Node::LargeOp {
+                symbol: '∑',
+                limits: false,
+            }]
+        );
+
+        let Node::Fenced { open, close, body } =
+            &parse("\\begin{pmatrix} 1 & 0 \\\\ 0 & 1 \\\\ \\end{pmatrix}").unwrap()[0]
+        else {
+            panic!("expected a fenced matrix");
+        };
+        assert_eq!((*open, *close), (Some('('), Some(')')));
+        let Node::Matrix { rows, .. } = &body[0] else {
+            panic!("expected a matrix");
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].len(), 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("\\frac{1}").is_err());
+        assert!(parse("{x").is_err());
+        assert!(parse("x}").is_err());
+        assert!(parse("x^2^3").is_err());
+        assert!(parse("\\left( x").is_err());
+        assert!(parse("\\begin{matrix} x \\end{cases}").is_err());
+        assert_eq!(
+            parse("\\unknown").unwrap_err(),
+            "unsupported command \\unknown"
+        );
+    }
+
+    #[test]
+    fn test_mathml() {
+        assert_eq!(
+            to_mathml("x^2", false),
+            "<math><semantics><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow>\
+             <annotation encoding=\"application/x-tex\">x^2</annotation></semantics></math>"
+        );
+
+        let display = to_mathml("\\sum_{i=1}^n \\sqrt[3]{x_i} < \\infty", true);
+        assert!(display.starts_with("<math display=\"block\">"));
+        assert!(display.contains(
+            "<munderover><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover>"
+        ));
+        assert!(display.contains("<mroot><msub><mi>x</mi><mi>i</mi></msub><mn>3</mn></mroot>"));
+        assert!(display.contains("<mo>&lt;</mo><mi>∞</mi>"));
+
+        assert_eq!(
+            to_mathml("\\oops{x}", false),
+            "<code class=\"math\">\\oops{x}</code>"
+        );
+    }
+}
@@ -1,8 +1,31 @@
 pub mod email;
 pub mod html;
+pub mod math;
 pub mod pdf;
 
-use crate::parse::FrontMatter;
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use pulldown_cmark::{
+    BlockQuoteKind,
+    CowStr,
+    Event,
+    Tag,
+    TagEnd,
+};
+
+use crate::parse::{
+    CodeBlockInfo,
+    FrontMatter,
+};
 
 pub fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
@@ -38,3 +61,683 @@ pub fn resolve_repo<'a>(
 ) -> Option<&'a String> {
     code_block_repo.or_else(|| front_matter.and_then(|fm| fm.repo.as_ref()))
 }
+
+/// Resolve a path from the document relative to the directory of the source file
+pub fn resolve_path(path: &str, source_path: Option<&Path>) -> PathBuf {
+    match source_path.and_then(|source| source.parent()) {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+/// How a GitHub alert (`> [!NOTE]`) is presented in every output format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlertStyle {
+    pub title: &'static str,
+    /// Accent colour of the border, icon and title
+    pub color: (u8, u8, u8),
+    /// Character shown in the round icon before the title
+    pub icon: char,
+}
+
+impl AlertStyle {
+    pub fn of(kind: BlockQuoteKind) -> Self {
+        let (title, color, icon) = match kind {
+            BlockQuoteKind::Note => ("Note", (0x09, 0x69, 0xda), 'i'),
+            BlockQuoteKind::Tip => ("Tip", (0x1a, 0x7f, 0x37), '+'),
+            BlockQuoteKind::Important => ("Important", (0x82, 0x50, 0xdf), '!'),
+            BlockQuoteKind::Warning => ("Warning", (0x9a, 0x67, 0x00), '!'),
+            BlockQuoteKind::Caution => ("Caution", (0xd1, 0x24, 0x2f), '×'),
+        };
+        Self { title, color, icon }
+    }
+
+    /// Pale tint of the accent colour for the callout background
+    pub fn background(&self) -> (u8, u8, u8) {
+        let tint = |channel: u8| 255 - (255 - channel) / 10;
+        (tint(self.color.0), tint(self.color.1), tint(self.color.2))
+    }
+
+    /// Class name suffix used by the HTML backends, as in `markdown-alert-note`
+    pub fn class(&self) -> String {
+        self.title.to_lowercase()
+    }
+}
+
+pub const ALERT_KINDS: [BlockQuoteKind; 5] = [
+    BlockQuoteKind::Note,
+    BlockQuoteKind::Tip,
+    BlockQuoteKind::Important,
+    BlockQuoteKind::Warning,
+    BlockQuoteKind::Caution,
+];
+
+/// Format a colour as a CSS hex colour
+pub fn css_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Footnote definitions taken out of a document's events, numbered in the
+/// order they are first referenced
+#[derive(Debug, Default)]
+pub struct Footnotes<'a> {
+    /// Label and definition events of each referenced note, in number order
+    notes: Vec<(String, Vec<Event<'a>>)>,
+}
+
+impl<'a> Footnotes<'a> {
+    /// Remove the footnote definitions from `events`. Definitions that are
+    /// never referenced are dropped.
+    pub fn extract(events: &mut Vec<Event<'a>>) -> Self {
+        let mut definitions: HashMap<String, Vec<Event<'a>>> = HashMap::new();
+        let mut definition: Option<(String, Vec<Event<'a>>)> = None;
+        let mut kept = Vec::with_capacity(events.len());
+        for event in events.drain(..) {
+            match event {
+                Event::Start(Tag::FootnoteDefinition(label)) => {
+                    definition = Some((label.to_lowercase(), Vec::new()));
+                }
+                Event::End(TagEnd::FootnoteDefinition) => {
+                    if let Some((label, body)) = definition.take() {
+                        definitions.entry(label).or_insert(body);
+                    }
+                }
+                event => match &mut definition {
+                    Some((_, body)) => body.push(event),
+                    None => kept.push(event),
+                },
+            }
+        }
+        *events = kept;
+
+        // Notes referenced from other notes follow the ones that refer to them
+        let mut footnotes = Self::default();
+        footnotes.number_references(events, &mut definitions);
+        let mut idx = 0;
+        while idx < footnotes.notes.len() {
+            let body = std::mem::take(&mut footnotes.notes[idx].1);
+            footnotes.number_references(&body, &mut definitions);
+            footnotes.notes[idx].1 = body;
+            idx += 1;
+        }
+        footnotes
+    }
+
+    fn number_references(
+        &mut self,
+        events: &[Event<'a>],
+        definitions: &mut HashMap<String, Vec<Event<'a>>>,
+    ) {
+        for event in events {
+            if let Event::FootnoteReference(label) = event
+                && let Some(body) = definitions.remove(&label.to_lowercase())
+            {
+                self.notes.push((label.to_lowercase(), body));
+            }
+        }
+    }
+
+    /// Number of the note a reference points to, counting from 1
+    pub fn number(&self, label: &str) -> Option<usize> {
+        let label = label.to_lowercase();
+        self.notes
+            .iter()
+            .position(|(note, _)| *note == label)
+            .map(|idx| idx + 1)
+    }
+
+    /// Number and definition events of each note, in order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &[Event<'a>])> {
+        self.notes
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, body))| (idx + 1, body.as_slice()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+}
+
+/// Number footnote references as links to a list of the notes added at the
+/// end, where each note links back to its references. `section` is the
+/// opening tag of the list, so each backend can style it.
+pub fn add_html_footnotes(events: &mut Vec<Event<'_>>, section: &str) {
+    let footnotes = Footnotes::extract(events);
+    let mut references = vec![0; footnotes.notes.len()];
+    let mut link_references = |events: &mut [Event<'_>]| {
+        for event in events.iter_mut() {
+            let Event::FootnoteReference(label) = event else {
+                continue;
+            };
+            let html = match footnotes.number(label) {
+                Some(number) => {
+                    references[number - 1] += 1;
+                    format!(
+                        "<sup class=\"footnote-ref\"><a href=\"#fn-{n}\" id=\"{id}\">{n}</a></sup>",
+                        n = number,
+                        id = footnote_ref_id(number, references[number - 1]),
+                    )
+                }
+                None => html_escape(&format!("[^{}]", label)),
+            };
+            *event = Event::InlineHtml(CowStr::from(html));
+        }
+    };
+    link_references(events);
+    if footnotes.is_empty() {
+        return;
+    }
+
+    let mut notes: Vec<Vec<Event<'_>>> = footnotes.iter().map(|(_, body)| body.to_vec()).collect();
+    for body in &mut notes {
+        link_references(body);
+    }
+
+    let mut html = format!("{}\n<ol>\n", section);
+    for (idx, body) in notes.into_iter().enumerate() {
+        let number = idx + 1;
+        let mut note = String::new();
+        pulldown_cmark::html::push_html(&mut note, body.into_iter());
+        let backlinks: String = (1..=references[idx])
+            .map(|count| {
+                format!(
+                    " <a href=\"#{}\" class=\"footnote-backref\">↩{}</a>",
+                    footnote_ref_id(number, count),
+                    if count > 1 {
+                        count.to_string()
+                    } else {
+                        String::new()
+                    }
+                )
+            })
+            .collect();
+        // The links back go at the end of the last paragraph
+        let note = match note.trim_end().strip_suffix("</p>") {
+            Some(start) => format!("{}{}</p>", start, backlinks),
+            None => format!("{}{}", note.trim_end(), backlinks),
+        };
+        html.push_str(&format!("<li id=\"fn-{}\">\n{}\n</li>\n", number, note));
+    }
+    html.push_str("</ol>\n</section>\n");
+    events.push(Event::Html(CowStr::from(html)));
+}
+
+/// Anchor of the `count`th reference to a footnote
+fn footnote_ref_id(number: usize, count: usize) -> String {
+    match count {
+        1 => format!("fnref-{}", number),
+        _ => format!("fnref-{}-{}", number, count),
+    }
+}
+
+/// Paragraph marking where the table of contents goes
+pub const TOC_MARKER: &str = "[[_TOC_]]";
+
+/// Whether the text of a paragraph is the TOC marker. The underscores in
+/// `[[_TOC_]]` parse as emphasis, so the text may have lost them.
+pub fn is_toc_marker(text: &str) -> bool {
+    matches!(text.trim(), "[[TOC]]" | TOC_MARKER)
+}
+
+/// Events of the paragraph holding only the TOC marker
+pub fn find_toc_marker(events: &[Event<'_>]) -> Option<std::ops::Range<usize>> {
+    let mut start = None;
+    let mut text = String::new();
+    for (idx, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                start = Some(idx);
+                text.clear();
+            }
+            Event::Text(t) => text.push_str(t),
+            Event::End(TagEnd::Paragraph) => {
+                if let Some(start) = start.take()
+                    && is_toc_marker(&text)
+                {
+                    return Some(start..idx + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Turn heading text into an anchor the way GitHub does: lowercase, spaces
+/// become hyphens and punctuation other than `-` and `_` is dropped
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Unique anchors for the headings of one document
+#[derive(Debug, Default)]
+pub struct HeadingIds {
+    /// How many headings asked for each slug so far
+    counts: HashMap<String, usize>,
+    ids: HashSet<String>,
+}
+
+impl HeadingIds {
+    /// Anchors for a document's headings, with every explicit `{#id}`
+    /// taken up front so no earlier heading's slug can claim it
+    pub fn for_events(events: &[Event<'_>]) -> Self {
+        let mut ids = Self::default();
+        for event in events {
+            if let Event::Start(Tag::Heading { id: Some(id), .. }) = event {
+                ids.ids.insert(id.to_string());
+            }
+        }
+        ids
+    }
+
+    /// Anchor for a heading: its explicit `{#id}`, or its slugified text with
+    /// `-1`, `-2`, ... appended when the slug is already taken
+    pub fn assign(&mut self, explicit: Option<&str>, text: &str) -> String {
+        if let Some(id) = explicit {
+            self.ids.insert(id.to_string());
+            return id.to_string();
+        }
+
+        let slug = slugify(text);
+        loop {
+            let count = self.counts.entry(slug.clone()).or_insert(0);
+            let id = match *count {
+                0 => slug.clone(),
+                n => format!("{}-{}", slug, n),
+            };
+            *count += 1;
+            if self.ids.insert(id.clone()) {
+                return id;
+            }
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+}
+
+/// Report problems with the attributes of a code block
+pub fn warn_code_block_attributes(info: &CodeBlockInfo) {
+    for warning in &info.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// How the lines of a fenced code block are read, going by its language
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CodeKind<'a> {
+    Plain,
+    /// A patch, as ` ```diff `, or ` ```diff-rust ` for a patch to code in
+    /// that language. The language is empty for a bare `diff`.
+    Diff(&'a str),
+    /// A shell session of prompted commands and their output
+    Console,
+}
+
+impl<'a> CodeKind<'a> {
+    pub fn of(language: &'a str) -> Self {
+        match language {
+            "diff" | "patch" => CodeKind::Diff(""),
+            "console" | "shell-session" => CodeKind::Console,
+            _ => language
+                .strip_prefix("diff-")
+                .map_or(CodeKind::Plain, CodeKind::Diff),
+        }
+    }
+
+    /// Language the text of the lines is highlighted as
+    pub fn highlight_language(&self, language: &'a str) -> &'a str {
+        match self {
+            CodeKind::Plain => language,
+            CodeKind::Diff(language) => language,
+            CodeKind::Console => "bash",
+        }
+    }
+
+    /// Split a line of the block into its parts
+    pub fn line(&self, line: &'a str) -> CodeLine<'a> {
+        match self {
+            CodeKind::Plain => CodeLine::Plain(line),
+            CodeKind::Diff(_) => {
+                let header = ["@@", "+++ ", "--- ", "diff ", "index ", "\\ "];
+                if header.iter().any(|prefix| line.starts_with(prefix)) {
+                    CodeLine::DiffHeader(line)
+                } else if let Some(text) = line.strip_prefix('+') {
+                    CodeLine::Added(text)
+                } else if let Some(text) = line.strip_prefix('-') {
+                    CodeLine::Removed(text)
+                } else {
+                    CodeLine::Unchanged(line.strip_prefix(' ').unwrap_or(line))
+                }
+            }
+            CodeKind::Console => match split_prompt(line) {
+                Some((prompt, command)) => CodeLine::Command { prompt, command },
+                None => CodeLine::Output(line),
+            },
+        }
+    }
+}
+
+/// A line of a code block, split by what it is in a diff or shell session
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CodeLine<'a> {
+    /// A line of any other block
+    Plain(&'a str),
+    /// Diff lines, without their `+`, `-` or space marker
+    Added(&'a str),
+    Removed(&'a str),
+    Unchanged(&'a str),
+    /// File and hunk headers of a diff, such as `@@ -1,4 +1,5 @@`
+    DiffHeader(&'a str),
+    /// A shell prompt, such as `$ ` or `user@host:~$ `, and the command after it
+    Command {
+        prompt: &'a str,
+        command: &'a str,
+    },
+    Output(&'a str),
+}
+
+impl CodeLine<'_> {
+    /// Marker shown before the text of a diff line
+    pub fn diff_marker(&self) -> Option<char> {
+        match self {
+            CodeLine::Added(_) => Some('+'),
+            CodeLine::Removed(_) => Some('-'),
+            CodeLine::Unchanged(_) => Some(' '),
+            _ => None,
+        }
+    }
+}
+
+/// Background and marker colour of added lines in a diff
+pub const DIFF_ADDED: ((u8, u8, u8), (u8, u8, u8)) = ((0xe6, 0xff, 0xec), (0x1a, 0x7f, 0x37));
+/// Background and marker colour of removed lines in a diff
+pub const DIFF_REMOVED: ((u8, u8, u8), (u8, u8, u8)) = ((0xff, 0xeb, 0xe9), (0xd1, 0x24, 0x2f));
+/// Colour of shell prompts and diff headers
+pub const CODE_MUTED: (u8, u8, u8) = (0x6e, 0x77, 0x81);
+
+/// Split a shell prompt from the command after it. A prompt is one word
+/// ending in `$`, `#`, `%` or `>` and a space, which either is just that
+/// character or names a user, host or directory, as in `user@host:~/src$ `.
+fn split_prompt(line: &str) -> Option<(&str, &str)> {
+    let word = line.split(char::is_whitespace).next()?;
+    let last = word.chars().last()?;
+    let stem = &word[..word.len() - last.len_utf8()];
+    let prompt = matches!(last, '$' | '#' | '%' | '>')
+        && (stem.is_empty() || stem.contains(['@', ':', '~']))
+        && (line.len() == word.len() || line[word.len()..].starts_with(' '));
+    if !prompt {
+        return None;
+    }
+    let end = (word.len() + 1).min(line.len());
+    Some((&line[..end], &line[end..]))
+}
+
+/// Print a warning for each `#fragment` link without a matching heading
+pub fn warn_broken_fragments<'a>(links: impl IntoIterator<Item = &'a str>, ids: &HeadingIds) {
+    let mut reported = HashSet::new();
+    for link in links {
+        if let Some(fragment) = link.strip_prefix('#')
+            && !ids.contains(fragment)
+            && reported.insert(fragment)
+        {
+            eprintln!("Warning: link to '#{}' has no matching heading", fragment);
+        }
+    }
+}
+
+/// Give every heading an `id` so fragment links can reach it, and warn
+/// about links to anchors that do not exist
+pub fn add_heading_ids(events: &mut [Event<'_>]) {
+    let mut ids = HeadingIds::for_events(events);
+    let mut heading: Option<(usize, String)> = None;
+
+    for idx in 0..events.len() {
+        match &events[idx] {
+            Event::Start(Tag::Heading { .. }) => heading = Some((idx, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading_text)) = &mut heading {
+                    heading_text.push_str(text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((start, text)) = heading.take()
+                    && let Event::Start(Tag::Heading { id, .. }) = &mut events[start]
+                {
+                    let anchor = ids.assign(id.as_deref(), &text);
+                    *id = Some(CowStr::from(anchor));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let links = events.iter().filter_map(|event| match event {
+        Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.as_ref()),
+        _ => None,
+    });
+    warn_broken_fragments(links, &ids);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heading_ids() {
+        assert_eq!(slugify("Getting Started!"), "getting-started");
+        assert_eq!(slugify("`to_pdf` & friends"), "to_pdf--friends");
+
+        let mut ids = HeadingIds::default();
+        assert_eq!(ids.assign(None, "Setup"), "setup");
+        assert_eq!(ids.assign(None, "Setup"), "setup-1");
+        assert_eq!(ids.assign(Some("install"), "Setup"), "install");
+        assert_eq!(ids.assign(Some("setup-2"), "Other"), "setup-2");
+        assert_eq!(ids.assign(None, "Setup"), "setup-3");
+        assert!(ids.contains("install"));
+        assert!(!ids.contains("missing"));
+
+        // An explicit id wins over the slug of an earlier heading
+        let mut events: Vec<Event> =
+            crate::parse::MarkdownParser::new("# Intro\n\n# Other {#intro}\n")
+                .unwrap()
+                .into_inner()
+                .collect();
+        add_heading_ids(&mut events);
+        let anchors: Vec<&str> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Start(Tag::Heading { id: Some(id), .. }) => Some(id.as_ref()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(anchors, ["intro-1", "intro"]);
+    }
+
+    #[test]
+    fn test_alert_callouts() {
+        let markdown = "> [!WARNING]\n> Mind the gap.\n\n> Plain quote\n";
+
+        let html = html::to_html(markdown);
+        assert!(html.contains("<div class=\"markdown-alert markdown-alert-warning\">"));
+        assert!(html.contains("<span class=\"markdown-alert-icon\">!</span>Warning</p>"));
+        assert!(html.contains(".markdown-alert-warning {"));
+        assert!(html.contains("<blockquote>"));
+
+        let email = email::to_html(markdown);
+        assert!(email.contains("border-left: 4px solid #9a6700"));
+        assert!(email.contains("<blockquote style="));
+
+        let text = email::to_plain_text(markdown);
+        assert!(text.starts_with("> Warning: Mind the gap."));
+    }
+
+    #[test]
+    fn test_strikethrough_and_scripts() {
+        let markdown = "~~old~~ **~~bold~~** note ^2^, x ~1~ and ~n~, e ^i pi^ and x ~max~\n";
+
+        let html = html::to_html(markdown);
+        assert!(html.contains("<del>old</del>"));
+        assert!(html.contains("x <sub>1</sub>"));
+        assert!(html.contains("note <sup>2</sup>"));
+
+        let text = email::to_plain_text(markdown);
+        assert_eq!(
+            text.trim_end(),
+            "~~old~~ ~~bold~~ note ², x ₁ and _n, e ^(i pi) and x _(max)"
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let markdown = "Twice[^x] and again[^x], then[^Y] and[^missing].\n\n[^Y]: Later *note*.\n\n[^x]: Earlier note.\n";
+
+        let html = html::to_html(markdown);
+        assert!(
+            html.contains(
+                "<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1\">1</a></sup>"
+            )
+        );
+        assert!(html.contains("id=\"fnref-1-2\""));
+        assert!(html.contains("<a href=\"#fn-2\" id=\"fnref-2\">2</a>"));
+        assert!(html.contains("[^missing]"));
+        let notes = html.find("<section class=\"footnotes\">").unwrap();
+        assert!(html[notes..].find("Earlier note") < html[notes..].find("Later"));
+        assert!(html[notes..].contains("href=\"#fnref-1-2\""));
+
+        let text = email::to_plain_text(markdown);
+        assert!(text.contains("Twice[1] and again[1], then[2] and[^missing]."));
+        assert!(text.contains("Notes\n-----\n\n[1] Earlier note.\n\n[2] Later note."));
+    }
+
+    #[test]
+    fn test_math() {
+        let markdown = "Energy $E = mc^2$ holds.\n\n$$\\frac{1}{2}$$\n";
+
+        let html = html::to_html(markdown);
+        assert!(html.contains("<math><semantics><mrow><mi>E</mi>"));
+        assert!(html.contains("<msup><mi>c</mi><mn>2</mn></msup>"));
+        assert!(html.contains("<math display=\"block\">"));
+        assert!(html.contains("<mfrac><mn>1</mn><mn>2</mn></mfrac>"));
+
+        // Mail clients seldom render MathML
+        let email = email::to_html(markdown);
+        assert!(!email.contains("<math"));
+        assert!(email.contains("E = mc^2"));
+
+        let text = email::to_plain_text(markdown);
+        assert!(text.contains("Energy $E = mc^2$ holds."));
+        assert!(text.contains("\n$$\\frac{1}{2}$$\n"));
+    }
+
+    #[test]
+    fn test_code_block_attributes() {
+        let markdown = "```rust {hl_lines=\"2-3\" title=\"Example\" linenos=true}\nfn a() {}\nfn b() {}\nfn c() {}\n```\n";
+
+        let html = html::to_html(markdown);
+        assert!(html.contains("<div class=\"code-filename\">Example</div>"));
+        assert!(html.contains("class=\"language-rust\""));
+        assert!(html.contains("<span class=\"line-number\">   1</span> fn a() {}\n"));
+        assert!(html.contains(
+            "<span class=\"highlighted-line\"><span class=\"line-number\">   2</span> fn b() {}</span>"
+        ));
+
+        let email = email::to_html(markdown);
+        assert!(email.contains(">Example</div>"));
+        assert!(email.contains("background-color: #fff8c5; margin: 0 -16px; padding: 0 16px;\">"));
+
+        let text = email::to_plain_text(markdown);
+        assert!(text.contains("Example:\n"));
+    }
+
+    #[test]
+    fn test_code_lines() {
+        assert_eq!(CodeKind::of("rust"), CodeKind::Plain);
+        assert_eq!(CodeKind::of("diff"), CodeKind::Diff(""));
+        assert_eq!(CodeKind::of("diff-rust"), CodeKind::Diff("rust"));
+        assert_eq!(CodeKind::of("shell-session"), CodeKind::Console);
+
+        let diff = CodeKind::Diff("rust");
+        assert_eq!(diff.highlight_language("diff-rust"), "rust");
+        assert_eq!(
+            diff.line("+++ b/src/lib.rs"),
+            CodeLine::DiffHeader("+++ b/src/lib.rs")
+        );
+        assert_eq!(
+            diff.line("@@ -1 +1 @@"),
+            CodeLine::DiffHeader("@@ -1 +1 @@")
+        );
+        assert_eq!(diff.line("+let a = 2;"), CodeLine::Added("let a = 2;"));
+        assert_eq!(diff.line("-let a = 1;"), CodeLine::Removed("let a = 1;"));
+        assert_eq!(diff.line(" fn b() {}"), CodeLine::Unchanged("fn b() {}"));
+
+        let console = CodeKind::Console;
+        assert_eq!(
+            console.line("$ ls -l"),
+            CodeLine::Command {
+                prompt: "$ ",
+                command: "ls -l"
+            }
+        );
+        assert_eq!(
+            console.line("me@host:~/src$ make"),
+            CodeLine::Command {
+                prompt: "me@host:~/src$ ",
+                command: "make"
+            }
+        );
+        assert_eq!(console.line("100% done"), CodeLine::Output("100% done"));
+        assert_eq!(console.line("total 8"), CodeLine::Output("total 8"));
+    }
+
+    #[test]
+    fn test_diff_and_console_blocks() {
+        let diff = "```diff-rust\n@@ -1 +1 @@\n-let a = 1;\n+let a = 2;\n```\n";
+        let html = html::to_html(diff);
+        assert!(html.contains("<span class=\"diff-header\">@@ -1 +1 @@</span>"));
+        assert!(html.contains(
+            "<span class=\"diff-added\"><span class=\"diff-marker\">+</span>let a = 2;</span>"
+        ));
+        assert!(html.contains(".diff-removed { background-color: #ffebe9; }"));
+        let email = email::to_html(diff);
+        assert!(email.contains("background-color: #e6ffec;"));
+        assert!(email.contains("color: #1a7f37;\">+</span>let a = 2;"));
+
+        let console = "```console\n$ ls\nREADME.md\n```\n";
+        let html = html::to_html(console);
+        assert!(html.contains(
+            "<span class=\"console-prompt\">$ </span><span class=\"console-command\">ls</span>\n<span class=\"console-output\">README.md</span>\n"
+        ));
+        assert!(html.contains(".console-prompt { color: #6e7781; user-select: none; }"));
+        let email = email::to_html(console);
+        assert!(email.contains("user-select: none;\">$ </span>"));
+    }
+
+    #[test]
+    fn test_html_table_of_contents() {
+        let html = html::to_html("[[TOC]]\n\n# One\n\n### Deep\n\n## Two\n\n# Three\n");
+        // Two goes under One beside Deep, as in the PDF outline
+        assert!(html.contains(
+            "<nav class=\"toc\">\n<ul>\n<li><a href=\"#one\">One</a><ul>\n<li><a href=\"#deep\">Deep</a></li>\n<li><a href=\"#two\">Two</a></li>\n</ul>\n</li>\n<li><a href=\"#three\">Three</a></li>\n</ul>\n</nav>\n"
+        ));
+    }
+
+    #[test]
+    fn test_html_heading_ids() {
+        let html = html::to_html("# Setup\n\n## Setup\n\n### Other {#custom}\n\n[go](#setup-1)\n");
+        assert!(html.contains("<h1 id=\"setup\">Setup</h1>"));
+        assert!(html.contains("<h2 id=\"setup-1\">Setup</h2>"));
+        assert!(html.contains("<h3 id=\"custom\">Other</h3>"));
+    }
+}
@@ -0,0 +1,232 @@
+//! Fitting highlighted code lines to the width of a code panel
+//!
+//! Lines wider than the panel are wrapped onto continuation rows, set in a
+//! smaller size so the widest fits, or cut short with an ellipsis.
+
+use syntect::highlighting::Style;
+
+use super::{
+    Mm,
+    font::{
+        BuiltinFont,
+        FontSet,
+    },
+};
+
+/// What happens to code lines wider than their panel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) enum CodeOverflow {
+    /// Carry on under the line, after a continuation mark
+    #[default]
+    Wrap,
+    /// Make the whole block smaller until its widest line fits
+    Shrink,
+    /// Cut the line short with an ellipsis
+    Truncate,
+}
+
+impl CodeOverflow {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "wrap" => Some(CodeOverflow::Wrap),
+            "shrink" => Some(CodeOverflow::Shrink),
+            "truncate" => Some(CodeOverflow::Truncate),
+            _ => None,
+        }
+    }
+}
+
+/// Smallest size `Shrink` sets code in; lines still too wide are truncated
+const MIN_SHRINK_SIZE: f32 = 5.0;
+
+/// One printed row of a code block
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct CodeRow {
+    /// Index of the code line the row is part of, from 0
+    pub line: usize,
+    /// Line number shown in the gutter, left out on continuation rows
+    pub number: Option<usize>,
+    pub spans: Vec<(Style, String)>,
+    /// Whether the row carries on the line above it
+    pub continuation: bool,
+}
+
+/// Indent of continuation rows, in characters of the code font
+pub(super) const HANG_CHARS: f32 = 2.0;
+
+/// Lay out highlighted lines as rows no wider than `width` and return them
+/// with the size to set them in
+pub(super) fn fit_lines(
+    lines: &[Vec<(Style, &str)>],
+    start_line: Option<usize>,
+    overflow: CodeOverflow,
+    width: Mm,
+    size: f32,
+    fonts: &FontSet,
+) -> (Vec<CodeRow>, f32) {
+    let measure = |text: &str, size: f32| fonts.text_width(text, BuiltinFont::Courier, size).0;
+    let number = |idx: usize| start_line.map(|start| start + idx);
+
+    let (overflow, size) = match overflow {
+        CodeOverflow::Shrink => {
+            let widest = lines
+                .iter()
+                .map(|line| {
+                    line.iter()
+                        .map(|(_, text)| measure(text, size))
+                        .sum::<f32>()
+                })
+                .fold(0.0, f32::max);
+            if widest <= width.0 {
+                (CodeOverflow::Shrink, size)
+            } else if widest * MIN_SHRINK_SIZE / size <= width.0 {
+                (CodeOverflow::Shrink, size * width.0 / widest)
+            } else {
+                (CodeOverflow::Truncate, MIN_SHRINK_SIZE)
+            }
+        }
+        overflow => (overflow, size),
+    };
+
+    // Characters that fit exactly are not pushed over by rounding
+    let width = Mm(width.0 + 0.001);
+    let mut rows = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let chars: Vec<(Style, char, f32)> = line
+            .iter()
+            .flat_map(|(style, text)| {
+                text.chars()
+                    .map(move |c| (*style, c, measure(c.encode_utf8(&mut [0; 4]), size)))
+            })
+            .collect();
+        let line_width: f32 = chars.iter().map(|(_, _, width)| width).sum();
+        if overflow == CodeOverflow::Shrink || line_width <= width.0 {
+            rows.push(CodeRow {
+                line: idx,
+                number: number(idx),
+                spans: spans(&chars),
+                continuation: false,
+            });
+            continue;
+        }
+
+        match overflow {
+            CodeOverflow::Truncate => {
+                let room = width.0 - measure("…", size);
+                let mut used = 0.0;
+                let mut end = 0;
+                while end < chars.len() && used + chars[end].2 <= room {
+                    used += chars[end].2;
+                    end += 1;
+                }
+                let mut kept = chars[..end].to_vec();
+                let style = kept.last().or(chars.first()).map(|(style, _, _)| *style);
+                if let Some(style) = style {
+                    kept.push((style, '…', 0.0));
+                }
+                rows.push(CodeRow {
+                    line: idx,
+                    number: number(idx),
+                    spans: spans(&kept),
+                    continuation: false,
+                });
+            }
+            _ => {
+                let hang = measure(" ", size) * HANG_CHARS;
+                let mut start = 0;
+                while start < chars.len() {
+                    let continuation = start > 0;
+                    let room = if continuation {
+                        width.0 - hang
+                    } else {
+                        width.0
+                    };
+                    let mut used = 0.0;
+                    let mut end = start;
+                    while end < chars.len() && used + chars[end].2 <= room {
+                        used += chars[end].2;
+                        end += 1;
+                    }
+                    if end < chars.len() {
+                        // Break after the last space on the row, if there is one
+                        if let Some(space) = chars[start..end]
+                            .iter()
+                            .rposition(|(_, c, _)| c.is_whitespace())
+                            .filter(|&space| space > 0)
+                        {
+                            end = start + space + 1;
+                        }
+                        // A row holds at least one character
+                        end = end.max(start + 1);
+                    }
+                    rows.push(CodeRow {
+                        line: idx,
+                        number: if continuation { None } else { number(idx) },
+                        spans: spans(&chars[start..end]),
+                        continuation,
+                    });
+                    start = end;
+                }
+            }
+        }
+    }
+    (rows, size)
+}
+
+/// Join characters of the same style back into runs of text
+fn spans(chars: &[(Style, char, f32)]) -> Vec<(Style, String)> {
+    let mut spans: Vec<(Style, String)> = Vec::new();
+    for (style, c, _) in chars {
+        match spans.last_mut() {
+            Some((last, text)) if last == style => text.push(*c),
+            _ => spans.push((*style, c.to_string())),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::FontConfig;
+
+    fn text(row: &CodeRow) -> String {
+        row.spans.iter().map(|(_, text)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fit_lines() {
+        let fonts = FontSet::load(&FontConfig::default(), None).unwrap();
+        let (plain, keyword) = (
+            Style::default(),
+            Style {
+                font_style: syntect::highlighting::FontStyle::BOLD,
+                ..Style::default()
+            },
+        );
+        let lines = vec![
+            vec![(keyword, "let"), (plain, " value = compute(argument);")],
+            vec![(plain, "x")],
+        ];
+        // Courier is 6 points a character at 10 points
+        let width = Mm(20.0 * 6.0 / 2.83465);
+
+        let (rows, size) = fit_lines(&lines, Some(7), CodeOverflow::Wrap, width, 10.0, &fonts);
+        assert_eq!(size, 10.0);
+        let texts: Vec<String> = rows.iter().map(text).collect();
+        assert_eq!(texts, ["let value = ", "compute(argument);", "x"]);
+        let numbers: Vec<Option<usize>> = rows.iter().map(|row| row.number).collect();
+        assert_eq!(numbers, [Some(7), None, Some(8)]);
+        assert!(rows[1].continuation);
+        // The keyword keeps its style on its side of the split
+        assert_eq!(rows[0].spans[0], (keyword, "let".to_string()));
+
+        let (rows, size) = fit_lines(&lines, None, CodeOverflow::Shrink, width, 10.0, &fonts);
+        assert_eq!(rows.len(), 2);
+        assert!((size - 10.0 * 20.0 / 30.0).abs() < 0.01);
+
+        let (rows, _) = fit_lines(&lines, None, CodeOverflow::Truncate, width, 10.0, &fonts);
+        assert_eq!(text(&rows[0]), "let value = compute…");
+        assert_eq!(text(&rows[1]), "x");
+    }
+}
@@ -0,0 +1,645 @@
+//! Fonts for the PDF backend
+//!
+//! Text is drawn with the standard Type1 fonts unless a TrueType/OpenType file is
+//! configured for a style, in which case the font is embedded as a subsetted CID
+//! font with Identity-H encoding.
+
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    hash::{
+        Hash,
+        Hasher,
+    },
+    path::Path,
+};
+
+use lopdf::{
+    Document,
+    Object,
+    ObjectId,
+    Stream,
+    StringFormat,
+    dictionary,
+};
+
+use super::{
+    Mm,
+    afm,
+    subset::subset_truetype,
+};
+use crate::{
+    fmt::resolve_path,
+    parse::FontConfig,
+};
+
+/// Built-in PDF font names
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(super) enum BuiltinFont {
+    Courier,
+    Helvetica,
+    HelveticaBold,
+    HelveticaOblique,
+    HelveticaBoldOblique,
+    /// Greek letters and mathematical symbols
+    Symbol,
+}
+
+impl BuiltinFont {
+    pub(super) fn to_pdf_name(self) -> &'static str {
+        match self {
+            BuiltinFont::Courier => "Courier",
+            BuiltinFont::Helvetica => "Helvetica",
+            BuiltinFont::HelveticaBold => "Helvetica-Bold",
+            BuiltinFont::HelveticaOblique => "Helvetica-Oblique",
+            BuiltinFont::HelveticaBoldOblique => "Helvetica-BoldOblique",
+            BuiltinFont::Symbol => "Symbol",
+        }
+    }
+
+    pub(super) fn to_font_key(self) -> String {
+        format!("F{}", self as u8)
+    }
+}
+
+/// Advance width of a character code in a standard font, in 1/1000 em
+fn builtin_glyph_width(font: BuiltinFont, code: u8) -> u16 {
+    let widths = match font {
+        BuiltinFont::Courier => return afm::COURIER,
+        BuiltinFont::Helvetica | BuiltinFont::HelveticaOblique => &afm::HELVETICA,
+        BuiltinFont::HelveticaBold | BuiltinFont::HelveticaBoldOblique => &afm::HELVETICA_BOLD,
+        BuiltinFont::Symbol => &afm::SYMBOL,
+    };
+    code.checked_sub(32)
+        .map(|idx| widths[idx as usize])
+        .unwrap_or(0)
+}
+
+/// Calculate text width in millimeters for a standard font and size
+fn calculate_text_width(text: &str, font: BuiltinFont, size: f32) -> Mm {
+    let units: u32 = encode_builtin(text, font)
+        .into_iter()
+        .map(|code| builtin_glyph_width(font, code) as u32)
+        .sum();
+    Mm(units as f32 * size / 1000.0 / 2.83465)
+}
+
+/// Encode text for a standard font in its encoding
+fn encode_builtin(text: &str, font: BuiltinFont) -> Vec<u8> {
+    match font {
+        BuiltinFont::Symbol => encode_symbol(text),
+        _ => encode_win_ansi(text),
+    }
+}
+
+/// Whether the standard text fonts have a glyph for a character
+pub(super) fn is_win_ansi(c: char) -> bool {
+    win_ansi_code(c).is_some()
+}
+
+/// Encode text for a standard font using WinAnsiEncoding
+///
+/// Characters outside the encoding are replaced with `?`.
+fn encode_win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| win_ansi_code(c).unwrap_or(b'?'))
+        .collect()
+}
+
+fn win_ansi_code(c: char) -> Option<u8> {
+    let code = match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        'ˆ' => 0x88,
+        '‰' => 0x89,
+        'Š' => 0x8a,
+        '‹' => 0x8b,
+        'Œ' => 0x8c,
+        'Ž' => 0x8e,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        '™' => 0x99,
+        'š' => 0x9a,
+        '›' => 0x9b,
+        'œ' => 0x9c,
+        'ž' => 0x9e,
+        'Ÿ' => 0x9f,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Encode text for the Symbol font using its built-in encoding
+///
+/// Characters outside the encoding are replaced with `?`.
+fn encode_symbol(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' ' | '!' | '#' | '%' | '&' | '('..=',' | '.'..='?' | '[' | ']' | '_' | '{'..='}' => {
+                c as u8
+            }
+            '-' | '−' => 0x2d,
+            '∀' => 0x22,
+            '∃' => 0x24,
+            '∋' => 0x27,
+            '∗' => 0x2a,
+            '≅' => 0x40,
+            'Α' => 0x41,
+            'Β' => 0x42,
+            'Χ' => 0x43,
+            'Δ' => 0x44,
+            'Ε' => 0x45,
+            'Φ' => 0x46,
+            'Γ' => 0x47,
+            'Η' => 0x48,
+            'Ι' => 0x49,
+            'ϑ' => 0x4a,
+            'Κ' => 0x4b,
+            'Λ' => 0x4c,
+            'Μ' => 0x4d,
+            'Ν' => 0x4e,
+            'Ο' => 0x4f,
+            'Π' => 0x50,
+            'Θ' => 0x51,
+            'Ρ' => 0x52,
+            'Σ' => 0x53,
+            'Τ' => 0x54,
+            'Υ' => 0x55,
+            'ς' => 0x56,
+            'Ω' => 0x57,
+            'Ξ' => 0x58,
+            'Ψ' => 0x59,
+            'Ζ' => 0x5a,
+            '∴' => 0x5c,
+            '⊥' => 0x5e,
+            'α' => 0x61,
+            'β' => 0x62,
+            'χ' => 0x63,
+            'δ' => 0x64,
+            'ε' => 0x65,
+            'φ' => 0x66,
+            'γ' => 0x67,
+            'η' => 0x68,
+            'ι' => 0x69,
+            'ϕ' => 0x6a,
+            'κ' => 0x6b,
+            'λ' => 0x6c,
+            'μ' => 0x6d,
+            'ν' => 0x6e,
+            'ο' => 0x6f,
+            'π' => 0x70,
+            'θ' => 0x71,
+            'ρ' => 0x72,
+            'σ' => 0x73,
+            'τ' => 0x74,
+            'υ' => 0x75,
+            'ϖ' => 0x76,
+            'ω' => 0x77,
+            'ξ' => 0x78,
+            'ψ' => 0x79,
+            'ζ' => 0x7a,
+            '∼' => 0x7e,
+            'ϒ' => 0xa1,
+            '′' => 0xa2,
+            '≤' => 0xa3,
+            '∞' => 0xa5,
+            '↔' => 0xab,
+            '←' => 0xac,
+            '↑' => 0xad,
+            '→' => 0xae,
+            '↓' => 0xaf,
+            '°' => 0xb0,
+            '±' => 0xb1,
+            '″' => 0xb2,
+            '≥' => 0xb3,
+            '×' => 0xb4,
+            '∝' => 0xb5,
+            '∂' => 0xb6,
+            '•' => 0xb7,
+            '÷' => 0xb8,
+            '≠' => 0xb9,
+            '≡' => 0xba,
+            '≈' => 0xbb,
+            '…' => 0xbc,
+            'ℵ' => 0xc0,
+            'ℑ' => 0xc1,
+            'ℜ' => 0xc2,
+            '℘' => 0xc3,
+            '⊗' => 0xc4,
+            '⊕' => 0xc5,
+            '∅' => 0xc6,
+            '∩' => 0xc7,
+            '∪' => 0xc8,
+            '⊃' => 0xc9,
+            '⊇' => 0xca,
+            '⊄' => 0xcb,
+            '⊂' => 0xcc,
+            '⊆' => 0xcd,
+            '∈' => 0xce,
+            '∉' => 0xcf,
+            '∠' => 0xd0,
+            '∇' => 0xd1,
+            '∏' => 0xd5,
+            '√' => 0xd6,
+            '⋅' => 0xd7,
+            '¬' => 0xd8,
+            '∧' => 0xd9,
+            '∨' => 0xda,
+            '⇔' => 0xdb,
+            '⇐' => 0xdc,
+            '⇑' => 0xdd,
+            '⇒' => 0xde,
+            '⇓' => 0xdf,
+            '◊' => 0xe0,
+            '⟨' | '〈' => 0xe1,
+            '∑' => 0xe5,
+            '⟩' | '〉' => 0xf1,
+            '∫' => 0xf2,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// A TrueType/OpenType font loaded from disk for embedding
+pub(super) struct EmbeddedFont {
+    data: Vec<u8>,
+    post_script_name: String,
+    is_cff: bool,
+    units_per_em: f32,
+    ascender: i16,
+    descender: i16,
+    cap_height: i16,
+    italic_angle: f32,
+    bbox: [i16; 4],
+    glyph_ids: HashMap<char, u16>,
+    advances: Vec<u16>,
+    /// Glyphs drawn so far, with the character they represent
+    used_glyphs: BTreeMap<u16, char>,
+}
+
+impl EmbeddedFont {
+    fn load(path: &Path) -> Result<Self, std::io::Error> {
+        let data = std::fs::read(path).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("Failed to read font {}: {}", path.display(), e),
+            )
+        })?;
+
+        let face = ttf_parser::Face::parse(&data, 0).map_err(|e| {
+            std::io::Error::other(format!("Failed to parse font {}: {}", path.display(), e))
+        })?;
+
+        let post_script_name = face
+            .names()
+            .into_iter()
+            .filter(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .find_map(|name| name.to_string())
+            .unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "Embedded".to_string())
+            })
+            .chars()
+            .filter(|c| c.is_ascii_graphic() && !"[](){}<>/%#".contains(*c))
+            .collect();
+
+        let mut glyph_ids = HashMap::new();
+        if let Some(cmap) = face.tables().cmap {
+            for subtable in cmap.subtables {
+                if !subtable.is_unicode() {
+                    continue;
+                }
+                subtable.codepoints(|code_point| {
+                    if let Some(c) = char::from_u32(code_point)
+                        && let Some(glyph) = subtable.glyph_index(code_point)
+                    {
+                        glyph_ids.entry(c).or_insert(glyph.0);
+                    }
+                });
+            }
+        }
+
+        let advances = (0..face.number_of_glyphs())
+            .map(|gid| {
+                face.glyph_hor_advance(ttf_parser::GlyphId(gid))
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let bbox = face.global_bounding_box();
+
+        Ok(Self {
+            post_script_name,
+            is_cff: face.tables().cff.is_some(),
+            units_per_em: face.units_per_em() as f32,
+            ascender: face.ascender(),
+            descender: face.descender(),
+            cap_height: face.capital_height().unwrap_or(face.ascender()),
+            italic_angle: face.italic_angle(),
+            bbox: [bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max],
+            glyph_ids,
+            advances,
+            used_glyphs: BTreeMap::new(),
+            data,
+        })
+    }
+
+    fn glyph_id(&self, c: char) -> u16 {
+        self.glyph_ids.get(&c).copied().unwrap_or(0)
+    }
+
+    /// Scale a value in font units to the PDF glyph space (1000 units per em)
+    fn to_glyph_space(&self, value: f32) -> f32 {
+        value * 1000.0 / self.units_per_em
+    }
+
+    /// Width of text in points
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        let units: f32 = text
+            .chars()
+            .map(|c| {
+                self.advances
+                    .get(self.glyph_id(c) as usize)
+                    .copied()
+                    .unwrap_or(0) as f32
+            })
+            .sum();
+        units * size / self.units_per_em
+    }
+
+    /// Encode text as two-byte glyph IDs, recording which glyphs are used
+    fn encode(&mut self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let gid = self.glyph_id(c);
+            self.used_glyphs.entry(gid).or_insert(c);
+            bytes.extend_from_slice(&gid.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Six letter tag that marks a font as a subset, derived from the glyphs it contains
+    fn subset_tag(&self) -> String {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.post_script_name.hash(&mut hasher);
+        for gid in self.used_glyphs.keys() {
+            gid.hash(&mut hasher);
+        }
+        let mut hash = hasher.finish();
+        (0..6)
+            .map(|_| {
+                let c = (b'A' + (hash % 26) as u8) as char;
+                hash /= 26;
+                c
+            })
+            .collect()
+    }
+
+    /// Write the Type0 font and its descendant CID font into the reserved object
+    fn write_to(&self, doc: &mut Document, font_id: ObjectId) {
+        let (base_font, font_file) = if self.is_cff {
+            // CFF outlines are embedded whole; subsetting them is not supported.
+            // OpenType font programs need PDF 1.6.
+            if doc.version.as_str() < "1.6" {
+                doc.version = "1.6".to_string();
+            }
+            let mut stream =
+                Stream::new(dictionary! { "Subtype" => "OpenType" }, self.data.clone());
+            let _ = stream.compress();
+            (self.post_script_name.clone(), ("FontFile3", stream))
+        } else {
+            let gids: Vec<u16> = self.used_glyphs.keys().copied().collect();
+            let data = subset_truetype(&self.data, &gids).unwrap_or_else(|| self.data.clone());
+            let mut stream = Stream::new(dictionary! { "Length1" => data.len() as i64 }, data);
+            let _ = stream.compress();
+            (
+                format!("{}+{}", self.subset_tag(), self.post_script_name),
+                ("FontFile2", stream),
+            )
+        };
+
+        let font_file_id = doc.add_object(font_file.1);
+
+        let descriptor_id = doc.add_object(dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => Object::Name(base_font.as_bytes().to_vec()),
+            "Flags" => 4,
+            "FontBBox" => self.bbox.iter().map(|&v| Object::from(self.to_glyph_space(v as f32).round() as i64)).collect::<Vec<_>>(),
+            "ItalicAngle" => self.italic_angle,
+            "Ascent" => self.to_glyph_space(self.ascender as f32).round() as i64,
+            "Descent" => self.to_glyph_space(self.descender as f32).round() as i64,
+            "CapHeight" => self.to_glyph_space(self.cap_height as f32).round() as i64,
+            "StemV" => 80,
+            font_file.0 => Object::Reference(font_file_id),
+        });
+
+        // Widths grouped into runs of consecutive glyph IDs: [first [w1 w2 ...] ...]
+        let mut widths = Vec::new();
+        let mut run: Vec<Object> = Vec::new();
+        let mut run_start = None;
+        let mut prev_gid = None;
+        for &gid in self.used_glyphs.keys() {
+            if prev_gid.is_none_or(|prev| prev + 1 != gid) {
+                if let Some(start) = run_start {
+                    widths.push(Object::Integer(start as i64));
+                    widths.push(Object::Array(std::mem::take(&mut run)));
+                }
+                run_start = Some(gid);
+            }
+            let advance = self.advances.get(gid as usize).copied().unwrap_or(0);
+            run.push(Object::Integer(
+                self.to_glyph_space(advance as f32).round() as i64
+            ));
+            prev_gid = Some(gid);
+        }
+        if let Some(start) = run_start {
+            widths.push(Object::Integer(start as i64));
+            widths.push(Object::Array(run));
+        }
+
+        let mut cid_font = dictionary! {
+            "Type" => "Font",
+            "Subtype" => if self.is_cff { "CIDFontType0" } else { "CIDFontType2" },
+            "BaseFont" => Object::Name(base_font.as_bytes().to_vec()),
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => Object::Reference(descriptor_id),
+            "W" => widths,
+        };
+        if !self.is_cff {
+            cid_font.set("CIDToGIDMap", "Identity");
+        }
+        let cid_font_id = doc.add_object(cid_font);
+
+        let to_unicode_id = doc.add_object(Stream::new(dictionary! {}, self.to_unicode_cmap()));
+
+        doc.objects.insert(
+            font_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type0",
+                "BaseFont" => Object::Name(base_font.as_bytes().to_vec()),
+                "Encoding" => "Identity-H",
+                "DescendantFonts" => vec![Object::Reference(cid_font_id)],
+                "ToUnicode" => Object::Reference(to_unicode_id),
+            }),
+        );
+    }
+
+    /// Build a ToUnicode CMap so text can be searched and copied
+    fn to_unicode_cmap(&self) -> Vec<u8> {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n\
+             12 dict begin\n\
+             begincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n\
+             /CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+        );
+
+        let entries: Vec<_> = self
+            .used_glyphs
+            .iter()
+            .filter(|(gid, _)| **gid != 0)
+            .collect();
+        for chunk in entries.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+            for (gid, c) in chunk {
+                let mut utf16 = [0u16; 2];
+                let hex: String = c
+                    .encode_utf16(&mut utf16)
+                    .iter()
+                    .map(|unit| format!("{:04X}", unit))
+                    .collect();
+                cmap.push_str(&format!("<{:04X}> <{}>\n", gid, hex));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+
+        cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+        cmap.into_bytes()
+    }
+}
+
+/// The fonts available to the PDF writer
+///
+/// Each built-in font can be replaced by an embedded font, so the rest of the
+/// writer keeps addressing text styles through [`BuiltinFont`].
+#[derive(Default)]
+pub(super) struct FontSet {
+    embedded: HashMap<BuiltinFont, EmbeddedFont>,
+}
+
+impl FontSet {
+    /// Load the fonts named in the configuration, resolving relative paths against the source file
+    pub(super) fn load(
+        config: &FontConfig,
+        source_path: Option<&Path>,
+    ) -> Result<Self, std::io::Error> {
+        let slots = [
+            (BuiltinFont::Helvetica, &config.regular),
+            (BuiltinFont::HelveticaBold, &config.bold),
+            (BuiltinFont::HelveticaOblique, &config.italic),
+            (BuiltinFont::HelveticaBoldOblique, &config.bold_italic),
+            (BuiltinFont::Courier, &config.mono),
+            (BuiltinFont::Symbol, &config.math),
+        ];
+
+        let mut embedded = HashMap::new();
+        for (font, path) in slots {
+            if let Some(path) = path {
+                let path = resolve_path(path, source_path);
+                embedded.insert(font, EmbeddedFont::load(&path)?);
+            }
+        }
+
+        Ok(Self { embedded })
+    }
+
+    pub(super) fn is_embedded(&self, font: BuiltinFont) -> bool {
+        self.embedded.contains_key(&font)
+    }
+
+    /// Width of text set in the given font and size
+    pub(super) fn text_width(&self, text: &str, font: BuiltinFont, size: f32) -> Mm {
+        match self.embedded.get(&font) {
+            Some(embedded) => Mm(embedded.text_width(text, size) / 2.83465),
+            None => calculate_text_width(text, font, size),
+        }
+    }
+
+    /// Encode text as a PDF string for the given font
+    pub(super) fn encode(&mut self, text: &str, font: BuiltinFont) -> Object {
+        match self.embedded.get_mut(&font) {
+            Some(embedded) => Object::String(embedded.encode(text), StringFormat::Hexadecimal),
+            None => Object::String(encode_builtin(text, font), StringFormat::Literal),
+        }
+    }
+
+    /// Write the embedded font objects for every font that was used
+    pub(super) fn write_to(&self, doc: &mut Document, font_ids: &HashMap<String, ObjectId>) {
+        for (font, embedded) in &self.embedded {
+            if let Some(&font_id) = font_ids.get(&font.to_font_key()) {
+                embedded.write_to(doc, font_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_win_ansi() {
+        assert_eq!(encode_win_ansi("abc"), b"abc");
+        assert_eq!(encode_win_ansi("café €5"), b"caf\xe9 \x805");
+        assert_eq!(encode_win_ansi("中"), b"?");
+    }
+
+    #[test]
+    fn test_encode_symbol() {
+        assert_eq!(encode_symbol("α ≤ ∑(2)"), b"\x61 \xa3 \xe5(2)");
+        // Latin letters live in the text fonts
+        assert_eq!(encode_symbol("−x"), b"-?");
+        // ∑ is 713 units wide
+        let width = calculate_text_width("∑", BuiltinFont::Symbol, 10.0);
+        assert!((width.to_points() - 7.13).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_builtin_text_width() {
+        // "Hello" in Helvetica is 722 + 556 + 222 + 222 + 556 = 2278 units
+        let width = calculate_text_width("Hello", BuiltinFont::Helvetica, 10.0);
+        assert!((width.to_points() - 22.78).abs() < 0.01);
+
+        let width = calculate_text_width("Hello", BuiltinFont::Courier, 10.0);
+        assert!((width.to_points() - 30.0).abs() < 0.01);
+
+        // Bold is wider than regular, oblique matches upright
+        let regular = calculate_text_width("mdreport", BuiltinFont::Helvetica, 12.0);
+        let bold = calculate_text_width("mdreport", BuiltinFont::HelveticaBold, 12.0);
+        let oblique = calculate_text_width("mdreport", BuiltinFont::HelveticaOblique, 12.0);
+        assert!(bold > regular);
+        assert_eq!(regular, oblique);
+    }
+}
@@ -0,0 +1,873 @@
+//! Layout of TeX math for PDF output
+//!
+//! A parsed formula is set into a box of glyphs, rules and stroked paths,
+//! positioned in points from the left end of its baseline. The rules are a
+//! simplified form of TeX's: four style sizes, spacing by atom class, and
+//! fraction bars, radicals and stretched delimiters drawn to fit what they
+//! enclose.
+
+use super::font::{
+    BuiltinFont,
+    FontSet,
+    is_win_ansi,
+};
+use crate::fmt::math::{
+    Accent,
+    Class,
+    Node,
+};
+
+/// Height of the math axis, the centre line of fractions and operators, in em
+const AXIS: f32 = 0.26;
+/// Thickness of fraction bars and radical strokes, in em
+const RULE: f32 = 0.05;
+const THIN_SPACE: f32 = 3.0 / 18.0;
+const MEDIUM_SPACE: f32 = 4.0 / 18.0;
+const THICK_SPACE: f32 = 5.0 / 18.0;
+
+/// Something drawn as part of a formula
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum MathItem {
+    Glyph {
+        x: f32,
+        y: f32,
+        text: String,
+        font: BuiltinFont,
+        size: f32,
+    },
+    /// Filled rectangle from its lower left corner
+    Rule {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    /// Stroked path through straight and Bézier segments
+    Path {
+        start: (f32, f32),
+        segments: Vec<Segment>,
+        width: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Segment {
+    Line(f32, f32),
+    /// Two control points and the end point
+    Curve([f32; 6]),
+}
+
+impl MathItem {
+    fn moved(self, dx: f32, dy: f32) -> MathItem {
+        match self {
+            MathItem::Glyph {
+                x,
+                y,
+                text,
+                font,
+                size,
+            } => MathItem::Glyph {
+                x: x + dx,
+                y: y + dy,
+                text,
+                font,
+                size,
+            },
+            MathItem::Rule {
+                x,
+                y,
+                width,
+                height,
+            } => MathItem::Rule {
+                x: x + dx,
+                y: y + dy,
+                width,
+                height,
+            },
+            MathItem::Path {
+                start,
+                segments,
+                width,
+            } => MathItem::Path {
+                start: (start.0 + dx, start.1 + dy),
+                segments: segments
+                    .into_iter()
+                    .map(|segment| match segment {
+                        Segment::Line(x, y) => Segment::Line(x + dx, y + dy),
+                        Segment::Curve([x1, y1, x2, y2, x3, y3]) => {
+                            Segment::Curve([x1 + dx, y1 + dy, x2 + dx, y2 + dy, x3 + dx, y3 + dy])
+                        }
+                    })
+                    .collect(),
+                width,
+            },
+        }
+    }
+}
+
+/// A laid out formula or part of one, in points
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct MathBox {
+    pub width: f32,
+    /// Height above the baseline
+    pub ascent: f32,
+    /// Depth below the baseline
+    pub descent: f32,
+    pub items: Vec<MathItem>,
+}
+
+impl MathBox {
+    fn space(width: f32) -> MathBox {
+        MathBox {
+            width,
+            ..MathBox::default()
+        }
+    }
+
+    /// Draw `other` with its origin at (x, y), growing this box's height to
+    /// cover it. The width is left to the caller.
+    fn place(&mut self, other: MathBox, x: f32, y: f32) {
+        self.ascent = self.ascent.max(other.ascent + y);
+        self.descent = self.descent.max(other.descent - y);
+        self.items
+            .extend(other.items.into_iter().map(|item| item.moved(x, y)));
+    }
+
+    fn stroke(&mut self, start: (f32, f32), segments: Vec<Segment>, width: f32) {
+        self.items.push(MathItem::Path {
+            start,
+            segments,
+            width,
+        });
+    }
+}
+
+/// TeX's math styles, from largest to smallest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Style {
+    Display,
+    Text,
+    Script,
+    ScriptScript,
+}
+
+impl Style {
+    fn scale(self) -> f32 {
+        match self {
+            Style::Display | Style::Text => 1.0,
+            Style::Script => 0.7,
+            Style::ScriptScript => 0.5,
+        }
+    }
+
+    /// Style of sub- and superscripts
+    fn script(self) -> Style {
+        match self {
+            Style::Display | Style::Text => Style::Script,
+            _ => Style::ScriptScript,
+        }
+    }
+
+    /// Style of numerators and denominators
+    fn fraction(self) -> Style {
+        match self {
+            Style::Display => Style::Text,
+            Style::Text => Style::Script,
+            _ => Style::ScriptScript,
+        }
+    }
+}
+
+/// Lay out a formula at the given font size, in display style when it
+/// stands on its own line
+pub(super) fn layout(nodes: &[Node], display: bool, size: f32, fonts: &FontSet) -> MathBox {
+    let style = if display { Style::Display } else { Style::Text };
+    Layout { fonts, size }.list(nodes, style)
+}
+
+struct Layout<'a> {
+    fonts: &'a FontSet,
+    size: f32,
+}
+
+/// Spacing class of a node, or `None` for explicit space
+fn class_of(node: &Node) -> Option<Class> {
+    match node {
+        Node::Symbol { class, .. } => Some(*class),
+        Node::LargeOp { .. } | Node::Function { .. } => Some(Class::Op),
+        Node::Scripts { base, .. } => class_of(base),
+        Node::Space(_) => None,
+        _ => Some(Class::Ord),
+    }
+}
+
+/// Height above and depth below the baseline of a character, in em
+fn char_extent(c: char) -> (f32, f32) {
+    let descent = if "gjpqy(),;[]{}|/βγζημξρςφχψϕ".contains(c) {
+        0.21
+    } else {
+        0.0
+    };
+    let ascent = if "acemnorsuvwxzαεικνοπρστυωχγημ".contains(c) {
+        0.53
+    } else if "+−=<>×÷±≤≥≠≈≡∼∗⋅".contains(c) {
+        0.6
+    } else {
+        0.72
+    };
+    (ascent, descent)
+}
+
+impl Layout<'_> {
+    fn size(&self, style: Style) -> f32 {
+        self.size * style.scale()
+    }
+
+    fn list(&self, nodes: &[Node], style: Style) -> MathBox {
+        let mut row = MathBox::default();
+        let mut previous: Option<Class> = None;
+        for (idx, node) in nodes.iter().enumerate() {
+            let mut class = class_of(node);
+            // A binary operator with nothing to combine is an ordinary symbol,
+            // as the minus sign of a negative number
+            if class == Some(Class::Bin) {
+                let next = nodes[idx + 1..].iter().find_map(class_of);
+                if matches!(
+                    previous,
+                    None | Some(Class::Bin | Class::Op | Class::Rel | Class::Open | Class::Punct)
+                ) || matches!(next, None | Some(Class::Rel | Class::Close | Class::Punct))
+                {
+                    class = Some(Class::Ord);
+                }
+            }
+            if let (Some(previous), Some(class)) = (previous, class) {
+                row.width += self.spacing(previous, class, style);
+            }
+            let node_box = self.node(node, style);
+            let x = row.width;
+            row.width += node_box.width;
+            row.place(node_box, x, 0.0);
+            if class.is_some() {
+                previous = class;
+            }
+        }
+        row
+    }
+
+    /// Space between neighbouring atoms; only thin spaces survive in scripts
+    fn spacing(&self, previous: Class, class: Class, style: Style) -> f32 {
+        use Class::*;
+
+        let (space, in_scripts) = match (previous, class) {
+            (Open, _) | (_, Close | Punct) | (Rel, Rel) => return 0.0,
+            (Ord | Op | Close, Op) | (Op, Ord) => (THIN_SPACE, true),
+            (Bin, _) | (_, Bin) => (MEDIUM_SPACE, false),
+            (Rel, _) | (_, Rel) => (THICK_SPACE, false),
+            (Punct, _) => (THIN_SPACE, false),
+            _ => return 0.0,
+        };
+        if style >= Style::Script && !in_scripts {
+            return 0.0;
+        }
+        space * self.size(style)
+    }
+
+    fn node(&self, node: &Node, style: Style) -> MathBox {
+        let size = self.size(style);
+        match node {
+            Node::Ident(c) => self.text(&c.to_string(), style, is_win_ansi(*c), false),
+            Node::Number(number) => self.text(number, style, false, false),
+            Node::Text { text, bold } => self.text(text, style, false, *bold),
+            Node::Function { name, .. } => self.text(name, style, false, false),
+            Node::Symbol { symbol, .. } if "⌊⌋⌈⌉‖".contains(*symbol) => {
+                self.delimiter(Some(*symbol), size * AXIS, size * 0.5, style)
+            }
+            Node::Symbol { symbol, .. } => self.text(&symbol.to_string(), style, false, false),
+            Node::LargeOp { symbol, .. } => self.large_op(*symbol, style),
+            Node::Group(nodes) => self.list(nodes, style),
+            Node::Frac {
+                numerator,
+                denominator,
+            } => self.fraction(numerator, denominator, style),
+            Node::Sqrt { index, body } => self.radical(index.as_deref(), body, style),
+            Node::Scripts { base, sub, sup } => {
+                self.scripts(base, sub.as_deref(), sup.as_deref(), style)
+            }
+            Node::Fenced { open, close, body } => {
+                let inner = self.list(body, style);
+                let axis = size * AXIS;
+                let half = (inner.ascent - axis).max(inner.descent + axis);
+                let mut fenced = self.delimiter(*open, axis, half, style);
+                let x = fenced.width;
+                fenced.width += inner.width;
+                fenced.place(inner, x, 0.0);
+                let close = self.delimiter(*close, axis, half, style);
+                let x = fenced.width;
+                fenced.width += close.width;
+                fenced.place(close, x, 0.0);
+                fenced
+            }
+            Node::Matrix { rows, align_left } => self.matrix(rows, *align_left, style),
+            Node::Accent { accent, body } => self.accent(*accent, body, style),
+            Node::Space(em) => MathBox::space(em * size),
+        }
+    }
+
+    /// Text set in runs of the text fonts, with anything they lack taken
+    /// from the symbol font
+    fn text(&self, text: &str, style: Style, italic: bool, bold: bool) -> MathBox {
+        let size = self.size(style);
+        let text_font = match (bold, italic) {
+            (false, false) => BuiltinFont::Helvetica,
+            (true, false) => BuiltinFont::HelveticaBold,
+            (false, true) => BuiltinFont::HelveticaOblique,
+            (true, true) => BuiltinFont::HelveticaBoldOblique,
+        };
+
+        let mut runs: Vec<(BuiltinFont, String)> = Vec::new();
+        for c in text.chars() {
+            let font = if is_win_ansi(c) {
+                text_font
+            } else {
+                BuiltinFont::Symbol
+            };
+            match runs.last_mut() {
+                Some((run_font, run)) if *run_font == font => run.push(c),
+                _ => runs.push((font, c.to_string())),
+            }
+        }
+
+        let mut text_box = MathBox::default();
+        for (font, run) in runs {
+            for c in run.chars() {
+                let (ascent, descent) = char_extent(c);
+                text_box.ascent = text_box.ascent.max(ascent * size);
+                text_box.descent = text_box.descent.max(descent * size);
+            }
+            let width = self.fonts.text_width(&run, font, size).to_points();
+            text_box.items.push(MathItem::Glyph {
+                x: text_box.width,
+                y: 0.0,
+                text: run,
+                font,
+                size,
+            });
+            text_box.width += width;
+        }
+        text_box
+    }
+
+    /// A sum, product or integral sign, centred on the axis
+    fn large_op(&self, symbol: char, style: Style) -> MathBox {
+        let scale = match (style, symbol) {
+            (Style::Display, '∫') => 2.0,
+            (Style::Display, _) => 1.5,
+            _ => 1.1,
+        };
+        let size = self.size(style) * scale;
+        // Glyph bounds in the symbol font
+        let (ascent, descent) = match symbol {
+            '∫' => (0.916, 0.107),
+            _ => (0.752, 0.108),
+        };
+        let shift = self.size(style) * AXIS - (ascent - descent) / 2.0 * size;
+        let text = symbol.to_string();
+        MathBox {
+            width: self
+                .fonts
+                .text_width(&text, BuiltinFont::Symbol, size)
+                .to_points(),
+            ascent: ascent * size + shift,
+            descent: descent * size - shift,
+            items: vec![MathItem::Glyph {
+                x: 0.0,
+                y: shift,
+                text,
+                font: BuiltinFont::Symbol,
+                size,
+            }],
+        }
+    }
+
+    fn fraction(&self, numerator: &[Node], denominator: &[Node], style: Style) -> MathBox {
+        let size = self.size(style);
+        let numerator = self.list(numerator, style.fraction());
+        let denominator = self.list(denominator, style.fraction());
+
+        let axis = size * AXIS;
+        let thickness = size * RULE;
+        let (gap, min_up, min_down) = if style == Style::Display {
+            (size * 0.15, size * 0.68, size * 0.6)
+        } else {
+            (size * 0.1, size * 0.4, size * 0.35)
+        };
+        let up = (axis + thickness / 2.0 + gap + numerator.descent).max(min_up);
+        let down = (denominator.ascent + gap + thickness / 2.0 - axis).max(min_down);
+
+        let padding = size * 0.1;
+        let width = numerator.width.max(denominator.width) + padding * 2.0;
+        let mut fraction = MathBox::space(width);
+        let x = (width - numerator.width) / 2.0;
+        fraction.place(numerator, x, up);
+        let x = (width - denominator.width) / 2.0;
+        fraction.place(denominator, x, -down);
+        fraction.items.push(MathItem::Rule {
+            x: padding / 2.0,
+            y: axis - thickness / 2.0,
+            width: width - padding,
+            height: thickness,
+        });
+        fraction
+    }
+
+    fn radical(&self, index: Option<&[Node]>, body: &[Node], style: Style) -> MathBox {
+        let size = self.size(style);
+        let body = self.list(body, style);
+        let thickness = size * RULE;
+        let top = body.ascent.max(size * 0.72) + size * 0.12 + thickness / 2.0;
+        let bottom = -(body.descent + size * 0.05);
+        let sign_width = size * 0.55;
+        let middle = bottom + (top - bottom) * 0.45;
+
+        // An index sits over the short stroke, pushing the sign right when wide
+        let index = index.map(|index| self.list(index, Style::ScriptScript));
+        let offset = index
+            .as_ref()
+            .map_or(0.0, |index| (index.width - sign_width * 0.5).max(0.0));
+
+        let mut radical = MathBox::space(offset + sign_width + body.width + size * 0.1);
+        let x = offset;
+        radical.stroke(
+            (x, middle),
+            vec![
+                Segment::Line(x + sign_width * 0.2, middle + size * 0.06),
+                Segment::Line(x + sign_width * 0.5, bottom),
+                Segment::Line(x + sign_width, top),
+                Segment::Line(radical.width, top),
+            ],
+            thickness,
+        );
+        radical.ascent = top + thickness;
+        radical.descent = -bottom;
+        if let Some(index) = index {
+            let x = offset + sign_width * 0.5 - index.width;
+            let y = middle + size * 0.12 + index.descent;
+            radical.place(index, x.max(0.0), y);
+        }
+        radical.place(body, x + sign_width, 0.0);
+        radical
+    }
+
+    fn scripts(
+        &self,
+        base: &Node,
+        sub: Option<&[Node]>,
+        sup: Option<&[Node]>,
+        style: Style,
+    ) -> MathBox {
+        let size = self.size(style);
+        let base_box = self.node(base, style);
+        let sub = sub.map(|sub| self.list(sub, style.script()));
+        let sup = sup.map(|sup| self.list(sup, style.script()));
+
+        let limits = style == Style::Display
+            && matches!(
+                base,
+                Node::LargeOp { limits: true, .. } | Node::Function { limits: true, .. }
+            );
+        if limits {
+            let gap = size * 0.15;
+            let width = [&sub, &sup]
+                .into_iter()
+                .flatten()
+                .map(|script| script.width)
+                .fold(base_box.width, f32::max);
+            let mut stacked = MathBox::space(width);
+            let base_top = base_box.ascent;
+            let base_bottom = base_box.descent;
+            let x = (width - base_box.width) / 2.0;
+            stacked.place(base_box, x, 0.0);
+            if let Some(sup) = sup {
+                let y = base_top + gap + sup.descent;
+                stacked.place(sup.clone(), (width - sup.width) / 2.0, y);
+            }
+            if let Some(sub) = sub {
+                let y = -(base_bottom + gap + sub.ascent);
+                stacked.place(sub.clone(), (width - sub.width) / 2.0, y);
+            }
+            return stacked;
+        }
+
+        // Superscripts clear the slant of an italic letter
+        let italic = matches!(base, Node::Ident(c) if is_win_ansi(*c));
+        let correction = if italic { size * 0.06 } else { 0.0 };
+        let mut up = (base_box.ascent - size * 0.25).max(size * 0.42);
+        let mut down = (base_box.descent + size * 0.05).max(size * 0.18);
+        if let Some(sup) = &sup {
+            up = up.max(sup.descent + size * 0.13);
+        }
+        if let Some(sub) = &sub {
+            down = down.max(sub.ascent - size * 0.42);
+        }
+        if let (Some(sup), Some(sub)) = (&sup, &sub) {
+            down = down.max(size * 0.25);
+            let gap = (up - sup.descent) - (sub.ascent - down);
+            if gap < size * 0.2 {
+                let extra = size * 0.2 - gap;
+                up += extra / 2.0;
+                down += extra / 2.0;
+            }
+        }
+
+        let x = base_box.width;
+        let mut scripted = MathBox::space(x);
+        scripted.place(base_box, 0.0, 0.0);
+        let mut scripts_width: f32 = 0.0;
+        if let Some(sup) = sup {
+            scripts_width = scripts_width.max(sup.width + correction);
+            scripted.place(sup, x + correction, up);
+        }
+        if let Some(sub) = sub {
+            scripts_width = scripts_width.max(sub.width);
+            scripted.place(sub, x, -down);
+        }
+        scripted.width += scripts_width + size * 0.05;
+        scripted
+    }
+
+    /// A delimiter reaching `half` above and below the axis, drawn when it
+    /// has to grow past the size of the glyph or the fonts lack one
+    fn delimiter(&self, delimiter: Option<char>, axis: f32, half: f32, style: Style) -> MathBox {
+        let size = self.size(style);
+        let Some(delimiter) = delimiter else {
+            return MathBox::space(size * 0.12);
+        };
+        if half <= size * 0.55 && !"⌊⌋⌈⌉‖".contains(delimiter) {
+            return self.text(&delimiter.to_string(), style, false, false);
+        }
+
+        let height = (half + size * 0.05) * 2.0;
+        let (bottom, top) = (axis - height / 2.0, axis + height / 2.0);
+        let middle = axis;
+        let width = match delimiter {
+            '(' | ')' => size * (0.3 + height / size * 0.04).min(0.5),
+            '{' | '}' => size * 0.45,
+            '⟨' | '⟩' => size * 0.35,
+            '|' => size * 0.25,
+            '‖' => size * 0.4,
+            _ => size * 0.3,
+        };
+        let pad = size * 0.06;
+        let mut drawn = MathBox {
+            width: width + pad * 2.0,
+            ascent: top,
+            descent: -bottom,
+            items: Vec::new(),
+        };
+
+        // Shapes of the opening delimiters; closing ones are mirrored
+        let (shape, mirrored) = match delimiter {
+            ')' => ('(', true),
+            ']' => ('[', true),
+            '}' => ('{', true),
+            '⟩' => ('⟨', true),
+            '⌋' => ('⌊', true),
+            '⌉' => ('⌈', true),
+            c => (c, false),
+        };
+        let x = |fraction: f32| {
+            let fraction = if mirrored { 1.0 - fraction } else { fraction };
+            pad + width * fraction
+        };
+        let thickness = size * 0.06;
+        let h = height;
+        match shape {
+            '(' => drawn.stroke(
+                (x(0.85), top),
+                vec![Segment::Curve([
+                    x(0.05),
+                    top - h * 0.25,
+                    x(0.05),
+                    bottom + h * 0.25,
+                    x(0.85),
+                    bottom,
+                ])],
+                thickness,
+            ),
+            '[' | '⌊' | '⌈' => {
+                // Floors drop the top arm and ceilings the bottom one
+                let mut points = vec![(x(0.85), top), (x(0.25), top), (x(0.25), bottom)];
+                if shape != '⌈' {
+                    points.push((x(0.85), bottom));
+                }
+                if shape == '⌊' {
+                    points.remove(0);
+                }
+                let segments = points[1..]
+                    .iter()
+                    .map(|&(x, y)| Segment::Line(x, y))
+                    .collect();
+                drawn.stroke(points[0], segments, thickness);
+            }
+            '{' => {
+                let quarter = h * 0.04;
+                drawn.stroke(
+                    (x(0.9), top),
+                    vec![
+                        Segment::Curve([
+                            x(0.55),
+                            top,
+                            x(0.5),
+                            top - quarter,
+                            x(0.5),
+                            top - h * 0.12,
+                        ]),
+                        Segment::Line(x(0.5), middle + h * 0.08),
+                        Segment::Curve([x(0.5), middle + quarter, x(0.3), middle, x(0.1), middle]),
+                        Segment::Curve([
+                            x(0.3),
+                            middle,
+                            x(0.5),
+                            middle - quarter,
+                            x(0.5),
+                            middle - h * 0.08,
+                        ]),
+                        Segment::Line(x(0.5), bottom + h * 0.12),
+                        Segment::Curve([x(0.5), bottom + quarter, x(0.55), bottom, x(0.9), bottom]),
+                    ],
+                    thickness,
+                );
+            }
+            '⟨' => drawn.stroke(
+                (x(0.85), top),
+                vec![
+                    Segment::Line(x(0.15), middle),
+                    Segment::Line(x(0.85), bottom),
+                ],
+                thickness,
+            ),
+            '‖' => {
+                for fraction in [0.3, 0.7] {
+                    drawn.stroke(
+                        (x(fraction), top),
+                        vec![Segment::Line(x(fraction), bottom)],
+                        thickness,
+                    );
+                }
+            }
+            _ => drawn.stroke(
+                (x(0.5), top),
+                vec![Segment::Line(x(0.5), bottom)],
+                thickness,
+            ),
+        }
+        drawn
+    }
+
+    fn matrix(&self, rows: &[Vec<Vec<Node>>], align_left: bool, style: Style) -> MathBox {
+        let size = self.size(style);
+        let cell_style = style.max(Style::Text);
+        let cells: Vec<Vec<MathBox>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| self.list(cell, cell_style)).collect())
+            .collect();
+
+        let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+        let mut column_widths = vec![0.0f32; columns];
+        for row in &cells {
+            for (column, cell) in row.iter().enumerate() {
+                column_widths[column] = column_widths[column].max(cell.width);
+            }
+        }
+        let row_extents: Vec<(f32, f32)> = cells
+            .iter()
+            .map(|row| {
+                row.iter().fold((size * 0.72, size * 0.25), |(a, d), cell| {
+                    (a.max(cell.ascent), d.max(cell.descent))
+                })
+            })
+            .collect();
+
+        let row_gap = size * 0.3;
+        let column_gap = if align_left { size } else { size * 0.8 };
+        let height = row_extents.iter().map(|(a, d)| a + d).sum::<f32>()
+            + row_gap * row_extents.len().saturating_sub(1) as f32;
+        let width =
+            column_widths.iter().sum::<f32>() + column_gap * columns.saturating_sub(1) as f32;
+
+        let top = size * AXIS + height / 2.0;
+        let mut matrix = MathBox {
+            width,
+            ascent: top,
+            descent: height - top,
+            items: Vec::new(),
+        };
+        let mut y = top;
+        for (row, (ascent, descent)) in cells.into_iter().zip(row_extents) {
+            let baseline = y - ascent;
+            let mut x = 0.0;
+            for (column, cell) in row.into_iter().enumerate() {
+                let offset = if align_left {
+                    0.0
+                } else {
+                    (column_widths[column] - cell.width) / 2.0
+                };
+                matrix.place(cell, x + offset, baseline);
+                x += column_widths[column] + column_gap;
+            }
+            y = baseline - descent - row_gap;
+        }
+        matrix
+    }
+
+    fn accent(&self, accent: Accent, body: &[Node], style: Style) -> MathBox {
+        let size = self.size(style);
+        let body = self.list(body, style);
+        let thickness = size * RULE;
+        let y = body.ascent + size * 0.08;
+        let centre = body.width / 2.0;
+        let mut accented = MathBox::space(body.width);
+        let mark_height = match accent {
+            Accent::Bar => {
+                accented.items.push(MathItem::Rule {
+                    x: 0.0,
+                    y,
+                    width: body.width,
+                    height: thickness,
+                });
+                thickness
+            }
+            Accent::Hat => {
+                let half = body.width.clamp(size * 0.35, size * 1.2) / 2.0;
+                accented.stroke(
+                    (centre - half, y),
+                    vec![
+                        Segment::Line(centre, y + size * 0.15),
+                        Segment::Line(centre + half, y),
+                    ],
+                    thickness * 0.8,
+                );
+                size * 0.15
+            }
+            Accent::Tilde => {
+                let half = body.width.clamp(size * 0.35, size * 1.2) / 2.0;
+                accented.stroke(
+                    (centre - half, y + size * 0.03),
+                    vec![Segment::Curve([
+                        centre - half / 3.0,
+                        y + size * 0.2,
+                        centre + half / 3.0,
+                        y - size * 0.08,
+                        centre + half,
+                        y + size * 0.09,
+                    ])],
+                    thickness * 0.8,
+                );
+                size * 0.15
+            }
+            Accent::Vec => {
+                let (start, end) = if body.width < size * 0.45 {
+                    (centre - size * 0.225, centre + size * 0.225)
+                } else {
+                    (0.0, body.width)
+                };
+                let shaft = y + size * 0.07;
+                let head = size * 0.1;
+                accented.stroke(
+                    (start, shaft),
+                    vec![Segment::Line(end, shaft)],
+                    thickness * 0.8,
+                );
+                accented.stroke(
+                    (end - head, shaft + head * 0.7),
+                    vec![
+                        Segment::Line(end, shaft),
+                        Segment::Line(end - head, shaft - head * 0.7),
+                    ],
+                    thickness * 0.8,
+                );
+                size * 0.14
+            }
+            Accent::Dot => {
+                let dot = size * 0.1;
+                accented.items.push(MathItem::Rule {
+                    x: centre - dot / 2.0,
+                    y,
+                    width: dot,
+                    height: dot,
+                });
+                dot
+            }
+        };
+        accented.place(body, 0.0, 0.0);
+        accented.ascent = accented.ascent.max(y + mark_height);
+        accented
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fmt::math::parse;
+
+    fn lay_out(tex: &str, display: bool) -> MathBox {
+        layout(&parse(tex).unwrap(), display, 12.0, &FontSet::default())
+    }
+
+    #[test]
+    fn test_layout_sizes() {
+        let x = lay_out("x", false);
+        let fraction = lay_out("\\frac{a}{b}", false);
+        assert!(fraction.ascent > x.ascent && fraction.descent > x.descent);
+        // Display fractions and sums are larger
+        let display = lay_out("\\frac{a}{b}", true);
+        assert!(display.ascent + display.descent > fraction.ascent + fraction.descent);
+        assert!(lay_out("\\sum x", true).ascent > lay_out("\\sum x", false).ascent);
+
+        // Scripts are smaller and raised or lowered
+        let squared = lay_out("x^2", false);
+        let Some(MathItem::Glyph { y, size, .. }) = squared.items.last() else {
+            panic!("expected the superscript glyph");
+        };
+        assert!(*y > 0.0 && *size < 12.0);
+        assert!(squared.width > x.width);
+
+        // Limits go above and below in display math and beside inline
+        let inline = lay_out("\\sum_{i=1}^{n}", false);
+        let stacked = lay_out("\\sum_{i=1}^{n}", true);
+        assert!(stacked.width < inline.width);
+        assert!(stacked.ascent > inline.ascent);
+    }
+
+    #[test]
+    fn test_binary_operator_spacing() {
+        // The minus of a negative number takes no space around it
+        let binary = lay_out("a - b", false);
+        let unary = lay_out("-b", false);
+        let a = lay_out("a", false);
+        let medium = 12.0 * MEDIUM_SPACE;
+        assert!((binary.width - unary.width - a.width - medium * 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_drawn_delimiters_grow() {
+        let small = lay_out("\\left( x \\right)", false);
+        assert!(
+            small
+                .items
+                .iter()
+                .all(|item| matches!(item, MathItem::Glyph { .. }))
+        );
+
+        let tall = lay_out("\\left( \\frac{\\frac{a}{b}}{c} \\right)", true);
+        let paths = tall
+            .items
+            .iter()
+            .filter(|item| matches!(item, MathItem::Path { .. }))
+            .count();
+        assert_eq!(paths, 2);
+
+        let matrix = lay_out("\\begin{bmatrix} 1 & 2 \\\\ 3 & 4 \\end{bmatrix}", true);
+        assert!(matrix.ascent > 12.0 && matrix.descent > 6.0);
+    }
+}
@@ -0,0 +1,157 @@
+//! A compact sRGB ICC profile, built in code
+//!
+//! PDF/A needs an output intent with an embedded ICC profile describing the
+//! colours the document was made for. Every colour the writer uses is
+//! DeviceRGB meant as sRGB, so this profile describes IEC 61966-2.1: the sRGB
+//! primaries adapted to the D50 profile connection space, and the sRGB tone
+//! curve sampled into a table.
+
+/// Samples in each tone curve
+const CURVE_POINTS: usize = 1024;
+
+/// The D50 illuminant of the profile connection space
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// sRGB primaries, Bradford-adapted to D50
+const RED: [f64; 3] = [0.4361, 0.2225, 0.0139];
+const GREEN: [f64; 3] = [0.3851, 0.7169, 0.0971];
+const BLUE: [f64; 3] = [0.1431, 0.0606, 0.7141];
+
+pub(super) const DESCRIPTION: &str = "sRGB IEC61966-2.1";
+
+/// Build a version 2.1 display profile for sRGB
+pub(super) fn srgb_profile() -> Vec<u8> {
+    let curve = tone_curve();
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", description(DESCRIPTION)),
+        (b"cprt", text("No copyright, use freely")),
+        (b"wtpt", xyz(D50)),
+        (b"rXYZ", xyz(RED)),
+        (b"gXYZ", xyz(GREEN)),
+        (b"bXYZ", xyz(BLUE)),
+        (b"rTRC", curve.clone()),
+        (b"gTRC", curve.clone()),
+        (b"bTRC", curve),
+    ];
+
+    // Header, then the tag table, then the tag data aligned to 4 bytes
+    let data_start = 128 + 4 + 12 * tags.len();
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    table.extend((tags.len() as u32).to_be_bytes());
+    for (signature, tag) in &tags {
+        table.extend(*signature);
+        table.extend(((data_start + data.len()) as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+        data.extend(tag);
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+    }
+
+    let size = 128 + table.len() + data.len();
+    let mut profile = Vec::with_capacity(size);
+    profile.extend((size as u32).to_be_bytes());
+    profile.extend([0; 4]); // Preferred CMM
+    profile.extend([2, 0x10, 0, 0]); // Version 2.1
+    profile.extend(b"mntr");
+    profile.extend(b"RGB ");
+    profile.extend(b"XYZ ");
+    for field in [2025u16, 1, 1, 0, 0, 0] {
+        profile.extend(field.to_be_bytes());
+    }
+    profile.extend(b"acsp");
+    profile.extend([0; 24]); // Platform, flags, manufacturer, model, attributes
+    profile.extend(0u32.to_be_bytes()); // Perceptual rendering intent
+    for value in D50 {
+        profile.extend(s15_fixed16(value));
+    }
+    profile.resize(128, 0); // Creator, profile ID and reserved bytes
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+/// The sRGB transfer function, from encoded values to linear light
+fn tone_curve() -> Vec<u8> {
+    let mut tag = Vec::with_capacity(12 + 2 * CURVE_POINTS);
+    tag.extend(b"curv");
+    tag.extend([0; 4]);
+    tag.extend((CURVE_POINTS as u32).to_be_bytes());
+    for idx in 0..CURVE_POINTS {
+        let encoded = idx as f64 / (CURVE_POINTS - 1) as f64;
+        let linear = if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        tag.extend(((linear * 65535.0).round() as u16).to_be_bytes());
+    }
+    tag
+}
+
+fn xyz(value: [f64; 3]) -> Vec<u8> {
+    let mut tag = Vec::with_capacity(20);
+    tag.extend(b"XYZ ");
+    tag.extend([0; 4]);
+    for component in value {
+        tag.extend(s15_fixed16(component));
+    }
+    tag
+}
+
+fn text(value: &str) -> Vec<u8> {
+    let mut tag = Vec::new();
+    tag.extend(b"text");
+    tag.extend([0; 4]);
+    tag.extend(value.as_bytes());
+    tag.push(0);
+    tag
+}
+
+/// A version 2 text description, with empty Unicode and ScriptCode parts
+fn description(value: &str) -> Vec<u8> {
+    let mut tag = Vec::new();
+    tag.extend(b"desc");
+    tag.extend([0; 4]);
+    tag.extend((value.len() as u32 + 1).to_be_bytes());
+    tag.extend(value.as_bytes());
+    tag.push(0);
+    tag.extend([0; 8]); // Unicode language code and length
+    tag.extend([0; 3]); // ScriptCode code and length
+    tag.extend([0; 67]); // ScriptCode description
+    tag
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(data: &[u8], at: usize) -> usize {
+        u32::from_be_bytes(data[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn test_profile_layout() {
+        let profile = srgb_profile();
+        assert_eq!(read_u32(&profile, 0), profile.len());
+        assert_eq!(&profile[36..40], b"acsp");
+        assert_eq!(&profile[12..24], b"mntrRGB XYZ ");
+
+        // Every tag lies inside the profile, on a 4-byte boundary
+        let count = read_u32(&profile, 128);
+        assert_eq!(count, 9);
+        for idx in 0..count {
+            let entry = 132 + 12 * idx;
+            let (offset, size) = (read_u32(&profile, entry + 4), read_u32(&profile, entry + 8));
+            assert_eq!(offset % 4, 0);
+            assert!(offset + size <= profile.len());
+        }
+        let red_trc = 132 + 12 * 6;
+        assert_eq!(&profile[read_u32(&profile, red_trc + 4)..][..4], b"curv");
+    }
+}
@@ -0,0 +1,643 @@
+//! Raster images for the PDF backend
+//!
+//! JPEG files are embedded as they are, with `DCTDecode`. PNG files are
+//! decoded to 8-bit gray or RGB samples and written with `FlateDecode`; an
+//! alpha channel, or transparency from a `tRNS` chunk, becomes a soft mask.
+
+use std::{
+    io::Read,
+    path::Path,
+};
+
+use flate2::read::ZlibDecoder;
+use lopdf::{
+    Document,
+    Object,
+    ObjectId,
+    Stream,
+    dictionary,
+};
+
+use super::Mm;
+
+/// Resolution assumed for images that do not record one
+const DEFAULT_DPI: f32 = 96.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorSpace {
+    Gray,
+    Rgb,
+    Cmyk,
+}
+
+impl ColorSpace {
+    fn from_components(components: usize) -> Option<Self> {
+        match components {
+            1 => Some(ColorSpace::Gray),
+            3 => Some(ColorSpace::Rgb),
+            4 => Some(ColorSpace::Cmyk),
+            _ => None,
+        }
+    }
+
+    fn to_pdf_name(self) -> &'static str {
+        match self {
+            ColorSpace::Gray => "DeviceGray",
+            ColorSpace::Rgb => "DeviceRGB",
+            ColorSpace::Cmyk => "DeviceCMYK",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Samples {
+    /// A JPEG file, passed through untouched
+    Jpeg {
+        data: Vec<u8>,
+        /// Adobe CMYK JPEGs store inverted samples
+        inverted: bool,
+    },
+    /// Decoded 8-bit samples, row by row
+    Pixels {
+        color: Vec<u8>,
+        alpha: Option<Vec<u8>>,
+    },
+}
+
+/// A decoded image, ready to be written as an image XObject
+#[derive(Clone, Debug)]
+pub(super) struct Image {
+    pub width: u32,
+    pub height: u32,
+    color_space: ColorSpace,
+    /// Horizontal and vertical resolution in dots per inch
+    dpi: (f32, f32),
+    samples: Samples,
+}
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+impl Image {
+    /// Read a PNG or JPEG file, telling them apart by their signature
+    pub fn load(path: &Path) -> Result<Self, std::io::Error> {
+        let data = std::fs::read(path)?;
+        if data.starts_with(PNG_SIGNATURE) {
+            decode_png(&data)
+        } else if data.starts_with(&[0xFF, 0xD8]) {
+            read_jpeg(data)
+        } else {
+            Err(invalid("not a PNG or JPEG image"))
+        }
+    }
+
+    /// Size of the image at its recorded resolution
+    pub fn natural_size(&self) -> (Mm, Mm) {
+        (
+            Mm(self.width as f32 * 25.4 / self.dpi.0),
+            Mm(self.height as f32 * 25.4 / self.dpi.1),
+        )
+    }
+
+    /// Write the image XObject, and its soft mask if it has one
+    pub fn write_to(&self, doc: &mut Document) -> ObjectId {
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => self.width as i64,
+            "Height" => self.height as i64,
+            "ColorSpace" => self.color_space.to_pdf_name(),
+            "BitsPerComponent" => 8,
+        };
+
+        let stream = match &self.samples {
+            Samples::Jpeg { data, inverted } => {
+                dict.set("Filter", "DCTDecode");
+                if *inverted {
+                    let decode: Vec<Object> =
+                        [1, 0].repeat(4).into_iter().map(Object::from).collect();
+                    dict.set("Decode", decode);
+                }
+                Stream::new(dict, data.clone()).with_compression(false)
+            }
+            Samples::Pixels { color, alpha } => {
+                if let Some(alpha) = alpha {
+                    let mut mask = Stream::new(
+                        dictionary! {
+                            "Type" => "XObject",
+                            "Subtype" => "Image",
+                            "Width" => self.width as i64,
+                            "Height" => self.height as i64,
+                            "ColorSpace" => "DeviceGray",
+                            "BitsPerComponent" => 8,
+                        },
+                        alpha.clone(),
+                    );
+                    let _ = mask.compress();
+                    dict.set("SMask", Object::Reference(doc.add_object(mask)));
+                }
+                let mut stream = Stream::new(dict, color.clone());
+                let _ = stream.compress();
+                stream
+            }
+        };
+        doc.add_object(stream)
+    }
+}
+
+/// Read the size, colour space and resolution from the JPEG markers
+fn read_jpeg(data: Vec<u8>) -> Result<Image, std::io::Error> {
+    let truncated = || invalid("truncated JPEG image");
+    let mut dpi = (DEFAULT_DPI, DEFAULT_DPI);
+    let mut adobe = false;
+    let mut pos = 2;
+
+    loop {
+        // Markers may be padded with any number of 0xFF bytes
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let (Some(&0xFF), Some(&marker)) = (data.get(pos), data.get(pos + 1)) else {
+            return Err(invalid("malformed JPEG marker"));
+        };
+        // Start of scan or end of image before any frame header
+        if marker == 0xDA || marker == 0xD9 {
+            return Err(invalid("JPEG image has no frame header"));
+        }
+        let length = data
+            .get(pos + 2..pos + 4)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+            .ok_or_else(truncated)?;
+        let segment = data.get(pos + 4..pos + 2 + length).ok_or_else(truncated)?;
+
+        match marker {
+            // JFIF header with its pixel density
+            0xE0 if segment.starts_with(b"JFIF\0") && segment.len() >= 12 => {
+                let x = u16::from_be_bytes([segment[8], segment[9]]) as f32;
+                let y = u16::from_be_bytes([segment[10], segment[11]]) as f32;
+                if x > 0.0 && y > 0.0 {
+                    match segment[7] {
+                        1 => dpi = (x, y),
+                        2 => dpi = (x * 2.54, y * 2.54),
+                        _ => {}
+                    }
+                }
+            }
+            0xEE if segment.starts_with(b"Adobe") => adobe = true,
+            // Start of frame, except the DHT, JPG and DAC markers that share the range
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                if segment.len() < 6 {
+                    return Err(truncated());
+                }
+                let height = u16::from_be_bytes([segment[1], segment[2]]) as u32;
+                let width = u16::from_be_bytes([segment[3], segment[4]]) as u32;
+                let color_space = ColorSpace::from_components(segment[5] as usize)
+                    .ok_or_else(|| invalid("unsupported number of JPEG colour components"))?;
+                if width == 0 || height == 0 {
+                    return Err(invalid("JPEG image has no size"));
+                }
+                return Ok(Image {
+                    width,
+                    height,
+                    color_space,
+                    dpi,
+                    samples: Samples::Jpeg {
+                        data,
+                        inverted: adobe && color_space == ColorSpace::Cmyk,
+                    },
+                });
+            }
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Most samples decoded from a PNG image, well beyond any page's needs
+const MAX_PNG_SAMPLES: usize = 1 << 28;
+
+/// Offsets and sizes of the seven Adam7 interlacing passes:
+/// (x start, y start, x step, y step)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+fn decode_png(data: &[u8]) -> Result<Image, std::io::Error> {
+    let truncated = || invalid("truncated PNG image");
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut compressed = Vec::new();
+    let mut dpi = (DEFAULT_DPI, DEFAULT_DPI);
+
+    let mut pos = PNG_SIGNATURE.len();
+    while pos + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let chunk = data.get(pos + 8..pos + 8 + length).ok_or_else(truncated)?;
+        match kind {
+            b"IHDR" if chunk.len() >= 13 => header = Some(chunk),
+            b"PLTE" => palette = chunk,
+            b"tRNS" => transparency = chunk,
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            // Pixels per metre
+            b"pHYs" if chunk.len() >= 9 && chunk[8] == 1 => {
+                let x = u32::from_be_bytes(chunk[0..4].try_into().unwrap()) as f32;
+                let y = u32::from_be_bytes(chunk[4..8].try_into().unwrap()) as f32;
+                if x > 0.0 && y > 0.0 {
+                    dpi = (x * 0.0254, y * 0.0254);
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        // Length, type, data and CRC
+        pos += 12 + length;
+    }
+
+    let header = header.ok_or_else(|| invalid("PNG image has no header"))?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    let depth = header[8] as usize;
+    let color_type = header[9];
+    let interlaced = header[12] == 1;
+
+    let channels = match (color_type, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) => 1,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (2, 8 | 16) => 3,
+        (4, 8 | 16) => 2,
+        (6, 8 | 16) => 4,
+        _ => {
+            return Err(invalid(format!(
+                "unsupported PNG colour type {} with bit depth {}",
+                color_type, depth
+            )));
+        }
+    };
+    if width == 0 || height == 0 {
+        return Err(invalid("PNG image has no size"));
+    }
+    if color_type == 3 && palette.is_empty() {
+        return Err(invalid("indexed PNG image has no palette"));
+    }
+
+    // The header is checked against the data before anything is allocated
+    // from its size
+    let too_large = || {
+        invalid(format!(
+            "PNG image of {}x{} pixels is too large",
+            width, height
+        ))
+    };
+    let pixel_count = width.checked_mul(height).ok_or_else(too_large)?;
+    let sample_count = pixel_count
+        .checked_mul(channels)
+        .filter(|&count| count <= MAX_PNG_SAMPLES)
+        .ok_or_else(too_large)?;
+    let bits_per_pixel = channels * depth;
+    let passes: &[(usize, usize, usize, usize)] = if interlaced { &ADAM7 } else { &[(0, 0, 1, 1)] };
+    // Width, height and row length in bytes of each pass
+    let mut pass_sizes = Vec::with_capacity(passes.len());
+    let mut expected = 0usize;
+    for &(x0, y0, dx, dy) in passes {
+        let pass_width = (width + dx - x0 - 1) / dx;
+        let pass_height = (height + dy - y0 - 1) / dy;
+        let row_bytes = pass_width
+            .checked_mul(bits_per_pixel)
+            .ok_or_else(too_large)?
+            .div_ceil(8);
+        if pass_width > 0 && pass_height > 0 {
+            expected = (row_bytes + 1)
+                .checked_mul(pass_height)
+                .and_then(|size| size.checked_add(expected))
+                .ok_or_else(too_large)?;
+        }
+        pass_sizes.push((pass_width, pass_height, row_bytes));
+    }
+
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed.as_slice())
+        .take(expected as u64)
+        .read_to_end(&mut raw)
+        .map_err(|e| invalid(format!("corrupt PNG image data: {}", e)))?;
+    if raw.len() < expected {
+        return Err(truncated());
+    }
+
+    // Every pixel as raw samples, before palette lookup or scaling
+    let mut pixels = vec![0u16; sample_count];
+    let mut offset = 0;
+    for (&(x0, y0, dx, dy), &(pass_width, pass_height, row_bytes)) in passes.iter().zip(&pass_sizes)
+    {
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let size = (row_bytes + 1) * pass_height;
+        let pass = raw.get(offset..offset + size).ok_or_else(truncated)?;
+        offset += size;
+
+        let rows = unfilter(pass, row_bytes, bits_per_pixel.div_ceil(8))?;
+        for (row_idx, row) in rows.chunks(row_bytes).enumerate() {
+            let y = y0 + row_idx * dy;
+            for col in 0..pass_width {
+                let x = x0 + col * dx;
+                for channel in 0..channels {
+                    let value = read_sample(row, col * channels + channel, depth);
+                    pixels[(y * width + x) * channels + channel] = value;
+                }
+            }
+        }
+    }
+
+    let max = ((1u32 << depth) - 1) as u16;
+    let scale = |value: u16| match depth {
+        16 => (value >> 8) as u8,
+        8 => value as u8,
+        _ => (value as u32 * 255 / max as u32) as u8,
+    };
+    let transparent_key = |at: usize| {
+        transparency
+            .get(at * 2..at * 2 + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let color_space = match color_type {
+        0 | 4 => ColorSpace::Gray,
+        _ => ColorSpace::Rgb,
+    };
+    let color_channels = if color_space == ColorSpace::Rgb { 3 } else { 1 };
+    let mut color = Vec::with_capacity(
+        pixel_count
+            .checked_mul(color_channels)
+            .ok_or_else(too_large)?,
+    );
+    let mut alpha = Vec::with_capacity(pixel_count);
+    for pixel in pixels.chunks(channels) {
+        match color_type {
+            0 => {
+                color.push(scale(pixel[0]));
+                alpha.push(if transparent_key(0) == Some(pixel[0]) {
+                    0
+                } else {
+                    255
+                });
+            }
+            2 => {
+                color.extend(pixel.iter().map(|&value| scale(value)));
+                let key = (0..3).map(transparent_key).collect::<Option<Vec<u16>>>();
+                alpha.push(if key.as_deref() == Some(pixel) {
+                    0
+                } else {
+                    255
+                });
+            }
+            3 => {
+                let index = pixel[0] as usize;
+                let entry = palette
+                    .get(index * 3..index * 3 + 3)
+                    .ok_or_else(|| invalid("PNG palette index out of range"))?;
+                color.extend_from_slice(entry);
+                alpha.push(transparency.get(index).copied().unwrap_or(255));
+            }
+            4 => {
+                color.push(scale(pixel[0]));
+                alpha.push(scale(pixel[1]));
+            }
+            _ => {
+                color.extend(pixel[..3].iter().map(|&value| scale(value)));
+                alpha.push(scale(pixel[3]));
+            }
+        }
+    }
+    let alpha = alpha.iter().any(|&value| value < 255).then_some(alpha);
+
+    Ok(Image {
+        width: width as u32,
+        height: height as u32,
+        color_space,
+        dpi,
+        samples: Samples::Pixels { color, alpha },
+    })
+}
+
+/// Undo the per-row PNG filters, returning the rows without filter bytes
+fn unfilter(
+    data: &[u8],
+    row_bytes: usize,
+    bytes_per_pixel: usize,
+) -> Result<Vec<u8>, std::io::Error> {
+    let rows = data.len() / (row_bytes + 1);
+    let mut out = vec![0u8; rows * row_bytes];
+    for row in 0..rows {
+        let filter = data[row * (row_bytes + 1)];
+        let line = &data[row * (row_bytes + 1) + 1..(row + 1) * (row_bytes + 1)];
+        let (done, current) = out.split_at_mut(row * row_bytes);
+        let previous = done
+            .get(done.len().saturating_sub(row_bytes)..)
+            .filter(|_| row > 0);
+        let current = &mut current[..row_bytes];
+
+        for idx in 0..row_bytes {
+            let left = if idx >= bytes_per_pixel {
+                current[idx - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = previous.map_or(0, |previous| previous[idx]);
+            let up_left = match previous {
+                Some(previous) if idx >= bytes_per_pixel => previous[idx - bytes_per_pixel],
+                _ => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(invalid(format!("unknown PNG filter type {}", filter))),
+            };
+            current[idx] = line[idx].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Sample number `idx` of a row of samples packed at the given bit depth
+fn read_sample(row: &[u8], idx: usize, depth: usize) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[idx * 2], row[idx * 2 + 1]]),
+        8 => row[idx] as u16,
+        _ => {
+            let bit = idx * depth;
+            let shift = 8 - depth - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8) as u16
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        Compression,
+        write::ZlibEncoder,
+    };
+
+    use super::*;
+
+    /// Build a PNG file from the header fields and unfiltered rows
+    fn encode_png(
+        width: u32,
+        height: u32,
+        depth: u8,
+        color_type: u8,
+        extra: &[(&[u8; 4], &[u8])],
+        rows: &[u8],
+    ) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(rows).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        let chunks = [(b"IHDR", header.as_slice())]
+            .into_iter()
+            .chain(extra.iter().copied())
+            .chain([(b"IDAT", compressed.as_slice()), (b"IEND", &[][..])]);
+        for (kind, data) in chunks {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+            // The decoder does not check CRCs
+            png.extend_from_slice(&[0; 4]);
+        }
+        png
+    }
+
+    #[test]
+    fn test_png_filters_and_alpha() {
+        // 2x2 RGBA, first row with the Sub filter, second with Up
+        let rows = [
+            1, 10, 20, 30, 255, 5, 5, 5, 0, //
+            2, 0, 0, 0, 0, 1, 1, 1, 129,
+        ];
+        let png = encode_png(
+            2,
+            2,
+            8,
+            6,
+            &[(b"pHYs", &[0, 0, 0x0B, 0x13, 0, 0, 0x0B, 0x13, 1])],
+            &rows,
+        );
+        let image = decode_png(&png).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.color_space, ColorSpace::Rgb);
+        // 2835 pixels per metre is 72 dpi
+        assert!((image.dpi.0 - 72.0).abs() < 0.1);
+        let Samples::Pixels { color, alpha } = image.samples else {
+            panic!("PNG should be decoded");
+        };
+        assert_eq!(color, [10, 20, 30, 15, 25, 35, 10, 20, 30, 16, 26, 36]);
+        assert_eq!(alpha.unwrap(), [255, 255, 255, 128]);
+    }
+
+    #[test]
+    fn test_alpha_becomes_soft_mask() {
+        // 1x1 gray and alpha
+        let image = decode_png(&encode_png(1, 1, 8, 4, &[], &[0, 200, 50])).unwrap();
+        let mut doc = Document::with_version("1.5");
+        let image_id = image.write_to(&mut doc);
+
+        let xobject = doc.get_object(image_id).unwrap().as_stream().unwrap();
+        assert_eq!(
+            xobject.dict.get(b"ColorSpace").unwrap().as_name().unwrap(),
+            b"DeviceGray"
+        );
+        let mask_id = xobject.dict.get(b"SMask").unwrap().as_reference().unwrap();
+        let mask = doc.get_object(mask_id).unwrap().as_stream().unwrap();
+        // Streams too small to gain from compression are left as they are
+        let alpha = mask.decompressed_content().unwrap_or(mask.content.clone());
+        assert_eq!(alpha, [50]);
+    }
+
+    #[test]
+    fn test_png_palette_and_opaque() {
+        // 4x1 image with 2-bit palette indices 0, 1, 2, 1
+        let palette = [0, 0, 0, 255, 0, 0, 0, 0, 255];
+        let png = encode_png(4, 1, 2, 3, &[(b"PLTE", &palette)], &[0, 0b00_01_10_01]);
+        let image = decode_png(&png).unwrap();
+        let Samples::Pixels { color, alpha } = image.samples else {
+            panic!("PNG should be decoded");
+        };
+        assert_eq!(color, [0, 0, 0, 255, 0, 0, 0, 0, 255, 255, 0, 0]);
+        // Fully opaque images need no soft mask
+        assert!(alpha.is_none());
+    }
+
+    #[test]
+    fn test_png_too_large_for_its_data() {
+        // A header claiming 100000x100000 RGBA with a few bytes of data is
+        // refused before its pixels are allocated
+        let png = encode_png(100_000, 100_000, 8, 6, &[], &[0; 16]);
+        assert!(png.len() < 200);
+        let error = decode_png(&png).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        // Within the size limit, data too short for the header is truncated
+        let png = encode_png(4000, 4000, 8, 6, &[], &[0; 16]);
+        assert_eq!(
+            decode_png(&png).unwrap_err().to_string(),
+            "truncated PNG image"
+        );
+    }
+
+    #[test]
+    fn test_jpeg_header() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // JFIF at 300 dpi
+        jpeg.extend_from_slice(&[0xFF, 0xE0, 0, 16]);
+        jpeg.extend_from_slice(b"JFIF\0\x01\x02\x01\x01\x2C\x01\x2C\0\0");
+        // Baseline frame, 40 high and 60 wide with 3 components
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0, 17, 8, 0, 40, 0, 60, 3]);
+        jpeg.extend_from_slice(&[0; 9]);
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+
+        let image = read_jpeg(jpeg).unwrap();
+        assert_eq!((image.width, image.height), (60, 40));
+        assert_eq!(image.color_space, ColorSpace::Rgb);
+        let (width, _) = image.natural_size();
+        assert!((width.0 - 60.0 * 25.4 / 300.0).abs() < 0.01);
+
+        assert!(read_jpeg(vec![0xFF, 0xD8, 0xFF, 0xD9]).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Bullets and numbers for list items
+//!
+//! Each nesting depth takes the next bullet or number format from the list
+//! style, starting again from the first once the formats run out.
+
+use crate::parse::ListStyle;
+
+/// What is drawn in front of the first line of a list item
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum ListMarker {
+    Bullet(Bullet),
+    /// Item number written in its list's format, such as `3.` or `iv)`
+    Number(String),
+    /// Task list checkbox, replacing the bullet or number
+    Checkbox(bool),
+}
+
+/// Bullet of an unordered list item
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Bullet {
+    Disc,
+    Circle,
+    Square,
+    /// Written as text in the body font
+    Text(String),
+}
+
+impl Bullet {
+    fn parse(name: &str) -> Self {
+        match name.trim() {
+            "disc" => Bullet::Disc,
+            "circle" => Bullet::Circle,
+            "square" => Bullet::Square,
+            text => Bullet::Text(text.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Counter {
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+/// Format of an ordered list item's number, such as `1.`, `a)` or `(i)`
+#[derive(Clone, Debug, PartialEq)]
+struct NumberFormat {
+    prefix: String,
+    counter: Counter,
+    suffix: String,
+}
+
+impl NumberFormat {
+    /// The first `1`, `a`, `A`, `i` or `I` stands for the number
+    fn parse(format: &str) -> Option<Self> {
+        let (at, counter) = format.char_indices().find_map(|(at, c)| {
+            let counter = match c {
+                '1' => Counter::Decimal,
+                'a' => Counter::LowerAlpha,
+                'A' => Counter::UpperAlpha,
+                'i' => Counter::LowerRoman,
+                'I' => Counter::UpperRoman,
+                _ => return None,
+            };
+            Some((at, counter))
+        })?;
+        Some(Self {
+            prefix: format[..at].to_string(),
+            counter,
+            suffix: format[at + 1..].to_string(),
+        })
+    }
+
+    fn format(&self, number: u64) -> String {
+        let number = match self.counter {
+            Counter::Decimal => None,
+            Counter::LowerAlpha => alphabetic(number),
+            Counter::UpperAlpha => alphabetic(number).map(|letters| letters.to_uppercase()),
+            Counter::LowerRoman => roman(number),
+            Counter::UpperRoman => roman(number).map(|numeral| numeral.to_uppercase()),
+        }
+        // Numbers with no letter form fall back to digits
+        .unwrap_or_else(|| number.to_string());
+        format!("{}{}{}", self.prefix, number, self.suffix)
+    }
+}
+
+/// `a` to `z`, then `aa`, `ab`, ... as spreadsheets name columns
+fn alphabetic(mut number: u64) -> Option<String> {
+    if number == 0 {
+        return None;
+    }
+    let mut letters = Vec::new();
+    while number > 0 {
+        number -= 1;
+        letters.push(b'a' + (number % 26) as u8);
+        number /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).ok()
+}
+
+fn roman(mut number: u64) -> Option<String> {
+    if !(1..4000).contains(&number) {
+        return None;
+    }
+    const NUMERALS: [(u64, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut numeral = String::new();
+    for (value, letters) in NUMERALS {
+        while number >= value {
+            numeral.push_str(letters);
+            number -= value;
+        }
+    }
+    Some(numeral)
+}
+
+/// Bullets and number formats by nesting depth
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct ListMarkers {
+    bullets: Vec<Bullet>,
+    numbers: Vec<NumberFormat>,
+}
+
+impl Default for ListMarkers {
+    fn default() -> Self {
+        Self {
+            bullets: vec![Bullet::Disc, Bullet::Circle, Bullet::Square],
+            numbers: ["1.", "a.", "i."]
+                .into_iter()
+                .filter_map(NumberFormat::parse)
+                .collect(),
+        }
+    }
+}
+
+impl ListMarkers {
+    /// Apply a list style to the default markers
+    pub fn from_config(style: Option<&ListStyle>) -> Result<Self, std::io::Error> {
+        let mut markers = Self::default();
+        let Some(style) = style else {
+            return Ok(markers);
+        };
+
+        if let Some(bullets) = style.bullets.as_ref().filter(|bullets| !bullets.is_empty()) {
+            markers.bullets = bullets.iter().map(|name| Bullet::parse(name)).collect();
+        }
+        if let Some(numbers) = style.numbers.as_ref().filter(|numbers| !numbers.is_empty()) {
+            markers.numbers = numbers
+                .iter()
+                .map(|format| {
+                    NumberFormat::parse(format).ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!(
+                                "List number format '{}' needs one of 1, a, A, i or I",
+                                format
+                            ),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(markers)
+    }
+
+    /// Bullet for an item of an unordered list `depth` lists deep, counting from 1
+    pub fn bullet(&self, depth: usize) -> ListMarker {
+        let bullet = &self.bullets[depth.saturating_sub(1) % self.bullets.len()];
+        ListMarker::Bullet(bullet.clone())
+    }
+
+    /// Number for an item of an ordered list `depth` lists deep, counting from 1
+    pub fn number(&self, depth: usize, number: u64) -> ListMarker {
+        let format = &self.numbers[depth.saturating_sub(1) % self.numbers.len()];
+        ListMarker::Number(format.format(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_formats() {
+        let format = |format: &str, number| NumberFormat::parse(format).unwrap().format(number);
+        assert_eq!(format("1.", 7), "7.");
+        assert_eq!(format("a)", 1), "a)");
+        assert_eq!(format("a.", 28), "ab.");
+        assert_eq!(format("A.", 26), "Z.");
+        assert_eq!(format("(i)", 4), "(iv)");
+        assert_eq!(format("I.", 1994), "MCMXCIV.");
+        assert_eq!(format("Step 1:", 3), "Step 3:");
+        // No letter form for zero
+        assert_eq!(format("i.", 0), "0.");
+        assert_eq!(NumberFormat::parse("#."), None);
+    }
+
+    #[test]
+    fn test_list_markers_by_depth() {
+        let markers = ListMarkers::default();
+        assert_eq!(markers.bullet(1), ListMarker::Bullet(Bullet::Disc));
+        assert_eq!(markers.bullet(3), ListMarker::Bullet(Bullet::Square));
+        assert_eq!(markers.bullet(4), ListMarker::Bullet(Bullet::Disc));
+        assert_eq!(markers.number(1, 3), ListMarker::Number("3.".to_string()));
+        assert_eq!(markers.number(2, 3), ListMarker::Number("c.".to_string()));
+        assert_eq!(markers.number(3, 3), ListMarker::Number("iii.".to_string()));
+
+        let style = ListStyle {
+            bullets: Some(vec!["-".to_string(), "circle".to_string()]),
+            numbers: Some(vec!["(A)".to_string()]),
+        };
+        let markers = ListMarkers::from_config(Some(&style)).unwrap();
+        assert_eq!(
+            markers.bullet(1),
+            ListMarker::Bullet(Bullet::Text("-".to_string()))
+        );
+        assert_eq!(markers.bullet(2), ListMarker::Bullet(Bullet::Circle));
+        assert_eq!(markers.number(2, 2), ListMarker::Number("(B)".to_string()));
+
+        let style = ListStyle {
+            numbers: Some(vec!["*".to_string()]),
+            ..Default::default()
+        };
+        assert!(ListMarkers::from_config(Some(&style)).is_err());
+    }
+}
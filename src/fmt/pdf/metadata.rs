@@ -0,0 +1,423 @@
+//! Document information dictionary and XMP metadata
+//!
+//! The same values are written twice: once to the trailer's `/Info`
+//! dictionary, which most viewers show in their document properties, and
+//! once as an XMP packet attached to the catalog, which is what search
+//! indexers and archival tools read. The two are kept equivalent.
+
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use lopdf::{
+    Dictionary,
+    Document,
+    Object,
+    ObjectId,
+    Stream,
+    dictionary,
+};
+
+use super::text_string;
+
+const PRODUCER: &str = concat!("mdreport ", env!("CARGO_PKG_VERSION"));
+
+/// Metadata collected from the front matter
+#[derive(Clone, Debug, Default)]
+pub(super) struct DocumentInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Vec<String>,
+    /// Document language, such as `en` or `de-CH`
+    pub lang: Option<String>,
+    /// Creation date from the front matter; the time of writing otherwise
+    pub created: Option<PdfDate>,
+    /// PDF/A part the document claims to conform to, at level B
+    pub pdfa_part: Option<u8>,
+}
+
+/// A calendar date with an optional time of day and UTC offset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct PdfDate {
+    year: u16,
+    month: u8,
+    day: u8,
+    /// Hour, minute and second, when the date has a time of day
+    time: Option<(u8, u8, u8)>,
+    /// Offset from UTC in minutes, when the time has one
+    offset: Option<i16>,
+}
+
+impl PdfDate {
+    /// Parse an ISO 8601 date such as `2025-10-16` or `2025-10-16T09:30:00+02:00`
+    ///
+    /// A space may separate the date from the time, seconds are optional and
+    /// fractions of a second are dropped.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let number = |digits: &str| -> Option<u16> {
+            (!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| digits.parse().ok())
+                .flatten()
+        };
+
+        let (date, rest) = match text.get(..10) {
+            Some(date) => (date, &text[10..]),
+            None => (text, ""),
+        };
+        let mut parts = date.split('-');
+        let (year, month, day) = (
+            parts
+                .next()
+                .filter(|part| part.len() == 4)
+                .and_then(number)?,
+            parts
+                .next()
+                .filter(|part| part.len() == 2)
+                .and_then(number)? as u8,
+            parts
+                .next()
+                .filter(|part| part.len() == 2)
+                .and_then(number)? as u8,
+        );
+        if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+            return None;
+        }
+
+        let mut result = PdfDate {
+            year,
+            month,
+            day,
+            time: None,
+            offset: None,
+        };
+        let Some(rest) = rest.strip_prefix(['T', ' ']) else {
+            return rest.is_empty().then_some(result);
+        };
+
+        let zone_start = rest.find(['Z', '+', '-']).unwrap_or(rest.len());
+        let (time, zone) = rest.split_at(zone_start);
+        let time = time.split('.').next().unwrap_or_default();
+        let mut fields = time.split(':');
+        let hour = fields.next().and_then(number)?;
+        let minute = fields.next().and_then(number)?;
+        let second = fields.next().map_or(Some(0), number)?;
+        if fields.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        result.time = Some((hour as u8, minute as u8, second as u8));
+
+        result.offset = match zone {
+            "" => None,
+            "Z" => Some(0),
+            _ => {
+                let sign = if zone.starts_with('-') { -1 } else { 1 };
+                let (hours, minutes) = zone[1..].split_once(':')?;
+                let (hours, minutes) = (number(hours)?, number(minutes)?);
+                if hours > 23 || minutes > 59 {
+                    return None;
+                }
+                Some(sign * (hours * 60 + minutes) as i16)
+            }
+        };
+        Some(result)
+    }
+
+    /// The current time in UTC
+    ///
+    /// `SOURCE_DATE_EPOCH` takes precedence over the system clock, so builds
+    /// can be reproduced byte for byte.
+    pub fn now() -> Self {
+        let seconds = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.trim().parse().ok())
+            .unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs())
+            });
+        Self::from_unix(seconds)
+    }
+
+    /// Convert seconds since the Unix epoch to a UTC date
+    fn from_unix(seconds: u64) -> Self {
+        let days = (seconds / 86_400) as i64;
+        let of_day = seconds % 86_400;
+
+        // Civil date from a day count, after Howard Hinnant's algorithm
+        let shifted = days + 719_468;
+        let era = shifted.div_euclid(146_097);
+        let day_of_era = shifted.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        PdfDate {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            time: Some((
+                (of_day / 3600) as u8,
+                (of_day / 60 % 60) as u8,
+                (of_day % 60) as u8,
+            )),
+            offset: Some(0),
+        }
+    }
+
+    /// Format as a PDF date string, such as `D:20251016093000+02'00'`
+    pub fn to_pdf(self) -> String {
+        let mut text = format!("D:{:04}{:02}{:02}", self.year, self.month, self.day);
+        if let Some((hour, minute, second)) = self.time {
+            text.push_str(&format!("{:02}{:02}{:02}", hour, minute, second));
+            match self.offset {
+                Some(0) => text.push('Z'),
+                Some(offset) => {
+                    let sign = if offset < 0 { '-' } else { '+' };
+                    let offset = offset.unsigned_abs();
+                    text.push_str(&format!("{}{:02}'{:02}'", sign, offset / 60, offset % 60));
+                }
+                None => {}
+            }
+        }
+        text
+    }
+
+    /// Format as an XMP date, such as `2025-10-16T09:30:00+02:00`
+    fn to_xmp(self) -> String {
+        let mut text = format!("{:04}-{:02}-{:02}", self.year, self.month, self.day);
+        if let Some((hour, minute, second)) = self.time {
+            text.push_str(&format!("T{:02}:{:02}:{:02}", hour, minute, second));
+            match self.offset {
+                Some(0) => text.push('Z'),
+                Some(offset) => {
+                    let sign = if offset < 0 { '-' } else { '+' };
+                    let offset = offset.unsigned_abs();
+                    text.push_str(&format!("{}{:02}:{:02}", sign, offset / 60, offset % 60));
+                }
+                None => {}
+            }
+        }
+        text
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A text field worth writing: set and not blank. The Info dictionary and
+/// the XMP packet must agree, so both go through this.
+fn non_blank(value: Option<&str>) -> Option<&str> {
+    value.filter(|value| !value.trim().is_empty())
+}
+
+/// Write the `/Info` dictionary and return it for the trailer
+pub(super) fn write_info(doc: &mut Document, info: &DocumentInfo, modified: PdfDate) -> ObjectId {
+    let created = info.created.unwrap_or(modified);
+    let mut dict = dictionary! {
+        "Creator" => Object::string_literal("mdreport"),
+        "Producer" => Object::string_literal(PRODUCER),
+        "CreationDate" => Object::string_literal(created.to_pdf()),
+        "ModDate" => Object::string_literal(modified.to_pdf()),
+    };
+    let set_text = |dict: &mut Dictionary, key: &str, value: Option<&str>| {
+        if let Some(value) = non_blank(value) {
+            dict.set(key, text_string(value));
+        }
+    };
+    set_text(&mut dict, "Title", info.title.as_deref());
+    set_text(&mut dict, "Author", info.author.as_deref());
+    set_text(&mut dict, "Subject", info.subject.as_deref());
+    let keywords = info.keywords.join(", ");
+    set_text(&mut dict, "Keywords", Some(&keywords));
+    doc.add_object(dict)
+}
+
+/// Write the XMP packet and return the stream for the catalog's `/Metadata`
+pub(super) fn write_xmp(doc: &mut Document, info: &DocumentInfo, modified: PdfDate) -> ObjectId {
+    let stream = Stream::new(
+        dictionary! {
+            "Type" => "Metadata",
+            "Subtype" => "XML",
+        },
+        xmp_packet(info, modified).into_bytes(),
+    )
+    // Metadata must stay readable by tools that do not parse PDF
+    .with_compression(false);
+    doc.add_object(stream)
+}
+
+fn xmp_packet(info: &DocumentInfo, modified: PdfDate) -> String {
+    let created = info.created.unwrap_or(modified);
+    let alt = |value: &str| {
+        format!(
+            "<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>",
+            escape_xml(value)
+        )
+    };
+
+    let mut dc = String::from("   <dc:format>application/pdf</dc:format>\n");
+    if let Some(title) = non_blank(info.title.as_deref()) {
+        dc.push_str(&format!("   <dc:title>{}</dc:title>\n", alt(title)));
+    }
+    if let Some(author) = non_blank(info.author.as_deref()) {
+        dc.push_str(&format!(
+            "   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+            escape_xml(author)
+        ));
+    }
+    if let Some(subject) = non_blank(info.subject.as_deref()) {
+        dc.push_str(&format!(
+            "   <dc:description>{}</dc:description>\n",
+            alt(subject)
+        ));
+    }
+    let keywords = info.keywords.join(", ");
+    let has_keywords = non_blank(Some(&keywords)).is_some();
+    if has_keywords {
+        let items: String = info
+            .keywords
+            .iter()
+            .map(|keyword| format!("<rdf:li>{}</rdf:li>", escape_xml(keyword)))
+            .collect();
+        dc.push_str(&format!(
+            "   <dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>\n",
+            items
+        ));
+    }
+    if let Some(lang) = &info.lang {
+        dc.push_str(&format!(
+            "   <dc:language><rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag></dc:language>\n",
+            escape_xml(lang)
+        ));
+    }
+
+    let pdfa = match info.pdfa_part {
+        Some(part) => format!(
+            "  <rdf:Description rdf:about=\"\"
+    xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">
+   <pdfaid:part>{}</pdfaid:part>
+   <pdfaid:conformance>B</pdfaid:conformance>
+  </rdf:Description>
+",
+            part
+        ),
+        None => String::new(),
+    };
+
+    let mut pdf = format!("   <pdf:Producer>{}</pdf:Producer>\n", PRODUCER);
+    if has_keywords {
+        pdf.push_str(&format!(
+            "   <pdf:Keywords>{}</pdf:Keywords>\n",
+            escape_xml(&keywords)
+        ));
+    }
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\"
+    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"
+    xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"
+    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">
+{dc}{pdf}   <xmp:CreatorTool>mdreport</xmp:CreatorTool>
+   <xmp:CreateDate>{created}</xmp:CreateDate>
+   <xmp:ModifyDate>{modified}</xmp:ModifyDate>
+   <xmp:MetadataDate>{modified}</xmp:MetadataDate>
+  </rdf:Description>
+{pdfa} </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>",
+        created = created.to_xmp(),
+        modified = modified.to_xmp(),
+    )
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dates() {
+        let date = PdfDate::parse("2025-10-16").unwrap();
+        assert_eq!(date.to_pdf(), "D:20251016");
+        assert_eq!(date.to_xmp(), "2025-10-16");
+
+        let date = PdfDate::parse("2025-10-16T09:30+02:00").unwrap();
+        assert_eq!(date.to_pdf(), "D:20251016093000+02'00'");
+        assert_eq!(date.to_xmp(), "2025-10-16T09:30:00+02:00");
+
+        let date = PdfDate::parse("2024-02-29 23:59:59.5Z").unwrap();
+        assert_eq!(date.to_pdf(), "D:20240229235959Z");
+
+        assert!(PdfDate::parse("2025-02-29").is_none());
+        assert!(PdfDate::parse("16 October 2025").is_none());
+        assert!(PdfDate::parse("2025-10-16T25:00").is_none());
+    }
+
+    #[test]
+    fn test_unix_time() {
+        assert_eq!(PdfDate::from_unix(0).to_xmp(), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            PdfDate::from_unix(1_709_251_199).to_xmp(),
+            "2024-02-29T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn test_xmp_matches_info() {
+        let info = DocumentInfo {
+            title: Some("Q3 <Report> & Notes".to_string()),
+            author: Some("Jane Doe".to_string()),
+            keywords: vec!["rust".to_string(), "pdf".to_string()],
+            created: PdfDate::parse("2025-10-16"),
+            ..Default::default()
+        };
+        let packet = xmp_packet(&info, PdfDate::from_unix(0));
+        assert!(packet.contains("Q3 &lt;Report&gt; &amp; Notes"));
+        assert!(packet.contains("<rdf:li>Jane Doe</rdf:li>"));
+        assert!(packet.contains("<pdf:Keywords>rust, pdf</pdf:Keywords>"));
+        assert!(packet.contains("<xmp:CreateDate>2025-10-16</xmp:CreateDate>"));
+        assert!(!packet.contains("dc:description"));
+
+        // Blank fields are left out of both, as the Info dictionary does
+        let info = DocumentInfo {
+            title: Some("  ".to_string()),
+            author: Some(String::new()),
+            ..Default::default()
+        };
+        let packet = xmp_packet(&info, PdfDate::from_unix(0));
+        assert!(!packet.contains("dc:title"));
+        assert!(!packet.contains("dc:creator"));
+        let mut doc = Document::with_version("1.7");
+        let info_id = write_info(&mut doc, &info, PdfDate::from_unix(0));
+        let dict = doc.get_dictionary(info_id).unwrap();
+        assert!(!dict.has(b"Title") && !dict.has(b"Author"));
+    }
+}
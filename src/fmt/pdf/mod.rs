@@ -0,0 +1,5930 @@
+mod afm;
+mod code;
+mod font;
+mod formula;
+mod icc;
+mod image;
+mod list;
+mod metadata;
+mod outline;
+mod page;
+mod pdfa;
+mod running;
+mod subset;
+mod tags;
+
+use std::{
+    io::Cursor,
+    path::{
+        Path,
+        PathBuf,
+    },
+    rc::Rc,
+};
+
+use lopdf::{
+    Document,
+    Object,
+    ObjectId,
+    Stream,
+    content::{
+        Content,
+        Operation,
+    },
+    dictionary,
+};
+use pulldown_cmark::{
+    BlockQuoteKind,
+    CodeBlockKind,
+    Event,
+    Tag,
+    TagEnd,
+};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{
+        Color,
+        Style,
+        Theme,
+        ThemeSet,
+    },
+    parsing::SyntaxSet,
+};
+
+use crate::{
+    fmt::{
+        AlertStyle,
+        CODE_MUTED,
+        CodeKind,
+        CodeLine,
+        DIFF_ADDED,
+        DIFF_REMOVED,
+        Footnotes,
+        HeadingIds,
+        build_github_url,
+        find_toc_marker,
+        is_toc_marker,
+        math::{
+            self,
+            Node,
+        },
+        pdf::{
+            code::{
+                CodeOverflow,
+                HANG_CHARS,
+                fit_lines,
+            },
+            font::{
+                BuiltinFont,
+                FontSet,
+            },
+            formula::{
+                MathBox,
+                MathItem,
+                Segment,
+            },
+            image::Image,
+            list::{
+                Bullet,
+                ListMarker,
+                ListMarkers,
+            },
+            metadata::{
+                DocumentInfo,
+                PdfDate,
+                write_info,
+                write_xmp,
+            },
+            outline::{
+                OutlineEntry,
+                write_destinations,
+                write_outline,
+            },
+            page::PageLayout,
+            running::{
+                PageValues,
+                RunningText,
+                expand,
+            },
+            tags::{
+                StructureTree,
+                mark_artifacts,
+            },
+        },
+        resolve_path,
+        resolve_repo,
+        warn_broken_fragments,
+    },
+    layout::{
+        FILL,
+        Hyphenator,
+        Item,
+        LineBreakParams,
+        find_line_breaks,
+    },
+    parse::{
+        CodeBlockInfo,
+        FontConfig,
+        FrontMatter,
+        MarkdownParser,
+        PageConfig,
+        parse_front_matter,
+    },
+};
+
+/// PDF measurement unit (millimeters)
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+struct Mm(f32);
+
+impl Mm {
+    /// Convert millimeters to PDF points (1 mm = 2.83465 points)
+    fn to_points(self) -> f32 {
+        self.0 * 2.83465
+    }
+}
+
+/// Convert an 8-bit colour to PDF colour components
+fn rgb((r, g, b): (u8, u8, u8)) -> (f32, f32, f32) {
+    (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+}
+
+/// Encode a PDF text string, using UTF-16BE when it is not plain ASCII
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::string_literal(text)
+    } else {
+        let bytes = [0xFE, 0xFF]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+    }
+}
+
+impl From<f32> for Mm {
+    fn from(value: f32) -> Self {
+        Mm(value)
+    }
+}
+
+impl std::ops::Sub for Mm {
+    type Output = Mm;
+    fn sub(self, rhs: Mm) -> Mm {
+        Mm(self.0 - rhs.0)
+    }
+}
+
+impl std::ops::SubAssign for Mm {
+    fn sub_assign(&mut self, rhs: Mm) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl std::ops::Add for Mm {
+    type Output = Mm;
+    fn add(self, rhs: Mm) -> Mm {
+        Mm(self.0 + rhs.0)
+    }
+}
+
+impl std::ops::AddAssign for Mm {
+    fn add_assign(&mut self, rhs: Mm) {
+        self.0 += rhs.0;
+    }
+}
+
+impl std::ops::Mul<f32> for Mm {
+    type Output = Mm;
+    fn mul(self, rhs: f32) -> Mm {
+        Mm(self.0 * rhs)
+    }
+}
+
+impl std::ops::Div<f32> for Mm {
+    type Output = Mm;
+    fn div(self, rhs: f32) -> Mm {
+        Mm(self.0 / rhs)
+    }
+}
+
+/// Slide theme configuration
+#[derive(Clone, Debug)]
+struct SlideTheme {
+    background: BackgroundStyle,
+    text_color: (f32, f32, f32),
+    heading_color: (f32, f32, f32),
+}
+
+/// Theme category for organizing themes
+#[derive(Clone, Debug, PartialEq)]
+pub enum ThemeCategory {
+    Solid,
+    Gradient,
+    Radial,
+}
+
+/// A slide theme definition with metadata
+#[derive(Clone, Debug)]
+pub struct SlideThemeDefinition {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub category: ThemeCategory,
+    theme: SlideTheme,
+}
+
+#[derive(Clone, Debug)]
+enum BackgroundStyle {
+    Solid((f32, f32, f32)),
+    Gradient {
+        from: (f32, f32, f32),
+        to: (f32, f32, f32),
+        direction: GradientDirection,
+    },
+    Radial {
+        center_color: (f32, f32, f32),
+        edge_color: (f32, f32, f32),
+        center_x: f32, // 0.0 to 1.0 (percentage of width)
+        center_y: f32, // 0.0 to 1.0 (percentage of height)
+        radius: f32,   // 0.0 to 1.0 (percentage of diagonal)
+    },
+}
+
+#[derive(Clone, Debug)]
+enum GradientDirection {
+    TopToBottom,
+    BottomToTop,
+    LeftToRight,
+    RightToLeft,
+    TopLeftToBottomRight,
+    TopRightToBottomLeft,
+    BottomLeftToTopRight,
+    BottomRightToTopLeft,
+}
+
+impl SlideTheme {
+    fn get_by_name(name: &str) -> Self {
+        SLIDE_THEMES
+            .iter()
+            .find(|def| def.name == name)
+            .map(|def| def.theme.clone())
+            .unwrap_or_else(Self::default)
+    }
+
+    fn with_direction(mut self, direction: GradientDirection) -> Self {
+        if let BackgroundStyle::Gradient {
+            direction: ref mut d,
+            ..
+        } = self.background
+        {
+            *d = direction;
+        }
+        self
+    }
+}
+
+impl Default for SlideTheme {
+    fn default() -> Self {
+        Self {
+            background: BackgroundStyle::Solid((1.0, 1.0, 1.0)),
+            text_color: (0.0, 0.0, 0.0),
+            heading_color: (0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Static list of all available slide themes
+static SLIDE_THEMES: &[SlideThemeDefinition] = &[
+    // Solid themes
+    SlideThemeDefinition {
+        name: "light",
+        description: "White background with dark text (default)",
+        category: ThemeCategory::Solid,
+        theme: SlideTheme {
+            background: BackgroundStyle::Solid((1.0, 1.0, 1.0)),
+            text_color: (0.0, 0.0, 0.0),
+            heading_color: (0.0, 0.0, 0.0),
+        },
+    },
+    SlideThemeDefinition {
+        name: "dark",
+        description: "Dark gray background with light text",
+        category: ThemeCategory::Solid,
+        theme: SlideTheme {
+            background: BackgroundStyle::Solid((0.1, 0.1, 0.1)),
+            text_color: (0.9, 0.9, 0.9),
+            heading_color: (1.0, 1.0, 1.0),
+        },
+    },
+    SlideThemeDefinition {
+        name: "blue",
+        description: "Dark blue background with light blue text",
+        category: ThemeCategory::Solid,
+        theme: SlideTheme {
+            background: BackgroundStyle::Solid((0.1, 0.2, 0.3)),
+            text_color: (0.9, 0.95, 1.0),
+            heading_color: (0.4, 0.7, 1.0),
+        },
+    },
+    // Gradient themes
+    SlideThemeDefinition {
+        name: "gradient-blue",
+        description: "Light to dark blue gradient",
+        category: ThemeCategory::Gradient,
+        theme: SlideTheme {
+            background: BackgroundStyle::Gradient {
+                from: (0.1, 0.2, 0.4),
+                to: (0.05, 0.1, 0.2),
+                direction: GradientDirection::TopToBottom,
+            },
+            text_color: (0.9, 0.95, 1.0),
+            heading_color: (0.5, 0.8, 1.0),
+        },
+    },
+    SlideThemeDefinition {
+        name: "gradient-purple",
+        description: "Light to dark purple gradient",
+        category: ThemeCategory::Gradient,
+        theme: SlideTheme {
+            background: BackgroundStyle::Gradient {
+                from: (0.3, 0.1, 0.4),
+                to: (0.15, 0.05, 0.25),
+                direction: GradientDirection::TopToBottom,
+            },
+            text_color: (0.95, 0.9, 1.0),
+            heading_color: (0.8, 0.5, 1.0),
+        },
+    },
+    SlideThemeDefinition {
+        name: "gradient-sunset",
+        description: "Warm sunset color gradient",
+        category: ThemeCategory::Gradient,
+        theme: SlideTheme {
+            background: BackgroundStyle::Gradient {
+                from: (0.4, 0.2, 0.3),
+                to: (0.2, 0.1, 0.2),
+                direction: GradientDirection::TopToBottom,
+            },
+            text_color: (1.0, 0.95, 0.9),
+            heading_color: (1.0, 0.8, 0.6),
+        },
+    },
+    // Radial themes
+    SlideThemeDefinition {
+        name: "radial-spotlight",
+        description: "Spotlight effect centered on page",
+        category: ThemeCategory::Radial,
+        theme: SlideTheme {
+            background: BackgroundStyle::Radial {
+                center_color: (0.2, 0.25, 0.3),
+                edge_color: (0.05, 0.05, 0.1),
+                center_x: 0.5,
+                center_y: 0.5,
+                radius: 0.8,
+            },
+            text_color: (0.9, 0.95, 1.0),
+            heading_color: (0.5, 0.8, 1.0),
+        },
+    },
+    SlideThemeDefinition {
+        name: "radial-vignette",
+        description: "Vignette effect with dark edges",
+        category: ThemeCategory::Radial,
+        theme: SlideTheme {
+            background: BackgroundStyle::Radial {
+                center_color: (0.15, 0.15, 0.15),
+                edge_color: (0.0, 0.0, 0.0),
+                center_x: 0.5,
+                center_y: 0.5,
+                radius: 1.0,
+            },
+            text_color: (0.95, 0.95, 0.95),
+            heading_color: (1.0, 1.0, 1.0),
+        },
+    },
+    SlideThemeDefinition {
+        name: "radial-corner",
+        description: "Radial gradient from corner",
+        category: ThemeCategory::Radial,
+        theme: SlideTheme {
+            background: BackgroundStyle::Radial {
+                center_color: (0.3, 0.2, 0.4),
+                edge_color: (0.1, 0.05, 0.15),
+                center_x: 0.0,
+                center_y: 1.0,
+                radius: 1.2,
+            },
+            text_color: (0.95, 0.9, 1.0),
+            heading_color: (0.8, 0.6, 1.0),
+        },
+    },
+];
+
+/// Get all available slide theme definitions
+pub fn get_slide_themes() -> &'static [SlideThemeDefinition] {
+    SLIDE_THEMES
+}
+
+/// PDF builder that manages page operations and layout
+struct PdfBuilder {
+    doc: Document,
+    current_ops: Vec<Operation>,
+    y_position: Mm,
+    page_width: Mm,
+    page_height: Mm,
+    left_margin: Mm,
+    /// Right edge of the text area
+    right_margin: Mm,
+    /// Baseline of the first line on a page
+    content_top: Mm,
+    /// Lowest point text may reach before a page break
+    content_bottom: Mm,
+    line_height: Mm,
+    in_text_section: bool,
+    font_ids: std::collections::HashMap<String, ObjectId>,
+    fonts: FontSet,
+    shading_ids: std::collections::HashMap<String, ObjectId>,
+    page_ids: Vec<ObjectId>,
+    is_slide: bool,
+    slide_theme: SlideTheme,
+    text_align: TextAlign,
+    hyphenator: Option<Hyphenator>,
+    outline: Vec<OutlineEntry>,
+    /// Deepest heading level included in the outline
+    outline_depth: u8,
+    heading_ids: HeadingIds,
+    /// Link annotations on the current page
+    annotations: Vec<ObjectId>,
+    /// Targets of `#fragment` links, checked against the headings at the end
+    fragment_links: Vec<String>,
+    /// Deepest heading level listed in the table of contents
+    toc_depth: u8,
+    /// Problems found while laying out, reported once the layout is final
+    warnings: Vec<String>,
+    /// Header and footer, drawn on every page at finalize
+    running: RunningText,
+    link_color: (f32, f32, f32),
+    link_underline: bool,
+    metadata: DocumentInfo,
+    tags: StructureTree,
+    /// Image XObjects by source path, named `Im1`, `Im2`, ... in order
+    images: Vec<(PathBuf, ObjectId, Mm, Mm)>,
+    /// Block quotes being drawn, innermost last
+    quotes: Vec<Quote>,
+    list_markers: ListMarkers,
+    /// Text column of the page, outside any quote or list
+    column: (Mm, Mm),
+    /// Footnotes by number, from 1
+    notes: Vec<Note>,
+    note_placement: NotePlacement,
+    /// Footnotes drawn at the foot of the current page, and the height kept for them
+    page_notes: Vec<usize>,
+    page_notes_height: Mm,
+    /// What code blocks do with lines wider than the page, unless they say
+    code_overflow: CodeOverflow,
+}
+
+/// Colour of link text on pages
+const LINK_COLOR: (f32, f32, f32) = (0.0, 0.3, 0.7);
+
+/// A block quote or alert callout that is open
+#[derive(Clone, Debug)]
+struct Quote {
+    kind: Option<BlockQuoteKind>,
+    /// Text column edges outside the quote, restored when it ends
+    outer_left: Mm,
+    outer_right: Mm,
+    /// Top of the part of the quote on the current page
+    top: Mm,
+    /// Position in the page operations where that part's rule or box is
+    /// inserted, so it is painted behind the text
+    ops_index: usize,
+}
+
+/// Indent of the text in a block quote, with the rule in the gap
+const QUOTE_INDENT: Mm = Mm(6.0);
+/// Space between an alert box and its text
+const ALERT_PADDING: Mm = Mm(4.0);
+/// Width of the coloured bar down the left of an alert box
+const ALERT_BAR: Mm = Mm(1.2);
+
+/// Indent of a top-level list's markers from the text column
+const LIST_INDENT: Mm = Mm(5.0);
+/// Space from a bullet or checkbox to the item text
+const BULLET_WIDTH: Mm = Mm(6.0);
+/// Space from the start of an item number to the item text
+const NUMBER_WIDTH: Mm = Mm(7.0);
+
+/// Where footnotes are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum NotePlacement {
+    /// At the foot of the page with the first reference
+    #[default]
+    Page,
+    /// Together at the end of the document
+    End,
+}
+
+/// A footnote's text and how far it has been laid out
+#[derive(Clone, Debug)]
+struct Note {
+    segments: Vec<TextSegment>,
+    /// Structure element, created where the note is first referenced
+    element: Option<usize>,
+    /// Whether space was kept for the note at the foot of a page
+    placed: bool,
+}
+
+/// Size of footnote text at the foot of the page
+const NOTE_SIZE: f32 = 9.0;
+/// Distance between the baselines of footnote lines
+const NOTE_LINE: Mm = Mm(4.0);
+/// Space above the first footnote on a page, holding the separator rule
+const NOTE_RULE_SPACE: Mm = Mm(6.0);
+/// Indent of footnote text past its number
+const NOTE_INDENT: Mm = Mm(5.0);
+
+/// Size of code block text
+const CODE_SIZE: f32 = 10.0;
+/// Space between the edges of a code panel and its text
+const CODE_PADDING: Mm = Mm(3.0);
+/// Height of the filename header across the top of a code panel
+const CODE_HEADER: Mm = Mm(7.0);
+
+/// Colours of a code block panel, from the highlighting theme
+#[derive(Clone, Copy, Debug)]
+struct CodeColors {
+    background: (f32, f32, f32),
+    /// Filename header, shaded from the background towards the text
+    header: (f32, f32, f32),
+    text: (f32, f32, f32),
+    /// Line numbers in the gutter
+    gutter_text: (f32, f32, f32),
+    /// Band behind highlighted lines
+    highlight: (f32, f32, f32),
+    /// Bands behind added and removed diff lines
+    added: (f32, f32, f32),
+    removed: (f32, f32, f32),
+    /// `+` and `-` markers of diff lines
+    added_marker: (f32, f32, f32),
+    removed_marker: (f32, f32, f32),
+}
+
+impl CodeColors {
+    fn of(theme: &Theme) -> Self {
+        let color = |color: Option<Color>, default| color.map_or(default, |c| rgb((c.r, c.g, c.b)));
+        let mut background = color(theme.settings.background, (1.0, 1.0, 1.0));
+        // A white panel would not show on the page, so it takes the light
+        // grey of HTML code blocks instead
+        if background == (1.0, 1.0, 1.0) {
+            background = rgb((0xf6, 0xf8, 0xfa));
+        }
+        let text = color(theme.settings.foreground, (0.0, 0.0, 0.0));
+        let shade = |amount: f32| {
+            (
+                background.0 + (text.0 - background.0) * amount,
+                background.1 + (text.1 - background.1) * amount,
+                background.2 + (text.2 - background.2) * amount,
+            )
+        };
+        // Themes give the line highlight as a translucent colour
+        let highlight = theme.settings.line_highlight.map_or(shade(0.12), |c| {
+            let (alpha, c) = (c.a as f32 / 255.0, rgb((c.r, c.g, c.b)));
+            (
+                background.0 + (c.0 - background.0) * alpha,
+                background.1 + (c.1 - background.1) * alpha,
+                background.2 + (c.2 - background.2) * alpha,
+            )
+        });
+        // Light panels take the diff colours of HTML, dark ones a tint of
+        // the marker colour
+        let light = background.0 * 0.3 + background.1 * 0.59 + background.2 * 0.11 > 0.5;
+        let diff_band = |(band, marker): ((u8, u8, u8), (u8, u8, u8))| {
+            let marker = rgb(marker);
+            if light {
+                rgb(band)
+            } else {
+                (
+                    background.0 + (marker.0 - background.0) * 0.3,
+                    background.1 + (marker.1 - background.1) * 0.3,
+                    background.2 + (marker.2 - background.2) * 0.3,
+                )
+            }
+        };
+        Self {
+            background,
+            header: shade(0.1),
+            text,
+            gutter_text: color(theme.settings.gutter_foreground, shade(0.5)),
+            highlight,
+            added: diff_band(DIFF_ADDED),
+            removed: diff_band(DIFF_REMOVED),
+            added_marker: rgb(DIFF_ADDED.1),
+            removed_marker: rgb(DIFF_REMOVED.1),
+        }
+    }
+}
+
+/// How paragraph text is aligned
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum TextAlign {
+    /// Flush left with natural word spacing
+    #[default]
+    Ragged,
+    /// Flush with both margins, adjusting the space between words
+    Justify,
+}
+
+impl PdfBuilder {
+    fn new(slide_theme: SlideTheme, fonts: FontSet, layout: PageLayout) -> Self {
+        let doc = Document::with_version("1.5");
+
+        Self {
+            doc,
+            current_ops: Vec::new(),
+            y_position: layout.height - layout.top,
+            page_width: layout.width,
+            page_height: layout.height,
+            left_margin: layout.left,
+            right_margin: layout.width - layout.right,
+            content_top: layout.height - layout.top,
+            content_bottom: layout.bottom,
+            line_height: Mm(6.0),
+            in_text_section: false,
+            font_ids: std::collections::HashMap::new(),
+            fonts,
+            shading_ids: std::collections::HashMap::new(),
+            page_ids: Vec::new(),
+            is_slide: false,
+            slide_theme,
+            text_align: TextAlign::default(),
+            hyphenator: None,
+            outline: Vec::new(),
+            outline_depth: 6,
+            heading_ids: HeadingIds::default(),
+            annotations: Vec::new(),
+            fragment_links: Vec::new(),
+            toc_depth: 3,
+            warnings: Vec::new(),
+            running: RunningText::default(),
+            link_color: LINK_COLOR,
+            link_underline: false,
+            metadata: DocumentInfo::default(),
+            tags: StructureTree::default(),
+            images: Vec::new(),
+            quotes: Vec::new(),
+            list_markers: ListMarkers::default(),
+            column: (layout.left, layout.width - layout.right),
+            notes: Vec::new(),
+            note_placement: NotePlacement::default(),
+            page_notes: Vec::new(),
+            page_notes_height: Mm(0.0),
+            code_overflow: CodeOverflow::default(),
+        }
+    }
+
+    fn new_slide(slide_theme: SlideTheme, fonts: FontSet) -> Self {
+        let doc = Document::with_version("1.5");
+
+        // Links take the heading colour, which every theme keeps readable
+        let link_color = slide_theme.heading_color;
+        let layout = PageLayout::slide();
+
+        Self {
+            doc,
+            current_ops: Vec::new(),
+            y_position: layout.height - layout.top,
+            page_width: layout.width,
+            page_height: layout.height,
+            left_margin: layout.left,
+            right_margin: layout.width - layout.right,
+            content_top: layout.height - layout.top,
+            content_bottom: layout.bottom,
+            line_height: Mm(6.0),
+            in_text_section: false,
+            font_ids: std::collections::HashMap::new(),
+            fonts,
+            shading_ids: std::collections::HashMap::new(),
+            page_ids: Vec::new(),
+            is_slide: true,
+            slide_theme,
+            text_align: TextAlign::default(),
+            hyphenator: None,
+            outline: Vec::new(),
+            outline_depth: 6,
+            heading_ids: HeadingIds::default(),
+            annotations: Vec::new(),
+            fragment_links: Vec::new(),
+            toc_depth: 3,
+            warnings: Vec::new(),
+            running: RunningText::default(),
+            link_color,
+            link_underline: false,
+            metadata: DocumentInfo::default(),
+            tags: StructureTree::default(),
+            images: Vec::new(),
+            quotes: Vec::new(),
+            list_markers: ListMarkers::default(),
+            column: (layout.left, layout.width - layout.right),
+            notes: Vec::new(),
+            note_placement: NotePlacement::default(),
+            page_notes: Vec::new(),
+            page_notes_height: Mm(0.0),
+            code_overflow: CodeOverflow::default(),
+        }
+    }
+
+    fn ensure_font(&mut self, font: BuiltinFont) -> String {
+        let font_key = font.to_font_key();
+        if !self.font_ids.contains_key(&font_key) {
+            let font_id = if self.fonts.is_embedded(font) {
+                // Written at finalize, once every glyph in the subset is known
+                self.doc.new_object_id()
+            } else {
+                let mut font_dict = dictionary! {
+                    "Type" => "Font",
+                    "Subtype" => "Type1",
+                    "BaseFont" => font.to_pdf_name(),
+                };
+                // Symbol keeps its built-in encoding
+                if font != BuiltinFont::Symbol {
+                    font_dict.set("Encoding", "WinAnsiEncoding");
+                }
+                self.doc.add_object(font_dict)
+            };
+            self.font_ids.insert(font_key.clone(), font_id);
+        }
+        font_key
+    }
+
+    /// Push a `Tj` operation showing text in the given font
+    fn show_text(&mut self, text: &str, font: BuiltinFont) {
+        let encoded = self.fonts.encode(text, font);
+        self.current_ops.push(Operation::new("Tj", vec![encoded]));
+    }
+
+    /// Start tagging what is drawn next as content of the innermost open
+    /// structure element
+    fn begin_marked(&mut self) {
+        if let Some(operation) = self.tags.mark(self.page_ids.len()) {
+            self.current_ops.push(operation);
+        }
+    }
+
+    fn end_marked(&mut self) {
+        if let Some(operation) = self.tags.unmark() {
+            self.current_ops.push(operation);
+        }
+    }
+
+    fn start_text_section(&mut self) {
+        if !self.in_text_section {
+            self.current_ops.push(Operation::new("BT", vec![]));
+            self.in_text_section = true;
+        }
+    }
+
+    fn end_text_section(&mut self) {
+        if self.in_text_section {
+            self.current_ops.push(Operation::new("ET", vec![]));
+            self.in_text_section = false;
+        }
+    }
+
+    fn check_page_break(&mut self, needed_height: Mm) {
+        if self.y_position - needed_height < self.content_bottom + self.page_notes_height {
+            self.new_page();
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.end_text_section();
+
+        // Quotes are drawn to the foot of this page and continue on the next
+        let foot = self.content_bottom + self.page_notes_height - self.line_height * 0.5;
+        let bottom = Mm(self.quote_bottom().0.max(foot.0));
+        for idx in (0..self.quotes.len()).rev() {
+            self.draw_quote_decoration(idx, bottom);
+        }
+
+        if !self.current_ops.is_empty() {
+            self.draw_page_notes();
+            self.add_page_to_doc();
+        }
+
+        self.y_position = self.content_top;
+        self.in_text_section = false;
+
+        // Draw background if in slide mode and not using white background
+        if self.is_slide {
+            self.draw_background();
+        }
+
+        let top = self.content_top + self.line_height * 0.7;
+        for quote in &mut self.quotes {
+            quote.top = top;
+            quote.ops_index = self.current_ops.len();
+        }
+    }
+
+    /// Height footnotes take at the foot of the page when `numbers` join the
+    /// ones already there
+    fn notes_height(&self, numbers: &[usize]) -> Mm {
+        if numbers.is_empty() {
+            return Mm(0.0);
+        }
+        let rule = if self.page_notes.is_empty() {
+            NOTE_RULE_SPACE
+        } else {
+            Mm(0.0)
+        };
+        numbers.iter().fold(rule, |height, &number| {
+            height + NOTE_LINE * self.note_lines(number) as f32
+        })
+    }
+
+    /// Number of lines a footnote takes at the foot of the page
+    fn note_lines(&self, number: usize) -> usize {
+        let words = segments_to_words(&self.notes[number - 1].segments, NOTE_SIZE, &self.fonts);
+        if words.is_empty() {
+            return 1;
+        }
+        let (items, _) = words_to_items(
+            &words,
+            NOTE_SIZE,
+            &self.fonts,
+            TextAlign::Ragged,
+            self.hyphenator.as_ref(),
+        );
+        let width = self.column.1 - self.column.0 - NOTE_INDENT;
+        find_line_breaks(&items, &LineBreakParams::new(width.0))
+            .len()
+            .max(1)
+    }
+
+    /// Keep space at the foot of the page for footnotes referenced on the
+    /// line about to be drawn
+    fn keep_note_space(&mut self, numbers: &[usize]) {
+        for &number in numbers {
+            self.page_notes_height += self.notes_height(&[number]);
+            self.page_notes.push(number);
+            self.notes[number - 1].placed = true;
+        }
+    }
+
+    /// Create the structure element of a footnote where it is first referenced
+    fn add_note_element(&mut self, number: usize) {
+        if self.notes[number - 1].element.is_none() {
+            // The note follows the text up to its reference
+            self.end_marked();
+            let element = self.tags.begin("Note");
+            self.tags.end();
+            self.notes[number - 1].element = Some(element);
+        }
+    }
+
+    /// Draw the footnotes referenced on this page in the space kept for them
+    fn draw_page_notes(&mut self) {
+        if self.page_notes.is_empty() {
+            return;
+        }
+        let numbers = std::mem::take(&mut self.page_notes);
+        let top = self.content_bottom + std::mem::replace(&mut self.page_notes_height, Mm(0.0));
+        let (left, right) = self.column;
+        self.end_text_section();
+
+        // Short rule between the text and the notes
+        let color = if self.is_slide {
+            self.slide_theme.text_color
+        } else {
+            (0.5, 0.5, 0.5)
+        };
+        let rule_y = top - Mm(2.5);
+        self.current_ops.push(Operation::new("q", vec![]));
+        self.current_ops.push(Operation::new(
+            "RG",
+            vec![color.0.into(), color.1.into(), color.2.into()],
+        ));
+        self.draw_line(left, rule_y, left + (right - left) * 0.3, rule_y, 0.5);
+        self.current_ops.push(Operation::new("Q", vec![]));
+
+        let y_position = self.y_position;
+        self.y_position = top - NOTE_RULE_SPACE;
+        for number in numbers {
+            self.draw_note(number, left, right, NOTE_SIZE, NOTE_LINE, false);
+        }
+        self.y_position = y_position;
+    }
+
+    /// Print the footnotes not placed on a page under a Notes heading at
+    /// the end of the document
+    fn draw_endnotes(&mut self) {
+        let numbers: Vec<usize> = (1..=self.notes.len())
+            .filter(|&number| !self.notes[number - 1].placed)
+            .collect();
+        if numbers.is_empty() {
+            return;
+        }
+
+        let size = 20.0;
+        self.move_down(self.line_height * 1.25);
+        self.check_page_break(Mm(size * 0.5));
+        let top = self.y_position + Mm(size / 2.83465);
+        self.add_heading(2, None, "Notes", top);
+        let color = self.is_slide.then_some(self.slide_theme.heading_color);
+        self.tags.begin("H2");
+        self.write_text_at_with_color(
+            "Notes",
+            BuiltinFont::HelveticaBold,
+            size,
+            self.left_margin,
+            self.y_position,
+            color,
+        );
+        self.tags.end();
+        self.move_down(self.line_height * 1.25);
+
+        for number in numbers {
+            let (left, right) = (self.left_margin, self.right_margin);
+            self.draw_note(number, left, right, 10.0, self.line_height * 0.8, true);
+            self.move_down(self.line_height * 0.3);
+        }
+    }
+
+    /// Draw a footnote's number and text at the current position
+    fn draw_note(
+        &mut self,
+        number: usize,
+        left: Mm,
+        right: Mm,
+        size: f32,
+        line_advance: Mm,
+        page_breaks: bool,
+    ) {
+        let note = &self.notes[number - 1];
+        let words = segments_to_words(&note.segments, size, &self.fonts);
+        match note.element {
+            Some(element) => self.tags.resume(element),
+            None => {
+                self.tags.begin("Note");
+            }
+        }
+        if page_breaks {
+            self.check_page_break(self.line_height);
+        }
+
+        self.tags.begin("Lbl");
+        self.write_text_at(
+            &number.to_string(),
+            BuiltinFont::Helvetica,
+            size,
+            left,
+            self.y_position,
+        );
+        self.tags.end();
+        if words.is_empty() {
+            self.move_down(line_advance);
+        } else {
+            let x = left + NOTE_INDENT;
+            self.write_lines(
+                &words,
+                x,
+                size,
+                right - x,
+                TextAlign::Ragged,
+                line_advance,
+                page_breaks,
+            );
+        }
+        self.tags.end();
+    }
+
+    /// Start a block quote, indenting the text column. Alerts get a
+    /// coloured box whose first line is an icon and the alert title.
+    fn begin_quote(&mut self, kind: Option<BlockQuoteKind>) {
+        self.end_text_section();
+        self.quotes.push(Quote {
+            kind,
+            outer_left: self.left_margin,
+            outer_right: self.right_margin,
+            top: self.y_position,
+            ops_index: self.current_ops.len(),
+        });
+        self.tags.begin("BlockQuote");
+
+        let Some(kind) = kind else {
+            self.left_margin += QUOTE_INDENT;
+            return;
+        };
+        self.left_margin += ALERT_BAR + ALERT_PADDING;
+        self.right_margin -= ALERT_PADDING;
+
+        let style = AlertStyle::of(kind);
+        let color = rgb(style.color);
+        let size = 11.0;
+        self.move_down(ALERT_PADDING + Mm(size / 2.83465 * 0.7));
+        self.check_page_break(self.line_height);
+
+        // Round icon, centred on the title's cap height
+        let radius = Mm(size / 2.83465 * 0.5);
+        let center_x = self.left_margin + radius;
+        let center_y = self.y_position + Mm(size / 2.83465 * 0.36);
+        self.current_ops.push(Operation::new("q", vec![]));
+        self.current_ops.push(Operation::new(
+            "rg",
+            vec![color.0.into(), color.1.into(), color.2.into()],
+        ));
+        self.circle(center_x, center_y, radius, "f");
+        self.current_ops.push(Operation::new("Q", vec![]));
+
+        let icon = style.icon.to_string();
+        let icon_size = size * 0.8;
+        let icon_width = self
+            .fonts
+            .text_width(&icon, BuiltinFont::HelveticaBold, icon_size);
+        self.tags.begin_artifact();
+        self.write_text_at_with_color(
+            &icon,
+            BuiltinFont::HelveticaBold,
+            icon_size,
+            center_x - icon_width / 2.0,
+            center_y - Mm(icon_size / 2.83465 * 0.36),
+            Some((1.0, 1.0, 1.0)),
+        );
+        self.tags.end_artifact();
+
+        self.tags.begin("P");
+        self.write_text_at_with_color(
+            style.title,
+            BuiltinFont::HelveticaBold,
+            size,
+            self.left_margin + radius * 2.0 + Mm(2.0),
+            self.y_position,
+            Some(color),
+        );
+        self.tags.end();
+        self.move_down(self.line_height * 0.5);
+    }
+
+    /// Finish the innermost block quote and restore the text column
+    fn end_quote(&mut self) {
+        let Some(kind) = self.quotes.last().map(|quote| quote.kind) else {
+            return;
+        };
+        self.end_text_section();
+        if kind.is_some() {
+            self.move_down(ALERT_PADDING - self.line_height * 0.5);
+        }
+        let bottom = self.quote_bottom();
+        self.draw_quote_decoration(self.quotes.len() - 1, bottom);
+
+        if let Some(quote) = self.quotes.pop() {
+            self.left_margin = quote.outer_left;
+            self.right_margin = quote.outer_right;
+        }
+        self.tags.end_if("BlockQuote");
+        self.move_down(self.line_height * 0.5);
+    }
+
+    /// Lowest point of the text drawn so far, leaving room for descenders
+    fn quote_bottom(&self) -> Mm {
+        self.y_position + self.line_height * 0.75
+    }
+
+    /// Paint the rule of a plain quote, or the box of an alert, for the part
+    /// of the quote on the current page
+    fn draw_quote_decoration(&mut self, idx: usize, bottom: Mm) {
+        let quote = &self.quotes[idx];
+        let height = quote.top - bottom;
+        if height.0 <= 0.0 {
+            return;
+        }
+
+        let rect = |x: Mm, width: Mm| {
+            Operation::new(
+                "re",
+                vec![
+                    x.to_points().into(),
+                    bottom.to_points().into(),
+                    width.to_points().into(),
+                    height.to_points().into(),
+                ],
+            )
+        };
+        let mut operations = vec![Operation::new("q", vec![])];
+        match quote.kind {
+            Some(kind) => {
+                let style = AlertStyle::of(kind);
+                let background = rgb(style.background());
+                let color = rgb(style.color);
+                let width = quote.outer_right - quote.outer_left;
+                operations.extend([
+                    Operation::new(
+                        "rg",
+                        vec![
+                            background.0.into(),
+                            background.1.into(),
+                            background.2.into(),
+                        ],
+                    ),
+                    rect(quote.outer_left, width),
+                    Operation::new("f", vec![]),
+                    Operation::new("rg", vec![color.0.into(), color.1.into(), color.2.into()]),
+                    rect(quote.outer_left, ALERT_BAR),
+                    Operation::new("f", vec![]),
+                ]);
+            }
+            None => {
+                let color = if self.is_slide {
+                    self.slide_theme.text_color
+                } else {
+                    (0.8, 0.8, 0.8)
+                };
+                operations.extend([
+                    Operation::new("rg", vec![color.0.into(), color.1.into(), color.2.into()]),
+                    rect(quote.outer_left + Mm(1.0), Mm(1.0)),
+                    Operation::new("f", vec![]),
+                ]);
+            }
+        }
+        operations.push(Operation::new("Q", vec![]));
+
+        let at = quote.ops_index.min(self.current_ops.len());
+        self.current_ops.splice(at..at, operations);
+    }
+
+    /// Add a circle path, painted with `paint` (`f` to fill, `S` to stroke)
+    fn circle(&mut self, x: Mm, y: Mm, radius: Mm, paint: &str) {
+        // Four Bézier quarter arcs, with the usual control point distance
+        let (x, y, r) = (x.to_points(), y.to_points(), radius.to_points());
+        let k = r * 0.5523;
+        let curve =
+            |points: [f32; 6]| Operation::new("c", points.into_iter().map(Object::from).collect());
+        self.current_ops.extend([
+            Operation::new("m", vec![(x + r).into(), y.into()]),
+            curve([x + r, y + k, x + k, y + r, x, y + r]),
+            curve([x - k, y + r, x - r, y + k, x - r, y]),
+            curve([x - r, y - k, x - k, y - r, x, y - r]),
+            curve([x + k, y - r, x + r, y - k, x + r, y]),
+            Operation::new(paint, vec![]),
+        ]);
+    }
+
+    fn draw_background(&mut self) {
+        let background = self.slide_theme.background.clone();
+        match background {
+            BackgroundStyle::Solid(color) => {
+                // Only draw background if it's not white
+                if color != (1.0, 1.0, 1.0) {
+                    self.current_ops.push(Operation::new("q", vec![]));
+
+                    // Set fill color
+                    self.current_ops.push(Operation::new(
+                        "rg",
+                        vec![color.0.into(), color.1.into(), color.2.into()],
+                    ));
+
+                    // Draw filled rectangle covering entire page
+                    self.current_ops.push(Operation::new(
+                        "re",
+                        vec![
+                            0.0.into(),
+                            0.0.into(),
+                            self.page_width.to_points().into(),
+                            self.page_height.to_points().into(),
+                        ],
+                    ));
+
+                    self.current_ops.push(Operation::new("f", vec![])); // Fill
+                    self.current_ops.push(Operation::new("Q", vec![]));
+                }
+            }
+            BackgroundStyle::Gradient {
+                from,
+                to,
+                direction,
+            } => {
+                self.draw_gradient(from, to, &direction);
+            }
+            BackgroundStyle::Radial {
+                center_color,
+                edge_color,
+                center_x,
+                center_y,
+                radius,
+            } => {
+                self.draw_radial_gradient(center_color, edge_color, center_x, center_y, radius);
+            }
+        }
+    }
+
+    fn draw_gradient(
+        &mut self,
+        from: (f32, f32, f32),
+        to: (f32, f32, f32),
+        direction: &GradientDirection,
+    ) {
+        // Create a key for this gradient to reuse if already created
+        let key = format!("{:?}_{:?}_{:?}", from, to, direction);
+
+        if !self.shading_ids.contains_key(&key) {
+            // Calculate coordinates based on direction
+            let (x0, y0, x1, y1) = match direction {
+                GradientDirection::TopToBottom => (0.0, self.page_height.to_points(), 0.0, 0.0),
+                GradientDirection::BottomToTop => (0.0, 0.0, 0.0, self.page_height.to_points()),
+                GradientDirection::LeftToRight => (0.0, 0.0, self.page_width.to_points(), 0.0),
+                GradientDirection::RightToLeft => (self.page_width.to_points(), 0.0, 0.0, 0.0),
+                GradientDirection::TopLeftToBottomRight => (
+                    0.0,
+                    self.page_height.to_points(),
+                    self.page_width.to_points(),
+                    0.0,
+                ),
+                GradientDirection::TopRightToBottomLeft => (
+                    self.page_width.to_points(),
+                    self.page_height.to_points(),
+                    0.0,
+                    0.0,
+                ),
+                GradientDirection::BottomLeftToTopRight => (
+                    0.0,
+                    0.0,
+                    self.page_width.to_points(),
+                    self.page_height.to_points(),
+                ),
+                GradientDirection::BottomRightToTopLeft => (
+                    self.page_width.to_points(),
+                    0.0,
+                    0.0,
+                    self.page_height.to_points(),
+                ),
+            };
+
+            // Create the shading function (Type 2 = exponential interpolation)
+            let function_dict = dictionary! {
+                "FunctionType" => 2,
+                "Domain" => Object::Array(vec![0.0.into(), 1.0.into()]),
+                "C0" => Object::Array(vec![from.0.into(), from.1.into(), from.2.into()]),
+                "C1" => Object::Array(vec![to.0.into(), to.1.into(), to.2.into()]),
+                "N" => 1.0, // Linear interpolation
+            };
+            let function_id = self.doc.add_object(function_dict);
+
+            // Create the shading dictionary (Type 2 = axial/linear gradient)
+            let shading_dict = dictionary! {
+                "ShadingType" => 2,
+                "ColorSpace" => "DeviceRGB",
+                "Coords" => Object::Array(vec![x0.into(), y0.into(), x1.into(), y1.into()]),
+                "Function" => Object::Reference(function_id),
+                "Extend" => Object::Array(vec![Object::Boolean(true), Object::Boolean(true)]), // Extend colors beyond gradient range
+            };
+            let shading_id = self.doc.add_object(shading_dict);
+            self.shading_ids.insert(key.clone(), shading_id);
+        }
+
+        let _shading_id = self.shading_ids[&key];
+        let shading_name = format!("Sh{}", self.shading_ids.len());
+
+        // Use the shading operator to paint the gradient
+        self.current_ops.push(Operation::new(
+            "sh",
+            vec![Object::Name(shading_name.as_bytes().to_vec())],
+        ));
+    }
+
+    fn draw_radial_gradient(
+        &mut self,
+        center_color: (f32, f32, f32),
+        edge_color: (f32, f32, f32),
+        center_x: f32,
+        center_y: f32,
+        radius: f32,
+    ) {
+        // Create a key for this radial gradient
+        let key = format!(
+            "radial_{:?}_{:?}_{}_{}_{}",
+            center_color, edge_color, center_x, center_y, radius
+        );
+
+        if !self.shading_ids.contains_key(&key) {
+            // Calculate center position and radius in points
+            let cx = self.page_width.to_points() * center_x;
+            let cy = self.page_height.to_points() * center_y;
+
+            // Calculate diagonal for radius scaling
+            let diagonal =
+                (self.page_width.to_points().powi(2) + self.page_height.to_points().powi(2)).sqrt();
+            let r = diagonal * radius;
+
+            // Create the shading function (Type 2 = exponential interpolation)
+            let function_dict = dictionary! {
+                "FunctionType" => 2,
+                "Domain" => Object::Array(vec![0.0.into(), 1.0.into()]),
+                "C0" => Object::Array(vec![center_color.0.into(), center_color.1.into(), center_color.2.into()]),
+                "C1" => Object::Array(vec![edge_color.0.into(), edge_color.1.into(), edge_color.2.into()]),
+                "N" => 1.0, // Linear interpolation
+            };
+            let function_id = self.doc.add_object(function_dict);
+
+            // Create the radial shading dictionary (Type 3 = radial gradient)
+            let shading_dict = dictionary! {
+                "ShadingType" => 3,
+                "ColorSpace" => "DeviceRGB",
+                "Coords" => Object::Array(vec![
+                    cx.into(), cy.into(), 0.0.into(),  // Start circle: center + radius 0
+                    cx.into(), cy.into(), r.into(),     // End circle: center + outer radius
+                ]),
+                "Function" => Object::Reference(function_id),
+                "Extend" => Object::Array(vec![Object::Boolean(true), Object::Boolean(true)]),
+            };
+            let shading_id = self.doc.add_object(shading_dict);
+            self.shading_ids.insert(key.clone(), shading_id);
+        }
+
+        let _shading_id = self.shading_ids[&key];
+        let shading_name = format!("Sh{}", self.shading_ids.len());
+
+        // Use the shading operator to paint the radial gradient
+        self.current_ops.push(Operation::new(
+            "sh",
+            vec![Object::Name(shading_name.as_bytes().to_vec())],
+        ));
+    }
+
+    fn add_page_to_doc(&mut self) {
+        // Create content stream
+        let operations = mark_artifacts(std::mem::take(&mut self.current_ops));
+        let content = Content { operations };
+        let content_data = content.encode().unwrap();
+
+        let content_stream = Stream::new(dictionary! {}, content_data);
+        let content_id = self.doc.add_object(content_stream);
+
+        // Create resources dictionary with fonts
+        let mut fonts_dict = lopdf::Dictionary::new();
+        for (font_key, font_id) in &self.font_ids {
+            fonts_dict.set(font_key.as_str(), Object::Reference(*font_id));
+        }
+
+        // Create shading dictionary for resources
+        let mut shading_dict = lopdf::Dictionary::new();
+        for (idx, (_key, shading_id)) in self.shading_ids.iter().enumerate() {
+            let shading_name = format!("Sh{}", idx + 1);
+            shading_dict.set(shading_name.as_str(), Object::Reference(*shading_id));
+        }
+
+        let mut resources = dictionary! {
+            "Font" => Object::Dictionary(fonts_dict),
+        };
+
+        if !shading_dict.is_empty() {
+            resources.set("Shading", Object::Dictionary(shading_dict));
+        }
+
+        if !self.images.is_empty() {
+            let mut xobjects = lopdf::Dictionary::new();
+            for (idx, (_, image_id, _, _)) in self.images.iter().enumerate() {
+                xobjects.set(format!("Im{}", idx + 1), Object::Reference(*image_id));
+            }
+            resources.set("XObject", Object::Dictionary(xobjects));
+        }
+
+        // Create page dictionary
+        let mut page_dict = dictionary! {
+            "Type" => "Page",
+            "MediaBox" => vec![
+                0.into(),
+                0.into(),
+                self.page_width.to_points().into(),
+                self.page_height.to_points().into(),
+            ],
+            "Contents" => Object::Reference(content_id),
+            "Resources" => resources,
+            // Index of the page's marked content in the structure parent tree
+            "StructParents" => self.page_ids.len() as i64,
+        };
+
+        if !self.annotations.is_empty() {
+            let annotations: Vec<Object> = std::mem::take(&mut self.annotations)
+                .into_iter()
+                .map(Object::Reference)
+                .collect();
+            page_dict.set("Annots", annotations);
+            // Tab through annotations in structure order
+            page_dict.set("Tabs", "S");
+        }
+
+        let page_id = self.doc.add_object(page_dict);
+
+        // Track page IDs
+        self.page_ids.push(page_id);
+    }
+
+    fn finalize(mut self) -> Document {
+        if !self.current_ops.is_empty() {
+            self.end_text_section();
+            self.draw_page_notes();
+            self.add_page_to_doc();
+        }
+
+        // Build page tree
+        let pages_refs: Vec<Object> = self
+            .page_ids
+            .iter()
+            .map(|id| Object::Reference(*id))
+            .collect();
+
+        let pages_dict = dictionary! {
+            "Type" => "Pages",
+            "Count" => self.page_ids.len() as i64,
+            "Kids" => pages_refs,
+        };
+        let pages_id = self.doc.add_object(pages_dict);
+
+        self.draw_running_text();
+        self.fonts.write_to(&mut self.doc, &self.font_ids);
+
+        // Update each page's Parent
+        for page_id in &self.page_ids {
+            if let Ok(page_obj) = self.doc.get_object_mut(*page_id)
+                && let Object::Dictionary(dict) = page_obj
+            {
+                dict.set("Parent", Object::Reference(pages_id));
+            }
+        }
+
+        // Set catalog
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => Object::Reference(pages_id),
+        };
+        if let Some(outline_id) = write_outline(
+            &mut self.doc,
+            &self.outline,
+            &self.page_ids,
+            self.left_margin,
+            self.outline_depth,
+        ) {
+            catalog.set("Outlines", Object::Reference(outline_id));
+            catalog.set("PageMode", "UseOutlines");
+        }
+        if let Some(dests_id) = write_destinations(
+            &mut self.doc,
+            &self.outline,
+            &self.page_ids,
+            self.left_margin,
+        ) {
+            catalog.set("Dests", Object::Reference(dests_id));
+        }
+        warn_broken_fragments(
+            self.fragment_links.iter().map(String::as_str),
+            &self.heading_ids,
+        );
+
+        if let Some(structure_id) = self.tags.write_to(&mut self.doc, &self.page_ids) {
+            catalog.set("StructTreeRoot", Object::Reference(structure_id));
+            catalog.set("MarkInfo", dictionary! { "Marked" => true });
+        }
+        let lang = self.metadata.lang.as_deref().unwrap_or("en");
+        catalog.set("Lang", Object::string_literal(lang));
+
+        let modified = PdfDate::now();
+        let info_id = write_info(&mut self.doc, &self.metadata, modified);
+        self.doc.trailer.set("Info", Object::Reference(info_id));
+        let metadata_id = write_xmp(&mut self.doc, &self.metadata, modified);
+        catalog.set("Metadata", Object::Reference(metadata_id));
+        if self.metadata.title.is_some() {
+            // Show the title in the viewer's title bar instead of the file name
+            catalog.set(
+                "ViewerPreferences",
+                dictionary! { "DisplayDocTitle" => true },
+            );
+        }
+
+        let catalog_id = self.doc.add_object(catalog);
+        self.doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        self.doc
+    }
+
+    /// Make a rectangle of the current page open `url`, or jump to a
+    /// heading when `url` is a `#fragment`
+    ///
+    /// The annotation becomes part of the structure `element`, normally a Link.
+    fn add_link(&mut self, x1: Mm, y1: Mm, x2: Mm, y2: Mm, url: &str, element: usize) {
+        let action = match url.strip_prefix('#') {
+            Some(fragment) => {
+                self.fragment_links.push(url.to_string());
+                dictionary! {
+                    "S" => "GoTo",
+                    "D" => Object::Name(fragment.as_bytes().to_vec()),
+                }
+            }
+            None => dictionary! {
+                "S" => "URI",
+                "URI" => Object::string_literal(url),
+            },
+        };
+        let annotation = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![
+                x1.to_points().into(),
+                y1.to_points().into(),
+                x2.to_points().into(),
+                y2.to_points().into(),
+            ],
+            "Border" => vec![0.into(), 0.into(), 0.into()],
+            // Print flag, so the annotation is kept when the page is printed
+            "F" => 4,
+            "A" => action,
+        };
+        let annotation_id = self.doc.add_object(annotation);
+        self.annotations.push(annotation_id);
+        self.tags
+            .add_annotation(element, self.page_ids.len(), annotation_id);
+    }
+
+    /// Draw link styling under a run of text on the baseline `y` and make it clickable
+    fn finish_link(&mut self, start: Mm, end: Mm, y: Mm, size: f32, url: &str, element: usize) {
+        let size = Mm(size / 2.83465);
+        if self.link_underline {
+            let underline_y = y - size * 0.12;
+            let (r, g, b) = self.link_color;
+            self.current_ops.push(Operation::new("q", vec![]));
+            self.current_ops
+                .push(Operation::new("RG", vec![r.into(), g.into(), b.into()]));
+            self.draw_line(start, underline_y, end, underline_y, 0.5);
+            self.current_ops.push(Operation::new("Q", vec![]));
+        }
+        self.add_link(start, y - size * 0.25, end, y + size * 0.85, url, element);
+    }
+
+    /// Draw the header and footer on every page that has been written
+    ///
+    /// Runs once the layout is done, so `{pages}` is known. The text goes in
+    /// a second content stream on each page.
+    fn draw_running_text(&mut self) {
+        let header = self.running.header.clone();
+        let footer = self.running.footer.clone();
+        if header.is_none() && footer.is_none() {
+            return;
+        }
+
+        let font = BuiltinFont::Helvetica;
+        let size = 9.0;
+        let color = if self.is_slide {
+            self.slide_theme.text_color
+        } else {
+            (0.4, 0.4, 0.4)
+        };
+
+        let pages = self.page_ids.len();
+        for (idx, page_id) in self.page_ids.clone().into_iter().enumerate() {
+            let section = self
+                .outline
+                .iter()
+                .take_while(|entry| entry.page <= idx)
+                .filter(|entry| entry.level <= 2)
+                .last()
+                .map_or("", |entry| entry.title.as_str());
+            let values = PageValues {
+                title: &self.running.title,
+                author: &self.running.author,
+                date: &self.running.date,
+                page: idx + 1,
+                pages,
+                section,
+            };
+
+            let mut lines = Vec::new();
+            // Centred in the top and bottom margins
+            let header_y = (self.page_height + self.content_top) / 2.0;
+            let footer_y = self.content_bottom / 2.0;
+            for (template, y) in [(&header, header_y), (&footer, footer_y)] {
+                let Some(template) = template else {
+                    continue;
+                };
+                if template.skip_first_page && idx == 0 {
+                    continue;
+                }
+                let slots = [&template.left, &template.center, &template.right];
+                for (slot, text) in slots.into_iter().enumerate() {
+                    if let Some(text) = text {
+                        lines.push((slot, y, expand(text, &values)));
+                    }
+                }
+            }
+
+            let font_key = self.ensure_font(font);
+            let mut operations = Vec::new();
+            for (slot, y, text) in lines {
+                let width = self.fonts.text_width(&text, font, size);
+                let x = match slot {
+                    0 => self.left_margin,
+                    1 => (self.page_width - width) / 2.0,
+                    _ => self.right_margin - width,
+                };
+                operations.extend([
+                    Operation::new("BT", vec![]),
+                    Operation::new("Td", vec![x.to_points().into(), y.to_points().into()]),
+                    Operation::new("rg", vec![color.0.into(), color.1.into(), color.2.into()]),
+                    Operation::new("Tf", vec![font_key.clone().into(), size.into()]),
+                    Operation::new("Tj", vec![self.fonts.encode(&text, font)]),
+                    Operation::new("ET", vec![]),
+                ]);
+            }
+            if operations.is_empty() {
+                continue;
+            }
+            operations.insert(
+                0,
+                Operation::new(
+                    "BDC",
+                    vec![
+                        "Artifact".into(),
+                        Object::Dictionary(dictionary! { "Type" => "Pagination" }),
+                    ],
+                ),
+            );
+            operations.push(Operation::new("EMC", vec![]));
+
+            let content = Content { operations }.encode().unwrap();
+            let stream_id = self.doc.add_object(Stream::new(dictionary! {}, content));
+            if let Ok(Object::Dictionary(page)) = self.doc.get_object_mut(page_id) {
+                if let Ok(body_id) = page.get(b"Contents").and_then(Object::as_reference) {
+                    page.set(
+                        "Contents",
+                        vec![Object::Reference(body_id), Object::Reference(stream_id)],
+                    );
+                }
+                // Pages written before the font was first used do not list it
+                // yet, and a page without text may have no fonts at all
+                if !page.has(b"Resources") {
+                    page.set("Resources", lopdf::Dictionary::new());
+                }
+                if let Ok(Object::Dictionary(resources)) = page.get_mut(b"Resources") {
+                    if !resources.has(b"Font") {
+                        resources.set("Font", lopdf::Dictionary::new());
+                    }
+                    if let Ok(Object::Dictionary(fonts)) = resources.get_mut(b"Font") {
+                        fonts.set(
+                            font_key.as_str(),
+                            Object::Reference(self.font_ids[&font_key]),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+
+    /// Draw a table of contents with dot leaders and page numbers, each line
+    /// linking to its heading
+    fn draw_toc(&mut self, entries: &[OutlineEntry]) {
+        self.check_page_break(Mm(15.0));
+        self.tags.begin("TOC");
+        self.tags.begin("Caption");
+        self.write_text_at(
+            "Contents",
+            BuiltinFont::HelveticaBold,
+            20.0,
+            self.left_margin,
+            self.y_position,
+        );
+        self.tags.end();
+        self.move_down(self.line_height * 1.5);
+
+        let size = 11.0;
+        let dot_width = self.fonts.text_width(".", BuiltinFont::Helvetica, size);
+        let space_width = self.fonts.text_width(" ", BuiltinFont::Helvetica, size);
+
+        let depth = self.toc_depth;
+        for entry in entries.iter().filter(|entry| entry.level <= depth) {
+            self.check_page_break(self.line_height);
+
+            let font = if entry.level == 1 {
+                BuiltinFont::HelveticaBold
+            } else {
+                BuiltinFont::Helvetica
+            };
+            let x = self.left_margin + Mm(6.0 * (entry.level - 1) as f32);
+            let number = (entry.page + 1).to_string();
+            let number_x =
+                self.right_margin - self.fonts.text_width(&number, BuiltinFont::Helvetica, size);
+
+            // Shorten titles that would leave no room for the leader
+            let available = number_x - x - space_width * 2.0 - dot_width * 3.0;
+            let mut title = entry.title.clone();
+            if self.fonts.text_width(&title, font, size) > available {
+                while !title.is_empty()
+                    && self.fonts.text_width(&format!("{}…", title), font, size) > available
+                {
+                    title.pop();
+                }
+                title = format!("{}…", title.trim_end());
+            }
+            let title_end = x + self.fonts.text_width(&title, font, size) + space_width;
+
+            // Dots end a space before the page number
+            let leader_end = number_x - space_width;
+            let dots = ((leader_end - title_end).0 / dot_width.0).floor().max(0.0) as usize;
+
+            self.tags.begin("TOCI");
+            let link = self.tags.begin("Link");
+            self.write_text_at(&title, font, size, x, self.y_position);
+            self.tags.begin_artifact();
+            self.write_text_at(
+                &".".repeat(dots),
+                BuiltinFont::Helvetica,
+                size,
+                leader_end - dot_width * dots as f32,
+                self.y_position,
+            );
+            self.tags.end_artifact();
+            self.write_text_at(
+                &number,
+                BuiltinFont::Helvetica,
+                size,
+                number_x,
+                self.y_position,
+            );
+
+            let height = Mm(size / 2.83465);
+            self.add_link(
+                x,
+                self.y_position - height * 0.25,
+                self.right_margin,
+                self.y_position + height * 0.85,
+                &format!("#{}", entry.id),
+                link,
+            );
+            self.tags.end();
+            self.tags.end();
+            self.move_down(self.line_height);
+        }
+
+        self.tags.end();
+        self.move_down(self.line_height);
+    }
+
+    /// Record a heading for the outline and its named destination, with
+    /// `top` on the current page
+    fn add_heading(&mut self, level: u8, explicit_id: Option<&str>, title: &str, top: Mm) {
+        self.outline.push(OutlineEntry {
+            level,
+            id: self.heading_ids.assign(explicit_id, title),
+            title: title.trim().to_string(),
+            page: self.page_ids.len(),
+            top,
+        });
+    }
+
+    /// Embed an image file, or reuse it if it was embedded before, and
+    /// return its resource name and natural size
+    fn ensure_image(&mut self, path: &Path) -> Result<(String, Mm, Mm), std::io::Error> {
+        let idx = match self.images.iter().position(|(known, ..)| known == path) {
+            Some(idx) => idx,
+            None => {
+                let image = Image::load(path)?;
+                let (width, height) = image.natural_size();
+                let image_id = image.write_to(&mut self.doc);
+                self.images
+                    .push((path.to_path_buf(), image_id, width, height));
+                self.images.len() - 1
+            }
+        };
+        let (_, _, width, height) = self.images[idx];
+        Ok((format!("Im{}", idx + 1), width, height))
+    }
+
+    /// Draw an image as a figure, shrunk to fit the text width and the page,
+    /// with its alt text as a caption underneath
+    ///
+    /// The figure moves to the next page when it does not fit on this one.
+    fn draw_image(&mut self, name: &str, width: Mm, height: Mm, alt: &str) {
+        let caption_size = 10.0;
+        let caption_font = BuiltinFont::HelveticaOblique;
+        let caption_advance = self.line_height * 0.8;
+        let text_width = self.right_margin - self.left_margin;
+        let caption_width = self.fonts.text_width(alt, caption_font, caption_size);
+        let caption_lines = (caption_width.0 / text_width.0).ceil();
+        let caption_height = caption_advance * caption_lines;
+
+        // The top of the image lines up with the top of a line of text
+        let rise = self.line_height * 0.5;
+        let max_height = self.content_top + rise - self.content_bottom - caption_height;
+        let scale = 1.0f32
+            .min(text_width.0 / width.0)
+            .min(max_height.0.max(0.0) / height.0);
+        let (width, height) = (width * scale, height * scale);
+
+        self.check_page_break(height - rise + caption_height);
+        let x = self.left_margin + (text_width - width) / 2.0;
+        let bottom = self.y_position + rise - height;
+
+        self.end_text_section();
+        let figure = (!alt.is_empty()).then(|| self.tags.begin("Figure"));
+        if let Some(figure) = figure {
+            self.tags.set_alt(figure, alt);
+        }
+        self.begin_marked();
+        self.current_ops.extend([
+            Operation::new("q", vec![]),
+            Operation::new(
+                "cm",
+                vec![
+                    width.to_points().into(),
+                    0.into(),
+                    0.into(),
+                    height.to_points().into(),
+                    x.to_points().into(),
+                    bottom.to_points().into(),
+                ],
+            ),
+            Operation::new("Do", vec![Object::Name(name.as_bytes().to_vec())]),
+            Operation::new("Q", vec![]),
+        ]);
+        self.end_marked();
+        self.y_position = bottom - caption_advance;
+
+        if figure.is_some() {
+            self.tags.begin("Caption");
+            if caption_lines <= 1.0 {
+                let x = self.left_margin + (text_width - caption_width) / 2.0;
+                self.write_text_at(alt, caption_font, caption_size, x, self.y_position);
+                self.move_down(caption_advance);
+            } else {
+                let words = segments_to_words(
+                    &[TextSegment::plain(alt.to_string(), TextStyle::ITALIC)],
+                    caption_size,
+                    &self.fonts,
+                );
+                self.write_lines(
+                    &words,
+                    self.left_margin,
+                    caption_size,
+                    text_width,
+                    TextAlign::Ragged,
+                    caption_advance,
+                    false,
+                );
+            }
+            self.tags.end();
+            self.tags.end();
+        }
+        self.move_down(self.line_height * 0.5);
+    }
+
+    /// Draw a highlighted code block as a panel filled with the theme's
+    /// background, under a title or filename header and beside
+    /// right-aligned line numbers when the block has them, with its `hl_lines`
+    /// on a brighter band. Added and removed lines of a diff go on green and
+    /// red bands after their marker. Lines too wide for the panel are fitted
+    /// as `overflow` says. A panel that runs past the foot of the page
+    /// continues on the next, repeating the header.
+    fn write_code_block(
+        &mut self,
+        lines: &[Vec<(Style, &str)>],
+        kinds: &[CodeLine],
+        info: &CodeBlockInfo,
+        url: Option<&str>,
+        overflow: CodeOverflow,
+        colors: CodeColors,
+    ) {
+        let heading = info.heading();
+        let start_line = info.first_line_number();
+        // The top of the panel lines up with the top of a line of text
+        let rise = self.line_height * 0.5;
+        let header = heading.map_or(Mm(0.0), |_| CODE_HEADER);
+        let left = self.left_margin;
+        let width = self.right_margin - self.left_margin;
+
+        let gutter = start_line.map(|start| {
+            let last = start + lines.len().saturating_sub(1);
+            self.fonts
+                .text_width(&last.to_string(), BuiltinFont::Courier, CODE_SIZE)
+        });
+        let marker_x = left + CODE_PADDING + gutter.map_or(Mm(0.0), |gutter| gutter + CODE_PADDING);
+        // Diff markers get a column of their own, two characters wide
+        let text_x = if kinds.iter().any(|line| line.diff_marker().is_some()) {
+            marker_x + self.fonts.text_width("  ", BuiltinFont::Courier, CODE_SIZE)
+        } else {
+            marker_x
+        };
+        let text_width = left + width - CODE_PADDING - text_x;
+
+        let (rows, size) = fit_lines(
+            lines,
+            start_line,
+            overflow,
+            text_width,
+            CODE_SIZE,
+            &self.fonts,
+        );
+        let advance = self.line_height * 0.8 * (size / CODE_SIZE);
+        let hang = self.fonts.text_width(" ", BuiltinFont::Courier, size) * HANG_CHARS;
+
+        let mut next = 0;
+        while next < rows.len() {
+            let continued = next > 0;
+            let remaining = rows.len() - next;
+            if continued {
+                self.new_page();
+            } else {
+                // Keep the header with the first two lines
+                let lines_height = advance * remaining.min(2) as f32;
+                self.check_page_break(header + CODE_PADDING * 2.0 + lines_height - rise);
+            }
+
+            let top = self.y_position + rise;
+            let room =
+                top - self.content_bottom - self.page_notes_height - header - CODE_PADDING * 2.0;
+            let count = ((room.0 / advance.0).floor() as usize).clamp(1, remaining);
+            let bottom = top - header - CODE_PADDING * 2.0 - advance * count as f32;
+
+            self.end_text_section();
+            self.fill_rect(left, bottom, width, top - bottom, colors.background);
+            if let Some(heading) = heading {
+                self.fill_rect(left, top - header, width, header, colors.header);
+                let baseline = top - header / 2.0 - Mm(1.2);
+                if continued {
+                    let label = format!("{} (continued)", heading);
+                    let x = left + CODE_PADDING;
+                    self.write_artifact_text(&label, x, baseline, CODE_SIZE, colors.text);
+                } else {
+                    let x = left + CODE_PADDING;
+                    let color = if url.is_some() {
+                        self.link_color
+                    } else {
+                        colors.text
+                    };
+                    self.tags.begin("P");
+                    let link_element = url.map(|_| self.tags.begin("Link"));
+                    self.write_text_at_with_color(
+                        heading,
+                        BuiltinFont::Courier,
+                        CODE_SIZE,
+                        x,
+                        baseline,
+                        Some(color),
+                    );
+                    if let (Some(url), Some(element)) = (url, link_element) {
+                        let width = self
+                            .fonts
+                            .text_width(heading, BuiltinFont::Courier, CODE_SIZE);
+                        self.finish_link(x, x + width, baseline, CODE_SIZE, url, element);
+                        self.tags.end();
+                    }
+                    self.tags.end();
+                }
+            }
+            if !continued {
+                self.tags.begin("Code");
+            }
+
+            let mut baseline = top - header - CODE_PADDING - advance * 0.75;
+            for row in &rows[next..next + count] {
+                let kind = kinds.get(row.line).copied().unwrap_or(CodeLine::Plain(""));
+                let band = match kind {
+                    _ if info.is_highlighted(row.line) => Some(colors.highlight),
+                    CodeLine::Added(_) => Some(colors.added),
+                    CodeLine::Removed(_) => Some(colors.removed),
+                    CodeLine::DiffHeader(_) => Some(colors.header),
+                    _ => None,
+                };
+                if let Some(band) = band {
+                    let bottom = baseline - advance * 0.25;
+                    self.fill_rect(left, bottom, width, advance, band);
+                }
+                // The marker is part of the code, so a copied diff keeps it
+                let marker = match kind {
+                    CodeLine::Added(_) => Some(("+", colors.added_marker)),
+                    CodeLine::Removed(_) => Some(("-", colors.removed_marker)),
+                    _ => None,
+                };
+                if let (Some((marker, color)), false) = (marker, row.continuation) {
+                    self.write_text_at_with_color(
+                        marker,
+                        BuiltinFont::Courier,
+                        size,
+                        marker_x,
+                        baseline,
+                        Some(color),
+                    );
+                }
+                // Line numbers are not part of the code
+                if let (Some(number), Some(gutter)) = (row.number, gutter) {
+                    let number = number.to_string();
+                    let number_width = self.fonts.text_width(&number, BuiltinFont::Courier, size);
+                    let x = left + CODE_PADDING + gutter - number_width;
+                    self.write_artifact_text(&number, x, baseline, size, colors.gutter_text);
+                }
+                let x = if row.continuation {
+                    self.draw_continuation_mark(text_x, baseline, size, colors.gutter_text);
+                    text_x + hang
+                } else {
+                    text_x
+                };
+
+                self.start_text_section();
+                self.current_ops.push(Operation::new(
+                    "Td",
+                    vec![x.to_points().into(), baseline.to_points().into()],
+                ));
+                self.begin_marked();
+                let courier_key = self.ensure_font(BuiltinFont::Courier);
+                for (style, text) in &row.spans {
+                    let fg = style.foreground;
+                    self.current_ops.push(Operation::new(
+                        "rg",
+                        vec![
+                            (fg.r as f32 / 255.0).into(),
+                            (fg.g as f32 / 255.0).into(),
+                            (fg.b as f32 / 255.0).into(),
+                        ],
+                    ));
+                    self.current_ops.push(Operation::new(
+                        "Tf",
+                        vec![courier_key.clone().into(), size.into()],
+                    ));
+                    self.show_text(text, BuiltinFont::Courier);
+                }
+                self.end_marked();
+                self.end_text_section();
+                baseline -= advance;
+            }
+
+            next += count;
+            self.y_position = bottom;
+        }
+        self.tags.end();
+
+        self.move_down(self.line_height * 1.25);
+    }
+
+    /// Write a line of code-font text that is decoration rather than content
+    fn write_artifact_text(&mut self, text: &str, x: Mm, y: Mm, size: f32, color: (f32, f32, f32)) {
+        self.end_text_section();
+        let font_key = self.ensure_font(BuiltinFont::Courier);
+        self.current_ops.extend([
+            Operation::new("BT", vec![]),
+            Operation::new("Td", vec![x.to_points().into(), y.to_points().into()]),
+            Operation::new("rg", vec![color.0.into(), color.1.into(), color.2.into()]),
+            Operation::new("Tf", vec![font_key.into(), size.into()]),
+        ]);
+        self.show_text(text, BuiltinFont::Courier);
+        self.current_ops.push(Operation::new("ET", vec![]));
+    }
+
+    /// Draw a hooked arrow, like `↳`, in front of a wrapped code row. It is
+    /// drawn rather than set in a font so it needs no extra font.
+    fn draw_continuation_mark(&mut self, x: Mm, baseline: Mm, size: f32, color: (f32, f32, f32)) {
+        self.end_text_section();
+        let (x, y) = (
+            x.to_points() + size * 0.1,
+            baseline.to_points() + size * 0.25,
+        );
+        let (width, height, head) = (size * 0.9, size * 0.45, size * 0.2);
+        let point = |x: f32, y: f32| vec![x.into(), y.into()];
+        self.current_ops.extend([
+            Operation::new("q", vec![]),
+            Operation::new("RG", vec![color.0.into(), color.1.into(), color.2.into()]),
+            Operation::new("w", vec![(size * 0.06).into()]),
+            Operation::new("m", point(x, y + height)),
+            Operation::new("l", point(x, y)),
+            Operation::new("l", point(x + width, y)),
+            Operation::new("m", point(x + width - head, y + head)),
+            Operation::new("l", point(x + width, y)),
+            Operation::new("l", point(x + width - head, y - head)),
+            Operation::new("S", vec![]),
+            Operation::new("Q", vec![]),
+        ]);
+    }
+
+    /// Draw wrapped text as a paragraph at the left margin
+    fn write_paragraph(&mut self, segments: &[TextSegment]) {
+        let words = segments_to_words(segments, 12.0, &self.fonts);
+        self.tags.begin("P");
+        self.write_wrapped_text(&words, self.left_margin, 12.0);
+        self.tags.end();
+        self.move_down(self.line_height * 0.5);
+    }
+
+    fn write_text_at(&mut self, text: &str, font: BuiltinFont, size: f32, x: Mm, y: Mm) {
+        self.write_text_at_with_color(text, font, size, x, y, None);
+    }
+
+    fn write_text_at_with_color(
+        &mut self,
+        text: &str,
+        font: BuiltinFont,
+        size: f32,
+        x: Mm,
+        y: Mm,
+        color_override: Option<(f32, f32, f32)>,
+    ) {
+        self.end_text_section();
+        self.start_text_section();
+
+        let font_key = self.ensure_font(font);
+
+        // Set text position
+        self.current_ops.push(Operation::new(
+            "Td",
+            vec![x.to_points().into(), y.to_points().into()],
+        ));
+
+        // Set text color from theme or override
+        let color = color_override.unwrap_or({
+            if self.is_slide {
+                self.slide_theme.text_color
+            } else {
+                (0.0, 0.0, 0.0) // Black for regular PDFs
+            }
+        });
+        self.current_ops.push(Operation::new(
+            "rg",
+            vec![color.0.into(), color.1.into(), color.2.into()],
+        ));
+
+        // Set font and size
+        self.current_ops
+            .push(Operation::new("Tf", vec![font_key.into(), size.into()]));
+
+        // Write text
+        self.begin_marked();
+        self.show_text(text, font);
+        self.end_marked();
+
+        self.end_text_section();
+    }
+
+    /// Draw a list item's marker in front of the line about to be written at
+    /// the text column, then open the item's body
+    fn draw_list_marker(&mut self, marker: &ListMarker) {
+        self.check_page_break(self.line_height * 1.5);
+
+        let label = self.tags.begin("Lbl");
+        let x = self.left_margin - BULLET_WIDTH;
+        match marker {
+            ListMarker::Checkbox(checked) => {
+                self.tags.set_alt(label, checkbox_alt(*checked));
+                self.draw_checkbox(x, self.y_position - Mm(0.4), *checked);
+            }
+            ListMarker::Number(number) => {
+                // Numbers line up on their right edge
+                let width = self.fonts.text_width(number, BuiltinFont::Helvetica, 12.0);
+                let x = self.left_margin - Mm(1.5) - width;
+                self.write_text_at(number, BuiltinFont::Helvetica, 12.0, x, self.y_position);
+            }
+            ListMarker::Bullet(Bullet::Text(text)) => {
+                self.write_text_at(text, BuiltinFont::Helvetica, 12.0, x, self.y_position);
+            }
+            ListMarker::Bullet(shape) => {
+                self.tags.set_alt(label, "bullet");
+                self.draw_bullet(shape, x, self.y_position);
+            }
+        }
+        self.tags.end();
+        self.tags.begin("LBody");
+    }
+
+    /// Draw a disc, circle or square bullet sitting on the baseline `y`,
+    /// centred on the x-height
+    fn draw_bullet(&mut self, shape: &Bullet, x: Mm, y: Mm) {
+        self.end_text_section();
+        self.begin_marked();
+
+        let color = if self.is_slide {
+            self.slide_theme.text_color
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        let radius = Mm(0.8);
+        let (center_x, center_y) = (x + Mm(1.0), y + Mm(1.1));
+        self.current_ops.push(Operation::new("q", vec![]));
+        self.current_ops.push(Operation::new(
+            "rg",
+            vec![color.0.into(), color.1.into(), color.2.into()],
+        ));
+        self.current_ops.push(Operation::new(
+            "RG",
+            vec![color.0.into(), color.1.into(), color.2.into()],
+        ));
+        match shape {
+            Bullet::Circle => {
+                self.current_ops.push(Operation::new("w", vec![0.5.into()]));
+                self.circle(center_x, center_y, radius, "S");
+            }
+            Bullet::Square => {
+                self.current_ops.push(Operation::new(
+                    "re",
+                    vec![
+                        (center_x - radius).to_points().into(),
+                        (center_y - radius).to_points().into(),
+                        (radius * 2.0).to_points().into(),
+                        (radius * 2.0).to_points().into(),
+                    ],
+                ));
+                self.current_ops.push(Operation::new("f", vec![]));
+            }
+            _ => self.circle(center_x, center_y, radius, "f"),
+        }
+        self.current_ops.push(Operation::new("Q", vec![]));
+
+        self.end_marked();
+    }
+
+    fn draw_checkbox(&mut self, x: Mm, y: Mm, checked: bool) {
+        self.end_text_section();
+        self.begin_marked();
+
+        let box_size = Mm(3.5);
+
+        // Draw box outline
+        self.current_ops.push(Operation::new(
+            "q", // Save graphics state
+            vec![],
+        ));
+
+        // Set line width
+        self.current_ops.push(Operation::new("w", vec![0.5.into()]));
+
+        // Set stroke color from theme
+        let color = if self.is_slide {
+            self.slide_theme.text_color
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        self.current_ops.push(Operation::new(
+            "RG",
+            vec![color.0.into(), color.1.into(), color.2.into()],
+        ));
+
+        // Draw rectangle
+        self.current_ops.push(Operation::new(
+            "re",
+            vec![
+                x.to_points().into(),
+                y.to_points().into(),
+                box_size.to_points().into(),
+                box_size.to_points().into(),
+            ],
+        ));
+
+        self.current_ops.push(Operation::new("S", vec![])); // Stroke
+
+        if checked {
+            // Draw checkmark (X shape)
+            let padding = Mm(0.7);
+            let x1 = x + padding;
+            let y1 = y + padding;
+            let x2 = x + box_size - padding;
+            let y2 = y + box_size - padding;
+
+            // First diagonal line
+            self.current_ops.push(Operation::new(
+                "m",
+                vec![x1.to_points().into(), y1.to_points().into()],
+            ));
+            self.current_ops.push(Operation::new(
+                "l",
+                vec![x2.to_points().into(), y2.to_points().into()],
+            ));
+            self.current_ops.push(Operation::new("S", vec![]));
+
+            // Second diagonal line
+            self.current_ops.push(Operation::new(
+                "m",
+                vec![x2.to_points().into(), y1.to_points().into()],
+            ));
+            self.current_ops.push(Operation::new(
+                "l",
+                vec![x1.to_points().into(), y2.to_points().into()],
+            ));
+            self.current_ops.push(Operation::new("S", vec![]));
+        }
+
+        self.current_ops.push(Operation::new(
+            "Q", // Restore graphics state
+            vec![],
+        ));
+        self.end_marked();
+    }
+
+    fn move_down(&mut self, amount: Mm) {
+        self.y_position -= amount;
+    }
+
+    /// Draw a line from (x1, y1) to (x2, y2)
+    fn draw_line(&mut self, x1: Mm, y1: Mm, x2: Mm, y2: Mm, width: f32) {
+        self.current_ops.push(Operation::new("q", vec![])); // Save state
+        self.current_ops
+            .push(Operation::new("w", vec![width.into()])); // Line width
+        self.current_ops.push(Operation::new(
+            "m",
+            vec![x1.to_points().into(), y1.to_points().into()],
+        )); // Move to
+        self.current_ops.push(Operation::new(
+            "l",
+            vec![x2.to_points().into(), y2.to_points().into()],
+        )); // Line to
+        self.current_ops.push(Operation::new("S", vec![])); // Stroke
+        self.current_ops.push(Operation::new("Q", vec![])); // Restore state
+    }
+
+    /// Fill a rectangle given by its lower left corner
+    fn fill_rect(&mut self, x: Mm, y: Mm, width: Mm, height: Mm, color: (f32, f32, f32)) {
+        self.current_ops.extend([
+            Operation::new("q", vec![]),
+            Operation::new("rg", vec![color.0.into(), color.1.into(), color.2.into()]),
+            Operation::new(
+                "re",
+                vec![
+                    x.to_points().into(),
+                    y.to_points().into(),
+                    width.to_points().into(),
+                    height.to_points().into(),
+                ],
+            ),
+            Operation::new("f", vec![]),
+            Operation::new("Q", vec![]),
+        ]);
+    }
+
+    /// Render wrapped text in a table cell and return the height used
+    fn write_wrapped_cell(&mut self, words: &[Word], x: Mm, size: f32, column_width: Mm) -> Mm {
+        let start_y = self.y_position;
+        self.write_lines(
+            words,
+            x,
+            size,
+            column_width,
+            TextAlign::Ragged,
+            self.line_height * 0.8,
+            false,
+        );
+        start_y - self.y_position
+    }
+
+    /// Render wrapped text using Knuth-Plass line breaking
+    fn write_wrapped_text(&mut self, words: &[Word], x: Mm, size: f32) {
+        let width = self.right_margin - x;
+        self.write_lines(
+            words,
+            x,
+            size,
+            width,
+            self.text_align,
+            self.line_height,
+            true,
+        );
+    }
+
+    /// Break words into lines of the given width and render them
+    #[allow(clippy::too_many_arguments)]
+    fn write_lines(
+        &mut self,
+        words: &[Word],
+        x: Mm,
+        size: f32,
+        width: Mm,
+        align: TextAlign,
+        line_advance: Mm,
+        page_breaks: bool,
+    ) {
+        if words.is_empty() {
+            return;
+        }
+
+        let (items, pieces) =
+            words_to_items(words, size, &self.fonts, align, self.hyphenator.as_ref());
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(width.0));
+
+        // Set text color from theme
+        let color = if self.is_slide {
+            self.slide_theme.text_color
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        let mut pieces = pieces.iter();
+        let mut line_start = 0;
+        // Link being drawn and its structure element, which may span lines
+        let mut open_link: Option<(&str, usize)> = None;
+        for line in &breaks {
+            // A line that could not be adjusted to fit is set at natural width
+            let ratio = if line.ratio.is_finite() {
+                line.ratio
+            } else {
+                0.0
+            };
+            let boxes = items[line_start..line.position]
+                .iter()
+                .filter(|item| matches!(item, Item::Box { .. }))
+                .count();
+            // Footnotes first referenced on this line go at the foot of its page
+            let mut line_notes: Vec<usize> = Vec::new();
+            if self.note_placement == NotePlacement::Page {
+                for number in pieces
+                    .clone()
+                    .take(boxes)
+                    .filter_map(|piece| piece.footnote)
+                {
+                    if !self.notes[number - 1].placed && !line_notes.contains(&number) {
+                        line_notes.push(number);
+                    }
+                }
+            }
+            // Inline math taller than the text makes room for itself
+            let (math_above, math_below) = pieces
+                .clone()
+                .take(boxes)
+                .filter_map(|piece| piece.math.as_deref())
+                .fold((0.0f32, 0.0f32), |(above, below), layout| {
+                    (
+                        above.max(layout.ascent - size * 0.75),
+                        below.max(layout.descent - size * 0.25),
+                    )
+                });
+            let (math_above, math_below) = (Mm(math_above / 2.83465), Mm(math_below / 2.83465));
+            if page_breaks {
+                self.check_page_break(
+                    self.line_height + math_above + math_below + self.notes_height(&line_notes),
+                );
+            }
+            self.keep_note_space(&line_notes);
+            self.move_down(math_above);
+
+            self.end_text_section();
+            self.start_text_section();
+
+            self.current_ops.push(Operation::new(
+                "Td",
+                vec![x.to_points().into(), self.y_position.to_points().into()],
+            ));
+
+            // Font and size of the last word drawn and the space owed after it
+            let mut previous_font: Option<(BuiltinFont, f32)> = None;
+            let mut started = false;
+            let mut gap: Option<Mm> = None;
+            // Pen position, and the extent of each link drawn on this line
+            let mut cursor = x;
+            let mut link_runs: Vec<(Mm, Mm, &str, usize)> = Vec::new();
+            let mut previous_link: Option<&str> = None;
+            // Extent and colour of each struck-through run on this line
+            let mut strike_runs: Vec<(Mm, Mm, (f32, f32, f32))> = Vec::new();
+            let mut previous_struck = false;
+            for item in &items[line_start..line.position] {
+                match *item {
+                    Item::Box { .. } => {
+                        let Some(piece) = pieces.next() else {
+                            break;
+                        };
+
+                        if let Some(gap) = gap.take() {
+                            // After a formula the next word is placed afresh
+                            // The space is set in the size of the word before
+                            // it, which is smaller after a raised or lowered run
+                            if let Some((font, font_size)) = previous_font
+                                && self.in_text_section
+                            {
+                                // A footnote mark closes the marked content it
+                                // was drawn in, and the space belongs to the text
+                                self.begin_marked();
+                                self.show_space(font, font_size, gap);
+                            }
+                            cursor += gap;
+                        }
+
+                        let link = piece.link.as_deref();
+                        if link != open_link.map(|(url, _)| url) {
+                            self.end_marked();
+                            if open_link.take().is_some() {
+                                self.tags.end();
+                            }
+                            open_link = link.map(|url| (url, self.tags.begin("Link")));
+                        }
+                        started = true;
+
+                        if let Some(layout) = &piece.math {
+                            self.end_marked();
+                            self.end_text_section();
+                            self.draw_math(layout, &piece.text, cursor, self.y_position);
+                            let start = cursor;
+                            cursor += piece.width;
+                            match (open_link, link_runs.last_mut()) {
+                                (Some((url, _)), Some(run)) if previous_link == Some(url) => {
+                                    run.1 = cursor;
+                                }
+                                (Some((url, element)), _) => {
+                                    link_runs.push((start, cursor, url, element))
+                                }
+                                (None, _) => {}
+                            }
+                            previous_link = link;
+                            previous_struck = false;
+                            continue;
+                        }
+                        if !self.in_text_section {
+                            self.start_text_section();
+                            self.current_ops.push(Operation::new(
+                                "Td",
+                                vec![
+                                    cursor.to_points().into(),
+                                    self.y_position.to_points().into(),
+                                ],
+                            ));
+                        }
+                        self.begin_marked();
+
+                        if link.is_some() != previous_link.is_some() || previous_font.is_none() {
+                            let (r, g, b) = if link.is_some() {
+                                self.link_color
+                            } else {
+                                color
+                            };
+                            self.current_ops
+                                .push(Operation::new("rg", vec![r.into(), g.into(), b.into()]));
+                        }
+
+                        let font = piece.style.as_font();
+                        let font_key = self.ensure_font(font);
+                        let piece_size = piece.style.size(size);
+                        self.current_ops.push(Operation::new(
+                            "Tf",
+                            vec![font_key.into(), piece_size.into()],
+                        ));
+                        let rise = piece.style.rise(size);
+                        if rise != 0.0 {
+                            self.current_ops
+                                .push(Operation::new("Ts", vec![rise.into()]));
+                        }
+                        self.show_text(&piece.text, font);
+                        if rise != 0.0 {
+                            self.current_ops
+                                .push(Operation::new("Ts", vec![0.0.into()]));
+                        }
+                        if let Some(number) = piece.footnote {
+                            self.add_note_element(number);
+                        }
+                        previous_font = Some((font, piece_size));
+
+                        let start = cursor;
+                        cursor += piece.width;
+
+                        // Struck words and the spaces between them share one line
+                        let struck = piece.style.contains(TextStyle::STRIKETHROUGH);
+                        if struck {
+                            let strike_color = if link.is_some() {
+                                self.link_color
+                            } else {
+                                color
+                            };
+                            match strike_runs.last_mut() {
+                                Some(run) if previous_struck && run.2 == strike_color => {
+                                    run.1 = cursor;
+                                }
+                                _ => strike_runs.push((start, cursor, strike_color)),
+                            }
+                        }
+                        previous_struck = struck;
+                        match (open_link, link_runs.last_mut()) {
+                            (Some((url, _)), Some(run)) if previous_link == Some(url) => {
+                                run.1 = cursor;
+                            }
+                            (Some((url, element)), _) => {
+                                link_runs.push((start, cursor, url, element))
+                            }
+                            (None, _) => {}
+                        }
+                        previous_link = link;
+                    }
+                    Item::Glue {
+                        width,
+                        stretch,
+                        shrink,
+                    } => {
+                        // Glue before the first word of a line is discarded
+                        if started && stretch != FILL {
+                            let adjust = if ratio > 0.0 { stretch } else { shrink };
+                            gap = Some(gap.unwrap_or(Mm(0.0)) + Mm(width + adjust * ratio));
+                        }
+                    }
+                    Item::Penalty { .. } => {}
+                }
+            }
+
+            // A line broken inside a word ends with a hyphen
+            if let Item::Penalty { width, .. } = items[line.position]
+                && width > 0.0
+                && let Some((font, font_size)) = previous_font
+            {
+                self.show_text("-", font);
+                let hyphen_width = self.fonts.text_width("-", font, font_size);
+                if let (Some(_), Some(run)) = (previous_link, link_runs.last_mut()) {
+                    run.1 += hyphen_width;
+                }
+                if let (true, Some(run)) = (previous_struck, strike_runs.last_mut()) {
+                    run.1 += hyphen_width;
+                }
+            }
+
+            self.end_marked();
+            self.end_text_section();
+            for (start, end, (r, g, b)) in strike_runs {
+                // Through the middle of the lowercase letters
+                let strike_y = self.y_position + Mm(size / 2.83465 * 0.3);
+                self.current_ops.push(Operation::new("q", vec![]));
+                self.current_ops
+                    .push(Operation::new("RG", vec![r.into(), g.into(), b.into()]));
+                self.draw_line(start, strike_y, end, strike_y, size * 0.06);
+                self.current_ops.push(Operation::new("Q", vec![]));
+            }
+            for (start, end, url, element) in link_runs {
+                self.finish_link(start, end, self.y_position, size, url, element);
+            }
+            self.move_down(line_advance + math_below);
+            line_start = line.position + 1;
+        }
+        if open_link.is_some() {
+            self.tags.end();
+        }
+    }
+
+    /// Draw a laid out formula from the left end of its baseline, tagged as
+    /// a formula that reads as its TeX source
+    fn draw_math(&mut self, layout: &MathBox, tex: &str, x: Mm, y: Mm) {
+        let (r, g, b) = if self.is_slide {
+            self.slide_theme.text_color
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        let (x, y) = (x.to_points(), y.to_points());
+
+        let element = self.tags.begin("Formula");
+        self.tags.set_alt(element, tex);
+        self.begin_marked();
+        self.current_ops.extend([
+            Operation::new("q", vec![]),
+            Operation::new("rg", vec![r.into(), g.into(), b.into()]),
+            Operation::new("RG", vec![r.into(), g.into(), b.into()]),
+            // Round caps and joins
+            Operation::new("J", vec![1.into()]),
+            Operation::new("j", vec![1.into()]),
+        ]);
+        for item in &layout.items {
+            match item {
+                MathItem::Glyph {
+                    x: glyph_x,
+                    y: glyph_y,
+                    text,
+                    font,
+                    size,
+                } => {
+                    let font_key = self.ensure_font(*font);
+                    self.current_ops.extend([
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec![font_key.into(), (*size).into()]),
+                        Operation::new("Td", vec![(x + glyph_x).into(), (y + glyph_y).into()]),
+                    ]);
+                    self.show_text(text, *font);
+                    self.current_ops.push(Operation::new("ET", vec![]));
+                }
+                MathItem::Rule {
+                    x: rule_x,
+                    y: rule_y,
+                    width,
+                    height,
+                } => self.current_ops.extend([
+                    Operation::new(
+                        "re",
+                        vec![
+                            (x + rule_x).into(),
+                            (y + rule_y).into(),
+                            (*width).into(),
+                            (*height).into(),
+                        ],
+                    ),
+                    Operation::new("f", vec![]),
+                ]),
+                MathItem::Path {
+                    start,
+                    segments,
+                    width,
+                } => {
+                    self.current_ops.extend([
+                        Operation::new("w", vec![(*width).into()]),
+                        Operation::new("m", vec![(x + start.0).into(), (y + start.1).into()]),
+                    ]);
+                    for segment in segments {
+                        self.current_ops.push(match *segment {
+                            Segment::Line(to_x, to_y) => {
+                                Operation::new("l", vec![(x + to_x).into(), (y + to_y).into()])
+                            }
+                            Segment::Curve(points) => Operation::new(
+                                "c",
+                                points
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(idx, point)| {
+                                        Object::from(point + if idx % 2 == 0 { x } else { y })
+                                    })
+                                    .collect(),
+                            ),
+                        });
+                    }
+                    self.current_ops.push(Operation::new("S", vec![]));
+                }
+            }
+        }
+        self.current_ops.push(Operation::new("Q", vec![]));
+        self.end_marked();
+        self.tags.end();
+    }
+
+    /// Draw display math centred on its own lines
+    fn write_display_math(&mut self, tex: &str) {
+        let nodes = match math::parse(tex) {
+            Ok(nodes) => nodes,
+            Err(error) => {
+                self.warn(format!("Cannot typeset math '{}': {}", tex, error));
+                let words = segments_to_words(
+                    &[TextSegment::plain(tex.to_string(), TextStyle::CODE)],
+                    12.0,
+                    &self.fonts,
+                );
+                self.tags.begin("P");
+                self.write_wrapped_text(&words, self.left_margin, 12.0);
+                self.tags.end();
+                self.move_down(self.line_height * 0.5);
+                return;
+            }
+        };
+        let layout = formula::layout(&nodes, true, 12.0, &self.fonts);
+
+        // The formula's top sits where a line of text would reach
+        let cap_height = Mm(12.0 * 0.75 / 2.83465);
+        let ascent = Mm(layout.ascent / 2.83465);
+        let descent = Mm(layout.descent / 2.83465);
+        self.check_page_break(ascent + descent);
+        let width = Mm(layout.width / 2.83465);
+        let x = self.left_margin + (self.right_margin - self.left_margin - width) / 2.0;
+        let baseline = self.y_position + cap_height - ascent;
+        self.draw_math(&layout, tex, x, baseline);
+        self.y_position = baseline - descent - cap_height - self.line_height * 0.5;
+    }
+
+    /// Draw a space that advances the pen by `gap`, so justified lines keep
+    /// real space characters for text extraction
+    fn show_space(&mut self, font: BuiltinFont, size: f32, gap: Mm) {
+        let natural = self.fonts.text_width(" ", font, size);
+        let adjust = -(gap - natural).to_points() * 1000.0 / size;
+        if adjust.abs() < 0.01 {
+            self.show_text(" ", font);
+            return;
+        }
+
+        let parts = vec![self.fonts.encode(" ", font), adjust.into()];
+        self.current_ops
+            .push(Operation::new("TJ", vec![Object::Array(parts)]));
+    }
+}
+
+/// Run of text with one set of styles
+#[derive(Clone, Debug)]
+struct TextSegment {
+    text: String,
+    style: TextStyle,
+    /// Target of the link the text belongs to
+    link: Option<String>,
+    /// Number of the footnote this segment is the reference mark of
+    footnote: Option<usize>,
+    /// Parsed inline math, with its TeX source as the text
+    math: Option<Rc<Vec<Node>>>,
+}
+
+impl TextSegment {
+    /// A segment outside any link
+    fn plain(text: String, style: TextStyle) -> TextSegment {
+        TextSegment {
+            text,
+            style,
+            link: None,
+            footnote: None,
+            math: None,
+        }
+    }
+
+    /// Inline math, parsed from its TeX source
+    fn math(tex: &str, style: TextStyle) -> Result<TextSegment, String> {
+        let nodes = math::parse(tex)?;
+        Ok(TextSegment {
+            math: Some(Rc::new(nodes)),
+            ..TextSegment::plain(tex.to_string(), style)
+        })
+    }
+}
+
+/// A word with formatting information for layout
+#[derive(Clone, Debug)]
+struct Word {
+    text: String,
+    style: TextStyle,
+    width: Mm,
+    link: Option<String>,
+    footnote: Option<usize>,
+    /// Continues the previous word without a space, as when the style
+    /// changes in the middle of a word
+    joined: bool,
+    /// Laid out inline math, drawn in place of the text
+    math: Option<Rc<MathBox>>,
+}
+
+/// Set of inline styles applied to a run of text, which combine freely
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct TextStyle(u8);
+
+impl TextStyle {
+    const BOLD: TextStyle = TextStyle(1);
+    const ITALIC: TextStyle = TextStyle(1 << 1);
+    /// Inline code, set in the monospace font
+    const CODE: TextStyle = TextStyle(1 << 2);
+    const STRIKETHROUGH: TextStyle = TextStyle(1 << 3);
+    const SUPERSCRIPT: TextStyle = TextStyle(1 << 4);
+    const SUBSCRIPT: TextStyle = TextStyle(1 << 5);
+
+    fn contains(self, other: TextStyle) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// This set with `other` added or removed
+    fn with(self, other: TextStyle, on: bool) -> TextStyle {
+        if on {
+            TextStyle(self.0 | other.0)
+        } else {
+            TextStyle(self.0 & !other.0)
+        }
+    }
+
+    fn as_font(self) -> BuiltinFont {
+        if self.contains(TextStyle::CODE) {
+            return BuiltinFont::Courier;
+        }
+        match (
+            self.contains(TextStyle::BOLD),
+            self.contains(TextStyle::ITALIC),
+        ) {
+            (false, false) => BuiltinFont::Helvetica,
+            (true, false) => BuiltinFont::HelveticaBold,
+            (false, true) => BuiltinFont::HelveticaOblique,
+            (true, true) => BuiltinFont::HelveticaBoldOblique,
+        }
+    }
+
+    /// Font size for text of this style in a run of the given size
+    fn size(self, size: f32) -> f32 {
+        if self.contains(TextStyle::SUPERSCRIPT) || self.contains(TextStyle::SUBSCRIPT) {
+            size * 0.7
+        } else {
+            size
+        }
+    }
+
+    /// Distance in points the text is raised above the baseline (negative
+    /// for lowered text)
+    fn rise(self, size: f32) -> f32 {
+        if self.contains(TextStyle::SUPERSCRIPT) {
+            size * 0.35
+        } else if self.contains(TextStyle::SUBSCRIPT) {
+            -size * 0.15
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Word {
+    fn new(text: String, style: TextStyle, font_size: f32, fonts: &FontSet) -> Self {
+        let width = fonts.text_width(&text, style.as_font(), style.size(font_size));
+        Self {
+            text,
+            style,
+            width,
+            link: None,
+            footnote: None,
+            joined: false,
+            math: None,
+        }
+    }
+}
+
+/// Text of a footnote with its inline styles and links. Paragraphs run
+/// together, and references to other notes keep only their number.
+fn note_segments(events: &[Event], footnotes: &Footnotes) -> Vec<TextSegment> {
+    let inline_style = |tag: TagEnd| match tag {
+        TagEnd::Strong => Some(TextStyle::BOLD),
+        TagEnd::Emphasis => Some(TextStyle::ITALIC),
+        TagEnd::Strikethrough => Some(TextStyle::STRIKETHROUGH),
+        TagEnd::Superscript => Some(TextStyle::SUPERSCRIPT),
+        TagEnd::Subscript => Some(TextStyle::SUBSCRIPT),
+        _ => None,
+    };
+
+    let mut segments = Vec::new();
+    let mut style = TextStyle::default();
+    let mut link: Option<String> = None;
+    for event in events {
+        let (text, text_style) = match event {
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                link = Some(dest_url.to_string());
+                continue;
+            }
+            Event::End(TagEnd::Link) => {
+                link = None;
+                continue;
+            }
+            Event::Start(tag) => {
+                if let Some(toggle) = inline_style(tag.to_end()) {
+                    style = style.with(toggle, true);
+                }
+                continue;
+            }
+            Event::End(TagEnd::Paragraph) => (" ".to_string(), style),
+            Event::End(tag) => {
+                if let Some(toggle) = inline_style(*tag) {
+                    style = style.with(toggle, false);
+                }
+                continue;
+            }
+            Event::Text(text) => (text.to_string(), style),
+            Event::Code(code) => (code.to_string(), style.with(TextStyle::CODE, true)),
+            Event::InlineMath(tex) | Event::DisplayMath(tex) => {
+                match TextSegment::math(tex, style) {
+                    Ok(segment) => {
+                        segments.push(TextSegment {
+                            link: link.clone(),
+                            ..segment
+                        });
+                        continue;
+                    }
+                    Err(_) => (tex.to_string(), style.with(TextStyle::CODE, true)),
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => (" ".to_string(), style),
+            Event::FootnoteReference(label) => match footnotes.number(label) {
+                Some(number) => (number.to_string(), style.with(TextStyle::SUPERSCRIPT, true)),
+                None => (format!("[^{}]", label), style),
+            },
+            _ => continue,
+        };
+        segments.push(TextSegment {
+            link: link.clone(),
+            ..TextSegment::plain(text, text_style)
+        });
+    }
+    segments
+}
+
+/// Alternate text of a task list checkbox, read in place of the drawing
+fn checkbox_alt(checked: bool) -> &'static str {
+    if checked { "checked" } else { "unchecked" }
+}
+
+/// Convert TextSegments into Words for line breaking
+fn segments_to_words(segments: &[TextSegment], font_size: f32, fonts: &FontSet) -> Vec<Word> {
+    let mut words: Vec<Word> = Vec::new();
+    // Whether the last segment ended in the middle of a word
+    let mut open_word = false;
+
+    for segment in segments {
+        // A formula is a single word, joined to any text it touches
+        if let Some(nodes) = &segment.math {
+            let layout = formula::layout(nodes, false, segment.style.size(font_size), fonts);
+            words.push(Word {
+                text: segment.text.clone(),
+                style: segment.style,
+                width: Mm(layout.width / 2.83465),
+                link: segment.link.clone(),
+                footnote: None,
+                joined: open_word,
+                math: Some(Rc::new(layout)),
+            });
+            open_word = true;
+            continue;
+        }
+        let joins = open_word && !segment.text.starts_with(char::is_whitespace);
+        for (idx, word_text) in segment.text.split_whitespace().enumerate() {
+            words.push(Word {
+                link: segment.link.clone(),
+                footnote: segment.footnote,
+                joined: idx == 0 && joins,
+                ..Word::new(word_text.to_string(), segment.style, font_size, fonts)
+            });
+        }
+        if !segment.text.is_empty() {
+            open_word = !words.is_empty() && !segment.text.ends_with(char::is_whitespace);
+        }
+    }
+
+    words
+}
+
+/// Penalty for breaking a line at a hyphenation point
+const HYPHEN_PENALTY: f32 = 50.0;
+
+/// Build the box/glue/penalty items for a run of words
+///
+/// Returns the items and the text drawn by each box, in order: whole words,
+/// or the pieces between hyphenation points when a hyphenator is given.
+fn words_to_items(
+    words: &[Word],
+    size: f32,
+    fonts: &FontSet,
+    align: TextAlign,
+    hyphenator: Option<&Hyphenator>,
+) -> (Vec<Item>, Vec<Word>) {
+    // End-of-line stretch for ragged text, letting lines fall short by a few ems
+    let ragged_stretch = Mm(size * 3.0 / 2.83465).0;
+
+    let mut items = Vec::with_capacity(words.len() * 4 + 3);
+    let mut pieces = Vec::with_capacity(words.len());
+    for (idx, word) in words.iter().enumerate() {
+        if idx > 0 && !word.joined {
+            let font = words[idx - 1].style.as_font();
+            let space = fonts.text_width(" ", font, size).0;
+            match align {
+                TextAlign::Justify => items.push(Item::Glue {
+                    width: space,
+                    stretch: space / 2.0,
+                    shrink: space / 3.0,
+                }),
+                TextAlign::Ragged => {
+                    // The stretch only survives at a line end: the glue after
+                    // the break point takes it back within a line
+                    items.push(Item::Glue {
+                        width: 0.0,
+                        stretch: ragged_stretch,
+                        shrink: 0.0,
+                    });
+                    items.push(Item::Penalty {
+                        width: 0.0,
+                        penalty: 0.0,
+                        flagged: false,
+                    });
+                    items.push(Item::Glue {
+                        width: space,
+                        stretch: -ragged_stretch,
+                        shrink: 0.0,
+                    });
+                }
+            }
+        }
+
+        let points = match hyphenator {
+            Some(hyphenator) if !word.style.contains(TextStyle::CODE) && word.math.is_none() => {
+                hyphenator.hyphenate(&word.text)
+            }
+            _ => vec![],
+        };
+        if points.is_empty() {
+            items.push(Item::Box {
+                width: word.width.0,
+            });
+            pieces.push(word.clone());
+            continue;
+        }
+
+        let font = word.style.as_font();
+        let hyphen_width = fonts.text_width("-", font, word.style.size(size)).0;
+        let mut start = 0;
+        for end in points.into_iter().chain([word.text.len()]) {
+            if start > 0 {
+                items.push(Item::Penalty {
+                    width: hyphen_width,
+                    penalty: HYPHEN_PENALTY,
+                    flagged: true,
+                });
+            }
+            let piece = Word {
+                link: word.link.clone(),
+                ..Word::new(word.text[start..end].to_string(), word.style, size, fonts)
+            };
+            items.push(Item::Box {
+                width: piece.width.0,
+            });
+            pieces.push(piece);
+            start = end;
+        }
+    }
+
+    Item::finish_paragraph(&mut items);
+    (items, pieces)
+}
+
+fn embed_file_attachment(doc: &mut Document, content: &str) -> Result<(), std::io::Error> {
+    let filename = "source";
+
+    // Create embedded file stream with the markdown content
+    let mut file_stream = Stream::new(
+        dictionary! {
+            "Type" => "EmbeddedFile",
+            "Subtype" => "text/markdown",
+            "Params" => dictionary! {
+                "Size" => content.len() as i64,
+                "ModDate" => Object::string_literal(PdfDate::now().to_pdf()),
+            },
+        },
+        content.as_bytes().to_vec(),
+    );
+    let _ = file_stream.compress();
+    let file_stream_id = doc.add_object(file_stream);
+
+    // Create FileSpec dictionary
+    let filespec = dictionary! {
+        "Type" => "Filespec",
+        "F" => Object::String(filename.as_bytes().to_vec(), lopdf::StringFormat::Literal),
+        "UF" => Object::String(filename.as_bytes().to_vec(), lopdf::StringFormat::Literal),
+        "EF" => dictionary! {
+            "F" => Object::Reference(file_stream_id),
+        },
+        "Desc" => Object::string_literal("Markdown source of this document"),
+        // The document was generated from this file (an associated file in PDF/A-3)
+        "AFRelationship" => "Source",
+    };
+    let filespec_id = doc.add_object(filespec);
+
+    // Create the EmbeddedFiles name tree dictionary
+    let embedded_files_dict = dictionary! {
+        "Names" => Object::Array(vec![
+            Object::String(filename.as_bytes().to_vec(), lopdf::StringFormat::Literal),
+            Object::Reference(filespec_id),
+        ]),
+    };
+    let embedded_files_id = doc.add_object(embedded_files_dict);
+
+    // Create the Names dictionary for the catalog
+    let catalog_names_dict = dictionary! {
+        "EmbeddedFiles" => Object::Reference(embedded_files_id),
+    };
+    let catalog_names_id = doc.add_object(catalog_names_dict);
+
+    // Get the catalog object and update it with the Names dictionary
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| std::io::Error::other(format!("Failed to get catalog: {}", e)))?;
+
+    catalog.set("Names", Object::Reference(catalog_names_id));
+    catalog.set("AF", vec![Object::Reference(filespec_id)]);
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TableStyle {
+    Simple, // No borders or separators
+    Lined,  // With borders and separators
+}
+
+/// Detect table style from the raw markdown text at the given range
+/// Detect all table styles in the markdown by scanning for table patterns
+fn detect_all_table_styles(markdown_content: &str) -> Vec<TableStyle> {
+    let mut styles = Vec::new();
+    let lines: Vec<&str> = markdown_content.lines().collect();
+    let mut i = 0;
+    let mut in_code_block = false;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let trimmed = line.trim();
+
+        // Track code blocks to avoid detecting tables inside them
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            i += 1;
+            continue;
+        }
+
+        // Skip if we're inside a code block
+        if in_code_block {
+            i += 1;
+            continue;
+        }
+
+        // Check if this looks like a table separator line
+        // Must have pipes or be a pipe-less separator (simple table)
+        let _has_pipes = trimmed.contains('|');
+        let is_separator = !trimmed.is_empty()
+            && trimmed.chars().filter(|&c| c == '-').count() > 2
+            && trimmed
+                .chars()
+                .all(|c| c == '-' || c == '|' || c == ':' || c.is_whitespace());
+
+        if is_separator && i > 0 {
+            // Look at the line before the separator to determine table style
+            let prev_line = lines[i - 1].trim();
+            // Previous line should also look like table content (contain |)
+            if !prev_line.is_empty() && prev_line.contains('|') {
+                let style = if prev_line.starts_with('|') && prev_line.ends_with('|') {
+                    TableStyle::Lined
+                } else {
+                    TableStyle::Simple
+                };
+                styles.push(style);
+            }
+        }
+
+        i += 1;
+    }
+
+    styles
+}
+
+/// Options for PDF generation, usually taken from the command line
+#[derive(Clone, Debug, Default)]
+pub struct PdfOptions {
+    /// Lay the document out as presentation slides
+    pub is_slide: bool,
+    /// Code syntax highlighting theme, overriding the front matter
+    pub code_theme: Option<String>,
+    /// Embed the markdown source as a file attachment
+    pub embed_source: bool,
+    /// Path of the markdown file, used to resolve relative paths in the document
+    pub source_path: Option<PathBuf>,
+    /// Fonts to embed, overriding the front matter
+    pub fonts: FontConfig,
+    /// Directory searched for `hyph-<lang>.tex` hyphenation patterns
+    pub hyphenation_dir: Option<PathBuf>,
+    /// Paper size, orientation and margins, overriding the front matter
+    pub page: PageConfig,
+    /// Write PDF/A-2b, or PDF/A-3b when the source is embedded, and fail if
+    /// the result does not conform
+    pub pdfa: bool,
+}
+
+/// Most layout passes made to settle the page numbers in a table of contents
+const MAX_LAYOUT_PASSES: usize = 4;
+
+/// Whether a paragraph of the document is the TOC marker, which a marker
+/// inside a code block is not
+fn has_toc_marker(markdown_content: &str) -> bool {
+    MarkdownParser::new(markdown_content).is_ok_and(|parser| {
+        let events: Vec<Event> = parser.into_inner().collect();
+        find_toc_marker(&events).is_some()
+    })
+}
+
+pub fn to_pdf<W: std::io::Write>(
+    markdown_content: &str,
+    mut output: W,
+    options: &PdfOptions,
+) -> Result<(), std::io::Error> {
+    let wants_toc = parse_front_matter(markdown_content)
+        .ok()
+        .flatten()
+        .and_then(|(fm, _)| fm.toc)
+        .unwrap_or(false)
+        || has_toc_marker(markdown_content);
+
+    let mut builder = render(markdown_content, options, wants_toc.then_some(&[]))?;
+    if wants_toc {
+        // The page of each heading is only known after layout, and listing the
+        // headings moves the ones that follow the TOC, so lay the document out
+        // again until the listed page numbers match where the headings land
+        for _ in 1..MAX_LAYOUT_PASSES {
+            let headings = builder.outline.clone();
+            builder = render(markdown_content, options, Some(&headings))?;
+            let settled = builder
+                .outline
+                .iter()
+                .map(|entry| entry.page)
+                .eq(headings.iter().map(|entry| entry.page));
+            if settled {
+                break;
+            }
+        }
+    }
+
+    for warning in &builder.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    if options.pdfa {
+        builder.metadata.pdfa_part = Some(if options.embed_source { 3 } else { 2 });
+    }
+    let mut doc = builder.finalize();
+
+    // Embed source markdown file if requested
+    if options.embed_source {
+        embed_file_attachment(&mut doc, markdown_content)?;
+    }
+
+    if options.pdfa {
+        pdfa::prepare(&mut doc, markdown_content.as_bytes())?;
+        let violations = pdfa::check(&doc);
+        if !violations.is_empty() {
+            let mut message = format!(
+                "The document does not conform to PDF/A:\n  - {}",
+                violations.join("\n  - ")
+            );
+            if violations.iter().any(|v| v.ends_with("is not embedded")) {
+                message.push_str(
+                    "\nPDF/A needs every font embedded: set regular, bold, italic, bold_italic, mono and math with --font or the fonts front matter",
+                );
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                message,
+            ));
+        }
+    }
+
+    doc.save_to(&mut output)
+        .map_err(|e| std::io::Error::other(format!("PDF save error: {}", e)))?;
+
+    Ok(())
+}
+
+/// Lay the document out on pages, ready to be finalized
+///
+/// With `toc` set, a table of contents listing those headings is drawn at the
+/// `[[_TOC_]]` marker, or after the front matter when there is no marker.
+fn render(
+    markdown_content: &str,
+    options: &PdfOptions,
+    toc: Option<&[OutlineEntry]>,
+) -> Result<PdfBuilder, std::io::Error> {
+    let is_slide = options.is_slide;
+    let theme_override = options.code_theme.as_deref();
+
+    let parser = MarkdownParser::new(markdown_content).unwrap();
+    let front_matter: Option<&FrontMatter> = parser.front_matter();
+
+    let font_config = options
+        .fonts
+        .clone()
+        .or(front_matter.and_then(|fm| fm.fonts.as_ref()));
+    let fonts = FontSet::load(&font_config, options.source_path.as_deref())?;
+
+    // Initialize syntax highlighting
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let theme_set = ThemeSet::load_defaults();
+
+    // Determine code syntax highlighting theme
+    let code_theme_name = theme_override
+        .or_else(|| front_matter.and_then(|fm| fm.code_theme.as_deref()))
+        .unwrap_or("InspiredGitHub");
+    let theme = theme_set
+        .themes
+        .get(code_theme_name)
+        .unwrap_or(&theme_set.themes["InspiredGitHub"]);
+    let code_colors = CodeColors::of(theme);
+
+    // Determine slide theme (only for slide mode)
+    let slide_theme = if is_slide {
+        let slide_theme_name = front_matter
+            .and_then(|fm| fm.slide_theme.as_deref())
+            .unwrap_or("light");
+        let mut theme = SlideTheme::get_by_name(slide_theme_name);
+
+        // Apply custom gradient direction if specified
+        if let Some(direction_str) = front_matter.and_then(|fm| fm.gradient_direction.as_ref()) {
+            let direction = match direction_str.as_str() {
+                "top-to-bottom" => GradientDirection::TopToBottom,
+                "bottom-to-top" => GradientDirection::BottomToTop,
+                "left-to-right" => GradientDirection::LeftToRight,
+                "right-to-left" => GradientDirection::RightToLeft,
+                "top-left-to-bottom-right" | "diagonal" => GradientDirection::TopLeftToBottomRight,
+                "top-right-to-bottom-left" => GradientDirection::TopRightToBottomLeft,
+                "bottom-left-to-top-right" => GradientDirection::BottomLeftToTopRight,
+                "bottom-right-to-top-left" => GradientDirection::BottomRightToTopLeft,
+                _ => GradientDirection::TopToBottom,
+            };
+            theme = theme.with_direction(direction);
+        }
+
+        theme
+    } else {
+        SlideTheme::default()
+    };
+
+    let mut builder = if is_slide {
+        PdfBuilder::new_slide(slide_theme, fonts)
+    } else {
+        let page_config = options.page.clone().or(front_matter.map(|fm| &fm.page));
+        PdfBuilder::new(slide_theme, fonts, PageLayout::from_config(&page_config)?)
+    };
+    builder.text_align = match front_matter.and_then(|fm| fm.text_align.as_deref()) {
+        Some("justify") => TextAlign::Justify,
+        _ => TextAlign::Ragged,
+    };
+
+    let lang = front_matter
+        .and_then(|fm| fm.lang.as_deref())
+        .unwrap_or("en");
+    if let Some(depth) = front_matter.and_then(|fm| fm.outline_depth) {
+        builder.outline_depth = depth;
+    }
+    builder.list_markers =
+        ListMarkers::from_config(front_matter.and_then(|fm| fm.list_style.as_ref()))?;
+    builder.note_placement = match front_matter.and_then(|fm| fm.footnotes.as_deref()) {
+        None | Some("page") => NotePlacement::Page,
+        Some("end") => NotePlacement::End,
+        Some(other) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown footnote placement '{}' (expected page or end)",
+                    other
+                ),
+            ));
+        }
+    };
+    if let Some(name) = front_matter.and_then(|fm| fm.code_overflow.as_deref()) {
+        builder.code_overflow = CodeOverflow::parse(name).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Unknown code overflow '{}' (expected wrap, shrink or truncate)",
+                    name
+                ),
+            )
+        })?;
+    }
+    builder.link_underline = front_matter
+        .and_then(|fm| fm.link_underline)
+        .unwrap_or(false);
+    if let Some(depth) = front_matter.and_then(|fm| fm.toc_depth) {
+        builder.toc_depth = depth;
+    }
+    if let Some(fm) = front_matter {
+        builder.running = RunningText {
+            header: fm.header.clone(),
+            footer: fm.footer.clone(),
+            title: fm.title.clone().unwrap_or_default(),
+            author: fm.author.clone().unwrap_or_default(),
+            date: fm.date.clone().unwrap_or_default(),
+        };
+
+        let created = fm.date.as_deref().and_then(PdfDate::parse);
+        if let (Some(date), None) = (&fm.date, created) {
+            builder.warn(format!(
+                "date '{}' is not an ISO 8601 date, the creation date will be the current time",
+                date
+            ));
+        }
+        builder.metadata = DocumentInfo {
+            title: fm.title.clone(),
+            author: fm.author.clone(),
+            subject: fm.subject.clone(),
+            keywords: fm.keywords.clone().unwrap_or_default().0,
+            lang: fm.lang.clone(),
+            created,
+            pdfa_part: None,
+        };
+    }
+    builder.hyphenator = Hyphenator::for_language(lang, options.hyphenation_dir.as_deref())?;
+    if builder.hyphenator.is_none() {
+        builder.warn(format!(
+            "no hyphenation patterns for language '{}', words will not be hyphenated",
+            lang
+        ));
+    }
+
+    // Draw background for first page in slide mode
+    if is_slide {
+        builder.draw_background();
+    }
+
+    // Render front matter if present
+    if let Some(fm) = front_matter {
+        if let Some(doc_title) = &fm.title {
+            builder.check_page_break(Mm(15.0));
+            builder.tags.begin("H1");
+            builder.write_text_at(
+                doc_title,
+                BuiltinFont::HelveticaBold,
+                28.0,
+                builder.left_margin,
+                builder.y_position,
+            );
+            builder.tags.end();
+            builder.move_down(builder.line_height * 2.5);
+        }
+
+        if let Some(author) = &fm.author {
+            builder.check_page_break(Mm(10.0));
+            let author_text = format!("By {}", author);
+            builder.tags.begin("P");
+            builder.write_text_at(
+                &author_text,
+                BuiltinFont::Helvetica,
+                14.0,
+                builder.left_margin,
+                builder.y_position,
+            );
+            builder.tags.end();
+            builder.move_down(builder.line_height * 1.2);
+        }
+
+        if let Some(date) = &fm.date {
+            builder.check_page_break(Mm(10.0));
+            let date_text = format!("Date: {}", date);
+            builder.tags.begin("P");
+            builder.write_text_at(
+                &date_text,
+                BuiltinFont::Helvetica,
+                14.0,
+                builder.left_margin,
+                builder.y_position,
+            );
+            builder.tags.end();
+            builder.move_down(builder.line_height * 1.5);
+        }
+
+        builder.move_down(builder.line_height);
+    }
+
+    // Without a marker the table of contents follows the front matter
+    let toc_at_marker = has_toc_marker(markdown_content);
+    if let Some(entries) = toc
+        && !toc_at_marker
+    {
+        builder.draw_toc(entries);
+    }
+
+    #[derive(Default)]
+    struct State {
+        text_buffer: String,
+        text_segments: Vec<TextSegment>,
+        current_cell_segments: Vec<TextSegment>,
+        /// Inline styles of the text being read
+        style: TextStyle,
+        in_table: bool,
+        in_code_block: bool,
+        in_table_head: bool,
+        table_style: Option<TableStyle>,
+        /// Lists being read, innermost last
+        lists: Vec<ListLevel>,
+        /// Marker of the current list item until its first line is drawn
+        list_marker: Option<ListMarker>,
+        prev_heading_level: Option<u8>,
+        in_heading: bool,
+        link: Option<String>,
+        /// Destination and alt text of the image being read
+        image: Option<(String, String)>,
+    }
+
+    struct ListLevel {
+        /// Number of the next item in an ordered list
+        next_number: Option<u64>,
+        /// Text column around the list, restored after each item
+        outer_left: Mm,
+    }
+
+    impl State {
+        fn in_item(&self) -> bool {
+            !self.lists.is_empty()
+        }
+
+        /// Draw the current item's marker if it is still due, followed by
+        /// the item text read so far. Called before a block inside the item.
+        fn write_item_text(&mut self, builder: &mut PdfBuilder) {
+            self.flush();
+            if let Some(marker) = self.list_marker.take() {
+                builder.draw_list_marker(&marker);
+            }
+            if !self.text_segments.is_empty() {
+                let words = segments_to_words(&self.text_segments, 12.0, &builder.fonts);
+                builder.write_wrapped_text(&words, builder.left_margin, 12.0);
+                self.text_segments.clear();
+            }
+        }
+
+        fn clear(&mut self) {
+            self.text_buffer.clear();
+            self.text_segments.clear();
+        }
+
+        fn flush(&mut self) {
+            // Headings are drawn from the text buffer as a single run
+            if !self.text_buffer.is_empty() && !self.in_heading {
+                let segment = TextSegment::plain(std::mem::take(&mut self.text_buffer), self.style);
+                self.push(segment);
+            }
+        }
+
+        /// Turn an inline style on or off for the text that follows
+        fn set_style(&mut self, style: TextStyle, on: bool) {
+            self.flush();
+            self.style = self.style.with(style, on);
+        }
+
+        /// Add a segment to the current cell or paragraph, inside the open link
+        fn push(&mut self, mut segment: TextSegment) {
+            segment.link = self.link.clone();
+            if self.in_table {
+                self.current_cell_segments.push(segment);
+            } else {
+                self.text_segments.push(segment);
+            }
+        }
+    }
+
+    let mut state = State::default();
+
+    let mut heading_level = 0u8;
+    let mut heading_id: Option<String> = None;
+    let mut code_buffer = String::new();
+    let mut code_lang = String::new();
+    let mut table_rows: Vec<Vec<Vec<TextSegment>>> = Vec::new();
+    let mut current_row: Vec<Vec<TextSegment>> = Vec::new();
+
+    let content_without_fm = markdown_content
+        .strip_prefix("---\n")
+        .and_then(|after_open| after_open.find("\n---\n").map(|pos| &after_open[pos + 5..]))
+        .unwrap_or(markdown_content);
+
+    // Pre-scan to detect all table styles
+    let table_styles = detect_all_table_styles(content_without_fm);
+    let mut current_table_index = 0;
+
+    // The parser is consumed below, so keep the front matter for the repo default
+    let front_matter = front_matter.cloned();
+
+    let mut events: Vec<Event> = parser.into_inner().collect();
+    builder.heading_ids = HeadingIds::for_events(&events);
+    let footnotes = Footnotes::extract(&mut events);
+    builder.notes = footnotes
+        .iter()
+        .map(|(_, body)| Note {
+            segments: note_segments(body, &footnotes),
+            element: None,
+            placed: false,
+        })
+        .collect();
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                if state.in_item() {
+                    state.write_item_text(&mut builder);
+                }
+                heading_level = level as u8;
+                heading_id = id.map(|id| id.to_string());
+                state.in_heading = true;
+                state.text_buffer.clear();
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                state.in_heading = false;
+                if !state.text_buffer.is_empty() {
+                    // In slide mode: check if we need a page break
+                    if builder.is_slide {
+                        // Auto page break before h2
+                        if heading_level == 2 {
+                            builder.new_page();
+                        }
+                        // Page break when going from lower level to higher level heading
+                        // (e.g., h3 to h1, or h4 to h2)
+                        else if let Some(prev_level) = state.prev_heading_level
+                            && heading_level < prev_level
+                        {
+                            builder.new_page();
+                        }
+                    }
+
+                    let font_size = match heading_level {
+                        1 => 24.0,
+                        2 => 20.0,
+                        3 => 16.0,
+                        _ => 14.0,
+                    };
+
+                    let spacing_before = match heading_level {
+                        1 => builder.line_height * 1.5,
+                        2 => builder.line_height * 1.25,
+                        _ => builder.line_height * 1.0,
+                    };
+
+                    let spacing_after = match heading_level {
+                        1 => builder.line_height * 1.5,
+                        2 => builder.line_height * 1.25,
+                        3 => builder.line_height * 1.5,
+                        _ => builder.line_height * 1.0,
+                    };
+
+                    builder.move_down(spacing_before);
+                    builder.check_page_break(Mm(font_size * 0.5));
+
+                    // Use heading color for slide mode
+                    let heading_color = if builder.is_slide {
+                        Some(builder.slide_theme.heading_color)
+                    } else {
+                        None
+                    };
+
+                    let top = builder.y_position + Mm(font_size / 2.83465);
+                    builder.add_heading(
+                        heading_level,
+                        heading_id.as_deref(),
+                        &state.text_buffer,
+                        top,
+                    );
+
+                    builder
+                        .tags
+                        .begin(["H1", "H2", "H3", "H4", "H5", "H6"][heading_level as usize - 1]);
+                    builder.write_text_at_with_color(
+                        &state.text_buffer,
+                        BuiltinFont::HelveticaBold,
+                        font_size,
+                        builder.left_margin,
+                        builder.y_position,
+                        heading_color,
+                    );
+                    builder.tags.end();
+                    builder.move_down(spacing_after);
+                    state.text_buffer.clear();
+
+                    // Update the previous heading level
+                    state.prev_heading_level = Some(heading_level);
+                }
+            }
+            Event::Start(Tag::Paragraph) => {
+                builder.move_down(builder.line_height * 0.5);
+                state.clear();
+            }
+            Event::End(TagEnd::Paragraph) => {
+                state.flush();
+
+                if let Some(entries) = toc
+                    && is_toc_marker(
+                        &state
+                            .text_segments
+                            .iter()
+                            .map(|segment| segment.text.as_str())
+                            .collect::<String>(),
+                    )
+                {
+                    builder.draw_toc(entries);
+                    state.text_segments.clear();
+                }
+
+                // The first paragraph of a list item carries its marker
+                if state.in_item()
+                    && let Some(marker) = state.list_marker.take()
+                {
+                    builder.draw_list_marker(&marker);
+                }
+                if !state.text_segments.is_empty() {
+                    builder.write_paragraph(&state.text_segments);
+                    state.text_segments.clear();
+                }
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                if state.in_item() {
+                    state.write_item_text(&mut builder);
+                }
+                state.in_code_block = true;
+                code_buffer.clear();
+                code_lang = match kind {
+                    CodeBlockKind::Fenced(lang) => lang.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if !code_buffer.is_empty() {
+                    let code_info: CodeBlockInfo = code_lang.parse().unwrap();
+                    for warning in &code_info.warnings {
+                        builder.warn(warning.clone());
+                    }
+
+                    // Link the filename to the source when a repo is known
+                    let github_url = code_info.filename.as_ref().and_then(|filename| {
+                        resolve_repo(code_info.repo.as_ref(), front_matter.as_ref()).map(|repo| {
+                            build_github_url(
+                                filename,
+                                code_info.start_line,
+                                repo,
+                                code_info.refspec.as_deref(),
+                            )
+                        })
+                    });
+
+                    let kind = CodeKind::of(&code_info.language);
+                    let syntax = syntax_set
+                        .find_syntax_by_token(kind.highlight_language(&code_info.language))
+                        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+                    // Diff markers and shell prompts are taken off before the
+                    // rest of the line is highlighted
+                    let code_lines: Vec<CodeLine> =
+                        code_buffer.lines().map(|line| kind.line(line)).collect();
+                    let plain = Style {
+                        foreground: theme.settings.foreground.unwrap_or(Color::BLACK),
+                        ..Style::default()
+                    };
+                    let (r, g, b) = CODE_MUTED;
+                    let muted = Style {
+                        foreground: theme.settings.gutter_foreground.unwrap_or(Color {
+                            r,
+                            g,
+                            b,
+                            a: 0xff,
+                        }),
+                        ..Style::default()
+                    };
+                    let mut highlighter = HighlightLines::new(syntax, theme);
+                    let mut highlight = |text| {
+                        highlighter
+                            .highlight_line(text, &syntax_set)
+                            .unwrap_or_else(|_| vec![])
+                    };
+                    let lines: Vec<_> = code_lines
+                        .iter()
+                        .map(|line| match *line {
+                            CodeLine::Plain(text)
+                            | CodeLine::Added(text)
+                            | CodeLine::Removed(text)
+                            | CodeLine::Unchanged(text) => highlight(text),
+                            CodeLine::DiffHeader(text) => vec![(muted, text)],
+                            CodeLine::Command { prompt, command } => {
+                                let mut spans = vec![(muted, prompt)];
+                                spans.extend(highlight(command));
+                                spans
+                            }
+                            CodeLine::Output(text) => vec![(plain, text)],
+                        })
+                        .collect();
+
+                    let overflow = match code_info.overflow.as_deref() {
+                        None => builder.code_overflow,
+                        Some(name) => CodeOverflow::parse(name).unwrap_or_else(|| {
+                            builder.warn(format!(
+                                "Unknown code overflow '{}' (expected wrap, shrink or truncate)",
+                                name
+                            ));
+                            builder.code_overflow
+                        }),
+                    };
+                    builder.write_code_block(
+                        &lines,
+                        &code_lines,
+                        &code_info,
+                        github_url.as_deref(),
+                        overflow,
+                        code_colors,
+                    );
+                    code_buffer.clear();
+                }
+                state.in_code_block = false;
+            }
+            Event::Start(Tag::List(first_number)) => {
+                if state.lists.is_empty() {
+                    builder.move_down(builder.line_height * 0.5);
+                } else {
+                    // A nested list follows the text of its item
+                    state.write_item_text(&mut builder);
+                }
+                state.lists.push(ListLevel {
+                    next_number: first_number,
+                    outer_left: builder.left_margin,
+                });
+                builder.tags.begin("L");
+            }
+            Event::End(TagEnd::List(_)) => {
+                builder.tags.end_if("L");
+                state.lists.pop();
+                if state.lists.is_empty() {
+                    builder.move_down(builder.line_height * 0.5);
+                }
+            }
+            Event::Start(Tag::Item) => {
+                builder.tags.begin("LI");
+                state.clear();
+                let depth = state.lists.len();
+                if let Some(level) = state.lists.last_mut() {
+                    let (marker, width) = match level.next_number {
+                        Some(number) => {
+                            level.next_number = Some(number + 1);
+                            (builder.list_markers.number(depth, number), NUMBER_WIDTH)
+                        }
+                        None => (builder.list_markers.bullet(depth), BULLET_WIDTH),
+                    };
+                    // Nested markers line up with their parent item's text
+                    let indent = if depth == 1 { LIST_INDENT } else { Mm(0.0) };
+                    builder.left_margin = level.outer_left + indent + width;
+                    state.list_marker = Some(marker);
+                }
+            }
+            Event::TaskListMarker(checked) => {
+                if state.list_marker.is_some() {
+                    state.list_marker = Some(ListMarker::Checkbox(checked));
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                state.write_item_text(&mut builder);
+                builder.tags.end_if("LBody");
+                builder.tags.end_if("LI");
+                if let Some(level) = state.lists.last() {
+                    builder.left_margin = level.outer_left;
+                }
+            }
+            Event::Start(Tag::Strong) => state.set_style(TextStyle::BOLD, true),
+            Event::End(TagEnd::Strong) => state.set_style(TextStyle::BOLD, false),
+            Event::Start(Tag::Emphasis) => state.set_style(TextStyle::ITALIC, true),
+            Event::End(TagEnd::Emphasis) => state.set_style(TextStyle::ITALIC, false),
+            Event::Start(Tag::Strikethrough) => state.set_style(TextStyle::STRIKETHROUGH, true),
+            Event::End(TagEnd::Strikethrough) => state.set_style(TextStyle::STRIKETHROUGH, false),
+            Event::Start(Tag::Superscript) => state.set_style(TextStyle::SUPERSCRIPT, true),
+            Event::End(TagEnd::Superscript) => state.set_style(TextStyle::SUPERSCRIPT, false),
+            Event::Start(Tag::Subscript) => state.set_style(TextStyle::SUBSCRIPT, true),
+            Event::End(TagEnd::Subscript) => state.set_style(TextStyle::SUBSCRIPT, false),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                state.flush();
+                state.link = Some(dest_url.to_string());
+            }
+            Event::End(TagEnd::Link) => {
+                state.flush();
+                state.link = None;
+            }
+            Event::Text(text) => {
+                if state.in_code_block {
+                    code_buffer.push_str(&text);
+                } else if let Some((_, alt)) = &mut state.image {
+                    alt.push_str(&text);
+                } else {
+                    state.text_buffer.push_str(&text);
+                }
+            }
+            Event::Code(code) => {
+                if let Some((_, alt)) = &mut state.image {
+                    alt.push_str(&code);
+                } else if state.in_heading {
+                    state.text_buffer.push_str(&code);
+                } else if !state.in_code_block {
+                    state.flush();
+                    let style = state.style.with(TextStyle::CODE, true);
+                    state.push(TextSegment::plain(code.to_string(), style));
+                }
+            }
+            Event::Start(Tag::Table(_)) => {
+                if state.in_item() {
+                    state.write_item_text(&mut builder);
+                }
+                state.in_table = true;
+                state.table_style = table_styles.get(current_table_index).copied();
+                current_table_index += 1;
+                table_rows.clear();
+            }
+            Event::End(TagEnd::Table) => {
+                if !table_rows.is_empty() {
+                    let num_cols = table_rows.iter().map(|row| row.len()).max().unwrap_or(0);
+                    let mut col_widths = vec![Mm(0.0); num_cols];
+
+                    // Size columns by the natural (unwrapped) width of their widest cell
+                    let space_width = builder.fonts.text_width(" ", BuiltinFont::Helvetica, 10.0);
+                    for row in table_rows.iter() {
+                        for (col_idx, cell) in row.iter().enumerate() {
+                            let words = segments_to_words(cell, 10.0, &builder.fonts);
+                            let natural_width = words
+                                .iter()
+                                .fold(Mm(0.0), |acc, word| acc + word.width + space_width);
+                            col_widths[col_idx] = Mm(col_widths[col_idx].0.max(natural_width.0));
+                        }
+                    }
+
+                    let available_width = builder.right_margin - builder.left_margin - Mm(10.0);
+                    let column_spacing = Mm(5.0);
+                    let total_spacing = column_spacing * (num_cols - 1) as f32;
+                    let usable_width = available_width - total_spacing;
+
+                    let total_width: f32 = col_widths.iter().map(|width| width.0).sum();
+                    let column_widths: Vec<Mm> = if total_width > 0.0 {
+                        col_widths
+                            .iter()
+                            .map(|&width| Mm((width.0 / total_width) * usable_width.0))
+                            .collect()
+                    } else {
+                        vec![usable_width / num_cols as f32; num_cols]
+                    };
+
+                    let is_lined = state.table_style == Some(TableStyle::Lined);
+                    let table_start_x = builder.left_margin + Mm(5.0);
+                    let line_width = 0.5;
+                    let table_width: Mm = column_widths.iter().fold(Mm(0.0), |acc, &w| acc + w)
+                        + column_spacing * (num_cols - 1) as f32;
+
+                    // For lined tables, add padding inside cells
+                    let cell_padding = if is_lined { Mm(2.5) } else { Mm(0.0) };
+                    let cell_top_padding = if is_lined { Mm(5.5) } else { Mm(0.0) };
+                    let cell_bottom_padding = if is_lined { Mm(-0.5) } else { Mm(0.0) };
+
+                    builder.tags.begin("Table");
+                    let mut is_first_row = true;
+                    for row in table_rows.iter() {
+                        builder.check_page_break(builder.line_height * 1.5);
+
+                        let row_start_y = builder.y_position;
+
+                        // Draw top border for lined tables (first row or after page break)
+                        if is_lined && is_first_row {
+                            builder.draw_line(
+                                table_start_x,
+                                row_start_y,
+                                table_start_x + table_width,
+                                row_start_y,
+                                line_width,
+                            );
+                        }
+                        builder.tags.begin("TR");
+
+                        let mut max_cell_height = Mm(0.0);
+
+                        let mut x_offset = table_start_x;
+                        for (col_idx, cell_segments) in row.iter().enumerate() {
+                            // For lined tables, start text below the top padding
+                            builder.y_position = row_start_y - cell_top_padding;
+
+                            let words = segments_to_words(cell_segments, 10.0, &builder.fonts);
+                            let col_width = column_widths.get(col_idx).copied().unwrap_or(Mm(50.0));
+
+                            // Add padding for lined tables
+                            let text_x = x_offset + cell_padding;
+                            let text_width = col_width - (cell_padding * 2.0);
+
+                            // The first row is the header row
+                            if is_first_row {
+                                let header = builder.tags.begin("TH");
+                                builder.tags.set_column_scope(header);
+                            } else {
+                                builder.tags.begin("TD");
+                            }
+                            let cell_height =
+                                builder.write_wrapped_cell(&words, text_x, 10.0, text_width);
+                            builder.tags.end();
+                            max_cell_height = Mm(max_cell_height
+                                .0
+                                .max(cell_height.0 + cell_top_padding.0 + cell_bottom_padding.0));
+
+                            x_offset += col_width + column_spacing;
+                        }
+                        builder.tags.end();
+                        is_first_row = false;
+
+                        let row_bottom_y = row_start_y - max_cell_height;
+                        builder.y_position = row_bottom_y;
+
+                        // Draw borders for this row in lined tables
+                        if is_lined {
+                            // Draw bottom border of row
+                            builder.draw_line(
+                                table_start_x,
+                                row_bottom_y,
+                                table_start_x + table_width,
+                                row_bottom_y,
+                                line_width,
+                            );
+
+                            // Draw vertical borders for this row
+                            let mut x = table_start_x;
+                            // Left border
+                            builder.draw_line(x, row_start_y, x, row_bottom_y, line_width);
+
+                            for col_width in &column_widths {
+                                x += *col_width + column_spacing;
+                                builder.draw_line(
+                                    x - column_spacing,
+                                    row_start_y,
+                                    x - column_spacing,
+                                    row_bottom_y,
+                                    line_width,
+                                );
+                            }
+                        }
+                    }
+
+                    builder.tags.end();
+                    builder.move_down(builder.line_height * 0.5);
+                    table_rows.clear();
+                }
+                state.in_table = false;
+                state.table_style = None;
+            }
+            Event::Start(Tag::TableHead) => {
+                state.in_table_head = true;
+                // TableHead directly contains cells, no TableRow wrapper
+                current_row.clear();
+            }
+            Event::End(TagEnd::TableHead) => {
+                // End of header, add the row
+                if !current_row.is_empty() {
+                    table_rows.push(current_row.clone());
+                    current_row.clear();
+                }
+                state.in_table_head = false;
+            }
+            Event::Start(Tag::TableRow) => {
+                current_row.clear();
+            }
+            Event::End(TagEnd::TableRow) => {
+                if !current_row.is_empty() {
+                    table_rows.push(current_row.clone());
+                    current_row.clear();
+                }
+            }
+            Event::Start(Tag::TableCell) => {
+                state.clear();
+            }
+            Event::End(TagEnd::TableCell) => {
+                state.flush();
+                current_row.push(std::mem::take(&mut state.current_cell_segments));
+            }
+            Event::Start(Tag::BlockQuote(kind)) => {
+                if state.in_item() {
+                    state.write_item_text(&mut builder);
+                }
+                builder.begin_quote(kind);
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                builder.end_quote();
+            }
+            // Images in headings and tables stay inline as their alt text
+            Event::Start(Tag::Image { dest_url, .. }) if !state.in_heading && !state.in_table => {
+                state.flush();
+                state.image = Some((dest_url.to_string(), String::new()));
+            }
+            Event::End(TagEnd::Image) => {
+                let Some((dest_url, alt)) = state.image.take() else {
+                    continue;
+                };
+                let image = if dest_url.contains("://") {
+                    Err(std::io::Error::other("remote images are not downloaded"))
+                } else {
+                    let path = resolve_path(&dest_url, options.source_path.as_deref());
+                    builder.ensure_image(&path)
+                };
+                match image {
+                    Ok((name, width, height)) => {
+                        // Text before the image in the same paragraph goes above it
+                        if state.in_item() {
+                            state.write_item_text(&mut builder);
+                        } else if !state.text_segments.is_empty() {
+                            builder.write_paragraph(&state.text_segments);
+                            state.text_segments.clear();
+                        }
+                        builder.draw_image(&name, width, height, alt.trim());
+                    }
+                    Err(e) => {
+                        builder.warn(format!("image '{}' left out: {}", dest_url, e));
+                        state.push(TextSegment::plain(alt, state.style));
+                    }
+                }
+            }
+            // References in headings keep no mark; their notes go at the end
+            Event::FootnoteReference(label) => match footnotes.number(&label) {
+                Some(number) if !state.in_heading && state.image.is_none() => {
+                    state.flush();
+                    let style = state.style.with(TextStyle::SUPERSCRIPT, true);
+                    state.push(TextSegment {
+                        footnote: Some(number),
+                        ..TextSegment::plain(number.to_string(), style)
+                    });
+                }
+                Some(_) => {}
+                None => state.text_buffer.push_str(&format!("[^{}]", label)),
+            },
+            Event::Rule => {
+                // Horizontal rule (---) triggers a page break
+                builder.new_page();
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if state.in_code_block {
+                    code_buffer.push('\n');
+                } else {
+                    state.text_buffer.push(' ');
+                }
+            }
+            Event::Start(_) => (),
+            Event::End(_) => (),
+            Event::InlineMath(tex) | Event::DisplayMath(tex)
+                if state.in_heading || state.image.is_some() =>
+            {
+                match &mut state.image {
+                    Some((_, alt)) => alt.push_str(&tex),
+                    None => state.text_buffer.push_str(&tex),
+                }
+            }
+            // Display math in a table cell is set inline
+            Event::DisplayMath(tex) if !state.in_table => {
+                state.flush();
+                if state.in_item() {
+                    state.write_item_text(&mut builder);
+                } else if !state.text_segments.is_empty() {
+                    builder.write_paragraph(&state.text_segments);
+                    state.text_segments.clear();
+                }
+                builder.write_display_math(&tex);
+            }
+            Event::InlineMath(tex) | Event::DisplayMath(tex) => {
+                state.flush();
+                match TextSegment::math(&tex, state.style) {
+                    Ok(segment) => state.push(segment),
+                    Err(error) => {
+                        builder.warn(format!("Cannot typeset math '{}': {}", tex, error));
+                        let style = state.style.with(TextStyle::CODE, true);
+                        state.push(TextSegment::plain(tex.to_string(), style));
+                    }
+                }
+            }
+            Event::Html(_cow_str) => (),
+            Event::InlineHtml(_cow_str) => (),
+        }
+    }
+
+    builder.draw_endnotes();
+
+    Ok(builder)
+}
+
+/// Extract embedded markdown from PDF bytes
+pub fn extract_markdown_from_pdf_bytes(pdf_bytes: &[u8]) -> Result<String, std::io::Error> {
+    // Load the PDF document from bytes using a cursor
+    let cursor = Cursor::new(pdf_bytes);
+    let doc = Document::load_from(cursor)
+        .map_err(|e| std::io::Error::other(format!("Failed to load PDF: {}", e)))?;
+
+    // Get the catalog
+    let catalog = doc
+        .catalog()
+        .map_err(|e| std::io::Error::other(format!("Failed to get catalog: {}", e)))?;
+
+    // Extract Names dictionary from catalog
+    let names_ref = catalog
+        .get(b"Names")
+        .map_err(|e| std::io::Error::other(format!("No Names dictionary in catalog: {}", e)))?;
+
+    // Resolve the Names dictionary reference
+    let names_id = if let Object::Reference(id) = names_ref {
+        *id
+    } else {
+        return Err(std::io::Error::other("Names is not a reference"));
+    };
+
+    let names_obj = doc
+        .get_object(names_id)
+        .map_err(|e| std::io::Error::other(format!("Failed to get Names object: {}", e)))?;
+
+    // Get EmbeddedFiles from Names
+    let embedded_files_ref = if let Object::Dictionary(dict) = names_obj {
+        dict.get(b"EmbeddedFiles").map_err(|e| {
+            std::io::Error::other(format!("No EmbeddedFiles in Names dictionary: {}", e))
+        })?
+    } else {
+        return Err(std::io::Error::other("Names object is not a dictionary"));
+    };
+
+    // Resolve EmbeddedFiles reference
+    let embedded_files_id = if let Object::Reference(id) = embedded_files_ref {
+        *id
+    } else {
+        return Err(std::io::Error::other("EmbeddedFiles is not a reference"));
+    };
+
+    let embedded_files_obj = doc
+        .get_object(embedded_files_id)
+        .map_err(|e| std::io::Error::other(format!("Failed to get EmbeddedFiles object: {}", e)))?;
+
+    // Get the Names array from EmbeddedFiles
+    let names_array = if let Object::Dictionary(dict) = embedded_files_obj {
+        dict.get(b"Names")
+            .map_err(|e| std::io::Error::other(format!("No Names array in EmbeddedFiles: {}", e)))?
+    } else {
+        return Err(std::io::Error::other(
+            "EmbeddedFiles object is not a dictionary",
+        ));
+    };
+
+    // Parse the Names array to find the filespec
+    let filespec_id = if let Object::Array(arr) = names_array {
+        // Names array is in format: [name1, ref1, name2, ref2, ...]
+        // We're looking for the "source" file
+        let mut found_id = None;
+        for i in (0..arr.len()).step_by(2) {
+            if let Some(Object::String(name_bytes, _)) = arr.get(i) {
+                let name = String::from_utf8_lossy(name_bytes);
+                if name == "source"
+                    && let Some(Object::Reference(id)) = arr.get(i + 1)
+                {
+                    found_id = Some(*id);
+                    break;
+                }
+            }
+        }
+        found_id.ok_or_else(|| std::io::Error::other("Source file not found in embedded files"))?
+    } else {
+        return Err(std::io::Error::other("Names is not an array"));
+    };
+
+    // Get the filespec object
+    let filespec_obj = doc
+        .get_object(filespec_id)
+        .map_err(|e| std::io::Error::other(format!("Failed to get filespec object: {}", e)))?;
+
+    // Get the EF (embedded file) dictionary from filespec
+    let ef_ref = if let Object::Dictionary(dict) = filespec_obj {
+        dict.get(b"EF")
+            .map_err(|e| std::io::Error::other(format!("No EF dictionary in filespec: {}", e)))?
+    } else {
+        return Err(std::io::Error::other("Filespec is not a dictionary"));
+    };
+
+    // Get the F (file) reference from EF
+    let file_stream_id = if let Object::Dictionary(ef_dict) = ef_ref {
+        if let Object::Reference(id) = ef_dict
+            .get(b"F")
+            .map_err(|e| std::io::Error::other(format!("No F reference in EF dictionary: {}", e)))?
+        {
+            *id
+        } else {
+            return Err(std::io::Error::other("F is not a reference"));
+        }
+    } else {
+        return Err(std::io::Error::other("EF is not a dictionary"));
+    };
+
+    // Get the embedded file stream
+    let file_stream_obj = doc
+        .get_object(file_stream_id)
+        .map_err(|e| std::io::Error::other(format!("Failed to get file stream object: {}", e)))?;
+
+    // Extract stream data (try decompression first, fall back to raw content)
+    let content = if let Object::Stream(stream) = file_stream_obj {
+        // Try to decompress if the stream has a Filter
+        if stream.dict.get(b"Filter").is_ok() {
+            stream
+                .decompressed_content()
+                .map_err(|e| std::io::Error::other(format!("Failed to decompress stream: {}", e)))?
+        } else {
+            // No filter, use raw content
+            stream.content.clone()
+        }
+    } else {
+        return Err(std::io::Error::other("Embedded file is not a stream"));
+    };
+
+    // Convert bytes to string
+    String::from_utf8(content)
+        .map_err(|e| std::io::Error::other(format!("Failed to convert to UTF-8: {}", e)))
+}
+
+/// Extract embedded markdown from a PDF file
+pub fn extract_markdown_from_pdf(pdf_path: &Path) -> Result<String, std::io::Error> {
+    // Read the PDF file into memory
+    let pdf_bytes = std::fs::read(pdf_path)?;
+    // Use the bytes-based extraction
+    extract_markdown_from_pdf_bytes(&pdf_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text shown on a page with the x position of the pen where it starts,
+    /// for text set in the standard Helvetica
+    fn shown_text_positions(doc: &Document, page: u32) -> Vec<(String, f32)> {
+        let fonts = FontSet::default();
+        let content = doc
+            .get_and_decode_page_content(doc.get_pages()[&page])
+            .unwrap();
+        let decode = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+        let mut shown = Vec::new();
+        let (mut x, mut size) = (0.0, 12.0);
+        for op in &content.operations {
+            match op.operator.as_str() {
+                "BT" => x = 0.0,
+                "Td" => x += op.operands[0].as_float().unwrap(),
+                "Tf" => size = op.operands[1].as_float().unwrap(),
+                "Tj" | "TJ" => {
+                    let parts = match op.operator.as_str() {
+                        "Tj" => op.operands.clone(),
+                        _ => op.operands[0].as_array().unwrap().clone(),
+                    };
+                    for part in parts {
+                        match part {
+                            Object::String(bytes, _) => {
+                                let text = decode(&bytes);
+                                let width = fonts.text_width(&text, BuiltinFont::Helvetica, size);
+                                shown.push((text, x));
+                                x += width.to_points();
+                            }
+                            adjust => x -= adjust.as_float().unwrap() * size / 1000.0,
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        shown
+    }
+
+    /// Test that simple markdown can be embedded and extracted
+    #[test]
+    fn test_roundtrip_simple_markdown() {
+        let markdown = "# Hello World\n\nThis is a test.";
+        let mut pdf_output = Vec::new();
+
+        // Generate PDF with embedded source
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // Extract the markdown back
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that markdown with front matter can be embedded and extracted
+    #[test]
+    fn test_roundtrip_with_frontmatter() {
+        let markdown = r#"---
+title: Test Document
+author: Test Author
+date: 2025-01-01
+code_theme: InspiredGitHub
+---
+
+# Main Title
+
+This is the content."#;
+        let mut pdf_output = Vec::new();
+
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that complex markdown with code blocks can be embedded and extracted
+    #[test]
+    fn test_roundtrip_with_code_blocks() {
+        let markdown = r#"# Code Example
+
+Here's some Rust code:
+
+```rust
+fn main() {
+    println!("Hello, world!");
+}
+```
+
+And some Python:
+
+```python
+def hello():
+    print("Hello, world!")
+```"#;
+        let mut pdf_output = Vec::new();
+
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that markdown with lists and formatting can be embedded and extracted
+    #[test]
+    fn test_roundtrip_with_lists_and_formatting() {
+        let markdown = r#"# Features
+
+## Unordered List
+- Item 1
+- Item 2
+  - Nested item
+- Item 3
+
+## Ordered List
+1. First
+2. Second
+3. Third
+
+## Formatting
+This has **bold**, *italic*, and `code` text.
+
+## Task List
+- [x] Completed task
+- [ ] Incomplete task"#;
+        let mut pdf_output = Vec::new();
+
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that markdown with tables can be embedded and extracted
+    #[test]
+    fn test_roundtrip_with_tables() {
+        let markdown = r#"# Table Example
+
+| Column 1 | Column 2 | Column 3 |
+|----------|----------|----------|
+| A        | B        | C        |
+| D        | E        | F        |"#;
+        let mut pdf_output = Vec::new();
+
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that special characters and unicode are preserved
+    #[test]
+    fn test_roundtrip_with_unicode() {
+        let markdown = r#"# Unicode Test
+
+This has special characters: é, ñ, 中文, 日本語, 한국어
+
+And symbols: © ™ ® → ← ↔ ✓ ✗
+
+Math-like: ∀ ∃ ∈ ∉ ⊂ ⊃ ∪ ∩"#;
+        let mut pdf_output = Vec::new();
+
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that markdown with links can be embedded and extracted
+    #[test]
+    fn test_roundtrip_with_links() {
+        let markdown = r#"# Links
+
+[Google](https://google.com)
+
+[GitHub](https://github.com)
+
+Reference style: [link][ref]
+
+[ref]: https://example.com"#;
+        let mut pdf_output = Vec::new();
+
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that when embed_source is false, extraction fails appropriately
+    #[test]
+    fn test_extraction_fails_when_not_embedded() {
+        let markdown = "# Test";
+        let mut pdf_output = Vec::new();
+
+        // Generate PDF WITHOUT embedded source
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        // Extraction should fail
+        let result = extract_markdown_from_pdf_bytes(&pdf_output);
+        assert!(result.is_err());
+    }
+
+    /// Test that slide mode PDFs can also embed and extract
+    #[test]
+    fn test_roundtrip_slide_mode() {
+        let markdown = r#"# Slide 1
+
+Content for first slide.
+
+## Slide 2
+
+Content for second slide."#;
+        let mut pdf_output = Vec::new();
+
+        // Generate slides with embedded source
+        to_pdf(
+            markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                is_slide: true,
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+
+    /// Test that headings become a nested outline shown when the PDF opens
+    #[test]
+    fn test_outline_from_headings() {
+        let markdown = "---\noutline_depth: 2\n---\n# Über\n\nText.\n\n## Part\n\n### Hidden\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let catalog = doc.catalog().unwrap();
+        assert_eq!(
+            catalog.get(b"PageMode").unwrap().as_name().unwrap(),
+            b"UseOutlines"
+        );
+
+        let outline_id = catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+        let outline = doc.get_dictionary(outline_id).unwrap();
+        assert_eq!(outline.get(b"Count").unwrap().as_i64().unwrap(), 2);
+
+        let first_id = outline.get(b"First").unwrap().as_reference().unwrap();
+        let first = doc.get_dictionary(first_id).unwrap();
+        let title = first.get(b"Title").unwrap().as_str().unwrap();
+        assert_eq!(
+            title,
+            [0xFE, 0xFF, 0x00, 0xDC, 0x00, 0x62, 0x00, 0x65, 0x00, 0x72]
+        );
+        assert_eq!(first.get(b"Count").unwrap().as_i64().unwrap(), 1);
+    }
+
+    /// Test that links become URI annotations, one per line a link wraps over
+    #[test]
+    fn test_link_annotations() {
+        let filler = "word ".repeat(28);
+        let markdown = format!(
+            "---\nrepo: owner/project\n---\n{}[a link that wraps onto the next line](https://example.com/page) end.\n\n```src/lib.rs:10\nfn main() {{}}\n```\n",
+            filler
+        );
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let annotations = doc
+            .get_dictionary(page_id)
+            .unwrap()
+            .get(b"Annots")
+            .unwrap()
+            .as_array()
+            .unwrap();
+
+        let uris: Vec<Vec<u8>> = annotations
+            .iter()
+            .map(|annotation| {
+                let annotation = doc
+                    .get_dictionary(annotation.as_reference().unwrap())
+                    .unwrap();
+                let action = annotation.get(b"A").unwrap().as_dict().unwrap();
+                action.get(b"URI").unwrap().as_str().unwrap().to_vec()
+            })
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                b"https://example.com/page".to_vec(),
+                b"https://example.com/page".to_vec(),
+                b"https://github.com/owner/project/blob/main/src/lib.rs#L10".to_vec(),
+            ]
+        );
+    }
+
+    /// Test that headings get named destinations and fragment links jump to them
+    #[test]
+    fn test_fragment_links() {
+        let markdown = "[see setup](#setup) and [details](#custom)\n\n# Setup\n\n# Custom\n\n## Other {#custom}\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let dests_id = doc
+            .catalog()
+            .unwrap()
+            .get(b"Dests")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let dests = doc.get_dictionary(dests_id).unwrap();
+        assert!(dests.has(b"setup"));
+        assert!(dests.has(b"custom"));
+        // The explicit id is kept for its heading, not taken by an earlier slug
+        assert!(dests.has(b"custom-1"));
+
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let annotations = doc
+            .get_dictionary(page_id)
+            .unwrap()
+            .get(b"Annots")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        let targets: Vec<&[u8]> = annotations
+            .iter()
+            .map(|annotation| {
+                let annotation = doc
+                    .get_dictionary(annotation.as_reference().unwrap())
+                    .unwrap();
+                let action = annotation.get(b"A").unwrap().as_dict().unwrap();
+                assert_eq!(action.get(b"S").unwrap().as_name().unwrap(), b"GoTo");
+                action.get(b"D").unwrap().as_name().unwrap()
+            })
+            .collect();
+        assert_eq!(targets, [b"setup".as_slice(), b"custom".as_slice()]);
+    }
+
+    /// Test that the table of contents lists the pages the headings land on
+    #[test]
+    fn test_table_of_contents_page_numbers() {
+        let body = "Lorem ipsum dolor sit amet. ".repeat(40);
+        let mut markdown = String::from("---\ntoc: true\n---\n");
+        for section in ["Alpha", "Beta", "Gamma"] {
+            markdown.push_str(&format!("# {}\n\n", section));
+            for _ in 0..6 {
+                markdown.push_str(&format!("{}\n\n", body));
+            }
+        }
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let pages = doc.get_pages();
+        let page_numbers: std::collections::HashMap<ObjectId, u32> =
+            pages.iter().map(|(number, id)| (*id, *number)).collect();
+        let dests_id = doc
+            .catalog()
+            .unwrap()
+            .get(b"Dests")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let dests = doc.get_dictionary(dests_id).unwrap();
+
+        let toc_page = doc.get_page_content(pages[&1]).unwrap();
+        let toc_text = String::from_utf8_lossy(&toc_page);
+        let mut previous = 0;
+        for id in ["alpha", "beta", "gamma"] {
+            let dest = dests.get(id.as_bytes()).unwrap().as_array().unwrap();
+            let page = page_numbers[&dest[0].as_reference().unwrap()];
+            assert!(page > previous, "{} should follow the previous section", id);
+            assert!(toc_text.contains(&format!("({}) Tj", page)));
+            previous = page;
+        }
+    }
+
+    /// Test that the TOC goes where a `[[TOC]]` paragraph is and that a
+    /// marker inside a code block is left as code
+    #[test]
+    fn test_table_of_contents_marker() {
+        let render = |markdown: &str| {
+            let mut pdf_output = Vec::new();
+            to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+            let doc = Document::load_mem(&pdf_output).unwrap();
+            let content = doc.get_page_content(doc.get_pages()[&1]).unwrap();
+            String::from_utf8_lossy(&content).into_owned()
+        };
+
+        let text = render("# Intro\n\n[[TOC]]\n\n# Usage\n");
+        assert_eq!(text.matches("(Contents) Tj").count(), 1);
+        assert!(!text.contains("([[TOC]]) Tj"));
+        // The list follows the heading before the marker
+        assert!(text.find("(Intro) Tj").unwrap() < text.find("(Contents) Tj").unwrap());
+
+        let text = render("# Intro\n\n```\n[[_TOC_]]\n```\n");
+        assert!(!text.contains("(Contents) Tj"));
+        assert!(text.contains("[[_TOC_]]"));
+    }
+
+    /// Test that headers and footers are filled in on every page
+    #[test]
+    fn test_running_headers_and_footers() {
+        let body = "Lorem ipsum dolor sit amet. ".repeat(40);
+        let markdown = format!(
+            "---\ntitle: Report\nheader:\n  left: \"{{title}}\"\n  right: \"{{section}}\"\n  skip_first_page: true\nfooter:\n  centre: \"Page {{page}} of {{pages}}\"\n---\n# Findings\n\n{}\n\n{}\n\n{}\n",
+            body, body, body
+        );
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+
+        let first = doc.get_page_content(pages[&1]).unwrap();
+        let first = String::from_utf8_lossy(&first);
+        assert!(first.contains("(Page 1 of 2) Tj"));
+        // The title is drawn in the body, but the header skips this page
+        assert_eq!(first.matches("(Report) Tj").count(), 1);
+
+        let second = doc.get_page_content(pages[&2]).unwrap();
+        let second = String::from_utf8_lossy(&second);
+        assert!(second.contains("(Page 2 of 2) Tj"));
+        assert!(second.contains("(Report) Tj"));
+        assert!(second.contains("(Findings) Tj"));
+    }
+
+    /// Test that every page lists the font of its running text, including
+    /// pages whose body uses other fonts
+    #[test]
+    fn test_running_text_fonts() {
+        let code: String = (0..120)
+            .map(|line| format!("let x{} = {};\n", line, line))
+            .collect();
+        let markdown = format!(
+            "---\nfooter:\n  centre: \"Page {{page}}\"\n---\n```\n{}```\n",
+            code
+        );
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        assert!(doc.get_pages().len() > 1);
+        for page_id in doc.get_pages().into_values() {
+            let fonts = doc
+                .get_dictionary(page_id)
+                .unwrap()
+                .get(b"Resources")
+                .unwrap()
+                .as_dict()
+                .unwrap()
+                .get(b"Font")
+                .unwrap()
+                .as_dict()
+                .unwrap();
+            let content = doc.get_and_decode_page_content(page_id).unwrap();
+            for op in content.operations.iter().filter(|op| op.operator == "Tf") {
+                let name = op.operands[0].as_name().unwrap();
+                assert!(
+                    fonts.has(name),
+                    "{} is not listed",
+                    String::from_utf8_lossy(name)
+                );
+            }
+        }
+    }
+
+    /// Test that front matter fills the document information and XMP metadata
+    #[test]
+    fn test_document_metadata() {
+        let markdown = "---\ntitle: Quarterly Résumé\nauthor: Jane Doe\ndate: 2025-10-16\nsubject: Sales figures\nkeywords: [sales, q3]\n---\n# Summary\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let info_id = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        let info = doc.get_dictionary(info_id).unwrap();
+        let text = |key: &[u8]| lopdf::decode_text_string(info.get(key).unwrap()).unwrap();
+        assert_eq!(text(b"Title"), "Quarterly Résumé");
+        assert_eq!(text(b"Author"), "Jane Doe");
+        assert_eq!(text(b"Subject"), "Sales figures");
+        assert_eq!(text(b"Keywords"), "sales, q3");
+        assert_eq!(text(b"CreationDate"), "D:20251016");
+        assert!(text(b"ModDate").starts_with("D:"));
+
+        let catalog = doc.catalog().unwrap();
+        let preferences = catalog
+            .get(b"ViewerPreferences")
+            .unwrap()
+            .as_dict()
+            .unwrap();
+        assert!(
+            preferences
+                .get(b"DisplayDocTitle")
+                .unwrap()
+                .as_bool()
+                .unwrap()
+        );
+
+        let metadata_id = catalog.get(b"Metadata").unwrap().as_reference().unwrap();
+        let metadata = doc.get_object(metadata_id).unwrap().as_stream().unwrap();
+        let packet = String::from_utf8_lossy(&metadata.content);
+        assert!(packet.contains("<rdf:li xml:lang=\"x-default\">Quarterly Résumé</rdf:li>"));
+        assert!(packet.contains("<rdf:li>q3</rdf:li>"));
+        assert!(packet.contains("<xmp:CreateDate>2025-10-16</xmp:CreateDate>"));
+    }
+
+    /// Test that content is tagged and the structure tree reaches links
+    #[test]
+    fn test_tagged_structure() {
+        let markdown = "# Title\n\nSee [the site](https://example.com).\n\n- [x] done\n\n| A | B |\n|---|---|\n| 1 | 2 |\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let catalog = doc.catalog().unwrap();
+        assert!(catalog.get(b"StructTreeRoot").is_ok());
+        let mark_info = catalog.get(b"MarkInfo").unwrap().as_dict().unwrap();
+        assert!(mark_info.get(b"Marked").unwrap().as_bool().unwrap());
+        assert_eq!(
+            lopdf::decode_text_string(catalog.get(b"Lang").unwrap()).unwrap(),
+            "en"
+        );
+
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let content = String::from_utf8_lossy(&doc.get_page_content(page_id).unwrap()).to_string();
+        for tag in [
+            "/H1 <</MCID 0>> BDC",
+            "/P <<",
+            "/Link <<",
+            "/Lbl <<",
+            "/TH <<",
+            "/TD <<",
+        ] {
+            assert!(content.contains(tag), "missing {}", tag);
+        }
+        // Table rules are artifacts
+        assert!(content.contains("/Artifact BMC"));
+
+        let roles: Vec<Vec<u8>> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.has_type(b"StructElem"))
+            .filter_map(|dict| Some(dict.get(b"S").ok()?.as_name().ok()?.to_vec()))
+            .collect();
+        assert!(roles.contains(&b"LBody".to_vec()));
+        assert!(roles.contains(&b"Table".to_vec()));
+
+        let page = doc.get_dictionary(page_id).unwrap();
+        let annotations = page.get(b"Annots").unwrap().as_array().unwrap();
+        let link = doc
+            .get_dictionary(annotations[0].as_reference().unwrap())
+            .unwrap();
+        assert!(link.get(b"StructParent").is_ok());
+    }
+
+    /// Test that every piece of text is inside marked content, as tagged
+    /// content or as an artifact
+    #[test]
+    fn test_text_is_marked() {
+        let markdown = "---\nfooter:\n  centre: \"Page {page}\"\n---\n# Title\n\nBody text[^a] and ^2^ more[^b] [a link](https://example.com) $x^2$ end.\n\n- item[^a] after\n\n| A | B[^b] c |\n|---|---|\n| `1` | 2 |\n\n[^a]: First *note*.\n[^b]: Second note.\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        for page_id in doc.get_pages().into_values() {
+            let content = doc.get_and_decode_page_content(page_id).unwrap();
+            let mut depth = 0;
+            for op in &content.operations {
+                match op.operator.as_str() {
+                    "BDC" | "BMC" => depth += 1,
+                    "EMC" => depth -= 1,
+                    "Tj" | "TJ" | "'" | "\"" => {
+                        assert!(depth > 0, "{:?} outside marked content", op)
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Test that PDF/A output is identified, self-contained and self-checked
+    #[test]
+    fn test_pdfa_output() {
+        let options = PdfOptions {
+            embed_source: true,
+            pdfa: true,
+            ..Default::default()
+        };
+
+        // The standard fonts cannot be embedded, so text fails the self-check
+        let error = to_pdf("# Title\n", &mut Vec::new(), &options).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("font Helvetica-Bold is not embedded")
+        );
+
+        // A document without text needs no fonts
+        let mut pdf_output = Vec::new();
+        to_pdf("***\n", &mut pdf_output, &options).unwrap();
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        assert!(pdfa::check(&doc).is_empty());
+        assert_eq!(doc.trailer.get(b"ID").unwrap().as_array().unwrap().len(), 2);
+
+        let catalog = doc.catalog().unwrap();
+        let metadata_id = catalog.get(b"Metadata").unwrap().as_reference().unwrap();
+        let metadata = doc.get_object(metadata_id).unwrap().as_stream().unwrap();
+        assert!(
+            String::from_utf8_lossy(&metadata.content).contains("<pdfaid:part>3</pdfaid:part>")
+        );
+
+        let intents = catalog.get(b"OutputIntents").unwrap().as_array().unwrap();
+        assert_eq!(intents.len(), 1);
+        let source_id = catalog.get(b"AF").unwrap().as_array().unwrap()[0]
+            .as_reference()
+            .unwrap();
+        let source = doc.get_dictionary(source_id).unwrap();
+        assert_eq!(
+            source.get(b"AFRelationship").unwrap().as_name().unwrap(),
+            b"Source"
+        );
+    }
+
+    /// Test that text set in embedded fonts passes the PDF/A self-check
+    #[test]
+    fn test_pdfa_output_with_embedded_fonts() {
+        let dir = Path::new("/usr/share/fonts/truetype/dejavu");
+        if !dir.exists() {
+            return;
+        }
+        let font = |name: &str| Some(dir.join(name).to_string_lossy().into_owned());
+        let options = PdfOptions {
+            pdfa: true,
+            fonts: FontConfig {
+                regular: font("DejaVuSans.ttf"),
+                bold: font("DejaVuSans-Bold.ttf"),
+                italic: font("DejaVuSans-Oblique.ttf"),
+                bold_italic: font("DejaVuSans-BoldOblique.ttf"),
+                mono: font("DejaVuSansMono.ttf"),
+                math: font("DejaVuSans.ttf"),
+            },
+            ..Default::default()
+        };
+        let markdown = "# Title\n\nPlain, **bold**, *italic*, ***both*** and `code`.\n\n\
+                        $\\alpha \\le \\sum x$\n\n```\nfn main() {}\n```\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &options).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        assert_eq!(pdfa::check(&doc), Vec::<String>::new());
+        let fonts = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0"))
+            .count();
+        assert!(fonts >= 5, "only {} fonts embedded", fonts);
+    }
+
+    /// Test that PDF/A output refuses a CMYK image, which the sRGB output
+    /// intent does not describe
+    #[test]
+    fn test_pdfa_output_with_cmyk_image() {
+        let dir = std::env::temp_dir().join(format!("mdreport-cmyk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Header of a 40x30 baseline JPEG with 4 components
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xC0, 0, 20, 8, 0, 30, 0, 40, 4];
+        jpeg.extend_from_slice(&[1, 0x11, 0, 2, 0x11, 0, 3, 0x11, 0, 4, 0x11, 0, 0xFF, 0xD9]);
+        std::fs::write(dir.join("cmyk.jpg"), &jpeg).unwrap();
+
+        let options = PdfOptions {
+            source_path: Some(dir.join("report.md")),
+            pdfa: true,
+            ..Default::default()
+        };
+        let error = to_pdf("![](cmyk.jpg)\n", &mut Vec::new(), &options).unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("page 1 draws a DeviceCMYK image"),
+            "{}",
+            error
+        );
+    }
+
+    /// Test that ragged lines fit the measure and justified lines fill it
+    #[test]
+    fn test_line_widths_match_alignment() {
+        let fonts = FontSet::default();
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+        let words = segments_to_words(
+            &[TextSegment::plain(text, TextStyle::default())],
+            12.0,
+            &fonts,
+        );
+        let english = Hyphenator::english();
+        let width = 120.0;
+
+        for align in [TextAlign::Ragged, TextAlign::Justify] {
+            let (items, _) = words_to_items(&words, 12.0, &fonts, align, Some(&english));
+            let breaks = find_line_breaks(&items, &LineBreakParams::new(width));
+
+            let mut line_start = 0;
+            for (idx, line) in breaks.iter().enumerate() {
+                let ratio = if line.ratio.is_finite() {
+                    line.ratio
+                } else {
+                    0.0
+                };
+                // Width of the line as drawn: glue before the first box is discarded
+                let mut line_width = 0.0;
+                let mut seen_box = false;
+                for item in &items[line_start..line.position] {
+                    match *item {
+                        Item::Box { width } => {
+                            line_width += width;
+                            seen_box = true;
+                        }
+                        Item::Glue {
+                            width,
+                            stretch,
+                            shrink,
+                        } if seen_box && stretch != FILL => {
+                            let adjust = if ratio > 0.0 { stretch } else { shrink };
+                            line_width += width + adjust * ratio;
+                        }
+                        _ => {}
+                    }
+                }
+
+                assert!(line_width <= width + 0.01, "{align:?} line {idx} overflows");
+                if align == TextAlign::Justify && idx + 1 < breaks.len() {
+                    assert!(
+                        (line_width - width).abs() < 0.01,
+                        "line {idx} not justified"
+                    );
+                }
+                line_start = line.position + 1;
+            }
+        }
+    }
+
+    /// Test that the hyphen at a break inside a styled word is measured in
+    /// the word's font and size
+    #[test]
+    fn test_hyphen_width_follows_style() {
+        let fonts = FontSet::default();
+        let english = Hyphenator::english();
+        let style = TextStyle::BOLD.with(TextStyle::SUPERSCRIPT, true);
+        let words = segments_to_words(
+            &[TextSegment::plain(
+                "internationalization".to_string(),
+                style,
+            )],
+            12.0,
+            &fonts,
+        );
+        let (items, pieces) =
+            words_to_items(&words, 12.0, &fonts, TextAlign::Ragged, Some(&english));
+        assert!(pieces.len() > 1);
+
+        let hyphen = fonts.text_width("-", BuiltinFont::HelveticaBold, 8.4).0;
+        let hyphens: Vec<f32> = items
+            .iter()
+            .filter_map(|item| match *item {
+                Item::Penalty { width, .. } if width > 0.0 => Some(width),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(hyphens.len(), pieces.len() - 1);
+        assert!(hyphens.iter().all(|&width| (width - hyphen).abs() < 1e-4));
+    }
+
+    /// Test that images become figures that fit the text width and the page
+    #[test]
+    fn test_embedded_images() {
+        let dir = std::env::temp_dir().join(format!("mdreport-images-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Header of a 3000x1000 baseline JPEG; the scan data is not read
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xC0, 0, 17, 8];
+        jpeg.extend_from_slice(&1000u16.to_be_bytes());
+        jpeg.extend_from_slice(&3000u16.to_be_bytes());
+        jpeg.extend_from_slice(&[3, 1, 0x11, 0, 2, 0x11, 0, 3, 0x11, 0, 0xFF, 0xD9]);
+        std::fs::write(dir.join("wide.jpg"), &jpeg).unwrap();
+
+        let body = "Lorem ipsum dolor sit amet. ".repeat(60);
+        let markdown = format!(
+            "{}\n\n![Wide chart](wide.jpg)\n\n![](missing.png)\n\n![Wide chart again](wide.jpg)\n",
+            body
+        );
+        let mut pdf_output = Vec::new();
+        to_pdf(
+            &markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                source_path: Some(dir.join("report.md")),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let images: Vec<&Stream> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .filter(|stream| {
+                stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Image")
+            })
+            .collect();
+        // The same file is embedded once
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].content, jpeg);
+        assert_eq!(
+            images[0].dict.get(b"Filter").unwrap().as_name().unwrap(),
+            b"DCTDecode"
+        );
+
+        // The second figure does not fit below the first and moves on
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+        for page_id in pages.values() {
+            let content = doc.get_and_decode_page_content(*page_id).unwrap();
+            let placement = content
+                .operations
+                .iter()
+                .find(|op| op.operator == "cm")
+                .unwrap();
+            // Scaled down to the 170 mm text width
+            let width = placement.operands[0].as_float().unwrap();
+            assert!((width - Mm(170.0).to_points()).abs() < 0.1);
+            assert!(content.operations.iter().any(|op| op.operator == "Do"));
+        }
+        let first = doc.get_page_content(pages[&1]).unwrap();
+        assert!(String::from_utf8_lossy(&first).contains("(Wide chart) Tj"));
+
+        let figures: Vec<String> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.get(b"S").and_then(Object::as_name).ok() == Some(b"Figure"))
+            .map(|dict| lopdf::decode_text_string(dict.get(b"Alt").unwrap()).unwrap())
+            .collect();
+        assert_eq!(figures.len(), 2);
+        assert!(figures.contains(&"Wide chart again".to_string()));
+    }
+
+    /// Test that quote rules continue across pages and alerts become callouts
+    #[test]
+    fn test_block_quotes_and_alerts() {
+        let body = "Lorem ipsum dolor sit amet. ".repeat(40);
+        let markdown = format!(
+            "> [!NOTE]\n> Read this first.\n>\n> > Nested quote\n\n> {}\n>\n> {}\n>\n> {}\n",
+            body, body, body
+        );
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let pages = doc.get_pages();
+        assert_eq!(pages.len(), 2);
+
+        let rule_width = Mm(1.0).to_points();
+        let rules = |page: u32| {
+            let content = doc.get_and_decode_page_content(pages[&page]).unwrap();
+            content
+                .operations
+                .iter()
+                .filter(|op| {
+                    op.operator == "re"
+                        && (op.operands[2].as_float().unwrap() - rule_width).abs() < 0.01
+                })
+                .count()
+        };
+        // The nested quote and the long quote on the first page, the long
+        // quote again on the second
+        assert_eq!(rules(1), 2);
+        assert_eq!(rules(2), 1);
+
+        let first = doc.get_and_decode_page_content(pages[&1]).unwrap();
+        let note = rgb(AlertStyle::of(BlockQuoteKind::Note).background());
+        let fills_note = first.operations.iter().any(|op| {
+            op.operator == "rg"
+                && (op.operands[0].as_float().unwrap() - note.0).abs() < 0.001
+                && (op.operands[2].as_float().unwrap() - note.2).abs() < 0.001
+        });
+        assert!(fills_note);
+        // The box is painted before the title drawn on it
+        let text = String::from_utf8_lossy(&doc.get_page_content(pages[&1]).unwrap()).to_string();
+        assert!(text.find(" re").unwrap() < text.find("(Note) Tj").unwrap());
+
+        let quotes = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.get(b"S").and_then(Object::as_name).ok() == Some(b"BlockQuote"))
+            .count();
+        assert_eq!(quotes, 3);
+    }
+
+    #[test]
+    fn test_nested_and_numbered_lists() {
+        let markdown = "---\nlist_style:\n  numbers: [\"1.\", \"A.\"]\n---\n\n3. Third\n4. Fourth\n   1. Inner\n   2. Inner too\n\n- Top\n  - Middle\n    - Deep\n      - [x] Done\n\n- Loose item\n\n  Second paragraph\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let content = doc.get_and_decode_page_content(page_id).unwrap();
+        let text = String::from_utf8_lossy(&doc.get_page_content(page_id).unwrap()).to_string();
+        for marker in ["(3.) Tj", "(4.) Tj", "(A.) Tj", "(B.) Tj"] {
+            assert!(text.contains(marker), "missing {}", marker);
+        }
+        assert!(!text.contains("(- ) Tj"));
+
+        // Each nesting level starts further right
+        let x_of = |needle: &str| {
+            let at = content
+                .operations
+                .iter()
+                .position(|op| {
+                    op.operator == "Tj"
+                        && op.operands[0]
+                            .as_str()
+                            .is_ok_and(|bytes| needle.as_bytes().starts_with(bytes))
+                })
+                .unwrap();
+            content.operations[..at]
+                .iter()
+                .rev()
+                .find(|op| op.operator == "Td")
+                .unwrap()
+                .operands[0]
+                .as_float()
+                .unwrap()
+        };
+        assert!(x_of("Top") < x_of("Middle"));
+        assert!(x_of("Middle") < x_of("Deep"));
+        assert!(x_of("Deep") < x_of("Done"));
+        assert!(x_of("Third") == x_of("Fourth"));
+        assert!(x_of("Fourth") < x_of("Inner"));
+        // Later paragraphs of an item keep its indent
+        assert_eq!(x_of("Loose item"), x_of("Second paragraph"));
+
+        // The deep task item gets a checkbox, the bullets are shapes
+        let labels: Vec<String> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.get(b"S").and_then(Object::as_name).ok() == Some(b"Lbl"))
+            .filter_map(|dict| lopdf::decode_text_string(dict.get(b"Alt").ok()?).ok())
+            .collect();
+        assert!(labels.contains(&"checked".to_string()));
+        assert_eq!(labels.iter().filter(|alt| *alt == "bullet").count(), 4);
+    }
+
+    #[test]
+    fn test_strikethrough_and_scripts() {
+        let markdown = "Keep ~~this *part* out~~ of note ^2^ and x ~i~ too.\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let content = doc.get_and_decode_page_content(page_id).unwrap();
+
+        // One line through the whole struck run, across its styles
+        let strokes = content
+            .operations
+            .iter()
+            .filter(|op| op.operator == "S")
+            .count();
+        assert_eq!(strokes, 1);
+        let fonts: Vec<f32> = content
+            .operations
+            .iter()
+            .filter(|op| op.operator == "Tf")
+            .map(|op| op.operands[1].as_float().unwrap())
+            .collect();
+        assert!(fonts.contains(&12.0));
+        assert!(fonts.iter().any(|&size| size < 12.0));
+        let rises: Vec<f32> = content
+            .operations
+            .iter()
+            .filter(|op| op.operator == "Ts")
+            .map(|op| op.operands[0].as_float().unwrap())
+            .filter(|&rise| rise != 0.0)
+            .collect();
+        assert_eq!(rises.len(), 2);
+        assert!(rises[0] > 0.0 && rises[1] < 0.0);
+
+        // A space after raised or lowered text is as wide as any other
+        let shown = shown_text_positions(&doc, 1);
+        let x_of = |text: &str| shown.iter().find(|(shown, _)| shown == text).unwrap().1;
+        let fonts = FontSet::default();
+        let width = |text: &str, size: f32| {
+            fonts
+                .text_width(text, BuiltinFont::Helvetica, size)
+                .to_points()
+        };
+        let space = width(" ", 12.0);
+        assert!((x_of("and") - (x_of("2") + width("2", 8.4) + space)).abs() < 0.01);
+        assert!((x_of("too.") - (x_of("i") + width("i", 8.4) + space)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let markdown = "Body text[^a] and more[^b].\n\n[^a]: First *note*.\n[^b]: Second note.\n";
+        let note_fonts = |markdown: &str| {
+            let mut pdf_output = Vec::new();
+            to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+            let doc = Document::load_mem(&pdf_output).unwrap();
+            let page_id = *doc.get_pages().values().next().unwrap();
+            let content = doc.get_and_decode_page_content(page_id).unwrap();
+            let fonts: Vec<f32> = content
+                .operations
+                .iter()
+                .filter(|op| op.operator == "Tf")
+                .map(|op| op.operands[1].as_float().unwrap())
+                .collect();
+            let notes = doc
+                .objects
+                .values()
+                .filter_map(|object| object.as_dict().ok())
+                .filter(|dict| dict.get(b"S").and_then(Object::as_name).ok() == Some(b"Note"))
+                .count();
+            let raw = String::from_utf8_lossy(&doc.get_page_content(page_id).unwrap()).to_string();
+            (fonts, notes, raw)
+        };
+
+        // Notes at the foot of the page, below a rule that is an artifact
+        let (fonts, notes, content) = note_fonts(markdown);
+        assert!(fonts.contains(&NOTE_SIZE));
+        assert_eq!(notes, 2);
+        assert!(content.contains("/Note <<"));
+        assert!(content.contains("/Artifact BMC"));
+
+        // Or gathered under a heading at the end of the document
+        let (fonts, notes, content) =
+            note_fonts(&format!("---\nfootnotes: end\n---\n\n{}", markdown));
+        assert!(!fonts.contains(&NOTE_SIZE));
+        assert_eq!(notes, 2);
+        assert!(content.contains("/H2 <<"));
+
+        let mut pdf_output = Vec::new();
+        let result = to_pdf(
+            &format!("---\nfootnotes: side\n---\n\n{}", markdown),
+            &mut pdf_output,
+            &PdfOptions::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    /// Test that the space after a footnote mark is as wide as any other,
+    /// so the words and links after it land where they are measured
+    #[test]
+    fn test_space_after_footnote_mark() {
+        let markdown = "word[^1] [next](https://example.com)\n\n[^1]: Note.\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let shown = shown_text_positions(&doc, 1);
+        let x_of = |text: &str| shown.iter().find(|(shown, _)| shown == text).unwrap().1;
+        let fonts = FontSet::default();
+        let width = |text: &str, size: f32| {
+            fonts
+                .text_width(text, BuiltinFont::Helvetica, size)
+                .to_points()
+        };
+        let expected = x_of("word") + width("word", 12.0) + width("1", 8.4) + width(" ", 12.0);
+        assert!((x_of("next") - expected).abs() < 0.01);
+
+        // The link starts where its text does
+        let annotation = doc
+            .get_dictionary(doc.get_pages()[&1])
+            .unwrap()
+            .get(b"Annots")
+            .unwrap()
+            .as_array()
+            .unwrap()[0]
+            .as_reference()
+            .unwrap();
+        let rect = doc
+            .get_dictionary(annotation)
+            .unwrap()
+            .get(b"Rect")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        assert!((rect[0].as_float().unwrap() - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_math() {
+        let markdown = "Energy $E = mc^2$ and $\\frac{a}{2}$ inline.\n\n$$\\sqrt{\\alpha + \\beta}$$\n\nBroken $\\foo{x}$ math.\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let content = String::from_utf8_lossy(&doc.get_page_content(page_id).unwrap()).to_string();
+
+        // Three formulas, the broken one left as code
+        assert_eq!(content.matches("/Formula <<").count(), 3);
+        assert!(content.contains("(\\\\foo{x}) Tj"));
+        // Fraction bar and radical sign
+        assert!(content.contains(" re\nf\n"));
+        assert!(content.contains(" l\nS\n"));
+
+        let formulas: Vec<String> = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .filter(|dict| dict.get(b"S").and_then(Object::as_name).ok() == Some(b"Formula"))
+            .map(|dict| {
+                let alt = dict.get(b"Alt").and_then(Object::as_str).unwrap();
+                String::from_utf8_lossy(alt).to_string()
+            })
+            .collect();
+        assert!(formulas.contains(&"E = mc^2".to_string()));
+        assert!(formulas.contains(&"\\sqrt{\\alpha + \\beta}".to_string()));
+
+        // Greek letters come from the Symbol font
+        let symbol = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .any(|dict| dict.get(b"BaseFont").and_then(Object::as_name).ok() == Some(b"Symbol"));
+        assert!(symbol);
+    }
+
+    #[test]
+    fn test_code_panels() {
+        let mut markdown =
+            String::from("---\ncode_theme: base16-ocean.dark\n---\n\n```src/main.rs:95\n");
+        for i in 0..80 {
+            markdown.push_str(&format!("let x{} = {};\n", i, i));
+        }
+        markdown.push_str("```\n");
+
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let pages: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+        assert_eq!(pages.len(), 2);
+        let content = |page: ObjectId| {
+            String::from_utf8_lossy(&doc.get_page_content(page).unwrap()).to_string()
+        };
+        let (first, second) = (content(pages[0]), content(pages[1]));
+
+        // Filled with the theme's background, numbered from the start line
+        let background = doc
+            .get_and_decode_page_content(pages[0])
+            .unwrap()
+            .operations
+            .iter()
+            .filter(|op| op.operator == "rg")
+            .any(|op| {
+                let channels: Vec<f32> =
+                    op.operands.iter().map(|o| o.as_float().unwrap()).collect();
+                channels == [43.0 / 255.0, 48.0 / 255.0, 59.0 / 255.0]
+            });
+        assert!(background);
+        assert!(first.contains("(src/main.rs) Tj"));
+        assert!(first.contains("(95) Tj"));
+        assert!(first.contains("/Code <<"));
+        assert!(!first.contains("(continued)"));
+
+        // The rest of the panel carries on under a repeated header
+        assert!(second.contains("(src/main.rs (continued)) Tj"));
+        assert!(second.contains("(174) Tj"));
+        assert!(second.find(" re\nf\n").unwrap() < second.find("(let) Tj").unwrap());
+    }
+
+    #[test]
+    fn test_code_overflow() {
+        let long_line = format!("let total = {};", vec!["value"; 30].join(" + "));
+        let operations = |markdown: &str| {
+            let mut pdf_output = Vec::new();
+            to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+            let doc = Document::load_mem(&pdf_output).unwrap();
+            let page_id = *doc.get_pages().values().next().unwrap();
+            doc.get_and_decode_page_content(page_id).unwrap().operations
+        };
+        let code_sizes = |operations: &[Operation]| -> Vec<f32> {
+            operations
+                .iter()
+                .filter(|op| op.operator == "Tf" && op.operands[0].as_name().ok() == Some(b"F0"))
+                .map(|op| op.operands[1].as_float().unwrap())
+                .collect()
+        };
+        let shown = |operations: &[Operation]| -> Vec<Vec<u8>> {
+            operations
+                .iter()
+                .filter(|op| op.operator == "Tj")
+                .filter_map(|op| op.operands[0].as_str().ok().map(<[u8]>::to_vec))
+                .collect()
+        };
+
+        // Wrapped by default, continuing after a drawn mark
+        let wrapped = operations(&format!("```rust\n{}\n```\n", long_line));
+        let rows = wrapped
+            .iter()
+            .filter(|op| op.operator == "BDC" && op.operands[0].as_name().ok() == Some(b"Code"))
+            .count();
+        assert!(rows > 1);
+        assert!(wrapped.iter().any(|op| op.operator == "S"));
+        assert!(code_sizes(&wrapped).iter().all(|&size| size == CODE_SIZE));
+
+        // Shrunk to fit, for the whole document
+        let shrunk = operations(&format!(
+            "---\ncode_overflow: shrink\n---\n\n```rust\n{}\n```\n",
+            long_line
+        ));
+        assert!(code_sizes(&shrunk).iter().all(|&size| size < CODE_SIZE));
+
+        // Or cut short for one block
+        let truncated = operations(&format!(
+            "```rust {{overflow=truncate}}\n{}\n```\n",
+            long_line
+        ));
+        let text = shown(&truncated).concat();
+        assert!(text.ends_with(b"\x85"));
+        assert!(text.len() < long_line.len());
+
+        let mut pdf_output = Vec::new();
+        let result = to_pdf(
+            "---\ncode_overflow: scroll\n---\n\n```\ncode\n```\n",
+            &mut pdf_output,
+            &PdfOptions::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_code_block_attributes() {
+        let markdown = "```rust {hl_lines=\"2\" title=\"Example\" linenos=true}\nfn a() {}\nfn b() {}\nfn c() {}\n```\n";
+        let mut pdf_output = Vec::new();
+        to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let page_id = *doc.get_pages().values().next().unwrap();
+
+        // The title heads the panel and lines are numbered from 1, with only
+        // the second on a band
+        let operations = doc.get_and_decode_page_content(page_id).unwrap().operations;
+        let title = operations
+            .iter()
+            .position(|op| op.operator == "Tj" && op.operands[0].as_str().ok() == Some(b"Example"))
+            .unwrap();
+        let drawn: Vec<String> = operations[title..]
+            .iter()
+            .filter_map(|op| match op.operator.as_str() {
+                "re" => Some("band".to_string()),
+                "Tj" => op.operands[0]
+                    .as_str()
+                    .ok()
+                    .filter(|text| text.iter().all(u8::is_ascii_digit))
+                    .map(|text| String::from_utf8_lossy(text).to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(drawn, ["1", "band", "2", "3"]);
+    }
+
+    #[test]
+    fn test_overlong_word_has_finite_spacing() {
+        // Hyphenation points break the word, leaving lines that end in a
+        // hyphen with no stretch to fill them
+        let word = "internationalization".repeat(12);
+        let markdown = format!(
+            "Some words before {} and after it.\n\n| Cell |\n|------|\n| a {} b |\n",
+            word, word
+        );
+        let mut pdf_output = Vec::new();
+        to_pdf(&markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+        let doc = Document::load_mem(&pdf_output).unwrap();
+
+        fn finite(object: &Object) -> bool {
+            match object {
+                Object::Real(value) => value.is_finite(),
+                Object::Array(items) => items.iter().all(finite),
+                _ => true,
+            }
+        }
+        for page_id in doc.get_pages().values() {
+            let content = doc.get_and_decode_page_content(*page_id).unwrap();
+            for op in &content.operations {
+                assert!(op.operands.iter().all(finite), "{:?}", op);
+            }
+            let raw = String::from_utf8_lossy(&doc.get_page_content(*page_id).unwrap()).to_string();
+            assert!(!raw.contains("NaN") && !raw.contains("inf"));
+        }
+    }
+
+    #[test]
+    fn test_diff_and_console_blocks() {
+        let page_operations = |markdown: &str| {
+            let mut pdf_output = Vec::new();
+            to_pdf(markdown, &mut pdf_output, &PdfOptions::default()).unwrap();
+            let doc = Document::load_mem(&pdf_output).unwrap();
+            let page_id = *doc.get_pages().values().next().unwrap();
+            doc.get_and_decode_page_content(page_id).unwrap().operations
+        };
+        let shown = |operations: &[Operation]| -> Vec<String> {
+            operations
+                .iter()
+                .filter(|op| op.operator == "Tj")
+                .filter_map(|op| op.operands[0].as_str().ok())
+                .map(|text| String::from_utf8_lossy(text).to_string())
+                .collect()
+        };
+        let fill = |operations: &[Operation], color: (u8, u8, u8)| {
+            let color = rgb(color);
+            operations.windows(2).any(|ops| {
+                let operands: Vec<f32> = ops[0]
+                    .operands
+                    .iter()
+                    .filter_map(|operand| operand.as_float().ok())
+                    .collect();
+                ops[0].operator == "rg"
+                    && ops[1].operator == "re"
+                    && operands == [color.0, color.1, color.2]
+            })
+        };
+
+        // Markers get their own column and the rest of the line is
+        // highlighted as Rust
+        let operations = page_operations(
+            "```diff-rust\n@@ -1 +1 @@\n-let a = 1;\n+let a = 2;\n fn b() {}\n```\n",
+        );
+        let text = shown(&operations);
+        assert_eq!(text.concat(), "@@ -1 +1 @@-let a = 1;+let a = 2;fn b() {}");
+        assert!(text.contains(&"+".to_string()));
+        assert!(text.contains(&"let".to_string()));
+        assert!(fill(&operations, DIFF_ADDED.0));
+        assert!(fill(&operations, DIFF_REMOVED.0));
+
+        // The prompt is set apart from the command
+        let operations = page_operations("```console\n$ cargo build\n   Compiling\n```\n");
+        let text = shown(&operations);
+        assert_eq!(text.concat(), "$ cargo build   Compiling");
+        assert_eq!(text[0], "$ ");
+        assert!(!fill(&operations, DIFF_ADDED.0));
+    }
+
+    /// Test that a TrueType font is subset and embedded as a CID font whose
+    /// text can be extracted again
+    #[test]
+    fn test_embedded_truetype_font() {
+        let path = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+        if !Path::new(path).exists() {
+            return;
+        }
+        let options = PdfOptions {
+            fonts: FontConfig {
+                regular: Some(path.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut pdf_output = Vec::new();
+        to_pdf("Größe café ünïcode", &mut pdf_output, &options).unwrap();
+
+        let doc = Document::load_mem(&pdf_output).unwrap();
+        let font = doc
+            .objects
+            .values()
+            .filter_map(|object| object.as_dict().ok())
+            .find(|dict| dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Type0"))
+            .unwrap();
+        assert_eq!(
+            font.get(b"Encoding").unwrap().as_name().unwrap(),
+            b"Identity-H"
+        );
+        let base_font = font.get(b"BaseFont").unwrap().as_name().unwrap();
+        assert!(base_font.ends_with(b"+DejaVuSans"));
+
+        let to_unicode = doc
+            .get_object(font.get(b"ToUnicode").unwrap().as_reference().unwrap())
+            .unwrap()
+            .as_stream()
+            .unwrap();
+        let cmap = String::from_utf8_lossy(&to_unicode.content);
+        assert!(cmap.contains("beginbfchar") || cmap.contains("beginbfrange"));
+
+        let cid_font = doc
+            .get_dictionary(
+                font.get(b"DescendantFonts").unwrap().as_array().unwrap()[0]
+                    .as_reference()
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            cid_font.get(b"Subtype").unwrap().as_name().unwrap(),
+            b"CIDFontType2"
+        );
+        assert_eq!(
+            cid_font.get(b"CIDToGIDMap").unwrap().as_name().unwrap(),
+            b"Identity"
+        );
+        let widths = cid_font.get(b"W").unwrap().as_array().unwrap();
+        assert!(!widths.is_empty() && widths.len().is_multiple_of(2));
+
+        // The subset is a valid font much smaller than the original
+        let descriptor = doc
+            .get_dictionary(
+                cid_font
+                    .get(b"FontDescriptor")
+                    .unwrap()
+                    .as_reference()
+                    .unwrap(),
+            )
+            .unwrap();
+        let font_file = doc
+            .get_object(
+                descriptor
+                    .get(b"FontFile2")
+                    .unwrap()
+                    .as_reference()
+                    .unwrap(),
+            )
+            .unwrap()
+            .as_stream()
+            .unwrap();
+        let subset = font_file.decompressed_content().unwrap();
+        assert!(ttf_parser::Face::parse(&subset, 0).is_ok());
+        assert!(subset.len() < std::fs::metadata(path).unwrap().len() as usize / 4);
+
+        let text = doc.extract_text(&[1]).unwrap();
+        assert!(text.contains("Größe café ünïcode"), "{:?}", text);
+    }
+
+    /// Test that a missing font file is reported instead of silently falling back
+    #[test]
+    fn test_missing_font_file_is_an_error() {
+        let markdown = "---\nfonts:\n  regular: does-not-exist.ttf\n---\n\n# Test";
+        let mut pdf_output = Vec::new();
+
+        let result = to_pdf(markdown, &mut pdf_output, &PdfOptions::default());
+        assert!(result.is_err());
+    }
+
+    /// Test that very large markdown content can be embedded and extracted
+    #[test]
+    fn test_roundtrip_large_content() {
+        // Generate a large markdown document
+        let mut markdown = String::from("# Large Document\n\n");
+        for i in 0..100 {
+            markdown.push_str(&format!("## Section {}\n\n", i));
+            markdown.push_str("Lorem ipsum dolor sit amet, consectetur adipiscing elit. ");
+            markdown
+                .push_str("Sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.\n\n");
+            markdown.push_str(&format!(
+                "```rust\nfn function_{}() {{\n    println!(\"test\");\n}}\n```\n\n",
+                i
+            ));
+        }
+
+        let mut pdf_output = Vec::new();
+        to_pdf(
+            &markdown,
+            &mut pdf_output,
+            &PdfOptions {
+                embed_source: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let extracted = extract_markdown_from_pdf_bytes(&pdf_output).unwrap();
+
+        assert_eq!(markdown, extracted);
+    }
+}
@@ -0,0 +1,223 @@
+//! Document outline (bookmarks) built from headings
+//!
+//! Each heading becomes an outline item whose destination is the top of the
+//! heading on its page. Items nest under the closest preceding heading of a
+//! lower level, so skipped levels (an H3 directly after an H1) still nest.
+
+use lopdf::{
+    Dictionary,
+    Document,
+    Object,
+    ObjectId,
+};
+
+use super::{
+    Mm,
+    text_string,
+};
+
+/// A heading recorded while laying out the document
+#[derive(Clone, Debug)]
+pub(super) struct OutlineEntry {
+    pub level: u8,
+    /// Anchor of the heading, used as its named destination
+    pub id: String,
+    pub title: String,
+    /// Index of the page in the page tree
+    pub page: usize,
+    /// Top of the heading, measured from the bottom of the page
+    pub top: Mm,
+}
+
+/// Write the outline tree and return the outline dictionary
+///
+/// Headings deeper than `max_depth` are left out. Returns `None` when no
+/// heading qualifies, so the catalog can omit `/Outlines` altogether.
+pub(super) fn write_outline(
+    doc: &mut Document,
+    entries: &[OutlineEntry],
+    page_ids: &[ObjectId],
+    left: Mm,
+    max_depth: u8,
+) -> Option<ObjectId> {
+    let entries: Vec<&OutlineEntry> = entries
+        .iter()
+        .filter(|entry| entry.level <= max_depth && entry.page < page_ids.len())
+        .collect();
+    if entries.is_empty() {
+        return None;
+    }
+
+    // Parent of each item, found with a stack of open headings
+    let mut parents: Vec<Option<usize>> = Vec::with_capacity(entries.len());
+    let mut stack: Vec<usize> = Vec::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        while stack
+            .last()
+            .is_some_and(|&open| entries[open].level >= entry.level)
+        {
+            stack.pop();
+        }
+        parents.push(stack.last().copied());
+        stack.push(idx);
+    }
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+    let mut roots = Vec::new();
+    for (idx, parent) in parents.iter().enumerate() {
+        match parent {
+            Some(parent) => children[*parent].push(idx),
+            None => roots.push(idx),
+        }
+    }
+
+    // Every item is open, so the count of an item is all of its descendants.
+    // Children always follow their parent, so walking backwards sees them first.
+    let mut descendants = vec![0i64; entries.len()];
+    for idx in (0..entries.len()).rev() {
+        if let Some(parent) = parents[idx] {
+            descendants[parent] += descendants[idx] + 1;
+        }
+    }
+
+    let outline_id = doc.new_object_id();
+    let item_ids: Vec<ObjectId> = entries.iter().map(|_| doc.new_object_id()).collect();
+
+    let link_siblings = |dict: &mut Dictionary, siblings: &[usize], position: usize| {
+        if position > 0 {
+            dict.set("Prev", Object::Reference(item_ids[siblings[position - 1]]));
+        }
+        if let Some(&next) = siblings.get(position + 1) {
+            dict.set("Next", Object::Reference(item_ids[next]));
+        }
+    };
+
+    for (idx, entry) in entries.iter().enumerate() {
+        let mut dict = Dictionary::new();
+        dict.set("Title", text_string(&entry.title));
+        dict.set(
+            "Parent",
+            Object::Reference(parents[idx].map_or(outline_id, |parent| item_ids[parent])),
+        );
+
+        let siblings = parents[idx].map_or(&roots, |parent| &children[parent]);
+        let position = siblings.iter().position(|&sibling| sibling == idx)?;
+        link_siblings(&mut dict, siblings, position);
+
+        if let (Some(&first), Some(&last)) = (children[idx].first(), children[idx].last()) {
+            dict.set("First", Object::Reference(item_ids[first]));
+            dict.set("Last", Object::Reference(item_ids[last]));
+            dict.set("Count", descendants[idx]);
+        }
+
+        dict.set("Dest", destination(entry, page_ids, left));
+        doc.objects.insert(item_ids[idx], Object::Dictionary(dict));
+    }
+
+    let mut outline = Dictionary::new();
+    outline.set("Type", "Outlines");
+    outline.set("First", Object::Reference(item_ids[roots[0]]));
+    outline.set("Last", Object::Reference(item_ids[roots[roots.len() - 1]]));
+    outline.set("Count", entries.len() as i64);
+    doc.objects.insert(outline_id, Object::Dictionary(outline));
+
+    Some(outline_id)
+}
+
+/// Write the named destination of every heading, so `#id` links can jump to it
+///
+/// Returns the dictionary for the catalog's `/Dests` entry.
+pub(super) fn write_destinations(
+    doc: &mut Document,
+    entries: &[OutlineEntry],
+    page_ids: &[ObjectId],
+    left: Mm,
+) -> Option<ObjectId> {
+    let mut dests = Dictionary::new();
+    for entry in entries.iter().filter(|entry| entry.page < page_ids.len()) {
+        dests.set(entry.id.as_bytes(), destination(entry, page_ids, left));
+    }
+    (!dests.is_empty()).then(|| doc.add_object(dests))
+}
+
+/// Jump to the top of a heading, keeping the reader's zoom
+fn destination(entry: &OutlineEntry, page_ids: &[ObjectId], left: Mm) -> Object {
+    Object::Array(vec![
+        Object::Reference(page_ids[entry.page]),
+        "XYZ".into(),
+        left.to_points().into(),
+        entry.top.to_points().into(),
+        Object::Null,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: u8, title: &str) -> OutlineEntry {
+        OutlineEntry {
+            level,
+            id: title.to_lowercase(),
+            title: title.to_string(),
+            page: 0,
+            top: Mm(200.0),
+        }
+    }
+
+    #[test]
+    fn test_nesting_and_depth() {
+        let mut doc = Document::with_version("1.5");
+        let page = doc.new_object_id();
+        let entries = [
+            entry(1, "Intro"),
+            entry(3, "Skipped level"),
+            entry(2, "Details"),
+            entry(4, "Too deep"),
+            entry(1, "Summary"),
+        ];
+
+        let outline_id = write_outline(&mut doc, &entries, &[page], Mm(20.0), 3).unwrap();
+        let outline = doc.get_dictionary(outline_id).unwrap();
+        assert_eq!(outline.get(b"Count").unwrap().as_i64().unwrap(), 4);
+
+        let intro_id = outline.get(b"First").unwrap().as_reference().unwrap();
+        let intro = doc.get_dictionary(intro_id).unwrap();
+        assert_eq!(intro.get(b"Count").unwrap().as_i64().unwrap(), 2);
+
+        let skipped_id = intro.get(b"First").unwrap().as_reference().unwrap();
+        let details_id = intro.get(b"Last").unwrap().as_reference().unwrap();
+        let skipped = doc.get_dictionary(skipped_id).unwrap();
+        assert_eq!(
+            skipped.get(b"Next").unwrap().as_reference().unwrap(),
+            details_id
+        );
+        assert_eq!(
+            skipped.get(b"Parent").unwrap().as_reference().unwrap(),
+            intro_id
+        );
+
+        // "Too deep" is left out, so Details has no children
+        let details = doc.get_dictionary(details_id).unwrap();
+        assert!(details.get(b"First").is_err());
+
+        let summary_id = outline.get(b"Last").unwrap().as_reference().unwrap();
+        let summary = doc.get_dictionary(summary_id).unwrap();
+        assert_eq!(
+            summary.get(b"Prev").unwrap().as_reference().unwrap(),
+            intro_id
+        );
+
+        let dest = summary.get(b"Dest").unwrap().as_array().unwrap();
+        assert_eq!(dest[0].as_reference().unwrap(), page);
+        assert_eq!(dest[1].as_name().unwrap(), b"XYZ");
+        assert!((dest[3].as_float().unwrap() - Mm(200.0).to_points()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_no_headings() {
+        let mut doc = Document::with_version("1.5");
+        let page = doc.new_object_id();
+        assert!(write_outline(&mut doc, &[entry(2, "Hidden")], &[page], Mm(20.0), 1).is_none());
+    }
+}
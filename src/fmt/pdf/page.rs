@@ -0,0 +1,195 @@
+//! Paper size, orientation and margins
+
+use super::Mm;
+use crate::parse::PageConfig;
+
+/// Dimensions of the pages and the area text is laid out in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct PageLayout {
+    pub width: Mm,
+    pub height: Mm,
+    /// Distance from the top edge to the first baseline
+    pub top: Mm,
+    pub right: Mm,
+    /// Space kept clear at the bottom of each page
+    pub bottom: Mm,
+    pub left: Mm,
+}
+
+impl PageLayout {
+    /// Portrait A4 with the margins reports have always used
+    fn a4() -> Self {
+        Self {
+            width: Mm(210.0),
+            height: Mm(297.0),
+            top: Mm(27.0),
+            right: Mm(20.0),
+            bottom: Mm(30.0),
+            left: Mm(20.0),
+        }
+    }
+
+    /// 16:9 presentation slides
+    pub fn slide() -> Self {
+        Self {
+            width: Mm(254.0),
+            height: Mm(142.875),
+            top: Mm(20.0),
+            right: Mm(15.0),
+            bottom: Mm(30.0),
+            left: Mm(15.0),
+        }
+    }
+
+    /// Apply a page configuration to the default A4 layout
+    pub fn from_config(config: &PageConfig) -> Result<Self, std::io::Error> {
+        let invalid =
+            |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+        let mut layout = Self::a4();
+
+        if let Some(size) = &config.page_size {
+            (layout.width, layout.height) =
+                paper_size(size).ok_or_else(|| invalid(format!("Unknown page size '{}'", size)))?;
+        }
+
+        let (short, long) = if layout.width < layout.height {
+            (layout.width, layout.height)
+        } else {
+            (layout.height, layout.width)
+        };
+        match config
+            .orientation
+            .as_deref()
+            .map(str::to_lowercase)
+            .as_deref()
+        {
+            // Without an orientation the size is kept as given, so a custom
+            // `297x210` page stays landscape
+            None => {}
+            Some("portrait") => (layout.width, layout.height) = (short, long),
+            Some("landscape") => (layout.width, layout.height) = (long, short),
+            Some(other) => {
+                return Err(invalid(format!(
+                    "Unknown orientation '{}' (expected portrait or landscape)",
+                    other
+                )));
+            }
+        }
+
+        if let Some(margins) = &config.margins {
+            margins.check().map_err(invalid)?;
+            layout.top = margins.top.map_or(layout.top, Mm);
+            layout.right = margins.right.map_or(layout.right, Mm);
+            layout.bottom = margins.bottom.map_or(layout.bottom, Mm);
+            layout.left = margins.left.map_or(layout.left, Mm);
+        }
+
+        if layout.left + layout.right >= layout.width || layout.top + layout.bottom >= layout.height
+        {
+            return Err(invalid(
+                "Page margins leave no room for content".to_string(),
+            ));
+        }
+
+        Ok(layout)
+    }
+}
+
+/// Width and height of a named paper size, or of a custom `WxH` size in
+/// millimetres (the default), inches (`in`) or points (`pt`)
+fn paper_size(name: &str) -> Option<(Mm, Mm)> {
+    let name = name.trim().to_lowercase();
+    let (width, height) = match name.as_str() {
+        "a4" => (210.0, 297.0),
+        "a5" => (148.0, 210.0),
+        "letter" => (215.9, 279.4),
+        "legal" => (215.9, 355.6),
+        custom => {
+            let (number, scale) = if let Some(inches) = custom.strip_suffix("in") {
+                (inches, 25.4)
+            } else if let Some(points) = custom.strip_suffix("pt") {
+                (points, 25.4 / 72.0)
+            } else {
+                (custom.strip_suffix("mm").unwrap_or(custom), 1.0)
+            };
+            let (width, height) = number.split_once('x')?;
+            let width = width.trim().parse::<f32>().ok()? * scale;
+            let height = height.trim().parse::<f32>().ok()? * scale;
+            if width <= 0.0 || height <= 0.0 {
+                return None;
+            }
+            (width, height)
+        }
+    };
+    Some((Mm(width), Mm(height)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Margins;
+
+    #[test]
+    fn test_page_layout_from_config() {
+        assert_eq!(
+            PageLayout::from_config(&PageConfig::default()).unwrap(),
+            PageLayout::a4()
+        );
+
+        let letter = PageLayout::from_config(&PageConfig {
+            page_size: Some("Letter".to_string()),
+            orientation: Some("landscape".to_string()),
+            margins: Some(Margins {
+                left: Some(25.0),
+                ..Default::default()
+            }),
+        })
+        .unwrap();
+        assert_eq!((letter.width, letter.height), (Mm(279.4), Mm(215.9)));
+        assert_eq!((letter.left, letter.right), (Mm(25.0), Mm(20.0)));
+
+        let (width, height) = paper_size("6x9in").unwrap();
+        assert!((width.0 - 152.4).abs() < 0.01 && (height.0 - 228.6).abs() < 0.01);
+        assert_eq!(paper_size("200 x 250mm"), Some((Mm(200.0), Mm(250.0))));
+        assert!(paper_size("B7").is_none());
+
+        let wide = PageLayout::from_config(&PageConfig {
+            page_size: Some("297x210".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!((wide.width, wide.height), (Mm(297.0), Mm(210.0)));
+        let turned = PageLayout::from_config(&PageConfig {
+            page_size: Some("297x210".to_string()),
+            orientation: Some("portrait".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!((turned.width, turned.height), (Mm(210.0), Mm(297.0)));
+
+        let cramped = PageConfig {
+            page_size: Some("50x50".to_string()),
+            ..Default::default()
+        };
+        assert!(PageLayout::from_config(&cramped).is_err());
+        // Negative margins would widen the text area past the page
+        let negative = PageConfig {
+            margins: Some(Margins {
+                left: Some(-50.0),
+                right: Some(-50.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(PageLayout::from_config(&negative).is_err());
+        let no_height = PageConfig {
+            margins: Some(Margins {
+                top: Some(150.0),
+                bottom: Some(150.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(PageLayout::from_config(&no_height).is_err());
+    }
+}
@@ -0,0 +1,410 @@
+//! PDF/A archival output
+//!
+//! Documents are written as PDF/A-2b. When the markdown source is embedded
+//! they claim PDF/A-3b instead, the only part that allows attachments that
+//! are not PDF/A themselves; the source is then an associated file of the
+//! document. [`prepare`] adds what ordinary output leaves out, and [`check`]
+//! reports anything that still breaks conformance.
+
+use std::hash::{
+    DefaultHasher,
+    Hash,
+    Hasher,
+};
+
+use lopdf::{
+    Dictionary,
+    Document,
+    Object,
+    ObjectId,
+    Stream,
+    StringFormat,
+    dictionary,
+};
+
+use super::icc;
+
+/// Add the output intent, document ID and transparency groups PDF/A needs
+///
+/// `seed` identifies the document, so the same source always gets the same ID.
+pub(super) fn prepare(doc: &mut Document, seed: &[u8]) -> Result<(), std::io::Error> {
+    doc.version = "1.7".to_string();
+
+    let mut profile = Stream::new(
+        dictionary! {
+            "N" => 3,
+            "Alternate" => "DeviceRGB",
+        },
+        icc::srgb_profile(),
+    );
+    let _ = profile.compress();
+    let profile_id = doc.add_object(profile);
+    let output_intent_id = doc.add_object(dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal(icc::DESCRIPTION),
+        "Info" => Object::string_literal(icc::DESCRIPTION),
+        "RegistryName" => Object::string_literal("http://www.color.org"),
+        "DestOutputProfile" => Object::Reference(profile_id),
+    });
+
+    // Pages that blend colours are composited in sRGB like everything else
+    let transparent: Vec<ObjectId> = doc
+        .get_pages()
+        .into_values()
+        .filter(|&page_id| uses_transparency(doc, page_id))
+        .collect();
+    for page_id in transparent {
+        if let Ok(page) = doc.get_dictionary_mut(page_id) {
+            page.set(
+                "Group",
+                dictionary! {
+                    "Type" => "Group",
+                    "S" => "Transparency",
+                    "CS" => "DeviceRGB",
+                },
+            );
+        }
+    }
+
+    let catalog = doc
+        .catalog_mut()
+        .map_err(|e| std::io::Error::other(format!("Failed to get catalog: {}", e)))?;
+    catalog.set("OutputIntents", vec![Object::Reference(output_intent_id)]);
+
+    let id = document_id(seed);
+    doc.trailer.set(
+        "ID",
+        vec![
+            Object::String(id.clone(), StringFormat::Hexadecimal),
+            Object::String(id, StringFormat::Hexadecimal),
+        ],
+    );
+    Ok(())
+}
+
+/// A 16-byte identifier derived from the document
+fn document_id(seed: &[u8]) -> Vec<u8> {
+    (0u8..2)
+        .flat_map(|half| {
+            let mut hasher = DefaultHasher::new();
+            half.hash(&mut hasher);
+            seed.hash(&mut hasher);
+            hasher.finish().to_be_bytes()
+        })
+        .collect()
+}
+
+/// List the ways a document falls short of the PDF/A level it claims
+///
+/// Covers what this writer can get wrong: the identification in the XMP
+/// metadata, the output intent, the document ID, unembedded fonts, device
+/// colour the output intent does not describe, transparency, annotations
+/// and attachments. An empty list means no violation was found.
+pub(super) fn check(doc: &Document) -> Vec<String> {
+    let mut violations = Vec::new();
+    let Ok(catalog) = doc.catalog() else {
+        return vec!["the document has no catalog".to_string()];
+    };
+
+    let part = catalog
+        .get_deref(b"Metadata", doc)
+        .and_then(Object::as_stream)
+        .ok()
+        .and_then(|metadata| {
+            let packet = String::from_utf8_lossy(&metadata.content);
+            let start = packet.find("<pdfaid:part>")? + "<pdfaid:part>".len();
+            let end = start + packet[start..].find('<')?;
+            packet[start..end].trim().parse::<u8>().ok()
+        });
+    if part.is_none() {
+        violations.push("the XMP metadata does not identify the PDF/A part".to_string());
+    }
+
+    let has_output_intent = catalog
+        .get_deref(b"OutputIntents", doc)
+        .and_then(Object::as_array)
+        .is_ok_and(|intents| {
+            intents.iter().any(|intent| {
+                doc.dereference(intent)
+                    .and_then(|(_, intent)| intent.as_dict())
+                    .is_ok_and(|intent| {
+                        intent
+                            .get(b"S")
+                            .and_then(Object::as_name)
+                            .is_ok_and(|s| s == b"GTS_PDFA1")
+                            && intent.has(b"DestOutputProfile")
+                    })
+            })
+        });
+    if !has_output_intent {
+        violations.push("there is no PDF/A output intent with an ICC profile".to_string());
+    }
+
+    if doc
+        .trailer
+        .get(b"ID")
+        .and_then(Object::as_array)
+        .map_or(true, |id| id.len() != 2)
+    {
+        violations.push("the trailer has no document ID".to_string());
+    }
+    if doc.trailer.has(b"Encrypt") {
+        violations.push("the document is encrypted".to_string());
+    }
+
+    for object in doc.objects.values() {
+        let Ok(font) = object.as_dict() else {
+            continue;
+        };
+        let is_font = font
+            .get(b"Type")
+            .and_then(Object::as_name)
+            .is_ok_and(|name| name == b"Font");
+        let subtype = font.get(b"Subtype").and_then(Object::as_name).ok();
+        // Type 0 fonts are embedded through their descendant, Type 3 fonts are content streams
+        if !is_font || matches!(subtype, Some(b"Type0" | b"Type3")) {
+            continue;
+        }
+        let embedded = doc
+            .get_dict_in_dict(font, b"FontDescriptor")
+            .is_ok_and(|descriptor| {
+                [&b"FontFile"[..], b"FontFile2", b"FontFile3"]
+                    .iter()
+                    .any(|key| descriptor.has(key))
+            });
+        if !embedded {
+            let name = font
+                .get(b"BaseFont")
+                .and_then(Object::as_name)
+                .map(String::from_utf8_lossy)
+                .unwrap_or_default();
+            violations.push(format!("font {} is not embedded", name));
+        }
+    }
+
+    for (number, page_id) in doc.get_pages() {
+        if let Ok(content) = doc.get_and_decode_page_content(page_id)
+            && content
+                .operations
+                .iter()
+                .any(|op| op.operator == "k" || op.operator == "K")
+        {
+            violations.push(format!(
+                "page {} uses DeviceCMYK colour, which the sRGB output intent does not describe",
+                number
+            ));
+        }
+        if doc.get_page_images(page_id).is_ok_and(|images| {
+            images
+                .iter()
+                .any(|image| image.color_space.as_deref() == Some("DeviceCMYK"))
+        }) {
+            violations.push(format!(
+                "page {} draws a DeviceCMYK image, which the sRGB output intent does not describe",
+                number
+            ));
+        }
+
+        let has_group = doc
+            .get_dictionary(page_id)
+            .and_then(|page| doc.get_dict_in_dict(page, b"Group"))
+            .is_ok_and(|group| group.has(b"CS"));
+        if !has_output_intent && !has_group && uses_transparency(doc, page_id) {
+            violations.push(format!(
+                "page {} uses transparency without a blending colour space",
+                number
+            ));
+        }
+
+        for annotation in doc.get_page_annotations(page_id).unwrap_or_default() {
+            check_annotation(annotation, number, &mut violations);
+        }
+    }
+
+    let attachments = catalog
+        .get_deref(b"Names", doc)
+        .and_then(Object::as_dict)
+        .and_then(|names| names.get_deref(b"EmbeddedFiles", doc))
+        .and_then(Object::as_dict)
+        .and_then(|tree| tree.get_deref(b"Names", doc))
+        .and_then(Object::as_array)
+        .map(|names| names.chunks(2).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for pair in attachments {
+        let [name, filespec] = pair else {
+            continue;
+        };
+        let name = name
+            .as_str()
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+        if part.is_some_and(|part| part < 3) {
+            violations.push(format!(
+                "attachment '{}' needs PDF/A-3, but the document claims PDF/A-{}",
+                name,
+                part.unwrap_or_default()
+            ));
+            continue;
+        }
+        let Ok((_, Object::Dictionary(filespec))) = doc.dereference(filespec) else {
+            continue;
+        };
+        if !filespec.has(b"AFRelationship") {
+            violations.push(format!("attachment '{}' has no AFRelationship", name));
+        }
+        let has_mime_type = doc
+            .get_dict_in_dict(filespec, b"EF")
+            .and_then(|ef| ef.get_deref(b"F", doc))
+            .and_then(Object::as_stream)
+            .is_ok_and(|stream| stream.dict.has(b"Subtype"));
+        if !has_mime_type {
+            violations.push(format!("attachment '{}' has no MIME type", name));
+        }
+    }
+
+    violations
+}
+
+/// Whether a page paints with soft masks, constant alpha or blend modes
+fn uses_transparency(doc: &Document, page_id: ObjectId) -> bool {
+    let Ok((resources, _)) = doc.get_page_resources(page_id) else {
+        return false;
+    };
+    let Some(resources) = resources else {
+        return false;
+    };
+
+    let transparent_state = |state: &Dictionary| {
+        let soft_mask = state
+            .get(b"SMask")
+            .is_ok_and(|mask| mask.as_name().map_or(true, |name| name != b"None"));
+        let alpha = [&b"CA"[..], b"ca"].iter().any(|key| {
+            state
+                .get(key)
+                .and_then(Object::as_float)
+                .is_ok_and(|alpha| alpha < 1.0)
+        });
+        let blend = state
+            .get(b"BM")
+            .and_then(Object::as_name)
+            .is_ok_and(|mode| mode != b"Normal" && mode != b"Compatible");
+        soft_mask || alpha || blend
+    };
+    let states = doc
+        .get_dict_in_dict(resources, b"ExtGState")
+        .map(|states| {
+            states.iter().any(|(_, state)| {
+                doc.dereference(state)
+                    .and_then(|(_, state)| state.as_dict())
+                    .is_ok_and(transparent_state)
+            })
+        })
+        .unwrap_or(false);
+    let masked_images = doc
+        .get_dict_in_dict(resources, b"XObject")
+        .map(|xobjects| {
+            xobjects.iter().any(|(_, xobject)| {
+                doc.dereference(xobject)
+                    .and_then(|(_, xobject)| xobject.as_stream())
+                    .is_ok_and(|xobject| xobject.dict.has(b"SMask"))
+            })
+        })
+        .unwrap_or(false);
+    states || masked_images
+}
+
+fn check_annotation(annotation: &Dictionary, page: u32, violations: &mut Vec<String>) {
+    const PRINT: i64 = 4;
+    const HIDDEN: i64 = 1 | 2 | 32;
+    let flags = annotation.get(b"F").and_then(Object::as_i64).unwrap_or(0);
+    if flags & PRINT == 0 || flags & HIDDEN != 0 {
+        violations.push(format!(
+            "an annotation on page {} is not set to print, or is hidden",
+            page
+        ));
+    }
+
+    let action = annotation
+        .get(b"A")
+        .and_then(Object::as_dict)
+        .and_then(|action| action.get(b"S"))
+        .and_then(Object::as_name);
+    if let Ok(
+        kind @ (b"Launch" | b"Sound" | b"Movie" | b"ResetForm" | b"ImportData" | b"Hide"
+        | b"SetOCGState" | b"Rendition" | b"Trans" | b"GoTo3DView" | b"JavaScript"),
+    ) = action
+    {
+        violations.push(format!(
+            "an annotation on page {} uses a {} action",
+            page,
+            String::from_utf8_lossy(kind)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_reports_violations() {
+        let mut doc = Document::with_version("1.7");
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let content_id = doc.add_object(Stream::new(dictionary! {}, b"0 0 0 1 k".to_vec()));
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "A" => dictionary! { "S" => "Launch" },
+        });
+        let page_id = doc.new_object_id();
+        let pages_id = doc.add_object(dictionary! {
+            "Type" => "Pages",
+            "Count" => 1,
+            "Kids" => vec![Object::Reference(page_id)],
+        });
+        doc.objects.insert(
+            page_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Page",
+                "Parent" => Object::Reference(pages_id),
+                "Contents" => Object::Reference(content_id),
+                "Resources" => dictionary! {
+                    "Font" => dictionary! { "F1" => Object::Reference(font_id) },
+                },
+                "Annots" => vec![Object::Reference(link_id)],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => Object::Reference(pages_id),
+        });
+        doc.trailer.set("Root", Object::Reference(catalog_id));
+
+        let violations = check(&doc);
+        let expected = [
+            "the XMP metadata does not identify the PDF/A part",
+            "there is no PDF/A output intent with an ICC profile",
+            "the trailer has no document ID",
+            "font Helvetica is not embedded",
+            "page 1 uses DeviceCMYK colour, which the sRGB output intent does not describe",
+            "an annotation on page 1 is not set to print, or is hidden",
+            "an annotation on page 1 uses a Launch action",
+        ];
+        assert_eq!(violations, expected);
+
+        // The output intent and ID are what prepare adds
+        prepare(&mut doc, b"source").unwrap();
+        let violations = check(&doc);
+        assert!(
+            !violations
+                .iter()
+                .any(|v| v.contains("no PDF/A output intent"))
+        );
+        assert!(!violations.iter().any(|v| v.contains("document ID")));
+    }
+}
@@ -0,0 +1,75 @@
+//! Running headers and footers
+//!
+//! Templates are expanded once every page is laid out, so `{pages}` knows the
+//! page count and `{section}` the heading each page falls under.
+
+use crate::parse::PageTemplate;
+
+/// Header and footer templates with the document values they refer to
+#[derive(Clone, Debug, Default)]
+pub(super) struct RunningText {
+    pub header: Option<PageTemplate>,
+    pub footer: Option<PageTemplate>,
+    pub title: String,
+    pub author: String,
+    pub date: String,
+}
+
+/// Values available to header and footer templates on one page
+pub(super) struct PageValues<'a> {
+    pub title: &'a str,
+    pub author: &'a str,
+    pub date: &'a str,
+    /// Page number, counting from 1
+    pub page: usize,
+    pub pages: usize,
+    /// Latest level 1 or 2 heading on or before the page
+    pub section: &'a str,
+}
+
+/// Replace the placeholders in a template; unknown ones are kept as written
+pub(super) fn expand(template: &str, values: &PageValues) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        result.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let Some(close) = rest.find('}') else {
+            break;
+        };
+        match &rest[1..close] {
+            "title" => result.push_str(values.title),
+            "author" => result.push_str(values.author),
+            "date" => result.push_str(values.date),
+            "page" => result.push_str(&values.page.to_string()),
+            "pages" => result.push_str(&values.pages.to_string()),
+            "section" => result.push_str(values.section),
+            _ => result.push_str(&rest[..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let values = PageValues {
+            title: "Report",
+            author: "Ada",
+            date: "2024-01-01",
+            page: 2,
+            pages: 7,
+            section: "Results",
+        };
+        assert_eq!(expand("{title} - {section}", &values), "Report - Results");
+        assert_eq!(expand("Page {page} of {pages}", &values), "Page 2 of 7");
+        assert_eq!(expand("{author}, {date}", &values), "Ada, 2024-01-01");
+        assert_eq!(expand("{unknown} {page", &values), "{unknown} {page");
+    }
+}
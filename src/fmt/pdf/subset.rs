@@ -0,0 +1,200 @@
+//! TrueType font subsetting
+//!
+//! Glyph IDs are kept stable so the subset can be addressed with an Identity
+//! CIDToGIDMap: outlines of unused glyphs are dropped from `glyf` and their
+//! `loca` entries become empty.
+
+use std::collections::BTreeSet;
+
+/// Tables kept in the subset. A CID font is addressed by glyph ID, so the
+/// character map, glyph names and layout tables are not needed.
+const KEPT_TABLES: &[&[u8; 4]] = &[
+    b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"OS/2", b"prep",
+];
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Locate a table in the font's table directory
+fn find_table<'a>(data: &'a [u8], tag: &[u8; 4]) -> Option<&'a [u8]> {
+    let num_tables = read_u16(data, 4)? as usize;
+    (0..num_tables).find_map(|idx| {
+        let record = 12 + idx * 16;
+        if data.get(record..record + 4)? != tag {
+            return None;
+        }
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        data.get(offset..offset + length)
+    })
+}
+
+/// Byte ranges of each glyph in `glyf`, read from `loca`
+fn glyph_ranges(loca: &[u8], long_offsets: bool, num_glyphs: usize) -> Option<Vec<(usize, usize)>> {
+    let offset_at = |idx: usize| {
+        if long_offsets {
+            read_u32(loca, idx * 4).map(|v| v as usize)
+        } else {
+            read_u16(loca, idx * 2).map(|v| v as usize * 2)
+        }
+    };
+
+    (0..num_glyphs)
+        .map(|gid| Some((offset_at(gid)?, offset_at(gid + 1)?)))
+        .collect()
+}
+
+/// Glyph IDs referenced by a composite glyph
+fn composite_components(glyph: &[u8]) -> Vec<u16> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut components = Vec::new();
+
+    let is_composite = read_u16(glyph, 0).is_some_and(|contours| (contours as i16) < 0);
+    if !is_composite {
+        return components;
+    }
+
+    let mut offset = 10;
+    while let (Some(flags), Some(gid)) = (read_u16(glyph, offset), read_u16(glyph, offset + 2)) {
+        components.push(gid);
+        offset += 4;
+        offset += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+
+    components
+}
+
+/// Build a subset of a TrueType font containing only the given glyphs
+///
+/// Returns `None` if the font is not a TrueType-outline font or is malformed,
+/// in which case the caller should embed the original file.
+pub(super) fn subset_truetype(data: &[u8], glyphs: &[u16]) -> Option<Vec<u8>> {
+    let head = find_table(data, b"head")?;
+    let maxp = find_table(data, b"maxp")?;
+    let loca = find_table(data, b"loca")?;
+    let glyf = find_table(data, b"glyf")?;
+
+    let num_glyphs = read_u16(maxp, 4)? as usize;
+    let long_offsets = read_u16(head, 50)? != 0;
+    let ranges = glyph_ranges(loca, long_offsets, num_glyphs)?;
+
+    // Collect the requested glyphs plus .notdef and every composite component
+    let mut keep = BTreeSet::new();
+    let mut pending: Vec<u16> = glyphs.iter().copied().chain([0]).collect();
+    while let Some(gid) = pending.pop() {
+        if (gid as usize) >= num_glyphs || !keep.insert(gid) {
+            continue;
+        }
+        let (start, end) = ranges[gid as usize];
+        if let Some(glyph) = glyf.get(start..end) {
+            pending.extend(composite_components(glyph));
+        }
+    }
+
+    // Rebuild glyf and a long-format loca
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((num_glyphs + 1) * 4);
+    for (gid, &(start, end)) in ranges.iter().enumerate() {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(gid as u16)) {
+            new_glyf.extend_from_slice(glyf.get(start..end)?);
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let mut new_head = head.to_vec();
+    new_head.get_mut(8..12)?.copy_from_slice(&[0; 4]); // checkSumAdjustment
+    new_head
+        .get_mut(50..52)?
+        .copy_from_slice(&1u16.to_be_bytes()); // indexToLocFormat
+
+    let mut tables: Vec<(&[u8; 4], Vec<u8>)> = KEPT_TABLES
+        .iter()
+        .filter_map(|&tag| {
+            let table = match tag {
+                b"glyf" => std::mem::take(&mut new_glyf),
+                b"loca" => std::mem::take(&mut new_loca),
+                b"head" => std::mem::take(&mut new_head),
+                _ => find_table(data, tag)?.to_vec(),
+            };
+            Some((tag, table))
+        })
+        .collect();
+    tables.sort_by_key(|(tag, _)| **tag);
+
+    // Write the offset table and table directory followed by the table data
+    let num_tables = tables.len() as u16;
+    let entry_selector = 15 - num_tables.leading_zeros() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+    let range_shift = num_tables * 16 - search_range;
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    font.extend_from_slice(&num_tables.to_be_bytes());
+    font.extend_from_slice(&search_range.to_be_bytes());
+    font.extend_from_slice(&entry_selector.to_be_bytes());
+    font.extend_from_slice(&range_shift.to_be_bytes());
+
+    let mut offset = 12 + tables.len() * 16;
+    let mut head_offset = 0;
+    for (tag, table) in &tables {
+        if *tag == b"head" {
+            head_offset = offset;
+        }
+        font.extend_from_slice(*tag);
+        font.extend_from_slice(&checksum(table).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(table.len() as u32).to_be_bytes());
+        offset += table.len().div_ceil(4) * 4;
+    }
+
+    for (_, table) in &tables {
+        font.extend_from_slice(table);
+        while font.len() % 4 != 0 {
+            font.push(0);
+        }
+    }
+
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+    font.get_mut(head_offset + 8..head_offset + 12)?
+        .copy_from_slice(&adjustment.to_be_bytes());
+
+    Some(font)
+}
@@ -0,0 +1,509 @@
+//! Structure tree for tagged PDF
+//!
+//! While the document is drawn, structure elements (paragraphs, headings,
+//! lists, tables, links) are opened and closed around what they contain, and
+//! every piece of content drawn inside an element becomes a marked-content
+//! sequence (`BDC`/`EMC`) with an MCID pointing back at it. Content drawn
+//! outside any element, such as rules, leaders and running headers, is
+//! marked as an artifact so assistive technology skips it.
+
+use lopdf::{
+    Document,
+    Object,
+    ObjectId,
+    content::Operation,
+    dictionary,
+};
+
+/// Something an element contains
+#[derive(Clone, Debug)]
+enum Kid {
+    Element(usize),
+    /// Marked content on a page
+    Content {
+        page: usize,
+        mcid: i64,
+    },
+    /// An annotation, such as the link annotation of a Link element
+    Annotation {
+        page: usize,
+        id: ObjectId,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    role: &'static str,
+    parent: Option<usize>,
+    kids: Vec<Kid>,
+    /// Alternate description, read instead of the content
+    alt: Option<String>,
+    /// Scope of a table header cell
+    scope: Option<&'static str>,
+}
+
+/// The structure elements of a document, built while it is drawn
+#[derive(Clone, Debug)]
+pub(super) struct StructureTree {
+    /// Every element, the `Document` element first
+    elements: Vec<Element>,
+    /// Elements that are open, innermost last
+    open: Vec<usize>,
+    /// Element owning each MCID, per page
+    page_content: Vec<Vec<usize>>,
+    /// Whether a marked-content sequence is open in the content stream
+    marked: bool,
+    /// Nesting of drawing that belongs to no element
+    artifact: usize,
+}
+
+impl Default for StructureTree {
+    fn default() -> Self {
+        Self {
+            elements: vec![Element {
+                role: "Document",
+                parent: None,
+                kids: Vec::new(),
+                alt: None,
+                scope: None,
+            }],
+            open: vec![0],
+            page_content: Vec::new(),
+            marked: false,
+            artifact: 0,
+        }
+    }
+}
+
+impl StructureTree {
+    /// Open an element inside the innermost open element and return it
+    pub fn begin(&mut self, role: &'static str) -> usize {
+        let parent = self.current();
+        let idx = self.elements.len();
+        self.elements.push(Element {
+            role,
+            parent: Some(parent),
+            kids: Vec::new(),
+            alt: None,
+            scope: None,
+        });
+        self.elements[parent].kids.push(Kid::Element(idx));
+        self.open.push(idx);
+        idx
+    }
+
+    /// Open an element created earlier again, to add content drawn
+    /// elsewhere, such as a footnote at the foot of the page
+    pub fn resume(&mut self, element: usize) {
+        self.open.push(element);
+    }
+
+    /// Close the innermost open element
+    pub fn end(&mut self) {
+        if self.open.len() > 1 {
+            self.open.pop();
+        }
+    }
+
+    /// Close the innermost open element if it has the given role
+    pub fn end_if(&mut self, role: &str) {
+        if self.elements[self.current()].role == role {
+            self.end();
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.open[self.open.len() - 1]
+    }
+
+    pub fn set_alt(&mut self, element: usize, alt: &str) {
+        self.elements[element].alt = Some(alt.to_string());
+    }
+
+    /// Mark a header cell as heading its column
+    pub fn set_column_scope(&mut self, element: usize) {
+        self.elements[element].scope = Some("Column");
+    }
+
+    /// Start a marked-content sequence for the innermost element on `page`
+    ///
+    /// Returns the `BDC` operation, or `None` when there is nothing to mark:
+    /// a sequence is already open, no element is open, or artifacts are
+    /// being drawn.
+    pub fn mark(&mut self, page: usize) -> Option<Operation> {
+        let element = self.current();
+        if self.marked || self.artifact > 0 || element == 0 {
+            return None;
+        }
+        if self.page_content.len() <= page {
+            self.page_content.resize(page + 1, Vec::new());
+        }
+        let mcid = self.page_content[page].len() as i64;
+        self.page_content[page].push(element);
+        self.elements[element]
+            .kids
+            .push(Kid::Content { page, mcid });
+        self.marked = true;
+
+        let role = self.elements[element].role;
+        Some(Operation::new(
+            "BDC",
+            vec![
+                Object::Name(role.as_bytes().to_vec()),
+                Object::Dictionary(dictionary! { "MCID" => mcid }),
+            ],
+        ))
+    }
+
+    /// End the open marked-content sequence, returning the `EMC` operation
+    pub fn unmark(&mut self) -> Option<Operation> {
+        std::mem::take(&mut self.marked).then(|| Operation::new("EMC", vec![]))
+    }
+
+    /// Stop marking content until [`StructureTree::end_artifact`]
+    pub fn begin_artifact(&mut self) {
+        self.artifact += 1;
+    }
+
+    pub fn end_artifact(&mut self) {
+        self.artifact = self.artifact.saturating_sub(1);
+    }
+
+    /// Record an annotation, such as a link, as part of an element
+    pub fn add_annotation(&mut self, element: usize, page: usize, id: ObjectId) {
+        if element != 0 {
+            self.elements[element]
+                .kids
+                .push(Kid::Annotation { page, id });
+        }
+    }
+
+    /// Whether an element, or any element inside it, has content
+    fn has_content(&self, element: usize) -> bool {
+        self.elements[element].kids.iter().any(|kid| match kid {
+            Kid::Element(child) => self.has_content(*child),
+            _ => true,
+        })
+    }
+
+    /// Write the structure tree and return the `/StructTreeRoot` dictionary
+    ///
+    /// Pages must carry `/StructParents` equal to their index; annotations in
+    /// the tree are given their `/StructParent` here.
+    pub fn write_to(&self, doc: &mut Document, page_ids: &[ObjectId]) -> Option<ObjectId> {
+        if !self.has_content(0) {
+            return None;
+        }
+
+        let root_id = doc.new_object_id();
+        let element_ids: Vec<Option<ObjectId>> = (0..self.elements.len())
+            .map(|idx| self.has_content(idx).then(|| doc.new_object_id()))
+            .collect();
+
+        // Annotations follow the pages in the parent tree
+        let mut next_key = page_ids.len() as i64;
+        let mut annotation_parents = Vec::new();
+
+        for (element, id) in self.elements.iter().zip(&element_ids) {
+            let Some(id) = *id else {
+                continue;
+            };
+            let page_of = |page: usize| page_ids.get(page).copied();
+            let first_page = element.kids.iter().find_map(|kid| match kid {
+                Kid::Content { page, .. } | Kid::Annotation { page, .. } => page_of(*page),
+                Kid::Element(_) => None,
+            });
+
+            let mut kids = Vec::new();
+            for kid in &element.kids {
+                match *kid {
+                    Kid::Element(child) => {
+                        if let Some(child_id) = element_ids[child] {
+                            kids.push(Object::Reference(child_id));
+                        }
+                    }
+                    Kid::Content { page, mcid } => match page_of(page) {
+                        Some(page_id) if Some(page_id) == first_page => kids.push(mcid.into()),
+                        Some(page_id) => kids.push(Object::Dictionary(dictionary! {
+                            "Type" => "MCR",
+                            "Pg" => Object::Reference(page_id),
+                            "MCID" => mcid,
+                        })),
+                        None => {}
+                    },
+                    Kid::Annotation {
+                        page,
+                        id: annotation_id,
+                    } => {
+                        let Some(page_id) = page_of(page) else {
+                            continue;
+                        };
+                        kids.push(Object::Dictionary(dictionary! {
+                            "Type" => "OBJR",
+                            "Pg" => Object::Reference(page_id),
+                            "Obj" => Object::Reference(annotation_id),
+                        }));
+                        if let Ok(annotation) = doc.get_dictionary_mut(annotation_id) {
+                            annotation.set("StructParent", next_key);
+                        }
+                        annotation_parents.push((next_key, id));
+                        next_key += 1;
+                    }
+                }
+            }
+
+            let parent = element
+                .parent
+                .and_then(|parent| element_ids[parent])
+                .unwrap_or(root_id);
+            let mut dict = dictionary! {
+                "Type" => "StructElem",
+                "S" => Object::Name(element.role.as_bytes().to_vec()),
+                "P" => Object::Reference(parent),
+                "K" => kids,
+            };
+            if let Some(page_id) = first_page {
+                dict.set("Pg", Object::Reference(page_id));
+            }
+            if let Some(alt) = &element.alt {
+                dict.set("Alt", super::text_string(alt));
+            }
+            if let Some(scope) = element.scope {
+                dict.set(
+                    "A",
+                    dictionary! {
+                        "O" => "Table",
+                        "Scope" => scope,
+                    },
+                );
+            }
+            doc.objects.insert(id, Object::Dictionary(dict));
+        }
+
+        // Maps each page's MCIDs, and each annotation, to its element
+        let mut nums = Vec::new();
+        for (page, owners) in self.page_content.iter().enumerate() {
+            if page >= page_ids.len() {
+                break;
+            }
+            nums.push(Object::Integer(page as i64));
+            nums.push(Object::Array(
+                owners
+                    .iter()
+                    .map(|&owner| element_ids[owner].map_or(Object::Null, Object::Reference))
+                    .collect(),
+            ));
+        }
+        for (key, element_id) in annotation_parents {
+            nums.push(Object::Integer(key));
+            nums.push(Object::Reference(element_id));
+        }
+
+        doc.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "StructTreeRoot",
+                "K" => vec![Object::Reference(element_ids[0]?)],
+                "ParentTree" => dictionary! { "Nums" => nums },
+                "ParentTreeNextKey" => next_key,
+            }),
+        );
+        Some(root_id)
+    }
+}
+
+/// Operators that paint, and so must be either tagged content or artifacts
+fn is_painting(operator: &str) -> bool {
+    matches!(
+        operator,
+        "Tj" | "TJ"
+            | "'"
+            | "\""
+            | "S"
+            | "s"
+            | "f"
+            | "F"
+            | "f*"
+            | "B"
+            | "B*"
+            | "b"
+            | "b*"
+            | "sh"
+            | "Do"
+    )
+}
+
+fn is_path_construction(operator: &str) -> bool {
+    matches!(operator, "m" | "l" | "c" | "v" | "y" | "h" | "re")
+}
+
+/// End of the graphics object starting at `start`: a text object, a saved
+/// graphics state, a path, or a single operator
+fn unit_end(operations: &[Operation], start: usize) -> usize {
+    let (open, close) = match operations[start].operator.as_str() {
+        "BT" => ("BT", "ET"),
+        "q" => ("q", "Q"),
+        operator if is_path_construction(operator) => {
+            return operations[start..]
+                .iter()
+                .position(|op| !is_path_construction(&op.operator) && op.operator != "W")
+                .map_or(operations.len(), |offset| start + offset + 1);
+        }
+        _ => return start + 1,
+    };
+    let mut depth = 0;
+    for (idx, op) in operations.iter().enumerate().skip(start) {
+        if op.operator == open {
+            depth += 1;
+        } else if op.operator == close {
+            depth -= 1;
+            if depth == 0 {
+                return idx + 1;
+            }
+        }
+    }
+    operations.len()
+}
+
+/// Mark everything painted outside marked content as an artifact
+///
+/// Graphics objects are kept whole, so the `BMC`/`EMC` pairs added here nest
+/// properly with text objects and saved graphics states.
+pub(super) fn mark_artifacts(operations: Vec<Operation>) -> Vec<Operation> {
+    let mut result = Vec::with_capacity(operations.len());
+    let mut artifact: Vec<Operation> = Vec::new();
+    let mut paints = false;
+    let flush = |result: &mut Vec<Operation>, artifact: &mut Vec<Operation>, paints: &mut bool| {
+        if *paints {
+            result.push(Operation::new("BMC", vec!["Artifact".into()]));
+            result.append(artifact);
+            result.push(Operation::new("EMC", vec![]));
+        } else {
+            result.append(artifact);
+        }
+        *paints = false;
+    };
+
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < operations.len() {
+        let operator = operations[idx].operator.as_str();
+        if depth > 0 || operator == "BDC" || operator == "BMC" {
+            flush(&mut result, &mut artifact, &mut paints);
+            match operator {
+                "BDC" | "BMC" => depth += 1,
+                "EMC" => depth -= 1,
+                _ => {}
+            }
+            result.push(operations[idx].clone());
+            idx += 1;
+            continue;
+        }
+
+        let end = unit_end(&operations, idx);
+        let unit = &operations[idx..end];
+        if unit
+            .iter()
+            .any(|op| op.operator == "BDC" || op.operator == "BMC")
+        {
+            // A text object holding tagged content is tagged throughout
+            flush(&mut result, &mut artifact, &mut paints);
+            result.extend_from_slice(unit);
+        } else {
+            paints |= unit.iter().any(|op| is_painting(&op.operator));
+            artifact.extend_from_slice(unit);
+        }
+        idx = end;
+    }
+    flush(&mut result, &mut artifact, &mut paints);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operators(operations: &[Operation]) -> Vec<&str> {
+        operations.iter().map(|op| op.operator.as_str()).collect()
+    }
+
+    #[test]
+    fn test_mark_artifacts() {
+        let op = |operator: &str| Operation::new(operator, vec![]);
+        let operations = vec![
+            op("q"),
+            op("re"),
+            op("f"),
+            op("Q"),
+            op("BT"),
+            op("BDC"),
+            op("Tj"),
+            op("EMC"),
+            op("ET"),
+            op("m"),
+            op("l"),
+            op("S"),
+            op("rg"),
+        ];
+        assert_eq!(
+            operators(&mark_artifacts(operations)),
+            [
+                "BMC", "q", "re", "f", "Q", "EMC", "BT", "BDC", "Tj", "EMC", "ET", "BMC", "m", "l",
+                "S", "rg", "EMC",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structure_tree() {
+        let mut tree = StructureTree::default();
+        let paragraph = tree.begin("P");
+        assert!(tree.mark(0).is_some());
+        // Only one sequence is open at a time
+        assert!(tree.mark(0).is_none());
+        assert!(tree.unmark().is_some());
+        let link = tree.begin("Link");
+        tree.mark(0);
+        tree.unmark();
+        tree.end();
+        tree.end();
+        // Empty elements are left out of the tree
+        tree.begin("L");
+        tree.end();
+
+        let mut doc = Document::with_version("1.7");
+        let page = doc.new_object_id();
+        let annotation = doc.add_object(dictionary! { "Subtype" => "Link" });
+        tree.add_annotation(link, 0, annotation);
+        let root_id = tree.write_to(&mut doc, &[page]).unwrap();
+
+        let root = doc.get_dictionary(root_id).unwrap();
+        let document_id = root.get(b"K").unwrap().as_array().unwrap()[0]
+            .as_reference()
+            .unwrap();
+        let document = doc.get_dictionary(document_id).unwrap();
+        let kids = document.get(b"K").unwrap().as_array().unwrap();
+        assert_eq!(kids.len(), 1);
+        let paragraph_elem = doc.get_dictionary(kids[0].as_reference().unwrap()).unwrap();
+        assert_eq!(paragraph_elem.get(b"S").unwrap().as_name().unwrap(), b"P");
+        assert_eq!(paragraph, 1);
+
+        let nums = root
+            .get(b"ParentTree")
+            .unwrap()
+            .as_dict()
+            .unwrap()
+            .get(b"Nums")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        // Page 0 with two MCIDs, then the annotation
+        assert_eq!(nums[1].as_array().unwrap().len(), 2);
+        assert_eq!(nums[2].as_i64().unwrap(), 1);
+        let annotation = doc.get_dictionary(annotation).unwrap();
+        assert_eq!(
+            annotation.get(b"StructParent").unwrap().as_i64().unwrap(),
+            1
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Hyphenation with Liang's pattern algorithm
+//!
+//! Patterns are read from TeX hyphenation files (`\patterns{...}` and
+//! `\hyphenation{...}` groups) or from plain lists with one pattern per line,
+//! as shipped by hyph-utf8 in `hyph-<lang>.pat.txt`. US English patterns are
+//! bundled; other languages are loaded from a local directory.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+};
+
+/// US English patterns and exceptions from plain TeX
+const ENGLISH_PATTERNS: &str = include_str!("patterns/hyph-en-us.tex");
+
+/// Hyphenation points for words of one language
+#[derive(Clone, Debug)]
+pub struct Hyphenator {
+    /// Letters of each pattern mapped to the values between them
+    patterns: HashMap<String, Vec<u8>>,
+    /// Whole words with explicit hyphenation points (as character indices)
+    exceptions: HashMap<String, Vec<usize>>,
+    max_pattern_len: usize,
+    /// Minimum number of characters before the first hyphen
+    left_min: usize,
+    /// Minimum number of characters after the last hyphen
+    right_min: usize,
+}
+
+impl Hyphenator {
+    /// The bundled US English patterns
+    pub fn english() -> Self {
+        Self::parse(ENGLISH_PATTERNS)
+    }
+
+    /// Find the patterns for a language such as `en` or `de-1996`
+    ///
+    /// Looks for `hyph-<lang>.tex` or `hyph-<lang>.pat.txt` in `dir` first,
+    /// then falls back to the bundled patterns for English. Returns `None`
+    /// when no patterns are available for the language.
+    pub fn for_language(lang: &str, dir: Option<&Path>) -> Result<Option<Self>, std::io::Error> {
+        let lang = lang.to_lowercase();
+
+        if let Some(dir) = dir {
+            for name in [
+                format!("hyph-{}.tex", lang),
+                format!("hyph-{}.pat.txt", lang),
+            ] {
+                let path = dir.join(name);
+                if path.is_file() {
+                    let source = std::fs::read_to_string(&path).map_err(|e| {
+                        std::io::Error::new(
+                            e.kind(),
+                            format!(
+                                "Failed to read hyphenation patterns {}: {}",
+                                path.display(),
+                                e
+                            ),
+                        )
+                    })?;
+                    return Ok(Some(Self::parse(&source)));
+                }
+            }
+        }
+
+        if lang == "en" || lang.starts_with("en-") || lang.starts_with("en_") {
+            return Ok(Some(Self::english()));
+        }
+
+        Ok(None)
+    }
+
+    /// Parse a TeX pattern file or a plain list of patterns
+    pub fn parse(source: &str) -> Self {
+        let mut hyphenator = Self {
+            patterns: HashMap::new(),
+            exceptions: HashMap::new(),
+            max_pattern_len: 0,
+            left_min: 2,
+            right_min: 3,
+        };
+
+        let mut group = if source.contains("\\patterns") {
+            None
+        } else {
+            Some("patterns")
+        };
+
+        for line in source.lines() {
+            let line = line.split('%').next().unwrap_or_default();
+            for mut token in line.split_whitespace() {
+                if let Some(rest) = token.strip_prefix("\\patterns{") {
+                    group = Some("patterns");
+                    token = rest;
+                } else if let Some(rest) = token.strip_prefix("\\hyphenation{") {
+                    group = Some("hyphenation");
+                    token = rest;
+                }
+
+                let closes_group = token.ends_with('}');
+                let token = token.trim_end_matches('}');
+                if !token.is_empty() {
+                    match group {
+                        Some("patterns") => hyphenator.add_pattern(token),
+                        Some("hyphenation") => hyphenator.add_exception(token),
+                        _ => {}
+                    }
+                }
+                if closes_group {
+                    group = None;
+                }
+            }
+        }
+
+        hyphenator
+    }
+
+    /// Add a pattern such as `.ach4` or `a1b2c`
+    fn add_pattern(&mut self, pattern: &str) {
+        let mut letters = String::new();
+        let mut values = vec![0];
+        for c in pattern.chars() {
+            match c.to_digit(10) {
+                Some(value) => *values.last_mut().unwrap() = value as u8,
+                None => {
+                    letters.push(c);
+                    values.push(0);
+                }
+            }
+        }
+
+        if letters.is_empty() {
+            return;
+        }
+        self.max_pattern_len = self.max_pattern_len.max(letters.chars().count());
+        self.patterns.insert(letters, values);
+    }
+
+    /// Add an exception such as `as-so-ciate`
+    fn add_exception(&mut self, hyphenated: &str) {
+        let mut word = String::new();
+        let mut points = Vec::new();
+        let mut len = 0;
+        for c in hyphenated.chars() {
+            if c == '-' {
+                points.push(len);
+            } else {
+                word.extend(c.to_lowercase());
+                len += 1;
+            }
+        }
+        if !word.is_empty() {
+            self.exceptions.insert(word, points);
+        }
+    }
+
+    /// Byte offsets in `word` where it may be hyphenated
+    ///
+    /// Leading and trailing punctuation is ignored. Only words made entirely
+    /// of letters are hyphenated, so identifiers, numbers and URLs are left alone.
+    pub fn hyphenate(&self, word: &str) -> Vec<usize> {
+        let is_punctuation = |c: char| !c.is_alphanumeric();
+        let prefix = word.len() - word.trim_start_matches(is_punctuation).len();
+        let word = word.trim_matches(is_punctuation);
+
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() < self.left_min + self.right_min || !chars.iter().all(|c| c.is_alphabetic())
+        {
+            return vec![];
+        }
+
+        let lower: Vec<char> = chars
+            .iter()
+            .map(|c| c.to_lowercase().next().unwrap_or(*c))
+            .collect();
+
+        // Character indices that start a new syllable
+        let points: Vec<usize> = match self.exceptions.get(&lower.iter().collect::<String>()) {
+            Some(points) => points.clone(),
+            None => {
+                let dotted: Vec<char> = std::iter::once('.')
+                    .chain(lower.iter().copied())
+                    .chain(std::iter::once('.'))
+                    .collect();
+
+                let mut values = vec![0u8; dotted.len() + 1];
+                let mut key = String::new();
+                for start in 0..dotted.len() {
+                    key.clear();
+                    for &c in &dotted[start..dotted.len().min(start + self.max_pattern_len)] {
+                        key.push(c);
+                        if let Some(pattern) = self.patterns.get(&key) {
+                            for (offset, &value) in pattern.iter().enumerate() {
+                                let slot = &mut values[start + offset];
+                                *slot = (*slot).max(value);
+                            }
+                        }
+                    }
+                }
+
+                // values[idx + 1] sits before character idx of the word
+                (1..chars.len())
+                    .filter(|&idx| values[idx + 1] % 2 == 1)
+                    .collect()
+            }
+        };
+
+        let byte_offsets: Vec<usize> = word.char_indices().map(|(offset, _)| offset).collect();
+        points
+            .into_iter()
+            .filter(|&idx| idx >= self.left_min && idx + self.right_min <= chars.len())
+            .map(|idx| prefix + byte_offsets[idx])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syllables(hyphenator: &Hyphenator, word: &str) -> String {
+        let mut result = String::new();
+        let mut last = 0;
+        for offset in hyphenator.hyphenate(word) {
+            result.push_str(&word[last..offset]);
+            result.push('-');
+            last = offset;
+        }
+        result.push_str(&word[last..]);
+        result
+    }
+
+    #[test]
+    fn test_english_patterns() {
+        let english = Hyphenator::english();
+        assert_eq!(syllables(&english, "hyphenation"), "hy-phen-ation");
+        assert_eq!(syllables(&english, "extensive"), "ex-ten-sive");
+        assert_eq!(syllables(&english, "Probability"), "Prob-a-bil-ity");
+        assert_eq!(syllables(&english, "walking"), "walk-ing");
+        assert_eq!(syllables(&english, "hello"), "hello");
+        assert_eq!(syllables(&english, "(extensive),"), "(ex-ten-sive),");
+    }
+
+    #[test]
+    fn test_exceptions_and_limits() {
+        let english = Hyphenator::english();
+        assert_eq!(syllables(&english, "associate"), "as-so-ciate");
+        assert_eq!(syllables(&english, "project"), "project");
+        // Too short, or not a plain word
+        assert_eq!(syllables(&english, "ahead"), "ahead");
+        assert_eq!(syllables(&english, "find_line_breaks"), "find_line_breaks");
+        assert_eq!(syllables(&english, "x86_64"), "x86_64");
+    }
+
+    #[test]
+    fn test_plain_pattern_list() {
+        // Break between every pair of letters
+        let hyphenator = Hyphenator::parse("a1b\nb1a\n");
+        assert_eq!(syllables(&hyphenator, "ababab"), "ab-a-bab");
+
+        let tex = Hyphenator::parse("% comment\n\\patterns{\na1b b1a\n}\n\\hyphenation{ba-baba}\n");
+        assert_eq!(syllables(&tex, "ababab"), "ab-a-bab");
+        assert_eq!(syllables(&tex, "bababa"), "ba-baba");
+    }
+
+    #[test]
+    fn test_patterns_from_directory() {
+        let dir = std::env::temp_dir().join(format!("mdreport-hyph-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hyph-xx.pat.txt"), "a1b\n").unwrap();
+
+        let loaded = Hyphenator::for_language("xx", Some(&dir)).unwrap().unwrap();
+        assert_eq!(syllables(&loaded, "aabaab"), "aa-baab");
+        assert!(
+            Hyphenator::for_language("yy", Some(&dir))
+                .unwrap()
+                .is_none()
+        );
+        assert!(Hyphenator::for_language("en-GB", None).unwrap().is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
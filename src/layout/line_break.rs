@@ -1,194 +1,774 @@
 //! An implementation of Knuth-Platt
 //! generated by Claude Code and based on:
 //! <https://github.com/jaroslov/knuth-plass-thoughts/blob/master/plass.md>
+//!
+//! Paragraphs are described with the box/glue/penalty model from "Breaking
+//! Paragraphs into Lines" (Knuth & Plass, 1981) and broken with the
+//! active-node algorithm from the same paper.
 
-/// Trait for items that can be laid out in lines
-pub trait LayoutItem {
-    /// Width of this item in layout units (e.g., millimeters or points)
-    fn width(&self) -> f32;
+use std::cell::RefCell;
+
+/// Penalty at or above which a break is forbidden; at or below its negation a break is forced
+pub const INFINITE_PENALTY: f32 = 10000.0;
+
+/// Stretchability that absorbs any amount of space, e.g. to fill the last line of a paragraph
+pub const FILL: f32 = f32::INFINITY;
+
+/// Badness assigned to lines that cannot stretch or shrink to the line width
+const INFINITE_BADNESS: f64 = 10000.0;
+
+/// An element of a paragraph in the box/glue/penalty model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Item {
+    /// Material that is never broken, such as a word
+    Box { width: f32 },
+    /// Space between boxes that can stretch or shrink; a legal break point after a box
+    Glue {
+        width: f32,
+        stretch: f32,
+        shrink: f32,
+    },
+    /// A possible break point with an aesthetic cost
+    ///
+    /// `width` is typeset only when the line is broken here, e.g. a hyphen.
+    /// Consecutive breaks at flagged penalties incur extra demerits.
+    Penalty {
+        width: f32,
+        penalty: f32,
+        flagged: bool,
+    },
+}
+
+impl Item {
+    /// A penalty that forces a line break
+    pub fn forced_break() -> Self {
+        Item::Penalty {
+            width: 0.0,
+            penalty: -INFINITE_PENALTY,
+            flagged: false,
+        }
+    }
+
+    /// Append the standard paragraph ending: glue that fills the last line,
+    /// which may not be broken before, and a forced break
+    pub fn finish_paragraph(items: &mut Vec<Item>) {
+        items.push(Item::Penalty {
+            width: 0.0,
+            penalty: INFINITE_PENALTY,
+            flagged: false,
+        });
+        items.push(Item::Glue {
+            width: 0.0,
+            stretch: FILL,
+            shrink: 0.0,
+        });
+        items.push(Item::forced_break());
+    }
+
+    fn is_box(&self) -> bool {
+        matches!(self, Item::Box { .. })
+    }
+
+    fn is_forced_break(&self) -> bool {
+        matches!(self, Item::Penalty { penalty, .. } if *penalty <= -INFINITE_PENALTY)
+    }
 }
 
-/// Entry in the dynamic programming table for line breaking
-#[derive(Debug, Clone)]
-struct BreakEntry {
-    /// Best score from this point to the end of the paragraph
-    score: f32,
-    /// Index of the next break point in the optimal solution
-    next_break: Option<usize>,
+/// Tuning parameters for [`find_line_breaks`]
+#[derive(Clone, Copy, Debug)]
+pub struct LineBreakParams {
+    /// Width every line is set to
+    pub line_width: f32,
+    /// Largest adjustment ratio accepted before falling back to overfull or very loose lines
+    pub tolerance: f32,
+    /// Demerits added to every line, favouring fewer lines
+    pub line_penalty: f32,
+    /// Demerits for two consecutive lines ending at flagged penalties (e.g. hyphens)
+    pub flagged_demerits: f32,
+    /// Demerits for adjacent lines whose fitness classes are not neighbours
+    pub fitness_demerits: f32,
+    /// Preferred difference from the optimal number of lines
+    pub looseness: i32,
 }
 
-impl BreakEntry {
-    fn unsolved() -> Self {
+impl LineBreakParams {
+    pub fn new(line_width: f32) -> Self {
         Self {
-            score: -1.0,
-            next_break: None,
+            line_width,
+            tolerance: 2.0,
+            line_penalty: 10.0,
+            flagged_demerits: 3000.0,
+            fitness_demerits: 3000.0,
+            looseness: 0,
         }
     }
+}
 
-    fn is_solved(&self) -> bool {
-        self.score >= 0.0
+/// A chosen line break
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Break {
+    /// Index of the glue or penalty item the line ends at
+    pub position: usize,
+    /// Adjustment ratio of the line ending here: the fraction of its total
+    /// stretch (positive) or shrink (negative) needed to fill the line width
+    pub ratio: f32,
+}
+
+/// Running totals of the items before a position
+#[derive(Clone, Copy, Debug, Default)]
+struct Totals {
+    width: f64,
+    stretch: f64,
+    shrink: f64,
+    /// Number of glues with infinite stretch
+    fills: u32,
+}
+
+impl Totals {
+    fn add_glue(&mut self, width: f32, stretch: f32, shrink: f32) {
+        self.width += width as f64;
+        if stretch.is_infinite() {
+            self.fills += 1;
+        } else {
+            self.stretch += stretch as f64;
+        }
+        self.shrink += shrink as f64;
     }
 }
 
-/// Find optimal line breaks using the Knuth-Plass algorithm
+/// A feasible break, linked to the best break before it
+#[derive(Clone, Debug)]
+struct Node {
+    position: usize,
+    line: usize,
+    fitness: usize,
+    /// Totals up to the first box after this break
+    totals: Totals,
+    demerits: f64,
+    ratio: f64,
+    previous: Option<usize>,
+}
+
+/// Most active breaks kept while scanning a paragraph
 ///
-/// Returns a vector of indices where line breaks should occur.
-/// These indices point to the first item of each new line.
+/// A line can start at most this many feasible breaks back, which bounds the
+/// work per break point on pathological input (e.g. thousands of tiny items
+/// per line). When the limit is hit the oldest breaks are dropped first.
+const MAX_ACTIVE_NODES: usize = 512;
+
+thread_local! {
+    static LINE_BREAKER: RefCell<LineBreaker> = RefCell::default();
+}
+
+/// Find optimal line breaks using the Knuth-Plass algorithm
 ///
-/// # Arguments
-/// * `items` - The items to break into lines
-/// * `ideal_width` - Target width for each line
-/// * `max_width` - Maximum allowed width for a line
+/// Returns the break that ends each line, in order. The last item must be a
+/// forced break (see [`Item::finish_paragraph`]) and is always the final break.
 ///
 /// # Algorithm
-/// Uses dynamic programming to find globally optimal breaks that minimize
-/// total badness, where badness = (ideal_width - actual_width)²
-pub fn find_line_breaks<T: LayoutItem>(
-    items: &[T],
-    ideal_width: f32,
-    max_width: f32,
-) -> Vec<usize> {
+/// Walks the paragraph once, keeping a list of active breaks that could
+/// still start the current line. Each line is scored with demerits based on
+/// its badness `100|r|³` (where `r` is the adjustment ratio), the penalty at
+/// its break, consecutive flagged breaks and jumps between fitness classes.
+/// If no set of breaks fits within `tolerance`, the paragraph is broken again
+/// accepting any ratio, and overfull lines where unavoidable.
+///
+/// Nothing recurses, and memory grows linearly with the number of feasible
+/// breaks. The working buffers are reused by later calls on the same thread.
+pub fn find_line_breaks(items: &[Item], params: &LineBreakParams) -> Vec<Break> {
     if items.is_empty() {
         return vec![];
     }
+    debug_assert!(
+        items.last().is_some_and(Item::is_forced_break),
+        "paragraph must end with a forced break"
+    );
 
-    // Initialize DP table
-    let mut entries: Vec<BreakEntry> = vec![BreakEntry::unsolved(); items.len()];
+    LINE_BREAKER.with_borrow_mut(|breaker| {
+        breaker
+            .run(items, params, params.tolerance as f64, false)
+            .or_else(|| breaker.run(items, params, f64::INFINITY, true))
+            .unwrap_or_default()
+    })
+}
 
-    // Solve starting from index 0
-    solve_breaks(items, &mut entries, 0, ideal_width, max_width);
+/// Best way found so far to end a line at the current break point
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    line: usize,
+    fitness: usize,
+    demerits: f64,
+    previous: usize,
+    ratio: f64,
+}
 
-    // Extract break points by following the chain
-    let mut breaks = vec![];
-    let mut current = 0;
+/// Working memory for the line breaker, kept between paragraphs
+#[derive(Debug, Default)]
+struct LineBreaker {
+    /// Every feasible break found so far; the active list and the chosen
+    /// breaks refer to them by index
+    nodes: Vec<Node>,
+    active: Vec<usize>,
+    kept: Vec<usize>,
+    candidates: Vec<Candidate>,
+}
 
-    while current < items.len() {
-        if let Some(next) = entries[current].next_break {
-            breaks.push(next);
-            current = next;
-        } else {
-            // Last line - no more breaks
-            break;
+impl LineBreaker {
+    fn run(
+        &mut self,
+        items: &[Item],
+        params: &LineBreakParams,
+        tolerance: f64,
+        emergency: bool,
+    ) -> Option<Vec<Break>> {
+        self.nodes.clear();
+        self.nodes.push(Node {
+            position: 0,
+            line: 0,
+            fitness: 1,
+            totals: Totals::default(),
+            demerits: 0.0,
+            ratio: 0.0,
+            previous: None,
+        });
+        self.active.clear();
+        self.active.push(0);
+
+        let mut pass = Pass {
+            items,
+            params,
+            tolerance,
+            emergency,
+            totals: Totals::default(),
+            breaker: self,
+        };
+
+        for (idx, item) in items.iter().enumerate() {
+            match *item {
+                Item::Box { width } => pass.totals.width += width as f64,
+                Item::Glue {
+                    width,
+                    stretch,
+                    shrink,
+                } => {
+                    if idx > 0 && items[idx - 1].is_box() {
+                        pass.try_break(idx);
+                    }
+                    pass.totals.add_glue(width, stretch, shrink);
+                }
+                Item::Penalty { penalty, .. } => {
+                    if penalty < INFINITE_PENALTY {
+                        pass.try_break(idx);
+                    }
+                }
+            }
+
+            if pass.breaker.active.is_empty() {
+                return None;
+            }
         }
+
+        let best = pass.choose_final()?;
+
+        let mut breaks = Vec::new();
+        let mut current = Some(best);
+        while let Some(node) = current {
+            let node = &self.nodes[node];
+            if node.previous.is_some() {
+                breaks.push(Break {
+                    position: node.position,
+                    ratio: node.ratio as f32,
+                });
+            }
+            current = node.previous;
+        }
+        breaks.reverse();
+        Some(breaks)
     }
+}
 
-    breaks
+/// State of a single pass over the paragraph
+struct Pass<'a> {
+    items: &'a [Item],
+    params: &'a LineBreakParams,
+    tolerance: f64,
+    /// Whether to accept overfull lines when nothing else fits
+    emergency: bool,
+    totals: Totals,
+    breaker: &'a mut LineBreaker,
 }
 
-/// Recursively solve the optimal line breaking problem from a given index
-fn solve_breaks<T: LayoutItem>(
-    items: &[T],
-    entries: &mut [BreakEntry],
-    idx: usize,
-    ideal_width: f32,
-    max_width: f32,
-) {
-    // Already solved this subproblem
-    if entries[idx].is_solved() {
-        return;
-    }
+impl Pass<'_> {
+    /// Pick the final break with the fewest demerits, honouring the looseness parameter
+    fn choose_final(&self) -> Option<usize> {
+        let last = self.items.len() - 1;
+        let nodes = &self.breaker.nodes;
+        let finished = || {
+            self.breaker
+                .active
+                .iter()
+                .copied()
+                .filter(move |&node| nodes[node].position == last)
+        };
 
-    let mut jdx = idx;
-    let mut line_width = 0.0;
-    let mut best_score = f32::MAX;
-    let mut best_break = None;
+        let best = finished().min_by(|&a, &b| nodes[a].demerits.total_cmp(&nodes[b].demerits))?;
+        if self.params.looseness == 0 {
+            return Some(best);
+        }
+
+        let target = nodes[best].line as i64 + self.params.looseness as i64;
+        finished().min_by(|&a, &b| {
+            let distance = |node: usize| (nodes[node].line as i64 - target).abs();
+            distance(a)
+                .cmp(&distance(b))
+                .then(nodes[a].demerits.total_cmp(&nodes[b].demerits))
+        })
+    }
 
-    // Try progressively longer lines starting from idx
-    while jdx < items.len() {
-        let item_width = items[jdx].width();
+    /// Adjustment ratio of a line from an active node to the break at `idx`
+    fn ratio(&self, node: &Node, idx: usize) -> f64 {
+        let mut width = self.totals.width - node.totals.width;
+        if let Item::Penalty { width: extra, .. } = self.items[idx] {
+            width += extra as f64;
+        }
+        let target = self.params.line_width as f64;
 
-        // Check if adding this item would exceed max width
-        let new_width = if jdx == idx {
-            item_width // First item on line
+        if width < target {
+            if self.totals.fills > node.totals.fills {
+                return 0.0;
+            }
+            let stretch = self.totals.stretch - node.totals.stretch;
+            if stretch > 0.0 {
+                (target - width) / stretch
+            } else {
+                f64::INFINITY
+            }
+        } else if width > target {
+            let shrink = self.totals.shrink - node.totals.shrink;
+            if shrink > 0.0 {
+                (target - width) / shrink
+            } else {
+                f64::NEG_INFINITY
+            }
         } else {
-            line_width + 1.0 + item_width // Add space + item
+            0.0
+        }
+    }
+
+    fn try_break(&mut self, idx: usize) {
+        let (penalty, flagged) = match self.items[idx] {
+            Item::Penalty {
+                penalty, flagged, ..
+            } => (penalty as f64, flagged),
+            _ => (0.0, false),
         };
+        let forced = penalty <= -INFINITE_PENALTY as f64;
 
-        if new_width > max_width {
-            break; // Line too long, stop trying
-        }
+        // Best candidate per (line number, fitness class); line numbers are only
+        // kept apart when looseness needs paragraphs of different lengths
+        let mut candidates = std::mem::take(&mut self.breaker.candidates);
+        let mut kept = std::mem::take(&mut self.breaker.kept);
+        candidates.clear();
+        kept.clear();
+        let mut deactivated: Option<usize> = None;
 
-        line_width = new_width;
-        jdx += 1;
+        for &node_idx in &self.breaker.active {
+            let node = &self.breaker.nodes[node_idx];
+            let ratio = self.ratio(node, idx);
 
-        // Calculate badness of this line
-        let line_score = {
-            let diff = ideal_width - line_width;
-            diff * diff
-        };
+            if ratio < -1.0 || forced {
+                // No later line can start here; remember the latest for emergencies
+                if ratio < -1.0
+                    && deactivated
+                        .is_none_or(|other| self.breaker.nodes[other].position < node.position)
+                {
+                    deactivated = Some(node_idx);
+                }
+            } else {
+                kept.push(node_idx);
+            }
 
-        // Check if this is the last line
-        if jdx >= items.len() {
-            // Last line doesn't contribute to badness
-            if line_score < best_score {
-                best_score = 0.0;
-                best_break = None; // No more breaks needed
+            if (-1.0..=self.tolerance).contains(&ratio) {
+                let candidate = Candidate {
+                    line: if self.params.looseness == 0 {
+                        0
+                    } else {
+                        node.line
+                    },
+                    fitness: fitness_class(ratio),
+                    demerits: node.demerits + self.demerits(node, ratio, penalty, flagged),
+                    previous: node_idx,
+                    // An emergency line with no stretch to fill it is set at
+                    // its natural width
+                    ratio: if ratio.is_finite() { ratio } else { 0.0 },
+                };
+                match candidates
+                    .iter_mut()
+                    .find(|c| c.line == candidate.line && c.fitness == candidate.fitness)
+                {
+                    Some(best) if best.demerits <= candidate.demerits => {}
+                    Some(best) => *best = candidate,
+                    None => candidates.push(candidate),
+                }
             }
-        } else {
-            // Recursively solve the rest of the paragraph
-            if !entries[jdx].is_solved() {
-                solve_breaks(items, entries, jdx, ideal_width, max_width);
+        }
+        std::mem::swap(&mut self.breaker.active, &mut kept);
+        self.breaker.kept = kept;
+
+        if candidates.is_empty()
+            && self.breaker.active.is_empty()
+            && self.emergency
+            && let Some(node_idx) = deactivated
+        {
+            // Nothing fits: accept an overfull line from the latest break
+            candidates.push(Candidate {
+                line: 0,
+                fitness: 0,
+                demerits: self.breaker.nodes[node_idx].demerits
+                    + INFINITE_BADNESS * INFINITE_BADNESS,
+                previous: node_idx,
+                ratio: -1.0,
+            });
+        }
+
+        if !candidates.is_empty() {
+            let totals = self.totals_after(idx);
+            for candidate in &candidates {
+                let line = self.breaker.nodes[candidate.previous].line + 1;
+                self.breaker.nodes.push(Node {
+                    position: idx,
+                    line,
+                    fitness: candidate.fitness,
+                    totals,
+                    demerits: candidate.demerits,
+                    ratio: candidate.ratio,
+                    previous: Some(candidate.previous),
+                });
+                self.breaker.active.push(self.breaker.nodes.len() - 1);
             }
 
-            // Total score is this line + the rest
-            let total_score = line_score + entries[jdx].score;
+            // Bounded lookback: forget the oldest breaks (the active list is
+            // ordered by position)
+            let excess = self.breaker.active.len().saturating_sub(MAX_ACTIVE_NODES);
+            self.breaker.active.drain(..excess);
+        }
+        self.breaker.candidates = candidates;
+    }
 
-            if total_score < best_score {
-                best_score = total_score;
-                best_break = Some(jdx);
+    /// Demerits of a line from `node` to the break at `idx`
+    fn demerits(&self, node: &Node, ratio: f64, penalty: f64, flagged: bool) -> f64 {
+        let badness = (100.0 * ratio.abs().powi(3)).min(INFINITE_BADNESS);
+        let base = (self.params.line_penalty as f64 + badness).powi(2);
+        let mut demerits = if penalty >= 0.0 {
+            base + penalty * penalty
+        } else if penalty > -INFINITE_PENALTY as f64 {
+            base - penalty * penalty
+        } else {
+            base
+        };
+
+        let previous_flagged = node.previous.is_some()
+            && matches!(
+                self.items[node.position],
+                Item::Penalty { flagged: true, .. }
+            );
+        if flagged && previous_flagged {
+            demerits += self.params.flagged_demerits as f64;
+        }
+        if fitness_class(ratio).abs_diff(node.fitness) > 1 {
+            demerits += self.params.fitness_demerits as f64;
+        }
+        demerits
+    }
+
+    /// Totals at the start of the line after a break at `idx`, skipping the
+    /// glue and penalties that are discarded at the start of a line
+    fn totals_after(&self, idx: usize) -> Totals {
+        let mut totals = self.totals;
+        for (offset, item) in self.items[idx..].iter().enumerate() {
+            match *item {
+                Item::Box { .. } => break,
+                Item::Glue {
+                    width,
+                    stretch,
+                    shrink,
+                } => totals.add_glue(width, stretch, shrink),
+                Item::Penalty { .. } => {
+                    if offset > 0 && item.is_forced_break() {
+                        break;
+                    }
+                }
             }
         }
+        totals
     }
+}
 
-    // Record the best solution for this subproblem
-    entries[idx].score = best_score;
-    entries[idx].next_break = best_break;
+/// Fitness class of a line: 0 tight, 1 decent, 2 loose, 3 very loose
+fn fitness_class(ratio: f64) -> usize {
+    if ratio < -0.5 {
+        0
+    } else if ratio <= 0.5 {
+        1
+    } else if ratio <= 1.0 {
+        2
+    } else {
+        3
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // Simple test item with fixed width
-    struct TestItem {
-        width: f32,
+    /// Words of the given widths separated by glue of width 1.0
+    fn paragraph(widths: &[f32]) -> Vec<Item> {
+        let mut items = Vec::new();
+        for (idx, &width) in widths.iter().enumerate() {
+            if idx > 0 {
+                items.push(Item::Glue {
+                    width: 1.0,
+                    stretch: 0.5,
+                    shrink: 0.3,
+                });
+            }
+            items.push(Item::Box { width });
+        }
+        Item::finish_paragraph(&mut items);
+        items
     }
 
-    impl LayoutItem for TestItem {
-        fn width(&self) -> f32 {
-            self.width
-        }
+    fn positions(breaks: &[Break]) -> Vec<usize> {
+        breaks.iter().map(|b| b.position).collect()
     }
 
     #[test]
     fn test_simple_break() {
-        let items = vec![
-            TestItem { width: 5.0 },
-            TestItem { width: 5.0 },
-            TestItem { width: 5.0 },
-        ];
+        let items = paragraph(&[5.0, 5.0, 5.0]);
 
-        let breaks = find_line_breaks(&items, 10.0, 15.0);
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(11.0));
 
-        // Should break after second item (5 + 1 + 5 = 11, then 5 on next line)
-        assert_eq!(breaks, vec![2]);
+        // Break at the glue after the second item (5 + 1 + 5 = 11, then 5 on the next line)
+        assert_eq!(positions(&breaks), vec![3, items.len() - 1]);
+        assert_eq!(breaks[0].ratio, 0.0);
     }
 
     #[test]
     fn test_no_breaks_needed() {
-        let items = vec![TestItem { width: 3.0 }, TestItem { width: 3.0 }];
+        let items = paragraph(&[3.0, 3.0]);
 
-        let breaks = find_line_breaks(&items, 10.0, 15.0);
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(15.0));
 
-        // All fits on one line
-        let expected: Vec<usize> = vec![];
-        assert_eq!(breaks, expected);
+        // All fits on one line, which ends at the final forced break
+        assert_eq!(positions(&breaks), vec![items.len() - 1]);
     }
 
     #[test]
     fn test_empty_input() {
-        let items: Vec<TestItem> = vec![];
-        let breaks = find_line_breaks(&items, 10.0, 15.0);
-        assert_eq!(breaks, Vec::<usize>::new());
+        let items: Vec<Item> = vec![];
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(15.0));
+        assert_eq!(breaks, Vec::<Break>::new());
+    }
+
+    #[test]
+    fn test_justified_lines_stretch_and_shrink() {
+        let items = paragraph(&[4.0; 12]);
+
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(20.0));
+
+        // Every line but the last is set within its stretch and shrink limits
+        for line in &breaks[..breaks.len() - 1] {
+            assert!((-1.0..=2.0).contains(&line.ratio), "ratio {}", line.ratio);
+        }
+        assert_eq!(breaks.last().unwrap().ratio, 0.0);
+    }
+
+    #[test]
+    fn test_penalties_guide_breaks() {
+        // Two 5 wide words, a forbidden break, then two more words
+        let mut items = vec![
+            Item::Box { width: 5.0 },
+            Item::Penalty {
+                width: 0.0,
+                penalty: INFINITE_PENALTY,
+                flagged: false,
+            },
+            Item::Glue {
+                width: 1.0,
+                stretch: 0.5,
+                shrink: 0.3,
+            },
+            Item::Box { width: 5.0 },
+        ];
+        items.extend(paragraph(&[5.0, 5.0]));
+        items.insert(
+            4,
+            Item::Glue {
+                width: 1.0,
+                stretch: 0.5,
+                shrink: 0.3,
+            },
+        );
+
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(11.0));
+
+        // The glue after a penalty is not a legal break, so the first break comes later
+        assert_eq!(positions(&breaks), vec![4, items.len() - 1]);
+    }
+
+    #[test]
+    fn test_flagged_penalties_add_demerits() {
+        // Words that can each be hyphenated once in the middle
+        let halves = [
+            (4.0, 5.0),
+            (4.0, 4.0),
+            (2.0, 4.0),
+            (6.0, 5.0),
+            (4.0, 4.0),
+            (4.0, 4.0),
+            (5.0, 4.0),
+            (4.0, 3.0),
+        ];
+        let mut items = Vec::new();
+        for (idx, (first, second)) in halves.into_iter().enumerate() {
+            if idx > 0 {
+                items.push(Item::Glue {
+                    width: 1.0,
+                    stretch: 1.0,
+                    shrink: 0.5,
+                });
+            }
+            items.push(Item::Box { width: first });
+            items.push(Item::Penalty {
+                width: 1.0,
+                penalty: 50.0,
+                flagged: true,
+            });
+            items.push(Item::Box { width: second });
+        }
+        Item::finish_paragraph(&mut items);
+
+        let consecutive_hyphens = |breaks: &[Break]| {
+            breaks.windows(2).any(|pair| {
+                pair.iter()
+                    .all(|b| matches!(items[b.position], Item::Penalty { flagged: true, .. }))
+            })
+        };
+
+        let mut params = LineBreakParams::new(16.0);
+        params.flagged_demerits = 0.0;
+        assert!(consecutive_hyphens(&find_line_breaks(&items, &params)));
+
+        params.flagged_demerits = 3000.0;
+        assert!(!consecutive_hyphens(&find_line_breaks(&items, &params)));
+    }
+
+    #[test]
+    fn test_looseness() {
+        let items = paragraph(&[3.0; 20]);
+        let mut params = LineBreakParams::new(20.0);
+        params.tolerance = 10.0;
+
+        let tight = find_line_breaks(&items, &params);
+        params.looseness = 1;
+        let loose = find_line_breaks(&items, &params);
+
+        assert_eq!(loose.len(), tight.len() + 1);
+    }
+
+    #[test]
+    fn test_overfull_box() {
+        // A word wider than the line still gets a line of its own
+        let items = paragraph(&[3.0, 30.0, 3.0]);
+
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(10.0));
+
+        assert_eq!(positions(&breaks), vec![1, 3, items.len() - 1]);
+        assert_eq!(breaks[1].ratio, -1.0);
+
+        // Even when nothing fits, the paragraph ends without an empty line
+        let items = paragraph(&[3.0]);
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(1.0));
+        assert_eq!(positions(&breaks), vec![items.len() - 1]);
+    }
+
+    /// A long paragraph of words with varying widths and justified glue
+    fn long_paragraph(words: usize) -> Vec<Item> {
+        let widths: Vec<f32> = (0..words)
+            .map(|idx| 2.0 + (idx * 7 % 11) as f32 * 0.5)
+            .collect();
+        paragraph(&widths)
+    }
+
+    #[test]
+    fn test_very_long_paragraph() {
+        let short = long_paragraph(50);
+        let short_breaks = find_line_breaks(&short, &LineBreakParams::new(60.0));
+
+        let items = long_paragraph(60_000);
+        assert!(items.len() > 100_000);
+
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(60.0));
+
+        assert_eq!(breaks.last().unwrap().position, items.len() - 1);
+        assert!(
+            breaks
+                .windows(2)
+                .all(|pair| pair[0].position < pair[1].position)
+        );
+        for line in &breaks[..breaks.len() - 1] {
+            assert!((-1.0..=2.0).contains(&line.ratio), "ratio {}", line.ratio);
+        }
+
+        // Buffers left over from the long paragraph do not leak into the next one
+        assert_eq!(
+            find_line_breaks(&short, &LineBreakParams::new(60.0)),
+            short_breaks
+        );
+    }
+
+    #[test]
+    fn test_bounded_lookback() {
+        // Hundreds of thousands of tiny items per line would make every break
+        // point compare against every earlier one without the lookback bound
+        let mut items = Vec::new();
+        for idx in 0..100_000 {
+            if idx > 0 {
+                items.push(Item::Glue {
+                    width: 0.01,
+                    stretch: 0.005,
+                    shrink: 0.003,
+                });
+            }
+            items.push(Item::Box { width: 0.01 });
+        }
+        Item::finish_paragraph(&mut items);
+
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(1000.0));
+
+        assert_eq!(breaks.last().unwrap().position, items.len() - 1);
+        assert!(
+            breaks
+                .windows(2)
+                .all(|pair| pair[0].position < pair[1].position)
+        );
+    }
+
+    #[test]
+    fn test_unbreakable_run() {
+        // A pasted log line: one huge box in a long paragraph
+        let mut widths = vec![3.0; 50_000];
+        widths[25_000] = 5000.0;
+        let items = paragraph(&widths);
+
+        let breaks = find_line_breaks(&items, &LineBreakParams::new(40.0));
+
+        // The huge box (item 50000) sits alone on an overfull line
+        let huge = breaks
+            .iter()
+            .position(|line| line.position == 50_001)
+            .unwrap();
+        assert_eq!(breaks[huge - 1].position, 49_999);
+        assert_eq!(breaks[huge].ratio, -1.0);
     }
 }
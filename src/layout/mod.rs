@@ -1,6 +1,12 @@
+mod hyphenate;
 mod line_break;
 
-pub use crate::layout::line_break::{
-    LayoutItem,
-    find_line_breaks,
+pub use crate::layout::{
+    hyphenate::Hyphenator,
+    line_break::{
+        FILL,
+        Item,
+        LineBreakParams,
+        find_line_breaks,
+    },
 };
@@ -4,3 +4,4 @@
 pub mod fmt;
 pub mod layout;
 pub mod parse;
+pub mod snippet;
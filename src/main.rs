@@ -1,6 +1,7 @@
 mod fmt;
 mod layout;
 mod parse;
+mod snippet;
 
 use std::path::PathBuf;
 
@@ -11,6 +12,15 @@ use clap::{
 };
 use syntect::highlighting::ThemeSet;
 
+use crate::{
+    fmt::pdf::PdfOptions,
+    parse::{
+        FontConfig,
+        Margins,
+        PageConfig,
+    },
+};
+
 #[derive(Debug, Clone, ValueEnum)]
 enum OutputFormat {
     Html,
@@ -49,6 +59,57 @@ struct Args {
     /// Extract embedded markdown from a PDF file
     #[arg(long)]
     extract: bool,
+
+    /// Embed a TrueType/OpenType font in the PDF for a text style
+    /// (regular, bold, italic, bold_italic, mono or math)
+    #[arg(long = "font", value_name = "STYLE=PATH", value_parser = parse_font_arg)]
+    fonts: Vec<(String, PathBuf)>,
+
+    /// Directory with TeX hyphenation patterns (hyph-<lang>.tex or hyph-<lang>.pat.txt)
+    #[arg(long, value_name = "DIR")]
+    hyphenation_dir: Option<PathBuf>,
+
+    /// PDF page size: A4, A5, Letter, Legal or WxH such as 200x250mm or 6x9in
+    #[arg(long, value_name = "SIZE")]
+    page_size: Option<String>,
+
+    /// PDF page orientation
+    #[arg(long, value_parser = ["portrait", "landscape"])]
+    orientation: Option<String>,
+
+    /// PDF page margins in mm, as CSS shorthand: "20", "25,20" or "25,20,30,20"
+    #[arg(long, value_name = "MM", value_parser = parse_margins_arg)]
+    margins: Option<Margins>,
+
+    /// Write archival PDF/A (PDF/A-3b with the embedded source, PDF/A-2b without);
+    /// every text style needs an embedded font
+    #[arg(long)]
+    pdfa: bool,
+
+    /// Check that code blocks quoting a file still match it, and exit
+    /// with an error if any do not
+    #[arg(long)]
+    check_snippets: bool,
+}
+
+fn parse_font_arg(arg: &str) -> Result<(String, PathBuf), String> {
+    let (style, path) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected STYLE=PATH, got '{}'", arg))?;
+    if FontConfig::default().slot_mut(style).is_none() {
+        return Err(format!(
+            "unknown font style '{}' (expected regular, bold, italic, bold_italic, mono or math)",
+            style
+        ));
+    }
+    let path = std::path::absolute(path).map_err(|e| e.to_string())?;
+    Ok((style.to_string(), path))
+}
+
+fn parse_margins_arg(arg: &str) -> Result<Margins, String> {
+    let margins: Margins = arg.parse()?;
+    margins.check()?;
+    Ok(margins)
 }
 
 fn main() {
@@ -139,6 +200,19 @@ fn main() {
     let markdown_content = std::fs::read_to_string(&input)
         .unwrap_or_else(|_| panic!("Failed to read input file: {}", input.display()));
 
+    if args.check_snippets {
+        let (checked, problems) = crate::snippet::check_snippets(&markdown_content, Some(&input));
+        for problem in &problems {
+            eprintln!("Error: {}", problem);
+        }
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+        println!("{} code snippets match their source", checked);
+        return;
+    }
+    let markdown_content = crate::snippet::fill_snippets(&markdown_content, Some(&input));
+
     let format = args.format.unwrap_or_else(|| {
         match args
             .output
@@ -153,6 +227,27 @@ fn main() {
         }
     });
 
+    let mut fonts = FontConfig::default();
+    for (style, path) in &args.fonts {
+        if let Some(slot) = fonts.slot_mut(style) {
+            *slot = Some(path.to_string_lossy().into_owned());
+        }
+    }
+    let pdf_options = PdfOptions {
+        is_slide: matches!(format, OutputFormat::Slides),
+        code_theme: args.code_theme.clone(),
+        embed_source: args.embed_source,
+        source_path: Some(input.clone()),
+        fonts,
+        hyphenation_dir: args.hyphenation_dir.clone(),
+        page: PageConfig {
+            page_size: args.page_size.clone(),
+            orientation: args.orientation.clone(),
+            margins: args.margins.clone(),
+        },
+        pdfa: args.pdfa,
+    };
+
     let output_path = args.output.unwrap_or_else(|| {
         let mut output = input.clone();
         output.set_extension(match format {
@@ -174,29 +269,23 @@ fn main() {
         OutputFormat::Pdf => {
             let output = std::fs::File::create(&output_path).unwrap();
             let mut output = std::io::BufWriter::new(output);
-            crate::fmt::pdf::to_pdf(
-                &markdown_content,
-                &mut output,
-                false,
-                args.code_theme.as_deref(),
-                args.embed_source,
-                Some(&input),
-            )
-            .unwrap();
+            if let Err(e) = crate::fmt::pdf::to_pdf(&markdown_content, &mut output, &pdf_options) {
+                drop(output);
+                let _ = std::fs::remove_file(&output_path);
+                eprintln!("Error generating PDF: {}", e);
+                std::process::exit(1);
+            }
             println!("PDF report generated: {}", output_path.display());
         }
         OutputFormat::Slides => {
             let output = std::fs::File::create(&output_path).unwrap();
             let mut output = std::io::BufWriter::new(output);
-            crate::fmt::pdf::to_pdf(
-                &markdown_content,
-                &mut output,
-                true,
-                args.code_theme.as_deref(),
-                args.embed_source,
-                Some(&input),
-            )
-            .unwrap();
+            if let Err(e) = crate::fmt::pdf::to_pdf(&markdown_content, &mut output, &pdf_options) {
+                drop(output);
+                let _ = std::fs::remove_file(&output_path);
+                eprintln!("Error generating PDF: {}", e);
+                std::process::exit(1);
+            }
             println!("Slides PDF generated: {}", output_path.display());
         }
         OutputFormat::Email => {
@@ -8,10 +8,269 @@ pub struct FrontMatter {
     pub title: Option<String>,
     pub author: Option<String>,
     pub date: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<Keywords>,
     pub code_theme: Option<String>,
     pub slide_theme: Option<String>,
     pub gradient_direction: Option<String>,
     pub repo: Option<String>,
+    pub fonts: Option<FontConfig>,
+    pub text_align: Option<String>,
+    pub lang: Option<String>,
+    pub outline_depth: Option<u8>,
+    pub link_underline: Option<bool>,
+    pub toc: Option<bool>,
+    pub toc_depth: Option<u8>,
+    pub header: Option<PageTemplate>,
+    pub footer: Option<PageTemplate>,
+    pub list_style: Option<ListStyle>,
+    pub footnotes: Option<String>,
+    /// What PDF output does with code lines wider than the page: `wrap`,
+    /// `shrink` or `truncate`
+    pub code_overflow: Option<String>,
+    /// Directory, or checkout of a git repository, that the filenames of
+    /// filled and checked code blocks are relative to. Defaults to the
+    /// directory of the document.
+    pub snippet_root: Option<String>,
+    #[serde(flatten)]
+    pub page: PageConfig,
+}
+
+/// Paper size, orientation and margins for PDF output
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct PageConfig {
+    /// `A4`, `A5`, `Letter`, `Legal` or a custom size such as `200x250mm` or `6x9in`
+    pub page_size: Option<String>,
+    /// `portrait` or `landscape`
+    pub orientation: Option<String>,
+    pub margins: Option<Margins>,
+}
+
+impl PageConfig {
+    /// Fill unset values from another configuration
+    pub fn or(self, other: Option<&PageConfig>) -> PageConfig {
+        let Some(other) = other else {
+            return self;
+        };
+        PageConfig {
+            page_size: self.page_size.or_else(|| other.page_size.clone()),
+            orientation: self.orientation.or_else(|| other.orientation.clone()),
+            margins: match (self.margins, &other.margins) {
+                (Some(margins), Some(other)) => Some(margins.or(other)),
+                (margins, other) => margins.or_else(|| other.clone()),
+            },
+        }
+    }
+}
+
+/// Page margins in millimetres. Sides left unset keep their default.
+///
+/// Written as a number for every side, as CSS-style shorthand such as
+/// `"25 20"` (vertical, horizontal) or `"25 20 30 20"` (top, right, bottom,
+/// left), or as a map of sides.
+#[derive(Clone, Debug, Deserialize, Default, PartialEq)]
+#[serde(try_from = "MarginsSpec")]
+pub struct Margins {
+    pub top: Option<f32>,
+    pub right: Option<f32>,
+    pub bottom: Option<f32>,
+    pub left: Option<f32>,
+}
+
+impl Margins {
+    /// Refuse a side that is negative or not a number
+    pub fn check(&self) -> Result<(), String> {
+        let sides = [
+            ("top", self.top),
+            ("right", self.right),
+            ("bottom", self.bottom),
+            ("left", self.left),
+        ];
+        for (side, value) in sides {
+            if let Some(value) = value
+                && !(value.is_finite() && value >= 0.0)
+            {
+                return Err(format!(
+                    "invalid {} margin {} (expected 0 or more millimetres)",
+                    side, value
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn or(self, other: &Margins) -> Margins {
+        Margins {
+            top: self.top.or(other.top),
+            right: self.right.or(other.right),
+            bottom: self.bottom.or(other.bottom),
+            left: self.left.or(other.left),
+        }
+    }
+}
+
+impl FromStr for Margins {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .trim_end_matches("mm")
+                    .parse::<f32>()
+                    .map_err(|_| format!("invalid margin '{}'", value))
+            })
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        let [top, right, bottom, left] = match values[..] {
+            [all] => [all; 4],
+            [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+            [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+            [top, right, bottom, left] => [top, right, bottom, left],
+            _ => return Err(format!("expected 1 to 4 margins, got '{}'", s)),
+        };
+        Ok(Margins {
+            top: Some(top),
+            right: Some(right),
+            bottom: Some(bottom),
+            left: Some(left),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MarginsSpec {
+    All(f32),
+    Shorthand(String),
+    Sides {
+        top: Option<f32>,
+        right: Option<f32>,
+        bottom: Option<f32>,
+        left: Option<f32>,
+    },
+}
+
+impl TryFrom<MarginsSpec> for Margins {
+    type Error = String;
+
+    fn try_from(spec: MarginsSpec) -> Result<Self, Self::Error> {
+        match spec {
+            MarginsSpec::All(all) => Ok(Margins {
+                top: Some(all),
+                right: Some(all),
+                bottom: Some(all),
+                left: Some(all),
+            }),
+            MarginsSpec::Shorthand(shorthand) => shorthand.parse(),
+            MarginsSpec::Sides {
+                top,
+                right,
+                bottom,
+                left,
+            } => Ok(Margins {
+                top,
+                right,
+                bottom,
+                left,
+            }),
+        }
+    }
+}
+
+/// Document keywords, written as a YAML list or a comma-separated string
+#[derive(Clone, Debug, Deserialize, Default, PartialEq)]
+#[serde(from = "KeywordsSpec")]
+pub struct Keywords(pub Vec<String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeywordsSpec {
+    List(Vec<String>),
+    Text(String),
+}
+
+impl From<KeywordsSpec> for Keywords {
+    fn from(spec: KeywordsSpec) -> Self {
+        let keywords = match spec {
+            KeywordsSpec::List(list) => list,
+            KeywordsSpec::Text(text) => text.split(',').map(str::to_string).collect(),
+        };
+        Keywords(
+            keywords
+                .into_iter()
+                .map(|keyword| keyword.trim().to_string())
+                .filter(|keyword| !keyword.is_empty())
+                .collect(),
+        )
+    }
+}
+
+/// Running header or footer text for PDF pages, with placeholders such as
+/// `{page}` and `{section}` in each slot
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct PageTemplate {
+    pub left: Option<String>,
+    #[serde(alias = "centre")]
+    pub center: Option<String>,
+    pub right: Option<String>,
+    /// Leave the first page (usually the title page) without this text
+    #[serde(default)]
+    pub skip_first_page: bool,
+}
+
+/// List item markers for PDF output, one entry per nesting depth. Deeper
+/// lists start again from the first entry.
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct ListStyle {
+    /// `disc`, `circle`, `square` or any text, such as `-` or `»`
+    pub bullets: Option<Vec<String>>,
+    /// Number formats such as `1.`, `a)`, `(i)` or `A.`
+    pub numbers: Option<Vec<String>>,
+}
+
+/// TrueType/OpenType font files to embed in PDF output, one per text style
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct FontConfig {
+    pub regular: Option<String>,
+    pub bold: Option<String>,
+    pub italic: Option<String>,
+    pub bold_italic: Option<String>,
+    pub mono: Option<String>,
+    /// Greek letters and symbols in math, in place of the standard Symbol font
+    pub math: Option<String>,
+}
+
+impl FontConfig {
+    /// Look up the font slot for a style name such as "bold" or "mono"
+    pub fn slot_mut(&mut self, style: &str) -> Option<&mut Option<String>> {
+        match style {
+            "regular" => Some(&mut self.regular),
+            "bold" => Some(&mut self.bold),
+            "italic" => Some(&mut self.italic),
+            "bold_italic" | "bold-italic" => Some(&mut self.bold_italic),
+            "mono" => Some(&mut self.mono),
+            "math" => Some(&mut self.math),
+            _ => None,
+        }
+    }
+
+    /// Fill unset slots from another configuration
+    pub fn or(self, other: Option<&FontConfig>) -> FontConfig {
+        let Some(other) = other else {
+            return self;
+        };
+        FontConfig {
+            regular: self.regular.or_else(|| other.regular.clone()),
+            bold: self.bold.or_else(|| other.bold.clone()),
+            italic: self.italic.or_else(|| other.italic.clone()),
+            bold_italic: self.bold_italic.or_else(|| other.bold_italic.clone()),
+            mono: self.mono.or_else(|| other.mono.clone()),
+            math: self.math.or_else(|| other.math.clone()),
+        }
+    }
 }
 
 pub struct MarkdownParser<'input> {
@@ -24,7 +283,11 @@ impl<'input> MarkdownParser<'input> {
         let mut options = Options::empty();
         options.insert(Options::ENABLE_TABLES);
         options.insert(Options::ENABLE_STRIKETHROUGH);
+        options.insert(Options::ENABLE_SUPERSCRIPT);
+        options.insert(Options::ENABLE_SUBSCRIPT);
         options.insert(Options::ENABLE_TASKLISTS);
+        options.insert(Options::ENABLE_FOOTNOTES);
+        options.insert(Options::ENABLE_MATH);
         options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
         options.insert(Options::ENABLE_GFM);
 
@@ -71,13 +334,113 @@ pub fn parse_front_matter(content: &str) -> Result<Option<(FrontMatter, &str)>,
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct CodeBlockInfo {
     pub language: String,
     pub filename: Option<String>,
     pub start_line: Option<usize>,
+    /// Last line of a `:first-last` range
+    pub end_line: Option<usize>,
     pub repo: Option<String>,
     pub refspec: Option<String>,
+    /// `overflow` attribute, overriding the document's `code_overflow`
+    pub overflow: Option<String>,
+    /// `title` attribute, shown in place of the filename
+    pub title: Option<String>,
+    /// `hl_lines` attribute: ranges of lines to highlight, counted from 1 at
+    /// the top of the block
+    pub highlight_lines: Vec<(usize, usize)>,
+    /// `linenos` attribute, turning line numbers on or off
+    pub line_numbers: Option<bool>,
+    /// `region` attribute: the lines of the file between its `ANCHOR: name`
+    /// or `#region name` marker and the matching end marker
+    pub region: Option<String>,
+    /// `symbol` attribute: the definition of that name in the file
+    pub symbol: Option<String>,
+    /// Problems with the attributes, to be reported
+    pub warnings: Vec<String>,
+}
+
+/// Parse line numbers and ranges such as `3-5,9`
+fn parse_line_ranges(value: &str) -> Option<Vec<(usize, usize)>> {
+    value
+        .split(',')
+        .map(|range| {
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            let (first, last) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+            (first <= last).then_some((first, last))
+        })
+        .collect()
+}
+
+/// Split `key=value` attributes in braces off the end of an info string, as
+/// in `rust {overflow="wrap"}`. A key without a value is set to `true`.
+fn split_attributes(info: &str) -> (&str, Vec<(String, String)>) {
+    let trimmed = info.trim_end();
+    let Some(open) = trimmed
+        .strip_suffix('}')
+        .and_then(|rest| rest.rfind('{'))
+        .filter(|&open| open == 0 || trimmed[..open].ends_with(' '))
+    else {
+        return (info, Vec::new());
+    };
+
+    let mut attributes = Vec::new();
+    let mut chars = trimmed[open + 1..trimmed.len() - 1].chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != '=') {
+            key.push(c);
+        }
+        if key.is_empty() {
+            break;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                value.extend(chars.by_ref().take_while(|&c| c != '"'));
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        } else {
+            value.push_str("true");
+        }
+        attributes.push((key, value));
+    }
+    (trimmed[..open].trim_end(), attributes)
+}
+
+/// Give the location of an info string the line range `first-last`, keeping
+/// its repository and attributes
+pub fn with_line_range(info: &str, first: usize, last: usize) -> String {
+    let (location, _) = split_attributes(info);
+    let (file_part, repo_part) = location.split_at(location.find(" @ ").unwrap_or(location.len()));
+    let filename = match file_part.rsplit_once(':') {
+        Some((filename, range)) if parse_line_range(range).is_some() => filename,
+        _ => file_part,
+    };
+    format!(
+        "{}:{}-{}{}{}",
+        filename,
+        first,
+        last,
+        repo_part,
+        &info[location.len()..]
+    )
+}
+
+/// Parse the line or `first-last` range after the colon of a location
+fn parse_line_range(range: &str) -> Option<(usize, Option<usize>)> {
+    match range.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+            (first <= last).then_some((first, Some(last)))
+        }
+        None => Some((range.parse().ok()?, None)),
+    }
 }
 
 impl FromStr for CodeBlockInfo {
@@ -87,9 +450,74 @@ impl FromStr for CodeBlockInfo {
     /// Also supports:
     /// - "path/to/file.rs:12 @ github-user/repo"
     /// - "path/to/file.rs:12"
+    /// - "path/to/file.rs:12-40" (a range of lines)
     /// - "path/to/file.rs @ github-user/repo#refspec"
     /// - "language" (plain language identifier)
+    ///
+    /// Any of these may end with attributes in braces, such as
+    /// `{hl_lines="3-5,9" title="Example" linenos=true}`.
     fn from_str(info: &str) -> Result<Self, Self::Err> {
+        let (info, attributes) = split_attributes(info);
+        let mut code_info = Self::parse_location(info);
+        for (key, value) in attributes {
+            code_info.set_attribute(&key, value);
+        }
+        Ok(code_info)
+    }
+}
+
+impl CodeBlockInfo {
+    /// Number of the first line when line numbers are shown: the `:line`
+    /// suffix, or 1 when `linenos` asks for numbers without one
+    pub fn first_line_number(&self) -> Option<usize> {
+        match self.line_numbers {
+            Some(false) => None,
+            Some(true) => Some(self.start_line.unwrap_or(1)),
+            None => self.start_line,
+        }
+    }
+
+    /// Whether the line at `index`, counted from 0, is listed in `hl_lines`
+    pub fn is_highlighted(&self, index: usize) -> bool {
+        self.highlight_lines
+            .iter()
+            .any(|&(first, last)| (first..=last).contains(&(index + 1)))
+    }
+
+    /// Heading over the block: the title, or else the filename
+    pub fn heading(&self) -> Option<&str> {
+        self.title.as_deref().or(self.filename.as_deref())
+    }
+
+    fn set_attribute(&mut self, key: &str, value: String) {
+        match key {
+            "overflow" => self.overflow = Some(value),
+            "title" => self.title = Some(value),
+            "region" => self.region = Some(value),
+            "symbol" => self.symbol = Some(value),
+            "hl_lines" => match parse_line_ranges(&value) {
+                Some(ranges) => self.highlight_lines = ranges,
+                None => self.warnings.push(format!(
+                    "code block hl_lines '{}' is not a list of lines such as \"3-5,9\"",
+                    value
+                )),
+            },
+            "linenos" => match value.as_str() {
+                "true" => self.line_numbers = Some(true),
+                "false" => self.line_numbers = Some(false),
+                _ => self.warnings.push(format!(
+                    "code block linenos '{}' should be true or false",
+                    value
+                )),
+            },
+            _ => self
+                .warnings
+                .push(format!("unknown code block attribute '{}' ignored", key)),
+        }
+    }
+
+    /// Parse the info string before any attributes
+    fn parse_location(info: &str) -> Self {
         let mut repo = None;
         let mut refspec = None;
         let mut file_part = info;
@@ -110,9 +538,9 @@ impl FromStr for CodeBlockInfo {
 
         // Now parse the file part for filename and line number
         if let Some(colon_pos) = file_part.rfind(':') {
-            // Check if the part after the colon is a number
+            // Check if the part after the colon is a line or range of lines
             let after_colon = &file_part[colon_pos + 1..];
-            if let Ok(line_num) = after_colon.parse::<usize>() {
+            if let Some((line_num, end_line)) = parse_line_range(after_colon) {
                 // It's a filename:line_number format
                 let filename = file_part[..colon_pos].to_string();
 
@@ -123,13 +551,15 @@ impl FromStr for CodeBlockInfo {
                     String::new()
                 };
 
-                return Ok(CodeBlockInfo {
+                return CodeBlockInfo {
                     language,
                     filename: Some(filename),
                     start_line: Some(line_num),
+                    end_line,
                     repo,
                     refspec,
-                });
+                    ..Default::default()
+                };
             }
         }
 
@@ -142,23 +572,21 @@ impl FromStr for CodeBlockInfo {
                 String::new()
             };
 
-            return Ok(CodeBlockInfo {
+            return CodeBlockInfo {
                 language,
                 filename: Some(filename),
                 start_line: None,
                 repo,
                 refspec,
-            });
+                ..Default::default()
+            };
         }
 
         // Regular language identifier
-        Ok(CodeBlockInfo {
+        CodeBlockInfo {
             language: info.to_string(),
-            filename: None,
-            start_line: None,
-            repo: None,
-            refspec: None,
-        })
+            ..Default::default()
+        }
     }
 }
 
@@ -176,6 +604,7 @@ mod tests {
                 start_line: None,
                 repo: None,
                 refspec: None,
+                ..Default::default()
             })
         );
     }
@@ -190,6 +619,7 @@ mod tests {
                 start_line: Some(42,),
                 repo: None,
                 refspec: None,
+                ..Default::default()
             })
         );
     }
@@ -204,6 +634,7 @@ mod tests {
                 start_line: Some(42,),
                 repo: Some("user/repo".into(),),
                 refspec: None,
+                ..Default::default()
             })
         );
     }
@@ -218,7 +649,130 @@ mod tests {
                 start_line: Some(42,),
                 repo: Some("user/repo".into(),),
                 refspec: Some("develop".into(),),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_code_block_info_with_line_range() {
+        assert_eq!(
+            CodeBlockInfo::from_str("src/foo.rs:10-40 @ user/repo#v1.2 {symbol=main}"),
+            Ok(CodeBlockInfo {
+                language: "rs".into(),
+                filename: Some("src/foo.rs".into()),
+                start_line: Some(10),
+                end_line: Some(40),
+                repo: Some("user/repo".into()),
+                refspec: Some("v1.2".into()),
+                symbol: Some("main".into()),
+                ..Default::default()
             })
         );
+        assert_eq!(
+            with_line_range("src/foo.rs @ user/repo {region=setup}", 3, 9),
+            "src/foo.rs:3-9 @ user/repo {region=setup}"
+        );
+        assert_eq!(
+            with_line_range("src/foo.rs:10-40", 12, 20),
+            "src/foo.rs:12-20"
+        );
+    }
+
+    #[test]
+    fn test_parse_code_block_info_with_attributes() {
+        assert_eq!(
+            CodeBlockInfo::from_str("src/main.rs:42 @ user/repo {overflow=shrink}"),
+            Ok(CodeBlockInfo {
+                language: "rs".into(),
+                filename: Some("src/main.rs".into()),
+                start_line: Some(42),
+                repo: Some("user/repo".into()),
+                refspec: None,
+                overflow: Some("shrink".into()),
+                ..Default::default()
+            })
+        );
+        let info = CodeBlockInfo::from_str(
+            r#"rust {hl_lines="3-5,9" title="Example" linenos=true colour=red}"#,
+        )
+        .unwrap();
+        assert_eq!(info.language, "rust");
+        assert_eq!(info.heading(), Some("Example"));
+        assert_eq!(info.highlight_lines, vec![(3, 5), (9, 9)]);
+        assert!(info.is_highlighted(2) && info.is_highlighted(8) && !info.is_highlighted(5));
+        assert_eq!(info.first_line_number(), Some(1));
+        assert_eq!(
+            info.warnings,
+            vec!["unknown code block attribute 'colour' ignored".to_string()]
+        );
+
+        let info = CodeBlockInfo::from_str("src/main.rs:42 {linenos=false hl_lines=x}").unwrap();
+        assert_eq!(info.first_line_number(), None);
+        assert!(info.highlight_lines.is_empty());
+        assert_eq!(info.warnings.len(), 1);
+        assert_eq!(
+            split_attributes(r#"rust {title="Two words" linenos}"#),
+            (
+                "rust",
+                vec![
+                    ("title".into(), "Two words".into()),
+                    ("linenos".into(), "true".into()),
+                ]
+            )
+        );
+        // Braces that are part of the info string itself are left alone
+        assert_eq!(split_attributes("a{b}").1, vec![]);
+    }
+
+    #[test]
+    fn test_parse_margins() {
+        let sides = |top, right, bottom, left| Margins {
+            top: Some(top),
+            right: Some(right),
+            bottom: Some(bottom),
+            left: Some(left),
+        };
+        assert_eq!("20".parse(), Ok(sides(20.0, 20.0, 20.0, 20.0)));
+        assert_eq!("25, 15mm".parse(), Ok(sides(25.0, 15.0, 25.0, 15.0)));
+        assert_eq!("10 20 30 40".parse(), Ok(sides(10.0, 20.0, 30.0, 40.0)));
+        assert!("1 2 3 4 5".parse::<Margins>().is_err());
+        assert!(sides(10.0, 0.0, 10.0, 0.0).check().is_ok());
+        assert!(sides(10.0, -5.0, 10.0, 0.0).check().is_err());
+        assert!(sides(f32::NAN, 0.0, 10.0, 0.0).check().is_err());
+
+        let (front_matter, _) =
+            parse_front_matter("---\npage_size: Letter\nmargins:\n  left: 30\n---\n")
+                .unwrap()
+                .unwrap();
+        assert_eq!(front_matter.page.page_size.as_deref(), Some("Letter"));
+        assert_eq!(
+            front_matter.page.margins,
+            Some(Margins {
+                left: Some(30.0),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_keywords() {
+        let keywords = |source: &str| {
+            parse_front_matter(source)
+                .unwrap()
+                .unwrap()
+                .0
+                .keywords
+                .unwrap()
+        };
+        let expected = Keywords(vec!["rust".to_string(), "pdf reports".to_string()]);
+        assert_eq!(
+            keywords("---\nkeywords: rust, pdf reports\n---\n"),
+            expected
+        );
+        assert_eq!(
+            keywords("---\nkeywords:\n  - rust\n  - pdf reports\n---\n"),
+            expected
+        );
     }
 }
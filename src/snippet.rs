@@ -0,0 +1,547 @@
+//! Code blocks that quote a source file
+//!
+//! An empty fence naming a file, as in ` ```src/foo.rs:10-40 @ user/repo#v1.2 `,
+//! is filled with the lines it names, read from the working tree or, when
+//! the block gives a ref, from that ref of the local git checkout. Code pasted
+//! into such a block can be checked against the file instead.
+
+use std::{
+    io,
+    ops::Range,
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
+    process::Command,
+    str::FromStr,
+};
+
+use pulldown_cmark::{
+    CodeBlockKind,
+    Event,
+    Tag,
+    TagEnd,
+};
+
+use crate::{
+    fmt::resolve_path,
+    parse::{
+        CodeBlockInfo,
+        MarkdownParser,
+        parse_front_matter,
+        with_line_range,
+    },
+};
+
+/// Words that start a definition named by the word after them
+const DEFINITION_KEYWORDS: [&str; 18] = [
+    "fn",
+    "struct",
+    "enum",
+    "trait",
+    "impl",
+    "mod",
+    "type",
+    "const",
+    "static",
+    "union",
+    "macro_rules",
+    "def",
+    "class",
+    "function",
+    "func",
+    "interface",
+    "let",
+    "var",
+];
+
+/// A fenced code block that names a file
+#[derive(Debug)]
+struct Snippet {
+    /// The block in the document, from the start of its opening line
+    range: Range<usize>,
+    /// Line of the document the block starts on, from 1
+    line: usize,
+    info: String,
+    code: CodeBlockInfo,
+    body: String,
+}
+
+impl Snippet {
+    /// Where the block is and what it quotes, for messages
+    fn describe(&self) -> String {
+        let (location, _) = self.info.split_once(" {").unwrap_or((&self.info, ""));
+        format!("code block at line {} ({})", self.line, location)
+    }
+}
+
+/// Where quoted files are read from
+struct Sources {
+    /// Directory the filenames are relative to
+    root: PathBuf,
+    /// Directory of the document, which the root must be inside
+    base: PathBuf,
+}
+
+impl Sources {
+    /// Read a file from the working tree, or from `refspec` with `git show`.
+    /// Files outside the root, a root outside the document's directory and
+    /// refs that git would take for options are refused.
+    fn read(&self, filename: &str, refspec: Option<&str>) -> io::Result<String> {
+        let root = self.root.canonicalize()?;
+        if !root.starts_with(self.base.canonicalize()?) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the snippet root is outside the document's directory",
+            ));
+        }
+        let path = self.path_of(filename)?;
+        let Some(refspec) = refspec else {
+            if !path.canonicalize()?.starts_with(&root) {
+                return Err(outside_root());
+            }
+            return std::fs::read_to_string(path);
+        };
+        if refspec.starts_with('-') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not a git ref", refspec),
+            ));
+        }
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.root)
+            .arg("show")
+            .arg("--end-of-options")
+            .arg(format!("{}:./{}", refspec, filename))
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Path of a file under the root, or an error if the filename is
+    /// absolute or climbs out of the root with `..`
+    fn path_of(&self, filename: &str) -> io::Result<PathBuf> {
+        let mut depth = 0usize;
+        for component in Path::new(filename).components() {
+            match component {
+                Component::Normal(_) => depth += 1,
+                Component::CurDir => {}
+                Component::ParentDir => depth = depth.checked_sub(1).ok_or_else(outside_root)?,
+                Component::RootDir | Component::Prefix(_) => return Err(outside_root()),
+            }
+        }
+        Ok(self.root.join(filename))
+    }
+
+    /// The lines a block quotes and the number of the first. A block with
+    /// only a first line quotes `count` lines when given, otherwise the rest
+    /// of the file.
+    fn quote(
+        &self,
+        snippet: &Snippet,
+        count: Option<usize>,
+    ) -> Result<(usize, Vec<String>), String> {
+        let code = &snippet.code;
+        let filename = code.filename.as_deref().unwrap_or_default();
+        let source = self
+            .read(filename, code.refspec.as_deref())
+            .map_err(|e| format!("{}: cannot read {}: {}", snippet.describe(), filename, e))?;
+        let lines: Vec<&str> = source.lines().collect();
+
+        let (first, last) = if let Some(name) = &code.region {
+            find_region(&lines, name).ok_or_else(|| {
+                format!(
+                    "{}: no region '{}' in {}",
+                    snippet.describe(),
+                    name,
+                    filename
+                )
+            })?
+        } else if let Some(name) = &code.symbol {
+            find_symbol(&lines, name).ok_or_else(|| {
+                format!(
+                    "{}: no definition of '{}' in {}",
+                    snippet.describe(),
+                    name,
+                    filename
+                )
+            })?
+        } else {
+            let first = code.start_line.unwrap_or(1);
+            let last = code
+                .end_line
+                .or(count.map(|count| first + count.saturating_sub(1)))
+                .unwrap_or(lines.len());
+            (first, last)
+        };
+        if first == 0 || first > lines.len() || last > lines.len() {
+            return Err(format!(
+                "{}: {} has only {} lines",
+                snippet.describe(),
+                filename,
+                lines.len()
+            ));
+        }
+        let quoted = lines[first - 1..last.max(first - 1)]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        Ok((first, quoted))
+    }
+}
+
+fn outside_root() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "the file is outside the snippet root",
+    )
+}
+
+/// The fenced code blocks that name a file, and where to read the files from
+fn find_snippets(markdown: &str, source_path: Option<&Path>) -> Option<(Vec<Snippet>, Sources)> {
+    let (front_matter, body) = match parse_front_matter(markdown).ok()? {
+        Some((front_matter, body)) => (Some(front_matter), body),
+        None => (None, markdown),
+    };
+    let root = front_matter
+        .and_then(|front_matter| front_matter.snippet_root)
+        .unwrap_or_else(|| ".".to_string());
+    let sources = Sources {
+        root: resolve_path(&root, source_path),
+        base: resolve_path(".", source_path),
+    };
+
+    // Offsets from the parser count from the end of the front matter
+    let offset = markdown.len() - body.len();
+    let mut snippets = Vec::new();
+    let mut current: Option<Snippet> = None;
+    let parser = MarkdownParser::new(body).ok()?.into_inner();
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let code = CodeBlockInfo::from_str(&info).unwrap();
+                if code.filename.is_some() {
+                    let start = offset + range.start;
+                    let line_start = markdown[..start].rfind('\n').map_or(0, |idx| idx + 1);
+                    current = Some(Snippet {
+                        range: line_start..offset + range.end,
+                        line: markdown[..line_start].matches('\n').count() + 1,
+                        info: info.to_string(),
+                        code,
+                        body: String::new(),
+                    });
+                }
+            }
+            Event::Text(text) => {
+                if let Some(snippet) = &mut current {
+                    snippet.body.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                snippets.extend(current.take());
+            }
+            _ => {}
+        }
+    }
+    Some((snippets, sources))
+}
+
+/// Fill each empty fenced code block that names a file with the lines it
+/// quotes, and give its info string their range so they are numbered and
+/// linked from the right line. Blocks that cannot be filled are left empty
+/// with a warning.
+pub fn fill_snippets(markdown: &str, source_path: Option<&Path>) -> String {
+    let Some((snippets, sources)) = find_snippets(markdown, source_path) else {
+        return markdown.to_string();
+    };
+
+    let mut filled = String::with_capacity(markdown.len());
+    let mut copied = 0;
+    for snippet in snippets.iter().filter(|snippet| snippet.body.is_empty()) {
+        let (first, lines) = match sources.quote(snippet, None) {
+            Ok(quote) => quote,
+            Err(message) => {
+                eprintln!("Warning: {}", message);
+                continue;
+            }
+        };
+        let last = first + lines.len().saturating_sub(1);
+        filled.push_str(&markdown[copied..snippet.range.start]);
+        filled.push_str(&fence(
+            &markdown[snippet.range.clone()],
+            &with_line_range(&snippet.info, first, last),
+            &lines,
+        ));
+        copied = snippet.range.end;
+    }
+    filled.push_str(&markdown[copied..]);
+    filled
+}
+
+/// Write `lines` into an empty fenced block with a new info string. Lines
+/// after the first repeat the block quote markers in front of the fence and
+/// are indented to it, and the fence is lengthened if the code holds one.
+fn fence(block: &str, info: &str, lines: &[String]) -> String {
+    let opening = block.lines().next().unwrap_or_default();
+    let start = opening.find(['`', '~']).unwrap_or_default();
+    let (lead, fence) = opening.split_at(start);
+    let fence_char = fence.chars().next().unwrap_or('`');
+    let fence_run = |line: &str| {
+        line.trim_start()
+            .chars()
+            .take_while(|&c| c == fence_char)
+            .count()
+    };
+    let length = lines
+        .iter()
+        .map(|line| fence_run(line) + 1)
+        .fold(fence_run(fence), usize::max);
+    let fence = fence_char.to_string().repeat(length);
+    let prefix: String = lead
+        .chars()
+        .map(|c| if c == '>' { '>' } else { ' ' })
+        .collect();
+
+    let mut filled = format!("{}{}{}\n", lead, fence, info);
+    for line in lines {
+        filled.push_str(&format!("{}{}\n", prefix, line));
+    }
+    filled.push_str(&format!("{}{}", prefix, fence));
+    if block.ends_with('\n') {
+        filled.push('\n');
+    }
+    filled
+}
+
+/// Check the code pasted into blocks that name a file and a line, region or
+/// symbol against the lines they quote, and that empty blocks can be filled.
+/// Returns the number of blocks checked and a message for each problem.
+pub fn check_snippets(markdown: &str, source_path: Option<&Path>) -> (usize, Vec<String>) {
+    let Some((snippets, sources)) = find_snippets(markdown, source_path) else {
+        return (0, Vec::new());
+    };
+
+    let mut checked = 0;
+    let mut problems = Vec::new();
+    for snippet in &snippets {
+        let code = &snippet.code;
+        let located = code.start_line.is_some() || code.region.is_some() || code.symbol.is_some();
+        if !located && !snippet.body.is_empty() {
+            continue;
+        }
+        checked += 1;
+        let pasted = trimmed_lines(snippet.body.lines());
+        let count = (!pasted.is_empty()).then_some(pasted.len());
+        let (first, source) = match sources.quote(snippet, count) {
+            Ok(quote) => quote,
+            Err(message) => {
+                problems.push(message);
+                continue;
+            }
+        };
+        let quoted = trimmed_lines(source.iter().map(String::as_str));
+        if pasted.is_empty() {
+            continue;
+        }
+        if let Some(idx) =
+            (0..pasted.len().max(quoted.len())).find(|&idx| pasted.get(idx) != quoted.get(idx))
+        {
+            problems.push(format!(
+                "{} no longer matches line {} of {}:\n  pasted: {}\n  source: {}",
+                snippet.describe(),
+                first + idx,
+                code.filename.as_deref().unwrap_or_default(),
+                pasted.get(idx).map_or("(no line)", |line| line),
+                quoted.get(idx).map_or("(no line)", |line| line),
+            ));
+        }
+    }
+    (checked, problems)
+}
+
+/// Lines without trailing whitespace or trailing blank lines, which do not
+/// count as differences
+fn trimmed_lines<'a>(lines: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut lines: Vec<&str> = lines.map(str::trim_end).collect();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines
+}
+
+/// First and last line, from 1, between the start and end markers of a
+/// region: `ANCHOR: name` and `ANCHOR_END: name`, or `#region name` and
+/// `#endregion`, in any comment syntax
+fn find_region(lines: &[&str], name: &str) -> Option<(usize, usize)> {
+    let start = lines
+        .iter()
+        .position(|line| marker_name(line, &["ANCHOR:", "#region"]) == Some(name))?;
+    let end = lines[start + 1..].iter().position(|line| {
+        marker_name(line, &["ANCHOR_END:", "#endregion"])
+            .is_some_and(|end| end == name || end.is_empty())
+    })?;
+    Some((start + 2, start + end + 1))
+}
+
+/// Name after a region marker on a line, such as `setup` in `// ANCHOR: setup`
+fn marker_name<'a>(line: &'a str, markers: &[&str]) -> Option<&'a str> {
+    markers.iter().find_map(|marker| {
+        let (_, rest) = line.split_once(marker)?;
+        Some(rest.split_whitespace().next().unwrap_or_default())
+    })
+}
+
+/// First and last line, from 1, of the definition of `name`: from the line
+/// naming it after a keyword such as `fn`, `struct` or `def` to its matching
+/// closing brace, its `;` when it has no body, or, for a line ending in `:`
+/// as in Python, the last line indented under it
+fn find_symbol(lines: &[&str], name: &str) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| {
+        let words: Vec<&str> = line
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|word| !word.is_empty())
+            .collect();
+        words
+            .windows(2)
+            .any(|pair| DEFINITION_KEYWORDS.contains(&pair[0]) && pair[1] == name)
+    })?;
+
+    if lines[start].trim_end().ends_with(':') {
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let mut last = start;
+        for (idx, line) in lines.iter().enumerate().skip(start + 1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if indent(line) <= indent(lines[start]) {
+                break;
+            }
+            last = idx;
+        }
+        return Some((start + 1, last + 1));
+    }
+
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate().skip(start) {
+        for c in line.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ';' if depth == 0 => return Some((start + 1, idx + 1)),
+                _ => {}
+            }
+        }
+        if depth <= 0 && line.contains('}') {
+            return Some((start + 1, idx + 1));
+        }
+    }
+    Some((start + 1, lines.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "use std::io;\n\n// ANCHOR: setup\nlet a = 1;\nlet b = 2;\n// ANCHOR_END: setup\n\npub fn main() {\n    if true {\n        run();\n    }\n}\n\nconst LIMIT: u32 = 5;\n\ndef helper(x):\n    return x\n\nprint(helper(1))\n";
+
+    #[test]
+    fn test_find_ranges() {
+        let lines: Vec<&str> = SOURCE.lines().collect();
+        assert_eq!(find_region(&lines, "setup"), Some((4, 5)));
+        assert_eq!(find_region(&lines, "other"), None);
+        assert_eq!(find_symbol(&lines, "main"), Some((8, 12)));
+        assert_eq!(find_symbol(&lines, "LIMIT"), Some((14, 14)));
+        assert_eq!(find_symbol(&lines, "helper"), Some((16, 17)));
+        assert_eq!(find_symbol(&lines, "missing"), None);
+    }
+
+    #[test]
+    fn test_fill_and_check_snippets() {
+        let dir = std::env::temp_dir().join(format!("mdreport-snippets-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), SOURCE).unwrap();
+        let document = dir.join("report.md");
+
+        let markdown = "# Code\n\n```src/lib.rs:8-12\n```\n\n> ```src/lib.rs {region=setup}\n> ```\n\n```src/lib.rs:99\n```\n";
+        let filled = fill_snippets(markdown, Some(&document));
+        assert!(filled.contains(
+            "```src/lib.rs:8-12\npub fn main() {\n    if true {\n        run();\n    }\n}\n```\n"
+        ));
+        assert!(
+            filled.contains(
+                "> ```src/lib.rs:4-5 {region=setup}\n> let a = 1;\n> let b = 2;\n> ```\n"
+            )
+        );
+        // A block past the end of the file is left as it was
+        assert!(filled.ends_with("```src/lib.rs:99\n```\n"));
+
+        let pasted = "```src/lib.rs:4\nlet a = 1;\nlet b = 2;\n```\n\n```src/lib.rs {symbol=LIMIT}\nconst LIMIT: u32 = 10;\n```\n\n```src/lib.rs\nnot checked\n```\n";
+        let (checked, problems) = check_snippets(pasted, Some(&document));
+        assert_eq!(checked, 2);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with(
+            "code block at line 6 (src/lib.rs) no longer matches line 14 of src/lib.rs"
+        ));
+        assert!(problems[0].contains("source: const LIMIT: u32 = 5;"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unsafe_snippet_sources() {
+        let dir = std::env::temp_dir().join(format!("mdreport-unsafe-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("docs/src")).unwrap();
+        std::fs::write(dir.join("docs/src/x.rs"), "fn x() {}\n").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret\n").unwrap();
+        let document = dir.join("docs/report.md");
+
+        // A ref that looks like an option is not handed to git
+        let markdown = "```src/x.rs:1 @ u/r#--output=out\n```\n";
+        assert_eq!(fill_snippets(markdown, Some(&document)), markdown);
+        let (_, problems) = check_snippets(markdown, Some(&document));
+        assert!(
+            problems[0].contains("'--output=out' is not a git ref"),
+            "{:?}",
+            problems
+        );
+        // Nothing was written beside the sources
+        assert_eq!(std::fs::read_dir(dir.join("docs")).unwrap().count(), 1);
+
+        // Files outside the root are refused
+        for filename in ["../secret.txt", "src/../../secret.txt"] {
+            let markdown = format!("```{}:1\n```\n", filename);
+            assert_eq!(fill_snippets(&markdown, Some(&document)), markdown);
+            let (_, problems) = check_snippets(&markdown, Some(&document));
+            assert!(
+                problems[0].contains("outside the snippet root"),
+                "{:?}",
+                problems
+            );
+        }
+        let absolute = dir.join("secret.txt");
+        let markdown = format!("```{}:1\n```\n", absolute.display());
+        assert_eq!(fill_snippets(&markdown, Some(&document)), markdown);
+
+        // So is a root outside the document's directory
+        for filename in ["secret.txt", "../secret.txt"] {
+            let markdown = format!("---\nsnippet_root: ..\n---\n```{}:1\n```\n", filename);
+            assert_eq!(fill_snippets(&markdown, Some(&document)), markdown);
+            let (_, problems) = check_snippets(&markdown, Some(&document));
+            assert!(
+                problems[0].contains("outside the document's directory"),
+                "{:?}",
+                problems
+            );
+        }
+        let markdown = "---\nsnippet_root: src\n---\n```x.rs:1\n```\n";
+        assert!(fill_snippets(markdown, Some(&document)).contains("fn x() {}"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -232,15 +232,14 @@ fn generate_pdf(
     let output_file = fs::File::create(&output_path).expect("Failed to create PDF file");
     let mut output = std::io::BufWriter::new(output_file);
 
-    mdreport::fmt::pdf::to_pdf(
-        markdown,
-        &mut output,
-        is_slides,
-        code_theme,
+    let options = mdreport::fmt::pdf::PdfOptions {
+        is_slide: is_slides,
+        code_theme: code_theme.map(str::to_string),
         embed_source,
-        source_path.map(|p| p.as_path()),
-    )
-    .expect("Failed to generate PDF");
+        source_path: source_path.cloned(),
+        ..Default::default()
+    };
+    mdreport::fmt::pdf::to_pdf(markdown, &mut output, &options).expect("Failed to generate PDF");
 
     println!("Generated: {}", output_path.display());
     1